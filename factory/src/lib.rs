@@ -28,6 +28,14 @@ pub struct TradingConfig {
     pub r_funding:    i128, // base hourly funding rate (SCALAR_18)
     pub r_base:       i128, // base hourly borrowing rate (SCALAR_18)
     pub r_var:        i128, // vault-level variable borrowing rate (SCALAR_18)
+    pub fee_free_wind_down: bool, // waive base/impact fees on closes while OnIce
+    pub max_pending:  u32,  // pending limit orders per user per market, 0 = unlimited
+    pub fill_bonus:   i128, // extra keeper share of fill fees per hour marked fillable (SCALAR_7)
+    pub liq_caller_cap: i128, // cap on a liquidation's keeper fee as a share of collateral, 0 = uncapped (SCALAR_7)
+    pub referral_share: i128, // referrer's share of a referred user's trading fees (SCALAR_7)
+    pub max_total_positions: u32, // pending + filled positions across all users, 0 = unlimited
+    pub fill_bounty: i128, // fixed amount a limit order escrows for the keeper that fills it (token_decimals)
+    pub keeper_min_shares: i128, // vault shares a keeper must hold to liquidate, 0 = permissionless
 }
 
 /// Factory contract for atomic deployment of trading pools (trading + vault).
@@ -105,10 +113,21 @@ impl Factory for FactoryContract {
         let trading_address = trading_deployer.deployed_address();
         let vault_address = vault_deployer.deployed_address();
 
-        // Deploy vault first (its constructor doesn't call trading)
+        // Deploy vault first (its constructor doesn't call trading).
+        // `deploy` is at the 10-argument contract limit, so factory vaults
+        // start with the minimum deposit and withdrawal fee disabled.
         vault_deployer.deploy_v2(
             init_meta.vault_hash,
-            (vault_name, vault_symbol, token.clone(), vault_decimals_offset, trading_address.clone(), vault_lock_time),
+            (
+                vault_name,
+                vault_symbol,
+                token.clone(),
+                vault_decimals_offset,
+                trading_address.clone(),
+                vault_lock_time,
+                0i128,
+                0i128,
+            ),
         );
 
         // Deploy trading (vault is already live so cross-contract calls work)
@@ -23,6 +23,14 @@ fn default_config() -> TradingConfig {
         r_funding: 10_000_000_000_000,
         r_base: 10_000_000_000_000,
         r_var: 10_000_000_000_000,
+        fee_free_wind_down: false,
+        max_pending: 0,
+        fill_bonus: 0,
+        liq_caller_cap: 0,
+        referral_share: 0,
+        max_total_positions: 0,
+        fill_bounty: 0,
+        keeper_min_shares: 0,
     }
 }
 
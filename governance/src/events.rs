@@ -1,6 +1,7 @@
 use soroban_sdk::{contractevent, Address, Symbol};
 
-/// Emitted when a new call is queued via `queue` or when `set_delay` creates a pending change.
+/// Emitted when a new call is queued via `queue` or when `set_delay` or
+/// `queue_upgrade` creates a pending change (`nonce` is `u32::MAX` for those).
 #[contractevent]
 #[derive(Clone)]
 pub struct Queued {
@@ -21,7 +22,8 @@ pub struct Executed {
     pub fn_name: Symbol,
 }
 
-/// Emitted when a queued call is cancelled by the owner.
+/// Emitted when a queued call is cancelled by the owner (`nonce` is `u32::MAX`
+/// for a pending upgrade cancelled via `cancel_upgrade`).
 #[contractevent]
 #[derive(Clone)]
 pub struct Cancelled {
@@ -1,8 +1,8 @@
 #![no_std]
 
 use soroban_sdk::{
-    contract, contractclient, contractimpl, panic_with_error, Address, Env, IntoVal, Symbol, Val,
-    Vec,
+    contract, contractclient, contractimpl, panic_with_error, Address, BytesN, Env, IntoVal,
+    Symbol, Val, Vec,
 };
 use soroban_sdk::unwrap::UnwrapOptimized;
 use stellar_access::ownable::{self, Ownable};
@@ -14,10 +14,12 @@ mod events;
 mod storage;
 
 pub use errors::GovernanceError;
-pub use storage::QueuedCall;
+pub use storage::{PendingDelay, PendingUpgrade, QueuedCall};
 
 /// Governance timelock for deferred admin operations. Config changes are queued
 /// with a mandatory delay. set_status bypasses delay for emergency halts.
+/// Upgrades of this contract are timelocked too, so it cannot be swapped for
+/// code that skips the delay on the contracts it owns.
 #[contract]
 pub struct GovernanceContract;
 
@@ -77,6 +79,24 @@ pub trait Governance {
     /// # Panics
     /// - `GovernanceError::NotQueued` (770) if nonce not found or expired
     fn get_queued(e: Env, nonce: u32) -> QueuedCall;
+
+    /// Returns the delay change queued by `set_delay`, if any, so it can be
+    /// verified before `apply_delay` is called.
+    fn get_pending_delay(e: Env) -> Option<PendingDelay>;
+
+    /// (Owner only) Queue an upgrade of this contract to `new_wasm_hash`. It can
+    /// be applied through `upgrade` once the current delay has passed. Replaces
+    /// any upgrade already pending.
+    fn queue_upgrade(e: Env, new_wasm_hash: BytesN<32>);
+
+    /// (Owner only) Cancel the pending upgrade.
+    ///
+    /// # Panics
+    /// - `GovernanceError::NotQueued` (770) if no upgrade is pending
+    fn cancel_upgrade(e: Env);
+
+    /// Returns the upgrade queued by `queue_upgrade`, if any.
+    fn get_pending_upgrade(e: Env) -> Option<PendingUpgrade>;
 }
 
 #[contractimpl]
@@ -205,19 +225,67 @@ impl Governance for GovernanceContract {
         storage::get_queued(&e, nonce)
             .unwrap_or_else(|| panic_with_error!(&e, GovernanceError::NotQueued))
     }
+
+    fn get_pending_delay(e: Env) -> Option<PendingDelay> {
+        storage::get_pending_delay(&e)
+    }
+
+    #[only_owner]
+    fn queue_upgrade(e: Env, new_wasm_hash: BytesN<32>) {
+        let delay = storage::get_delay(&e);
+        let unlock_time = e.ledger().timestamp() + delay;
+        let pending = PendingUpgrade {
+            wasm_hash: new_wasm_hash,
+            unlock_time,
+        };
+        storage::set_pending_upgrade(&e, &pending, delay);
+        events::Queued {
+            nonce: u32::MAX,
+            target: e.current_contract_address(),
+            fn_name: Symbol::new(&e, "upgrade"),
+            unlock_time,
+        }
+        .publish(&e);
+    }
+
+    #[only_owner]
+    fn cancel_upgrade(e: Env) {
+        if storage::get_pending_upgrade(&e).is_none() {
+            panic_with_error!(&e, GovernanceError::NotQueued);
+        }
+        storage::remove_pending_upgrade(&e);
+        events::Cancelled { nonce: u32::MAX }.publish(&e);
+    }
+
+    fn get_pending_upgrade(e: Env) -> Option<PendingUpgrade> {
+        storage::get_pending_upgrade(&e)
+    }
 }
 
 #[contractimpl(contracttrait)]
 impl Ownable for GovernanceContract {}
 
+/// Only applies an upgrade queued via `queue_upgrade` whose delay has passed.
+///
+/// # Panics
+/// - `GovernanceError::Unauthorized` (1) if `operator` is not the owner
+/// - `GovernanceError::NotQueued` (770) if `new_wasm_hash` is not the pending upgrade
+/// - `GovernanceError::NotUnlocked` (771) if the delay has not yet passed
 #[contractimpl]
 impl Upgradeable for GovernanceContract {
-    fn upgrade(e: &Env, new_wasm_hash: soroban_sdk::BytesN<32>, operator: Address) {
+    fn upgrade(e: &Env, new_wasm_hash: BytesN<32>, operator: Address) {
         operator.require_auth();
         let owner = ownable::get_owner(e).unwrap_optimized();
         if operator != owner {
             panic_with_error!(e, GovernanceError::Unauthorized)
         }
+        let pending = storage::get_pending_upgrade(e)
+            .filter(|pending| pending.wasm_hash == new_wasm_hash)
+            .unwrap_or_else(|| panic_with_error!(e, GovernanceError::NotQueued));
+        if pending.unlock_time > e.ledger().timestamp() {
+            panic_with_error!(e, GovernanceError::NotUnlocked);
+        }
+        storage::remove_pending_upgrade(e);
         upgradeable::upgrade(e, &new_wasm_hash);
     }
 }
@@ -1,4 +1,4 @@
-use soroban_sdk::{contracttype, Address, Env, Symbol, Val, Vec};
+use soroban_sdk::{contracttype, Address, BytesN, Env, Symbol, Val, Vec};
 use soroban_sdk::unwrap::UnwrapOptimized;
 
 #[contracttype]
@@ -8,6 +8,7 @@ pub enum GovKey {
     Nonce,
     Queued(u32),
     PendingDelay,
+    PendingUpgrade,
 }
 
 #[contracttype]
@@ -26,6 +27,13 @@ pub struct PendingDelay {
     pub unlock_time: u64,
 }
 
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PendingUpgrade {
+    pub wasm_hash: BytesN<32>,
+    pub unlock_time: u64,
+}
+
 pub const ONE_DAY_LEDGERS: u32 = 17280;
 pub const MIN_TTL_LEDGERS: u32 = ONE_DAY_LEDGERS; // 1 day floor
 
@@ -55,6 +63,10 @@ pub fn get_pending_delay(e: &Env) -> Option<PendingDelay> {
     e.storage().temporary().get(&GovKey::PendingDelay)
 }
 
+pub fn get_pending_upgrade(e: &Env) -> Option<PendingUpgrade> {
+    e.storage().temporary().get(&GovKey::PendingUpgrade)
+}
+
 pub fn set_delay(e: &Env, delay: u64) {
     e.storage().instance().set(&GovKey::Delay, &delay);
 }
@@ -77,6 +89,15 @@ pub fn set_pending_delay(e: &Env, pending: &PendingDelay, delay: u64) {
         .extend_ttl(&key, threshold, bump);
 }
 
+pub fn set_pending_upgrade(e: &Env, pending: &PendingUpgrade, delay: u64) {
+    let key = GovKey::PendingUpgrade;
+    e.storage().temporary().set(&key, pending);
+    let (threshold, bump) = ttl_for_delay(delay);
+    e.storage()
+        .temporary()
+        .extend_ttl(&key, threshold, bump);
+}
+
 pub fn next_nonce(e: &Env) -> u32 {
     let key = GovKey::Nonce;
     let current: u32 = e.storage().instance().get(&key).unwrap_or(0);
@@ -91,3 +112,7 @@ pub fn remove_queued(e: &Env, nonce: u32) {
 pub fn remove_pending_delay(e: &Env) {
     e.storage().temporary().remove(&GovKey::PendingDelay);
 }
+
+pub fn remove_pending_upgrade(e: &Env) {
+    e.storage().temporary().remove(&GovKey::PendingUpgrade);
+}
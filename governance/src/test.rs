@@ -1,6 +1,6 @@
-use crate::{GovernanceContract, GovernanceClient};
+use crate::{GovernanceContract, GovernanceContractClient, GovernanceClient, PendingDelay, PendingUpgrade};
 use soroban_sdk::testutils::{Address as _, Ledger, LedgerInfo};
-use soroban_sdk::{contract, contractimpl, Address, Env, IntoVal, Symbol, Val, Vec};
+use soroban_sdk::{contract, contractimpl, Address, BytesN, Env, IntoVal, Symbol, Val, Vec};
 
 #[contract]
 pub struct MockTarget;
@@ -17,6 +17,16 @@ impl MockTarget {
         e.storage().instance().set(&Symbol::new(&e, "config"), &value);
     }
 
+    /// Mirrors `Upgradeable::upgrade`: records the hash instead of swapping WASM.
+    pub fn upgrade(e: Env, new_wasm_hash: BytesN<32>, operator: Address) {
+        operator.require_auth();
+        e.storage().instance().set(&Symbol::new(&e, "wasm"), &new_wasm_hash);
+    }
+
+    pub fn get_wasm(e: Env) -> Option<BytesN<32>> {
+        e.storage().instance().get(&Symbol::new(&e, "wasm"))
+    }
+
     pub fn get_status(e: Env) -> u32 {
         e.storage().instance().get(&Symbol::new(&e, "status")).unwrap_or(0)
     }
@@ -96,6 +106,37 @@ fn test_cancel_removes_queued() {
     assert!(result.is_err());
 }
 
+#[test]
+fn test_queued_upgrade_waits_for_delay_and_can_be_cancelled() {
+    let (e, _owner, gov_id, target_id) = setup_env();
+    let client = GovernanceClient::new(&e, &gov_id);
+    let target_client = MockTargetClient::new(&e, &target_id);
+    let upgrade = Symbol::new(&e, "upgrade");
+
+    set_ledger_timestamp(&e, 1000);
+    let hash = BytesN::from_array(&e, &[7u8; 32]);
+    let args: Vec<Val> = Vec::from_array(&e, [hash.into_val(&e), gov_id.into_val(&e)]);
+    let nonce = client.queue(&target_id, &upgrade, &args);
+
+    // Not applied before the delay
+    set_ledger_timestamp(&e, 1000 + DELAY - 1);
+    assert!(client.try_execute(&nonce).is_err());
+    assert_eq!(target_client.get_wasm(), None);
+
+    set_ledger_timestamp(&e, 1000 + DELAY);
+    client.execute(&nonce);
+    assert_eq!(target_client.get_wasm(), Some(hash));
+
+    // A cancelled upgrade never applies
+    let other = BytesN::from_array(&e, &[9u8; 32]);
+    let args: Vec<Val> = Vec::from_array(&e, [other.into_val(&e), gov_id.into_val(&e)]);
+    let nonce = client.queue(&target_id, &upgrade, &args);
+    client.cancel(&nonce);
+    set_ledger_timestamp(&e, 1000 + 3 * DELAY);
+    assert!(client.try_execute(&nonce).is_err());
+    assert_eq!(target_client.get_wasm(), Some(BytesN::from_array(&e, &[7u8; 32])));
+}
+
 #[test]
 #[should_panic(expected = "Error(Contract, #770)")]
 fn test_cancel_nonexistent_fails() {
@@ -222,6 +263,25 @@ fn test_set_delay_through_timelock() {
     assert_eq!(client.get_delay(), 7200);
 }
 
+#[test]
+fn test_get_pending_delay() {
+    let (e, _owner, gov_id, _target_id) = setup_env();
+    let client = GovernanceClient::new(&e, &gov_id);
+
+    set_ledger_timestamp(&e, 1000);
+    assert_eq!(client.get_pending_delay(), None);
+
+    client.set_delay(&7200);
+    assert_eq!(
+        client.get_pending_delay(),
+        Some(PendingDelay { new_delay: 7200, unlock_time: 1000 + DELAY })
+    );
+
+    set_ledger_timestamp(&e, 1000 + DELAY + 1);
+    client.apply_delay();
+    assert_eq!(client.get_pending_delay(), None);
+}
+
 #[test]
 #[should_panic(expected = "Error(Contract, #770)")]
 fn test_apply_delay_without_pending_fails() {
@@ -232,6 +292,76 @@ fn test_apply_delay_without_pending_fails() {
     client.apply_delay();
 }
 
+#[test]
+fn test_queue_and_cancel_upgrade() {
+    let (e, _owner, gov_id, _target_id) = setup_env();
+    let client = GovernanceClient::new(&e, &gov_id);
+
+    set_ledger_timestamp(&e, 1000);
+    assert_eq!(client.get_pending_upgrade(), None);
+
+    let hash = BytesN::from_array(&e, &[7u8; 32]);
+    client.queue_upgrade(&hash);
+    assert_eq!(
+        client.get_pending_upgrade(),
+        Some(PendingUpgrade { wasm_hash: hash, unlock_time: 1000 + DELAY })
+    );
+
+    client.cancel_upgrade();
+    assert_eq!(client.get_pending_upgrade(), None);
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #770)")]
+fn test_upgrade_without_queue_fails() {
+    let (e, owner, gov_id, _target_id) = setup_env();
+
+    set_ledger_timestamp(&e, 1000);
+    GovernanceContractClient::new(&e, &gov_id).upgrade(&BytesN::from_array(&e, &[7u8; 32]), &owner);
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #771)")]
+fn test_upgrade_before_delay_fails() {
+    let (e, owner, gov_id, _target_id) = setup_env();
+    let client = GovernanceClient::new(&e, &gov_id);
+
+    set_ledger_timestamp(&e, 1000);
+    let hash = BytesN::from_array(&e, &[7u8; 32]);
+    client.queue_upgrade(&hash);
+
+    set_ledger_timestamp(&e, 1000 + DELAY - 1);
+    GovernanceContractClient::new(&e, &gov_id).upgrade(&hash, &owner);
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #770)")]
+fn test_upgrade_to_other_hash_than_queued_fails() {
+    let (e, owner, gov_id, _target_id) = setup_env();
+    let client = GovernanceClient::new(&e, &gov_id);
+
+    set_ledger_timestamp(&e, 1000);
+    client.queue_upgrade(&BytesN::from_array(&e, &[7u8; 32]));
+
+    set_ledger_timestamp(&e, 1000 + DELAY);
+    GovernanceContractClient::new(&e, &gov_id).upgrade(&BytesN::from_array(&e, &[9u8; 32]), &owner);
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #770)")]
+fn test_cancelled_upgrade_cannot_apply() {
+    let (e, owner, gov_id, _target_id) = setup_env();
+    let client = GovernanceClient::new(&e, &gov_id);
+
+    set_ledger_timestamp(&e, 1000);
+    let hash = BytesN::from_array(&e, &[7u8; 32]);
+    client.queue_upgrade(&hash);
+    client.cancel_upgrade();
+
+    set_ledger_timestamp(&e, 1000 + DELAY);
+    GovernanceContractClient::new(&e, &gov_id).upgrade(&hash, &owner);
+}
+
 #[test]
 fn test_event_emission() {
     let (e, _owner, gov_id, target_id) = setup_env();
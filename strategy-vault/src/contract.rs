@@ -1,10 +1,16 @@
-use soroban_sdk::{contract, contractimpl, Address, Env, MuxedAddress, String};
+#![allow(clippy::too_many_arguments)]
+
+use soroban_sdk::{contract, contractimpl, vec, Address, Env, MuxedAddress, String, Vec};
 use stellar_tokens::{
     fungible::{Base, FungibleToken},
     vault::{FungibleVault, Vault},
 };
 
-use crate::{storage, strategy::StrategyVault};
+use crate::{
+    rewards::Rewards,
+    storage,
+    strategy::{StrategyVault, VaultInitialized},
+};
 
 /// ERC-4626 tokenized vault with share-aware deposit locking. Backs trader
 /// positions with depositor collateral. Only recently deposited shares are
@@ -22,13 +28,26 @@ impl StrategyVaultContract {
         decimals_offset: u32,
         strategy: Address,
         lock_time: u64,
+        min_deposit: i128,
+        withdrawal_fee: i128,
     ) {
-        Vault::set_asset(&e, asset);
+        Vault::set_asset(&e, asset.clone());
         Vault::set_decimals_offset(&e, decimals_offset);
         Base::set_metadata(&e, Vault::decimals(&e), name, symbol);
 
         storage::set_lock_time(&e, &lock_time);
         storage::set_strategy(&e, &strategy);
+        storage::set_min_deposit(&e, &min_deposit);
+        StrategyVault::set_withdrawal_fee(&e, withdrawal_fee);
+
+        VaultInitialized {
+            asset,
+            strategy,
+            lock_time,
+            min_deposit,
+            withdrawal_fee,
+        }
+        .publish(&e);
     }
 
     /// Returns the lock time in seconds.
@@ -37,12 +56,95 @@ impl StrategyVaultContract {
         storage::get_lock_time(&e)
     }
 
+    /// Returns the minimum assets accepted per deposit or mint (0 = disabled).
+    pub fn min_deposit(e: Env) -> i128 {
+        storage::extend_instance(&e);
+        storage::get_min_deposit(&e)
+    }
+
+    /// Returns the share of each exit kept by the vault for remaining holders
+    /// (SCALAR_7, 0 = disabled).
+    pub fn withdrawal_fee(e: Env) -> i128 {
+        storage::extend_instance(&e);
+        storage::get_withdrawal_fee(&e)
+    }
+
     /// Returns the number of shares the user can currently withdraw/transfer.
     pub fn available_shares(e: Env, user: Address) -> i128 {
         storage::extend_instance(&e);
         StrategyVault::available_shares(&e, &user)
     }
 
+    /// Returns the user's deposit lock as `(locked_shares, unlock_time, withdrawable_now)`,
+    /// or `None` if the user has no deposit history. The share balance itself
+    /// is `balance(user)`.
+    pub fn withdrawal_status(e: Env, user: Address) -> Option<(i128, u64, bool)> {
+        storage::extend_instance(&e);
+        StrategyVault::withdrawal_status(&e, &user)
+    }
+
+    /// Returns `(assets_out, assets_locked)` for the owner exiting now: the assets
+    /// redeeming all unlocked shares would pay, and the value still locked.
+    pub fn preview_exit(e: Env, owner: Address) -> (i128, i128) {
+        storage::extend_instance(&e);
+        StrategyVault::preview_exit(&e, &owner)
+    }
+
+    /// `redeem` with slippage protection: reverts with `InsufficientOutput` if the
+    /// shares pay fewer than `min_assets_out` at the rate when the call executes,
+    /// e.g. after a strategy loss since the owner signed. Returns the assets paid.
+    pub fn redeem_min(
+        e: Env,
+        shares: i128,
+        min_assets_out: i128,
+        receiver: Address,
+        owner: Address,
+        operator: Address,
+    ) -> i128 {
+        let assets = <Self as FungibleVault>::redeem(&e, shares, receiver, owner, operator);
+        StrategyVault::require_min_out(&e, assets, min_assets_out);
+        assets
+    }
+
+    /// Returns the strategies the vault backs. This vault has exactly one.
+    pub fn strategies(e: Env) -> Vec<Address> {
+        storage::extend_instance(&e);
+        vec![&e, storage::get_strategy(&e)]
+    }
+
+    /// Returns each strategy with its net impact on vault assets: total assets
+    /// minus net LP flows (deposits and donations less withdrawals).
+    pub fn strategy_impacts(e: Env) -> Vec<(Address, i128)> {
+        storage::extend_instance(&e);
+        vec![&e, (storage::get_strategy(&e), StrategyVault::impact(&e))]
+    }
+
+    /// Returns the strategy's `(outstanding_debt, realized_profit)`. Returns
+    /// repay drawn principal first; only the excess counts as profit, so
+    /// `realized_profit - outstanding_debt` equals the strategy's impact.
+    pub fn strategy_book(e: Env) -> (i128, i128) {
+        storage::extend_instance(&e);
+        let book = StrategyVault::book(&e);
+        (book.debt, book.profit)
+    }
+
+    /// Returns the strategy's principal `(borrowed, repaid)` over the vault's
+    /// lifetime. Inflows only repay principal up to `borrowed`; anything past it
+    /// is reported as profit by `strategy_book`, never as returned principal.
+    pub fn strategy_principal(e: Env) -> (i128, i128) {
+        storage::extend_instance(&e);
+        let book = StrategyVault::book(&e);
+        (book.borrowed, book.repaid)
+    }
+
+    /// Donate tokens to the vault without minting shares. Raises the share
+    /// price for all holders; used to distribute profit out-of-band.
+    pub fn donate(e: Env, from: Address, amount: i128) {
+        from.require_auth();
+        StrategyVault::donate(&e, &from, amount);
+        storage::extend_instance(&e);
+    }
+
     /// Strategy (trading contract) withdraws tokens from the vault to pay
     /// winning positions. Decreases `total_assets` and thus share price.
     pub fn strategy_withdraw(e: Env, strategy: Address, amount: i128) {
@@ -50,6 +152,26 @@ impl StrategyVaultContract {
         StrategyVault::withdraw(&e, &strategy, amount);
         storage::extend_instance(&e);
     }
+
+    /// Strategy registers the swap router used to compound a secondary
+    /// reward token into the vault asset. The trading contract forwards its
+    /// owner's `set_reward_router` here.
+    pub fn set_reward_router(e: Env, strategy: Address, reward: Address, router: Address) {
+        strategy.require_auth();
+        Rewards::set_router(&e, &strategy, &reward, &router);
+        storage::extend_instance(&e);
+    }
+
+    /// Strategy swaps the vault's balance of `reward` into the vault asset via
+    /// the registered router. No shares are minted, so the proceeds raise the
+    /// share price for all holders. Returns the asset amount received. The
+    /// trading contract forwards its owner's `compound_reward` here.
+    pub fn compound(e: Env, strategy: Address, reward: Address, min_out: i128) -> i128 {
+        strategy.require_auth();
+        let amount_out = Rewards::compound(&e, &strategy, &reward, min_out);
+        storage::extend_instance(&e);
+        amount_out
+    }
 }
 
 // Override transfer/transfer_from to enforce share-aware lock.
@@ -68,24 +190,47 @@ impl FungibleToken for StrategyVaultContract {
     }
 }
 
-// Override deposit/mint to record locked shares.
-// Override withdraw/redeem to enforce share-aware lock.
+// Override deposit/mint to enforce the minimum deposit and record locked shares.
+// Override withdraw/redeem to require outstanding shares, enforce share-aware lock,
+// and keep the withdrawal fee in the vault; the previews and max_withdraw net it out.
+// Exits are synchronous, so `receiver` is fixed by the authorizing owner (or an
+// operator spending its allowance) in the same call; nobody else can redirect it.
+// `convert_to_shares` / `convert_to_assets` are inherited unchanged: pure conversions
+// at the current rate for integrators, ignoring deposit locks and the minimum deposit.
 #[contractimpl(contracttrait)]
 impl FungibleVault for StrategyVaultContract {
     fn deposit(e: &Env, assets: i128, receiver: Address, from: Address, operator: Address) -> i128 {
+        StrategyVault::require_min_deposit(e, assets);
         let shares = Vault::deposit(e, assets, receiver.clone(), from, operator);
         StrategyVault::record_deposit(e, &receiver, shares);
+        StrategyVault::record_flow(e, assets);
         storage::extend_instance(e);
         shares
     }
 
     fn mint(e: &Env, shares: i128, receiver: Address, from: Address, operator: Address) -> i128 {
+        StrategyVault::require_min_deposit(e, Vault::preview_mint(e, shares));
         let assets = Vault::mint(e, shares, receiver.clone(), from, operator);
         StrategyVault::record_deposit(e, &receiver, shares);
+        StrategyVault::record_flow(e, assets);
         storage::extend_instance(e);
         assets
     }
 
+    fn max_withdraw(e: &Env, owner: Address) -> i128 {
+        let gross = Vault::max_withdraw(e, owner);
+        gross - StrategyVault::fee_on_gross(e, gross)
+    }
+
+    fn preview_withdraw(e: &Env, assets: i128) -> i128 {
+        Vault::preview_withdraw(e, StrategyVault::gross_for_net(e, assets))
+    }
+
+    fn preview_redeem(e: &Env, shares: i128) -> i128 {
+        let gross = Vault::preview_redeem(e, shares);
+        gross - StrategyVault::fee_on_gross(e, gross)
+    }
+
     fn withdraw(
         e: &Env,
         assets: i128,
@@ -93,16 +238,28 @@ impl FungibleVault for StrategyVaultContract {
         owner: Address,
         operator: Address,
     ) -> i128 {
-        let shares_needed = Vault::preview_withdraw(e, assets);
-        StrategyVault::require_available(e, &owner, shares_needed);
-        let shares = Vault::withdraw(e, assets, receiver, owner, operator);
+        operator.require_auth();
+        StrategyVault::require_shares(e);
+        let fee = StrategyVault::gross_for_net(e, assets) - assets;
+        let shares = Vault::preview_withdraw(e, assets + fee);
+        StrategyVault::require_available(e, &owner, shares);
+        Vault::withdraw_internal(e, &receiver, &owner, &operator, assets, shares);
+        StrategyVault::record_flow(e, -assets);
+        StrategyVault::emit_withdrawal_fee(e, &owner, assets, fee);
         storage::extend_instance(e);
         shares
     }
 
     fn redeem(e: &Env, shares: i128, receiver: Address, owner: Address, operator: Address) -> i128 {
+        operator.require_auth();
+        StrategyVault::require_shares(e);
         StrategyVault::require_available(e, &owner, shares);
-        let assets = Vault::redeem(e, shares, receiver, owner, operator);
+        let gross = Vault::preview_redeem(e, shares);
+        let fee = StrategyVault::fee_on_gross(e, gross);
+        let assets = gross - fee;
+        Vault::withdraw_internal(e, &receiver, &owner, &operator, assets, shares);
+        StrategyVault::record_flow(e, -assets);
+        StrategyVault::emit_withdrawal_fee(e, &owner, assets, fee);
         storage::extend_instance(e);
         assets
     }
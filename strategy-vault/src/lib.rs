@@ -1,6 +1,7 @@
 #![no_std]
 
 mod contract;
+mod rewards;
 mod storage;
 mod strategy;
 pub use contract::{StrategyVaultContract, StrategyVaultContractClient};
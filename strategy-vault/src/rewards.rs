@@ -0,0 +1,102 @@
+//! Reward compounding: swap secondary reward tokens into the vault asset.
+//!
+//! Strategies that receive a reward token other than the vault asset register a
+//! swap router for it. Compounding sells the vault's reward balance through that
+//! router and keeps the proceeds without minting shares, raising the share price.
+//!
+//! Both calls take the strategy's auth. The trading contract reaches them
+//! through its owner-only `set_reward_router` and `compound_reward`.
+
+use soroban_sdk::{contractclient, contractevent, panic_with_error, token, Address, Env};
+use stellar_tokens::vault::Vault;
+
+use crate::storage;
+use crate::strategy::{StrategyVault, StrategyVaultError};
+
+/// Swap router interface used to sell reward tokens for the vault asset.
+#[allow(dead_code)] // Trait is used by the generated Client via #[contractclient]
+#[contractclient(name = "SwapRouterClient")]
+pub trait SwapRouter {
+    /// Swap `amount_in` of `token_in` (already transferred to the router) for at
+    /// least `min_out` of `token_out`, sent to `to`. Returns the amount out.
+    fn swap_exact_in(
+        e: Env,
+        token_in: Address,
+        token_out: Address,
+        amount_in: i128,
+        min_out: i128,
+        to: Address,
+    ) -> i128;
+}
+
+#[contractevent]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SetRewardRouter {
+    #[topic]
+    pub reward: Address,
+    pub router: Address,
+}
+
+#[contractevent]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Compound {
+    #[topic]
+    pub reward: Address,
+    pub amount_in: i128,
+    pub amount_out: i128,
+}
+
+pub struct Rewards;
+
+impl Rewards {
+    /// Register (or replace) the swap router used to compound `reward`.
+    pub fn set_router(env: &Env, strategy: &Address, reward: &Address, router: &Address) {
+        StrategyVault::require_strategy(env, strategy);
+        if *reward == Vault::query_asset(env) {
+            panic_with_error!(env, StrategyVaultError::InvalidRewardToken);
+        }
+        storage::set_reward_router(env, reward, router);
+
+        SetRewardRouter {
+            reward: reward.clone(),
+            router: router.clone(),
+        }
+        .publish(env);
+    }
+
+    /// Swap the vault's full `reward` balance into the vault asset.
+    /// Returns the asset amount received (0 if there was nothing to compound).
+    pub fn compound(env: &Env, strategy: &Address, reward: &Address, min_out: i128) -> i128 {
+        StrategyVault::require_strategy(env, strategy);
+        let router = storage::get_reward_router(env, reward)
+            .unwrap_or_else(|| panic_with_error!(env, StrategyVaultError::RewardNotRegistered));
+
+        let vault = env.current_contract_address();
+        let reward_client = token::Client::new(env, reward);
+        let amount_in = reward_client.balance(&vault);
+        if amount_in == 0 {
+            return 0;
+        }
+
+        let asset = Vault::query_asset(env);
+        let asset_client = token::Client::new(env, &asset);
+        let before = asset_client.balance(&vault);
+        reward_client.transfer(&vault, &router, &amount_in);
+        SwapRouterClient::new(env, &router).swap_exact_in(reward, &asset, &amount_in, &min_out, &vault);
+
+        // Measure what actually arrived rather than trusting the router's return value
+        let amount_out = asset_client.balance(&vault) - before;
+        if amount_out < min_out {
+            panic_with_error!(env, StrategyVaultError::InsufficientOutput);
+        }
+
+        Compound {
+            reward: reward.clone(),
+            amount_in,
+            amount_out,
+        }
+        .publish(env);
+
+        amount_out
+    }
+}
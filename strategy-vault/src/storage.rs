@@ -12,12 +12,32 @@ pub struct DepositLock {
     pub shares: i128,
 }
 
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[contracttype]
+pub struct StrategyBook {
+    /// Principal drawn by the strategy and not yet returned.
+    pub debt: i128,
+    /// Returns in excess of drawn principal.
+    pub profit: i128,
+    /// Principal drawn over the vault's lifetime.
+    pub borrowed: i128,
+    /// Principal returned over the vault's lifetime; inflows past it are profit.
+    pub repaid: i128,
+    /// Strategy impact at the last settlement; inflows since are returns.
+    pub impact: i128,
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 #[contracttype]
 pub enum StrategyStorageKey {
     LockTime,
     Strategy,
     DepositLock(Address),
+    RewardRouter(Address),
+    NetDeposits,
+    MinDeposit,
+    StrategyBook,
+    WithdrawalFee,
 }
 
 pub fn extend_instance(e: &Env) {
@@ -39,6 +59,34 @@ pub fn set_lock_time(e: &Env, lock_time: &u64) {
         .set::<StrategyStorageKey, u64>(&StrategyStorageKey::LockTime, lock_time);
 }
 
+/// Minimum assets per deposit or mint; 0 disables the check.
+pub fn get_min_deposit(e: &Env) -> i128 {
+    e.storage()
+        .instance()
+        .get::<StrategyStorageKey, i128>(&StrategyStorageKey::MinDeposit)
+        .unwrap_or(0)
+}
+
+pub fn set_min_deposit(e: &Env, min_deposit: &i128) {
+    e.storage()
+        .instance()
+        .set::<StrategyStorageKey, i128>(&StrategyStorageKey::MinDeposit, min_deposit);
+}
+
+/// Share of every exit kept by the vault for remaining holders (SCALAR_7); 0 disables it.
+pub fn get_withdrawal_fee(e: &Env) -> i128 {
+    e.storage()
+        .instance()
+        .get::<StrategyStorageKey, i128>(&StrategyStorageKey::WithdrawalFee)
+        .unwrap_or(0)
+}
+
+pub fn set_withdrawal_fee(e: &Env, rate: &i128) {
+    e.storage()
+        .instance()
+        .set::<StrategyStorageKey, i128>(&StrategyStorageKey::WithdrawalFee, rate);
+}
+
 pub fn get_strategy(e: &Env) -> Address {
     e.storage()
         .instance()
@@ -52,6 +100,33 @@ pub fn set_strategy(e: &Env, strategy: &Address) {
         .set::<StrategyStorageKey, Address>(&StrategyStorageKey::Strategy, strategy);
 }
 
+/// Net assets contributed by LPs: deposits and donations minus withdrawals.
+pub fn get_net_deposits(e: &Env) -> i128 {
+    e.storage()
+        .instance()
+        .get::<StrategyStorageKey, i128>(&StrategyStorageKey::NetDeposits)
+        .unwrap_or(0)
+}
+
+pub fn set_net_deposits(e: &Env, amount: &i128) {
+    e.storage()
+        .instance()
+        .set::<StrategyStorageKey, i128>(&StrategyStorageKey::NetDeposits, amount);
+}
+
+pub fn get_strategy_book(e: &Env) -> StrategyBook {
+    e.storage()
+        .instance()
+        .get::<StrategyStorageKey, StrategyBook>(&StrategyStorageKey::StrategyBook)
+        .unwrap_or_default()
+}
+
+pub fn set_strategy_book(e: &Env, book: &StrategyBook) {
+    e.storage()
+        .instance()
+        .set::<StrategyStorageKey, StrategyBook>(&StrategyStorageKey::StrategyBook, book);
+}
+
 pub fn get_deposit_lock(e: &Env, user: &Address) -> Option<DepositLock> {
     let key = StrategyStorageKey::DepositLock(user.clone());
     let result = e
@@ -75,3 +150,15 @@ pub fn set_deposit_lock(e: &Env, user: &Address, lock: &DepositLock) {
         .persistent()
         .extend_ttl(&key, BALANCE_TTL_THRESHOLD, BALANCE_EXTEND_AMOUNT);
 }
+
+pub fn get_reward_router(e: &Env, reward: &Address) -> Option<Address> {
+    e.storage()
+        .instance()
+        .get::<StrategyStorageKey, Address>(&StrategyStorageKey::RewardRouter(reward.clone()))
+}
+
+pub fn set_reward_router(e: &Env, reward: &Address, router: &Address) {
+    e.storage()
+        .instance()
+        .set::<StrategyStorageKey, Address>(&StrategyStorageKey::RewardRouter(reward.clone()), router);
+}
@@ -1,9 +1,10 @@
 //! Strategy integration and share-aware deposit locking.
 
+use soroban_fixed_point_math::SorobanFixedPoint;
 use soroban_sdk::{contracterror, contractevent, panic_with_error, token, Address, Env};
 use stellar_tokens::{fungible::Base, vault::Vault};
 
-use crate::storage::{self, DepositLock};
+use crate::storage::{self, DepositLock, StrategyBook};
 
 #[contracterror]
 #[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
@@ -12,6 +13,24 @@ pub enum StrategyVaultError {
     InvalidAmount = 790,
     SharesLocked = 791,
     UnauthorizedStrategy = 792,
+    RewardNotRegistered = 793,
+    InsufficientOutput = 794,
+    InvalidRewardToken = 795,
+    BelowMinDeposit = 796,
+    NoShares = 797,
+}
+
+/// Emitted once by the constructor with the vault's terms. The vault is its own
+/// share token and backs a single strategy.
+#[contractevent]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct VaultInitialized {
+    #[topic]
+    pub asset: Address,
+    pub strategy: Address,
+    pub lock_time: u64,
+    pub min_deposit: i128,
+    pub withdrawal_fee: i128,
 }
 
 #[contractevent]
@@ -22,6 +41,28 @@ pub struct StrategyWithdraw {
     pub amount: i128,
 }
 
+#[contractevent]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Donate {
+    #[topic]
+    pub from: Address,
+    pub amount: i128,
+}
+
+/// Emitted when an exit pays the withdrawal fee. The fee stays in the vault.
+#[contractevent]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct WithdrawalFee {
+    #[topic]
+    pub owner: Address,
+    pub assets: i128,
+    pub fee: i128,
+}
+
+const SCALAR_7: i128 = 10_000_000;
+/// Highest withdrawal fee rate the vault accepts: 10%.
+pub const MAX_WITHDRAWAL_FEE: i128 = 1_000_000;
+
 pub struct StrategyVault;
 
 impl StrategyVault {
@@ -41,6 +82,26 @@ impl StrategyVault {
         if available > 0 { available } else { 0 }
     }
 
+    /// Returns the user's deposit lock as `(locked_shares, unlock_time, withdrawable_now)`,
+    /// or `None` if the user has never received deposited shares. Locked shares
+    /// are capped at the current balance.
+    pub fn withdrawal_status(e: &Env, user: &Address) -> Option<(i128, u64, bool)> {
+        let lock = storage::get_deposit_lock(e, user)?;
+        let unlock_time = lock.timestamp + storage::get_lock_time(e);
+        let shares = lock.shares.min(Base::balance(e, user));
+        Some((shares, unlock_time, e.ledger().timestamp() >= unlock_time))
+    }
+
+    /// Returns `(assets_out, assets_locked)`: what redeeming every unlocked share
+    /// would pay now after the withdrawal fee, and the value the deposit lock
+    /// still holds back.
+    pub fn preview_exit(e: &Env, owner: &Address) -> (i128, i128) {
+        let available = Self::available_shares(e, owner);
+        let locked = Base::balance(e, owner) - available;
+        let exit = Vault::preview_redeem(e, available);
+        (exit - Self::fee_on_gross(e, exit), Vault::preview_redeem(e, locked))
+    }
+
     /// Panics if `amount` shares exceed the user's available (unlocked) balance.
     pub fn require_available(e: &Env, user: &Address, amount: i128) {
         if amount > Self::available_shares(e, user) {
@@ -70,18 +131,144 @@ impl StrategyVault {
         );
     }
 
-    /// Strategy withdraws tokens from the vault.
-    /// This decreases total_assets and thus the share price.
-    pub fn withdraw(env: &Env, strategy: &Address, amount: i128) {
+    /// Transfer tokens into the vault without minting shares.
+    /// This increases total_assets and thus the share price for all holders.
+    pub fn donate(env: &Env, from: &Address, amount: i128) {
         if amount <= 0 {
             panic_with_error!(env, StrategyVaultError::InvalidAmount);
         }
+
+        let asset = Vault::query_asset(env);
+        let token_client = token::Client::new(env, &asset);
+        token_client.transfer(from, env.current_contract_address(), &amount);
+        Self::record_flow(env, amount);
+
+        Donate {
+            from: from.clone(),
+            amount,
+        }
+        .publish(env);
+    }
+
+    /// Store the withdrawal fee rate (SCALAR_7).
+    ///
+    /// Panics with `InvalidAmount` if the rate is negative or above `MAX_WITHDRAWAL_FEE`.
+    pub fn set_withdrawal_fee(e: &Env, rate: i128) {
+        if !(0..=MAX_WITHDRAWAL_FEE).contains(&rate) {
+            panic_with_error!(e, StrategyVaultError::InvalidAmount);
+        }
+        storage::set_withdrawal_fee(e, &rate);
+    }
+
+    /// Withdrawal fee on an exit worth `gross` assets, rounded up so the vault
+    /// never under-collects.
+    pub fn fee_on_gross(e: &Env, gross: i128) -> i128 {
+        let rate = storage::get_withdrawal_fee(e);
+        gross.fixed_mul_ceil(e, &rate, &SCALAR_7)
+    }
+
+    /// Gross exit value that pays out `net` assets after the withdrawal fee.
+    pub fn gross_for_net(e: &Env, net: i128) -> i128 {
+        let keep = SCALAR_7 - storage::get_withdrawal_fee(e);
+        net.fixed_div_ceil(e, &keep, &SCALAR_7)
+    }
+
+    /// Publish the fee an exit of `assets` paid, if any.
+    pub fn emit_withdrawal_fee(e: &Env, owner: &Address, assets: i128, fee: i128) {
+        if fee > 0 {
+            WithdrawalFee {
+                owner: owner.clone(),
+                assets,
+                fee,
+            }
+            .publish(e);
+        }
+    }
+
+    /// Panics if no shares are outstanding.
+    ///
+    /// Deposit locks count shares that stay in the holder's balance, so a lock
+    /// can never outlive the supply; this guards the exit path regardless.
+    pub fn require_shares(e: &Env) {
+        if Base::total_supply(e) <= 0 {
+            panic_with_error!(e, StrategyVaultError::NoShares);
+        }
+    }
+
+    /// Panics if an exit pays fewer than `min_out` assets.
+    pub fn require_min_out(e: &Env, assets: i128, min_out: i128) {
+        if assets < min_out {
+            panic_with_error!(e, StrategyVaultError::InsufficientOutput);
+        }
+    }
+
+    /// Panics if a deposit of `assets` is below the configured minimum.
+    /// A minimum of 0 disables the check.
+    pub fn require_min_deposit(e: &Env, assets: i128) {
+        if assets < storage::get_min_deposit(e) {
+            panic_with_error!(e, StrategyVaultError::BelowMinDeposit);
+        }
+    }
+
+    /// Track LP asset flows so strategy impact can be separated from deposits.
+    pub fn record_flow(e: &Env, assets: i128) {
+        storage::set_net_deposits(e, &(storage::get_net_deposits(e) + assets));
+    }
+
+    /// Net effect of the strategy on vault assets: everything not explained by
+    /// LP deposits, withdrawals, or donations. Negative when the strategy has
+    /// drawn more than it returned.
+    pub fn impact(e: &Env) -> i128 {
+        Vault::total_assets(e) - storage::get_net_deposits(e)
+    }
+
+    /// The strategy's book with returns since the last settlement applied.
+    ///
+    /// Strategies return assets by plain transfer, so any rise in `impact` since
+    /// the last settlement is a return: it repays outstanding debt first and only
+    /// the excess counts as profit. A fall outside `withdraw` (none today) is
+    /// booked as debt. Donations go through `record_flow` like LP deposits, so
+    /// they never move `impact` and are never booked as a return.
+    pub fn book(e: &Env) -> StrategyBook {
+        let mut book = storage::get_strategy_book(e);
+        let impact = Self::impact(e);
+        let returned = impact - book.impact;
+        if returned > 0 {
+            let repaid = returned.min(book.debt);
+            book.debt -= repaid;
+            book.repaid += repaid;
+            book.profit += returned - repaid;
+        } else {
+            book.debt -= returned;
+        }
+        book.impact = impact;
+        book
+    }
+
+    /// Panics unless `strategy` is the vault's registered strategy.
+    pub fn require_strategy(env: &Env, strategy: &Address) {
         if storage::get_strategy(env) != *strategy {
             panic_with_error!(env, StrategyVaultError::UnauthorizedStrategy);
         }
+    }
+
+    /// Strategy withdraws tokens from the vault.
+    /// This decreases total_assets and thus the share price.
+    pub fn withdraw(env: &Env, strategy: &Address, amount: i128) {
+        if amount <= 0 {
+            panic_with_error!(env, StrategyVaultError::InvalidAmount);
+        }
+        Self::require_strategy(env, strategy);
 
         let asset = Vault::query_asset(env);
         let token_client = token::Client::new(env, &asset);
+        // Settle returns received so far before booking the new draw
+        let mut book = Self::book(env);
+        book.debt += amount;
+        book.borrowed += amount;
+        book.impact -= amount;
+        storage::set_strategy_book(env, &book);
+
         token_client.transfer(&env.current_contract_address(), strategy, &amount);
 
         StrategyWithdraw {
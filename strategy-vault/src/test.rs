@@ -1,10 +1,12 @@
 
 use soroban_sdk::{
-    testutils::{Address as _, Ledger},
+    testutils::{Address as _, Events as _, Ledger},
     token::StellarAssetClient,
-    Address, Env, String,
+    xdr::{ScErrorCode, ScErrorType},
+    Address, Env, Event as _, String,
 };
 
+use crate::strategy::VaultInitialized;
 use crate::{StrategyVaultContract, StrategyVaultContractClient};
 
 const SCALAR_7: i128 = 10_000_000;
@@ -16,6 +18,31 @@ fn setup_test<'a>() -> (
     Address,
     Address,
     Address,
+) {
+    setup_test_with_min_deposit(0)
+}
+
+fn setup_test_with_min_deposit<'a>(
+    min_deposit: i128,
+) -> (
+    Env,
+    StrategyVaultContractClient<'a>,
+    Address,
+    Address,
+    Address,
+) {
+    setup_test_with_params(min_deposit, 0)
+}
+
+fn setup_test_with_params<'a>(
+    min_deposit: i128,
+    withdrawal_fee: i128,
+) -> (
+    Env,
+    StrategyVaultContractClient<'a>,
+    Address,
+    Address,
+    Address,
 ) {
     let env = Env::default();
     env.mock_all_auths();
@@ -38,6 +65,8 @@ fn setup_test<'a>() -> (
             0u32,
             strategy.clone(),
             LOCK_TIME,
+            min_deposit,
+            withdrawal_fee,
         ),
     );
 
@@ -45,6 +74,23 @@ fn setup_test<'a>() -> (
     (env, vault, token.address(), user, strategy)
 }
 
+// ==================== Constructor Tests ====================
+
+#[test]
+fn test_constructor_publishes_vault_terms() {
+    let (env, vault, token, _, strategy) = setup_test_with_params(10 * SCALAR_7, 50_000);
+
+    let expected = VaultInitialized {
+        asset: token,
+        strategy,
+        lock_time: LOCK_TIME,
+        min_deposit: 10 * SCALAR_7,
+        withdrawal_fee: 50_000,
+    };
+    let events = env.events().all().filter_by_contract(&vault.address);
+    assert_eq!(events.events(), &[expected.to_xdr(&env, &vault.address)]);
+}
+
 // ==================== Lock Mechanism Tests ====================
 
 #[test]
@@ -168,6 +214,59 @@ fn test_withdraw_after_unlock_succeeds() {
     assert!(shares > 0);
 }
 
+// ==================== Withdraw-To Tests ====================
+// There is no withdrawal queue: ERC-4626 `withdraw`/`redeem` take the receiver
+// directly, and only the owner (or an operator holding a share allowance) can set it.
+
+#[test]
+fn test_withdraw_to_receiver_after_unlock() {
+    let (env, vault, token, user, _) = setup_test();
+    let receiver = Address::generate(&env);
+    let token_client = soroban_sdk::token::TokenClient::new(&env, &token);
+
+    vault.deposit(&(1000 * SCALAR_7), &user, &user, &user);
+    env.ledger()
+        .set_timestamp(env.ledger().timestamp() + LOCK_TIME + 1);
+
+    let user_before = token_client.balance(&user);
+    vault.withdraw(&(500 * SCALAR_7), &receiver, &user, &user);
+
+    assert_eq!(token_client.balance(&receiver), 500 * SCALAR_7);
+    assert_eq!(token_client.balance(&user), user_before);
+    assert_eq!(vault.balance(&user), 500 * SCALAR_7);
+}
+
+#[test]
+fn test_operator_redeem_to_receiver_with_allowance() {
+    let (env, vault, token, user, _) = setup_test();
+    let operator = Address::generate(&env);
+    let receiver = Address::generate(&env);
+    let token_client = soroban_sdk::token::TokenClient::new(&env, &token);
+
+    vault.deposit(&(1000 * SCALAR_7), &user, &user, &user);
+    env.ledger()
+        .set_timestamp(env.ledger().timestamp() + LOCK_TIME + 1);
+    vault.approve(&user, &operator, &(400 * SCALAR_7), &(env.ledger().sequence() + 100));
+
+    let assets = vault.redeem(&(400 * SCALAR_7), &receiver, &user, &operator);
+
+    assert_eq!(token_client.balance(&receiver), assets);
+    assert_eq!(vault.allowance(&user, &operator), 0);
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #101)")] // InsufficientAllowance
+fn test_third_party_cannot_redirect_withdrawal() {
+    let (env, vault, _, user, _) = setup_test();
+    let thief = Address::generate(&env);
+
+    vault.deposit(&(1000 * SCALAR_7), &user, &user, &user);
+    env.ledger()
+        .set_timestamp(env.ledger().timestamp() + LOCK_TIME + 1);
+
+    vault.withdraw(&(500 * SCALAR_7), &thief, &user, &thief);
+}
+
 // ==================== Share-Aware Lock Tests ====================
 
 #[test]
@@ -371,6 +470,121 @@ fn test_transfer_from_after_unlock_succeeds() {
     assert!(vault.max_redeem(&recipient) > 0);
 }
 
+#[test]
+fn test_lock_cannot_outlive_shares() {
+    let (env, vault, _, user, _) = setup_test();
+
+    let shares = vault.deposit(&(1000 * SCALAR_7), &user, &user, &user);
+    // Locked shares remain in the holder's balance and so in total supply
+    assert_eq!(vault.total_supply(), shares);
+    assert!(vault.try_redeem(&shares, &user, &user, &user).is_err());
+    assert_eq!(vault.total_supply(), shares);
+
+    env.ledger()
+        .set_timestamp(env.ledger().timestamp() + LOCK_TIME + 1);
+
+    vault.redeem(&shares, &user, &user, &user);
+    assert_eq!(vault.total_supply(), 0);
+    assert_eq!(vault.available_shares(&user), 0);
+}
+
+#[test]
+fn test_withdrawal_status_without_deposit_is_none() {
+    let (_env, vault, _, user, _) = setup_test();
+
+    assert_eq!(vault.withdrawal_status(&user), None);
+}
+
+#[test]
+fn test_withdrawal_status_while_locked() {
+    let (env, vault, _, user, _) = setup_test();
+
+    let shares = vault.deposit(&(1000 * SCALAR_7), &user, &user, &user);
+    let unlock_time = env.ledger().timestamp() + LOCK_TIME;
+
+    assert_eq!(vault.withdrawal_status(&user), Some((shares, unlock_time, false)));
+}
+
+#[test]
+fn test_withdrawal_status_after_unlock() {
+    let (env, vault, _, user, _) = setup_test();
+
+    let shares = vault.deposit(&(1000 * SCALAR_7), &user, &user, &user);
+    let unlock_time = env.ledger().timestamp() + LOCK_TIME;
+    env.ledger().set_timestamp(unlock_time);
+
+    assert_eq!(vault.withdrawal_status(&user), Some((shares, unlock_time, true)));
+    assert_eq!(vault.available_shares(&user), shares);
+}
+
+#[test]
+fn test_preview_exit_splits_unlocked_and_locked_assets() {
+    let (env, vault, _, user, _) = setup_test();
+
+    vault.deposit(&(1000 * SCALAR_7), &user, &user, &user);
+    assert_eq!(vault.preview_exit(&user), (0, 1000 * SCALAR_7));
+
+    env.ledger()
+        .set_timestamp(env.ledger().timestamp() + LOCK_TIME + 1);
+    vault.deposit(&(500 * SCALAR_7), &user, &user, &user);
+
+    let (out, locked) = vault.preview_exit(&user);
+    assert_eq!((out, locked), (1000 * SCALAR_7, 500 * SCALAR_7));
+    assert_eq!(vault.redeem(&vault.available_shares(&user), &user, &user, &user), out);
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #797)")] // NoShares
+fn test_withdraw_with_no_shares_fails() {
+    let (_env, vault, _, user, _) = setup_test();
+
+    vault.withdraw(&(100 * SCALAR_7), &user, &user, &user);
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #797)")] // NoShares
+fn test_redeem_with_stale_lock_and_no_supply_fails() {
+    let (env, vault, _, user, _) = setup_test();
+
+    let shares = vault.deposit(&(1000 * SCALAR_7), &user, &user, &user);
+    env.ledger()
+        .set_timestamp(env.ledger().timestamp() + LOCK_TIME + 1);
+    vault.redeem(&shares, &user, &user, &user);
+
+    // The lock entry outlives the supply it was recorded against
+    assert_eq!(vault.total_supply(), 0);
+    assert!(vault.withdrawal_status(&user).is_some());
+    vault.redeem(&1, &user, &user, &user);
+}
+
+#[test]
+fn test_redeem_min_pays_at_or_above_min() {
+    let (env, vault, _, user, _) = setup_test();
+
+    let shares = vault.deposit(&(1000 * SCALAR_7), &user, &user, &user);
+    env.ledger()
+        .set_timestamp(env.ledger().timestamp() + LOCK_TIME + 1);
+
+    let assets = vault.redeem_min(&shares, &(1000 * SCALAR_7 - 1), &user, &user, &user);
+    assert!(assets >= 1000 * SCALAR_7 - 1);
+    assert_eq!(vault.balance(&user), 0);
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #794)")] // InsufficientOutput
+fn test_redeem_min_after_strategy_loss_fails() {
+    let (env, vault, _, user, strategy) = setup_test();
+
+    let shares = vault.deposit(&(1000 * SCALAR_7), &user, &user, &user);
+    env.ledger()
+        .set_timestamp(env.ledger().timestamp() + LOCK_TIME + 1);
+    let quoted = vault.preview_redeem(&shares);
+
+    // Strategy draws 10% between the quote and the exit
+    vault.strategy_withdraw(&strategy, &(100 * SCALAR_7));
+    vault.redeem_min(&shares, &quoted, &user, &user, &user);
+}
+
 // ==================== Strategy Tests ====================
 
 #[test]
@@ -403,3 +617,382 @@ fn test_zero_strategy_withdraw_fails() {
     vault.deposit(&(10_000 * SCALAR_7), &user, &user, &user);
     vault.strategy_withdraw(&strategy, &0);
 }
+
+#[test]
+fn test_strategy_impacts_track_strategy_flows_only() {
+    let (env, vault, token, user, strategy) = setup_test();
+
+    assert_eq!(vault.strategies(), soroban_sdk::vec![&env, strategy.clone()]);
+
+    vault.deposit(&(10_000 * SCALAR_7), &user, &user, &user);
+    vault.strategy_withdraw(&strategy, &(2000 * SCALAR_7));
+    // Strategy returns part of it via a plain transfer
+    soroban_sdk::token::Client::new(&env, &token).transfer(&strategy, &vault.address, &(500 * SCALAR_7));
+    // LP flows don't count towards strategy impact
+    vault.donate(&user, &(100 * SCALAR_7));
+    env.ledger().with_mut(|li| li.timestamp += LOCK_TIME);
+    vault.withdraw(&(1000 * SCALAR_7), &user, &user, &user);
+
+    assert_eq!(
+        vault.strategy_impacts(),
+        soroban_sdk::vec![&env, (strategy, -1500 * SCALAR_7)]
+    );
+}
+
+#[test]
+fn test_strategy_over_repayment_books_excess_as_profit() {
+    let (env, vault, token, user, strategy) = setup_test();
+    let asset = soroban_sdk::token::Client::new(&env, &token);
+    StellarAssetClient::new(&env, &token).mint(&strategy, &(1000 * SCALAR_7));
+
+    vault.deposit(&(10_000 * SCALAR_7), &user, &user, &user);
+    vault.strategy_withdraw(&strategy, &(2000 * SCALAR_7));
+    assert_eq!(vault.strategy_book(), (2000 * SCALAR_7, 0));
+
+    // Partial repayment only reduces the debt
+    asset.transfer(&strategy, &vault.address, &(500 * SCALAR_7));
+    assert_eq!(vault.strategy_book(), (1500 * SCALAR_7, 0));
+
+    // Repaying past the principal books the excess as profit
+    asset.transfer(&strategy, &vault.address, &(2000 * SCALAR_7));
+    assert_eq!(vault.strategy_book(), (0, 500 * SCALAR_7));
+
+    // Profit is kept when the strategy draws again
+    vault.strategy_withdraw(&strategy, &(300 * SCALAR_7));
+    assert_eq!(vault.strategy_book(), (300 * SCALAR_7, 500 * SCALAR_7));
+    assert_eq!(
+        vault.strategy_impacts(),
+        soroban_sdk::vec![&env, (strategy, 200 * SCALAR_7)]
+    );
+}
+
+#[test]
+fn test_donation_not_booked_as_strategy_return() {
+    let (env, vault, _token, user, strategy) = setup_test();
+
+    vault.deposit(&(10_000 * SCALAR_7), &user, &user, &user);
+    vault.strategy_withdraw(&strategy, &(1000 * SCALAR_7));
+
+    // A donation neither repays the strategy's debt nor counts as its profit
+    vault.donate(&user, &(1500 * SCALAR_7));
+    assert_eq!(vault.strategy_book(), (1000 * SCALAR_7, 0));
+    assert_eq!(vault.strategy_principal(), (1000 * SCALAR_7, 0));
+    assert_eq!(
+        vault.strategy_impacts(),
+        soroban_sdk::vec![&env, (strategy, -1000 * SCALAR_7)]
+    );
+}
+
+#[test]
+fn test_strategy_principal_separated_from_profit() {
+    let (env, vault, token, user, strategy) = setup_test();
+    let asset = soroban_sdk::token::Client::new(&env, &token);
+    StellarAssetClient::new(&env, &token).mint(&strategy, &(200 * SCALAR_7));
+
+    vault.deposit(&(10_000 * SCALAR_7), &user, &user, &user);
+    vault.strategy_withdraw(&strategy, &(1000 * SCALAR_7));
+    asset.transfer(&strategy, &vault.address, &(1200 * SCALAR_7));
+
+    assert_eq!(vault.strategy_principal(), (1000 * SCALAR_7, 1000 * SCALAR_7));
+    assert_eq!(vault.strategy_book(), (0, 200 * SCALAR_7));
+}
+
+// ==================== Minimum Deposit Tests ====================
+
+#[test]
+fn test_deposit_at_min_deposit_succeeds() {
+    let (_env, vault, _token, user, _strategy) = setup_test_with_min_deposit(SCALAR_7);
+
+    assert_eq!(vault.min_deposit(), SCALAR_7);
+    vault.deposit(&SCALAR_7, &user, &user, &user);
+    assert_eq!(vault.total_assets(), SCALAR_7);
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #796)")] // BelowMinDeposit
+fn test_deposit_below_min_deposit_fails() {
+    let (_env, vault, _token, user, _strategy) = setup_test_with_min_deposit(SCALAR_7);
+
+    vault.deposit(&(SCALAR_7 - 1), &user, &user, &user);
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #796)")] // BelowMinDeposit
+fn test_mint_below_min_deposit_fails() {
+    let (_env, vault, _token, user, _strategy) = setup_test_with_min_deposit(SCALAR_7);
+
+    vault.mint(&1, &user, &user, &user);
+}
+
+#[test]
+fn test_zero_min_deposit_accepts_dust() {
+    let (_env, vault, _token, user, _strategy) = setup_test();
+
+    vault.deposit(&1, &user, &user, &user);
+    assert_eq!(vault.balance(&user), 1);
+}
+
+// ==================== Withdrawal Fee Tests ====================
+
+#[test]
+fn test_withdrawal_fee_stays_with_remaining_holders() {
+    let (env, vault, token, user, _) = setup_test_with_params(0, 100_000); // 1%
+    let other = Address::generate(&env);
+    StellarAssetClient::new(&env, &token).mint(&other, &(1000 * SCALAR_7));
+
+    let shares = vault.deposit(&(1000 * SCALAR_7), &user, &user, &user);
+    let other_shares = vault.deposit(&(1000 * SCALAR_7), &other, &other, &other);
+    env.ledger()
+        .set_timestamp(env.ledger().timestamp() + LOCK_TIME + 1);
+
+    let quoted = vault.preview_redeem(&shares);
+    let user_before = soroban_sdk::token::TokenClient::new(&env, &token).balance(&user);
+    let assets = vault.redeem(&shares, &user, &user, &user);
+    let received = soroban_sdk::token::TokenClient::new(&env, &token).balance(&user) - user_before;
+
+    // The user receives the value of their shares less 1%, the vault keeps the fee
+    assert_eq!(assets, quoted);
+    assert_eq!(received, assets);
+    assert!((assets - 990 * SCALAR_7).abs() <= 1);
+    assert_eq!(vault.total_assets(), 2000 * SCALAR_7 - assets);
+    assert!(vault.convert_to_assets(&other_shares) > 1000 * SCALAR_7);
+    // The fee is LP-to-LP, not a strategy return
+    assert_eq!(vault.strategy_impacts().get(0).unwrap().1, 0);
+}
+
+#[test]
+fn test_withdraw_with_fee_pays_exact_assets() {
+    let (env, vault, token, user, _) = setup_test_with_params(0, 100_000); // 1%
+
+    let shares = vault.deposit(&(1000 * SCALAR_7), &user, &user, &user);
+    env.ledger()
+        .set_timestamp(env.ledger().timestamp() + LOCK_TIME + 1);
+
+    let before = soroban_sdk::token::TokenClient::new(&env, &token).balance(&user);
+    let burned = vault.withdraw(&(495 * SCALAR_7), &user, &user, &user);
+    let received = soroban_sdk::token::TokenClient::new(&env, &token).balance(&user) - before;
+
+    // 495 out costs the shares of 500 gross
+    assert_eq!(received, 495 * SCALAR_7);
+    assert!((burned - shares / 2).abs() <= 1);
+    assert!((vault.max_withdraw(&user) - vault.preview_redeem(&vault.balance(&user))).abs() <= 1);
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #790)")] // InvalidAmount
+fn test_withdrawal_fee_above_max_fails() {
+    setup_test_with_params(0, 1_000_001);
+}
+
+// ==================== Donation Tests ====================
+
+#[test]
+fn test_donate_increases_assets_without_minting() {
+    let (_env, vault, _token, user, _strategy) = setup_test();
+
+    vault.deposit(&(10_000 * SCALAR_7), &user, &user, &user);
+    let initial_supply = vault.total_supply();
+    let initial_value = vault.convert_to_assets(&vault.balance(&user));
+
+    vault.donate(&user, &(1000 * SCALAR_7));
+
+    assert_eq!(vault.total_assets(), 11_000 * SCALAR_7);
+    assert_eq!(vault.total_supply(), initial_supply);
+    assert!(vault.convert_to_assets(&vault.balance(&user)) > initial_value);
+}
+
+#[test]
+fn test_convert_round_trip_within_one_unit() {
+    let (_env, vault, _token, user, strategy) = setup_test();
+
+    vault.deposit(&(10_000 * SCALAR_7), &user, &user, &user);
+    // Move the rate off 1:1 in both directions
+    vault.donate(&user, &(1_234 * SCALAR_7 + 7));
+    vault.strategy_withdraw(&strategy, &(333 * SCALAR_7 + 1));
+
+    for amount in [1, 999, 12_345 * SCALAR_7 + 3] {
+        let assets = vault.convert_to_assets(&vault.convert_to_shares(&amount));
+        assert!((amount - assets).abs() <= 1 && assets <= amount);
+        let shares = vault.convert_to_shares(&vault.convert_to_assets(&amount));
+        assert!((amount - shares).abs() <= 1 && shares <= amount);
+    }
+}
+
+#[test]
+fn test_convert_ignores_deposit_lock() {
+    let (_env, vault, _token, user, _strategy) = setup_test();
+
+    vault.deposit(&(1_000 * SCALAR_7), &user, &user, &user);
+
+    // The lock holds every share back from an exit, but not from the conversion
+    assert_eq!(vault.preview_exit(&user), (0, 1_000 * SCALAR_7));
+    assert_eq!(vault.convert_to_assets(&vault.balance(&user)), 1_000 * SCALAR_7);
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #790)")] // InvalidAmount
+fn test_zero_donate_fails() {
+    let (_, vault, _, user, _) = setup_test();
+
+    vault.donate(&user, &0);
+}
+
+// ==================== Reward Compounding Tests ====================
+
+/// Mock router: pays out 2 units of `token_out` per unit in, from its own balance.
+#[soroban_sdk::contract]
+struct MockRouter;
+
+#[soroban_sdk::contractimpl]
+impl MockRouter {
+    pub fn swap_exact_in(
+        e: Env,
+        _token_in: Address,
+        token_out: Address,
+        amount_in: i128,
+        _min_out: i128,
+        to: Address,
+    ) -> i128 {
+        let out = amount_in * 2;
+        soroban_sdk::token::Client::new(&e, &token_out).transfer(&e.current_contract_address(), &to, &out);
+        out
+    }
+}
+
+#[test]
+fn test_compound_reward_raises_assets_without_minting() {
+    let (env, vault, token, user, strategy) = setup_test();
+    let reward = env.register_stellar_asset_contract_v2(Address::generate(&env)).address();
+    let router = env.register(MockRouter, ());
+    StellarAssetClient::new(&env, &token).mint(&router, &(10_000 * SCALAR_7));
+
+    vault.deposit(&(10_000 * SCALAR_7), &user, &user, &user);
+    let supply = vault.total_supply();
+    vault.set_reward_router(&strategy, &reward, &router);
+    StellarAssetClient::new(&env, &reward).mint(&vault.address, &(500 * SCALAR_7));
+
+    let out = vault.compound(&strategy, &reward, &(1000 * SCALAR_7));
+
+    assert_eq!(out, 1000 * SCALAR_7);
+    assert_eq!(vault.total_assets(), 11_000 * SCALAR_7);
+    assert_eq!(vault.total_supply(), supply);
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #793)")] // RewardNotRegistered
+fn test_compound_unregistered_reward_fails() {
+    let (env, vault, _, _, strategy) = setup_test();
+    let reward = env.register_stellar_asset_contract_v2(Address::generate(&env)).address();
+
+    vault.compound(&strategy, &reward, &0);
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #794)")] // InsufficientOutput
+fn test_compound_below_min_out_fails() {
+    let (env, vault, token, _, strategy) = setup_test();
+    let reward = env.register_stellar_asset_contract_v2(Address::generate(&env)).address();
+    let router = env.register(MockRouter, ());
+    StellarAssetClient::new(&env, &token).mint(&router, &(10_000 * SCALAR_7));
+
+    vault.set_reward_router(&strategy, &reward, &router);
+    StellarAssetClient::new(&env, &reward).mint(&vault.address, &(500 * SCALAR_7));
+
+    vault.compound(&strategy, &reward, &(1001 * SCALAR_7));
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #795)")] // InvalidRewardToken
+fn test_set_reward_router_for_asset_fails() {
+    let (env, vault, token, _, strategy) = setup_test();
+    let router = env.register(MockRouter, ());
+
+    vault.set_reward_router(&strategy, &token, &router);
+}
+
+// ==================== Reentrancy Tests ====================
+// Soroban rejects contract re-entry at the host level: a call chain can't enter
+// a contract that is already on it. A hostile underlying asset therefore can't
+// call back into the vault from a transfer, so the vault keeps no guard flag of
+// its own. These tests pin that down.
+
+/// Hostile asset: on every transfer out of the vault it tries to re-enter
+/// `withdraw` on the vault for the same owner.
+#[soroban_sdk::contract]
+struct ReentrantToken;
+
+#[soroban_sdk::contractimpl]
+impl ReentrantToken {
+    pub fn decimals(_e: Env) -> u32 {
+        7
+    }
+
+    pub fn balance(e: Env, id: Address) -> i128 {
+        e.storage().instance().get(&id).unwrap_or(0)
+    }
+
+    pub fn mint(e: Env, to: Address, amount: i128) {
+        let balance = Self::balance(e.clone(), to.clone());
+        e.storage().instance().set(&to, &(balance + amount));
+    }
+
+    /// Arm the callback: transfers out of `vault` re-enter its `withdraw` for `owner`.
+    pub fn arm(e: Env, vault: Address, owner: Address) {
+        e.storage().instance().set(&soroban_sdk::symbol_short!("target"), &(vault, owner));
+    }
+
+    pub fn transfer(e: Env, from: Address, to: soroban_sdk::MuxedAddress, amount: i128) {
+        let to = to.address();
+        let from_balance = Self::balance(e.clone(), from.clone());
+        e.storage().instance().set(&from, &(from_balance - amount));
+        Self::mint(e.clone(), to, amount);
+
+        let target: Option<(Address, Address)> = e.storage().instance().get(&soroban_sdk::symbol_short!("target"));
+        if let Some((vault, owner)) = target {
+            if from == vault {
+                StrategyVaultContractClient::new(&e, &vault).withdraw(&amount, &owner, &owner, &owner);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_reentrant_asset_cannot_reenter_withdraw() {
+    let env = Env::default();
+    env.mock_all_auths_allowing_non_root_auth();
+
+    let token = env.register(ReentrantToken, ());
+    let token_client = ReentrantTokenClient::new(&env, &token);
+    let user = Address::generate(&env);
+    token_client.mint(&user, &(10_000 * SCALAR_7));
+
+    let vault_address = env.register(
+        StrategyVaultContract,
+        (
+            String::from_str(&env, "Vault Shares"),
+            String::from_str(&env, "vTKN"),
+            token.clone(),
+            0u32,
+            Address::generate(&env),
+            LOCK_TIME,
+            0i128,
+            0i128,
+        ),
+    );
+    let vault = StrategyVaultContractClient::new(&env, &vault_address);
+    vault.deposit(&(1000 * SCALAR_7), &user, &user, &user);
+    env.ledger().set_timestamp(env.ledger().timestamp() + LOCK_TIME + 1);
+
+    // Unarmed, a withdrawal goes through
+    vault.withdraw(&(100 * SCALAR_7), &user, &user, &user);
+    let shares = vault.balance(&user);
+    let assets = vault.total_assets();
+
+    // Armed, the host refuses the nested withdraw and the whole call reverts
+    token_client.arm(&vault_address, &user);
+    let reentry = soroban_sdk::Error::from_type_and_code(ScErrorType::Context, ScErrorCode::InvalidAction);
+    assert_eq!(vault.try_withdraw(&(100 * SCALAR_7), &user, &user, &user), Err(Ok(reentry)));
+    assert_eq!(vault.balance(&user), shares);
+    assert_eq!(vault.total_assets(), assets);
+    assert_eq!(token_client.balance(&user), 9_100 * SCALAR_7);
+}
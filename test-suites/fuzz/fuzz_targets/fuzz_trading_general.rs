@@ -326,7 +326,7 @@ fuzz_target!(|input: FuzzInput| {
 
                 let result = fixture.trading.try_place_limit(
                     user, &feed, &collateral, &notional, is_long,
-                    &entry_price, &0i128, &0i128,
+                    &entry_price, &0i128, &0i128, &0i128, &false, &0u64,
                 );
                 verify_expected_error(&result, "PlaceLimit", LIMIT_ERRORS);
 
@@ -20,5 +20,13 @@ pub fn to_factory_config(tc: &trading::TradingConfig) -> factory::TradingConfig
         r_funding: tc.r_funding,
         r_base: tc.r_base,
         r_var: tc.r_var,
+        fee_free_wind_down: tc.fee_free_wind_down,
+        max_pending: tc.max_pending,
+        fill_bonus: tc.fill_bonus,
+        liq_caller_cap: tc.liq_caller_cap,
+        referral_share: tc.referral_share,
+        max_total_positions: tc.max_total_positions,
+        fill_bounty: tc.fill_bounty,
+        keeper_min_shares: tc.keeper_min_shares,
     }
 }
@@ -108,6 +108,13 @@ impl TestFixture<'_> {
         self.trading.set_market(&market_id, config);
     }
 
+    /// Apply `f` to a listed market's config through `update_market`.
+    pub fn update_market(&self, market_id: u32, f: impl FnOnce(&mut MarketConfig)) {
+        let mut config = self.trading.get_market_config(&market_id);
+        f(&mut config);
+        self.trading.update_market(&market_id, &config);
+    }
+
     /// Build a signed price update blob for the given feeds at the current ledger timestamp.
     pub fn price_update(&self, feeds: &[pyth_helper::FeedInput]) -> Bytes {
         let ts = self.env.ledger().timestamp();
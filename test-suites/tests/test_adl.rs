@@ -261,11 +261,10 @@ fn test_adl_multi_market_scenario() {
     // floor(1_000_000_000_000 × 943_072_714_977_973_127 / 10^18) = 943_072_714_977
     //
     // PnL at $107k: ($107k - $110k) / $110k ~= -2.73%
-    // ratio = trunc(-$3k × 10^8 / $110k) = -2_727_272
-    // pnl ~= -$2,572.02 = trunc(943_072_714_977 × -2_727_272 / 10^8) = -25_720_158_095
+    // pnl ~= -$2,572.02 = floor(943_072_714_977 × -$3k / $110k) = -25_720_164_954
     //
     // equity = $3k + (-$2,572.02) ~= $427.98
-    // col + pnl = 30_000_000_000 + (-25_720_158_095) = 4_279_841_905
+    // col + pnl = 30_000_000_000 + (-25_720_164_954) = 4_279_835_046
     //
     // liq_threshold = eff_not × 0.5% ~= $471.54
     // floor(943_072_714_977 × 50_000 / 10_000_000) = 4_715_363_574
@@ -287,19 +286,17 @@ fn test_adl_multi_market_scenario() {
     // eff_not = $200k × 0.9431 ~= $188,614.54
     // floor(2_000_000_000_000 × 943_072_714_977_973_127 / 10^18) = 1_886_145_429_955
     // PnL: ($107k - $90k) / $90k ~= +18.89%
-    // ratio = floor($17k × 10^8 / $90k) = 18_888_888
-    // pnl ~= $35,627.19 = floor(1_886_145_429_955 × 18_888_888 / 10^8) = 356_271_897_781
+    // pnl ~= $35,627.19 = floor(1_886_145_429_955 × $17k / $90k) = 356_271_914_547
     // payout = $5k + $35,627.19 ~= $40,627.19
-    // 50_000_000_000 + 356_271_897_781 = 406_271_897_781
+    // 50_000_000_000 + 356_271_914_547 = 406_271_914_547
     let pay_alice_btc = fixture.trading.close_position(&alice, &btc_long_alice, &btc_107k);
-    assert_eq!(pay_alice_btc, 406_271_897_781);
+    assert_eq!(pay_alice_btc, 406_271_914_547);
 
     // Bob BTC long: $150k @$100k, $4k col, compound ADL idx 0.9431.
     // eff_not = $150k × 0.9431 ~= $141,460.91
     // floor(1_500_000_000_000 × 943_072_714_977_973_127 / 10^18) = 1_414_609_072_466
     // PnL: ($107k - $100k) / $100k = +7%
-    // ratio = floor($7k × 10^8 / $100k) = 7_000_000
-    // pnl ~= $9,902.26 = floor(1_414_609_072_466 × 7_000_000 / 10^8) = 99_022_635_072
+    // pnl ~= $9,902.26 = floor(1_414_609_072_466 × $7k / $100k) = 99_022_635_072
     // payout = $4k + $9,902.26 ~= $13,902.26
     // 40_000_000_000 + 99_022_635_072 = 139_022_635_072
     let pay_bob_btc = fixture.trading.close_position(&bob, &btc_long_bob, &btc_107k);
@@ -309,8 +306,7 @@ fn test_adl_multi_market_scenario() {
     // eff_not = $100k × 0.9431 ~= $94,307.27
     // floor(1_000_000_000_000 × 943_072_714_977_973_127 / 10^18) = 943_072_714_977
     // PnL: ($200k - $107k) / $200k = +46.5%
-    // ratio = floor($93k × 10^8 / $200k) = 46_500_000
-    // pnl ~= $43,852.88 = floor(943_072_714_977 × 46_500_000 / 10^8) = 438_528_812_464
+    // pnl ~= $43,852.88 = floor(943_072_714_977 × $93k / $200k) = 438_528_812_464
     // payout = $25k + $43,852.88 ~= $68,852.88
     // 250_000_000_000 + 438_528_812_464 = 688_528_812_464
     let pay_dave_btc = fixture.trading.close_position(&dave, &btc_short_dave, &btc_107k);
@@ -320,8 +316,7 @@ fn test_adl_multi_market_scenario() {
     // eff_not = $200k × 0.9431 ~= $188,614.54
     // floor(2_000_000_000_000 × 943_072_714_977_973_127 / 10^18) = 1_886_145_429_955
     // PnL: ($2.5k - $2k) / $2.5k = +20%
-    // ratio = floor($500 × 10^8 / $2.5k) = 20_000_000
-    // pnl ~= $37,722.91 = floor(1_886_145_429_955 × 20_000_000 / 10^8) = 377_229_085_991
+    // pnl ~= $37,722.91 = floor(1_886_145_429_955 × $500 / $2.5k) = 377_229_085_991
     // payout = $25k + $37,722.91 ~= $62,722.91
     // 250_000_000_000 + 377_229_085_991 = 627_229_085_991
     let pay_alice_eth = fixture.trading.close_position(&alice, &eth_short_alice, &eth_2k);
@@ -331,23 +326,21 @@ fn test_adl_multi_market_scenario() {
     // eff_not = $150k × 0.9431 ~= $141,460.91
     // floor(1_500_000_000_000 × 943_072_714_977_973_127 / 10^18) = 1_414_609_072_466
     // PnL: ($2.2k - $2k) / $2.2k ~= +9.09%
-    // ratio = floor($200 × 10^8 / $2.2k) = 9_090_909
-    // pnl ~= $12,860.08 = floor(1_414_609_072_466 × 9_090_909 / 10^8) = 128_600_823_483
+    // pnl ~= $12,860.08 = floor(1_414_609_072_466 × $200 / $2.2k) = 128_600_824_769
     // payout = $20k + $12,860.08 ~= $32,860.08
-    // 200_000_000_000 + 128_600_823_483 = 328_600_823_483
+    // 200_000_000_000 + 128_600_824_769 = 328_600_824_769
     let pay_bob_eth = fixture.trading.close_position(&bob, &eth_short_bob, &eth_2k);
-    assert_eq!(pay_bob_eth, 328_600_823_483);
+    assert_eq!(pay_bob_eth, 328_600_824_769);
 
     // Carol ETH long: $100k @$1.5k, $15k col, compound ADL idx 0.9431.
     // eff_not = $100k × 0.9431 ~= $94,307.27
     // floor(1_000_000_000_000 × 943_072_714_977_973_127 / 10^18) = 943_072_714_977
     // PnL: ($2k - $1.5k) / $1.5k ~= +33.33%
-    // ratio = floor($500 × 10^8 / $1.5k) = 33_333_333
-    // pnl ~= $31,435.76 = floor(943_072_714_977 × 33_333_333 / 10^8) = 314_357_568_515
+    // pnl ~= $31,435.76 = floor(943_072_714_977 × $500 / $1.5k) = 314_357_571_659
     // payout = $15k + $31,435.76 ~= $46,435.76
-    // 150_000_000_000 + 314_357_568_515 = 464_357_568_515
+    // 150_000_000_000 + 314_357_571_659 = 464_357_571_659
     let pay_carol_eth = fixture.trading.close_position(&carol, &eth_long_carol, &eth_2k);
-    assert_eq!(pay_carol_eth, 464_357_568_515);
+    assert_eq!(pay_carol_eth, 464_357_571_659);
 
     // XLM: no ADL, $0.10 entry = $0.10 close, pnl = $0, no fees.
     // payout = col = $1k = 10_000_000_000
@@ -524,14 +517,13 @@ fn test_entry_weight_aggregate_vs_individual_pnl() {
     let mut indiv_sum: i128 = 0;
     for price_i64 in &entry_prices {
         let entry = *price_i64 as i128;
-        let ratio = (close_price - entry) * PRICE_SCALAR / entry;
-        indiv_sum += notional * ratio / PRICE_SCALAR;
+        indiv_sum += notional * (close_price - entry) / entry;
     }
-    assert_eq!(indiv_sum, 101_137_508_000);
+    assert_eq!(indiv_sum, 101_137_512_353);
 
     // Drift is bounded: < 10 token units for 10 positions
     let diff = agg_pnl - indiv_sum;
-    assert_eq!(diff, -438_000);
+    assert_eq!(diff, -442_353);
     assert!(diff.abs() < 10 * SCALAR_7, "drift {} exceeds bound", diff);
 }
 
@@ -4,6 +4,7 @@ use test_suites::setup::create_fixture_with_data;
 use test_suites::test_fixture::TestFixture;
 use test_suites::constants::{BTC_PRICE_I64, SCALAR_7, SECONDS_PER_WEEK};
 use trading::testutils::{default_market, FEED_BTC, PRICE_SCALAR};
+use trading::PriceData;
 
 // ==========================================
 // Helper Functions
@@ -59,6 +60,9 @@ fn place_limit_long(fixture: &TestFixture, user: &Address, entry_price: i128) ->
         &entry_price,
         &0,
         &0,
+        &0,
+        &false,
+        &0,
     )
 }
 
@@ -138,8 +142,7 @@ fn test_long_profit() {
     let vault_2 = fixture.vault.total_assets();
     let treasury_2 = fixture.token.balance(&fixture.treasury.address);
 
-    // PnL: ratio = floor(($110k - $100k) × 10^8 / $100k) = 10_000_000
-    //      pnl   = floor(100B × 10_000_000 / 10^8) = 10_000_000_000
+    // PnL: pnl = floor(100B × ($110k - $100k) / $100k) = 10_000_000_000
     //
     // equity = col + pnl - close_fees
     //        = 9_949_999_988 + 10_000_000_000 - 50_000_012 = 19_899_999_976
@@ -192,8 +195,7 @@ fn test_long_loss() {
     let vault_2 = fixture.vault.total_assets();
     let treasury_2 = fixture.token.balance(&fixture.treasury.address);
 
-    // PnL: ratio = floor(($95k - $100k) × 10^8 / $100k) = -5_000_000
-    //      pnl   = floor(100B × -5_000_000 / 10^8) = -5_000_000_000
+    // PnL: pnl = floor(100B × ($95k - $100k) / $100k) = -5_000_000_000
     //
     // equity = 9_949_999_988 + (-5_000_000_000) - 50_000_012 = 4_899_999_976
     assert_eq!(payout, 4_899_999_976);
@@ -247,8 +249,7 @@ fn test_short_profit() {
     let vault_2 = fixture.vault.total_assets();
     let treasury_2 = fixture.token.balance(&fixture.treasury.address);
 
-    // PnL (short): ratio = floor(($100k - $90k) × 10^8 / $100k) = 10_000_000
-    //              pnl   = floor(100B × 10_000_000 / 10^8) = +10_000_000_000
+    // PnL (short): pnl = floor(100B × ($100k - $90k) / $100k) = +10_000_000_000
     //
     // equity = 9_949_999_988 + 10_000_000_000 - 50_000_012 = 19_899_999_976
     assert_eq!(payout, 19_899_999_976);
@@ -292,8 +293,7 @@ fn test_short_loss() {
     let vault_2 = fixture.vault.total_assets();
     let treasury_2 = fixture.token.balance(&fixture.treasury.address);
 
-    // PnL (short): ratio = floor(($100k - $105k) × 10^8 / $100k) = -5_000_000
-    //              pnl   = floor(100B × -5_000_000 / 10^8) = -5_000_000_000
+    // PnL (short): pnl = floor(100B × ($100k - $105k) / $100k) = -5_000_000_000
     //
     // equity = 9_949_999_988 + (-5_000_000_000) - 50_000_012 = 4_899_999_976
     assert_eq!(payout, 4_899_999_976);
@@ -312,6 +312,82 @@ fn test_short_loss() {
     assert_eq!(user_delta + vault_delta + treasury_delta, 0);
 }
 
+// ==========================================
+// 1b. Conservation across randomized closes
+//
+// Generalizes the per-test conservation checks above. Default fixture
+// (rates enabled) so funding/borrowing rounding is exercised too.
+// Each close must release exactly the position's collateral, split
+// between user, vault and treasury; after all closes the trading
+// contract holds nothing and no tokens were created or destroyed.
+// ==========================================
+
+#[test]
+fn test_conservation_randomized_closes() {
+    let fixture = setup_fixture();
+    let users: Vec<Address> = (0..4).map(|_| Address::generate(&fixture.env)).collect();
+    for user in &users {
+        fixture.token.mint(user, &(1_000_000 * SCALAR_7));
+    }
+
+    // Deterministic LCG so failures are reproducible
+    let mut seed: u64 = 0x5eed_1616;
+    let mut next = |bound: u64| -> u64 {
+        seed = seed.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1_442_695_040_888_963_407);
+        (seed >> 33) % bound
+    };
+
+    let users_0: Vec<i128> = users.iter().map(|u| fixture.token.balance(u)).collect();
+    let vault_0 = fixture.vault.total_assets();
+    let treasury_0 = fixture.token.balance(&fixture.treasury.address);
+    let trading_0 = fixture.token.balance(&fixture.trading.address);
+
+    let mut positions: Vec<(Address, u32)> = Vec::new();
+    for i in 0..24 {
+        let user = &users[i % users.len()];
+        let collateral = 500 + next(4_500) as i128;
+        let notional = collateral * (2 + next(40) as i128);
+        let id = if next(2) == 0 {
+            fixture.open_long(user, FEED_BTC, collateral, notional, BTC_PRICE_I64)
+        } else {
+            fixture.open_short(user, FEED_BTC, collateral, notional, BTC_PRICE_I64)
+        };
+        positions.push((user.clone(), id));
+    }
+
+    for (user, id) in positions.iter() {
+        fixture.jump(31 + next(6 * 3600));
+        // Close price within ±5% of entry
+        let price = BTC_PRICE_I64 - 5_000 * PRICE_SCALAR as i64 + (next(10_001) as i64) * PRICE_SCALAR as i64;
+        let col = fixture.trading.get_position(user, id).col;
+
+        let user_before = fixture.token.balance(user);
+        let vault_before = fixture.vault.total_assets();
+        let treasury_before = fixture.token.balance(&fixture.treasury.address);
+
+        let payout = fixture.trading.close_position(user, id, &fixture.btc_price(price));
+
+        let user_delta = fixture.token.balance(user) - user_before;
+        let vault_delta = fixture.vault.total_assets() - vault_before;
+        let treasury_delta = fixture.token.balance(&fixture.treasury.address) - treasury_before;
+        assert_eq!(user_delta, payout);
+        assert!(payout >= 0);
+        assert_eq!(user_delta + vault_delta + treasury_delta, col);
+    }
+
+    // Nothing stranded in (or borrowed from) the trading contract
+    assert_eq!(fixture.token.balance(&fixture.trading.address), trading_0);
+
+    let users_delta: i128 = users
+        .iter()
+        .zip(users_0.iter())
+        .map(|(u, b)| fixture.token.balance(u) - b)
+        .sum();
+    let vault_delta = fixture.vault.total_assets() - vault_0;
+    let treasury_delta = fixture.token.balance(&fixture.treasury.address) - treasury_0;
+    assert_eq!(users_delta + vault_delta + treasury_delta, 0);
+}
+
 // ==========================================
 // 2. Keeper Triggers (4 tests)
 //
@@ -444,6 +520,7 @@ fn test_limit_order_place_fill_close() {
     assert!(!pos.filled);
     assert_eq!(pos.col, 1_000 * SCALAR_7);
     assert_eq!(fixture.trading.get_market_data(&FEED_BTC).l_notional, 0);
+    assert_eq!(fixture.trading.get_user_pending(&user, &FEED_BTC), 1);
 
     // Fill at $101k
     let fill_price = fixture.btc_price(101_000 * PRICE_SCALAR as i64);
@@ -455,6 +532,8 @@ fn test_limit_order_place_fill_close() {
     let pos_filled = fixture.trading.get_position(&user, &position_id);
     assert!(pos_filled.filled);
     assert_eq!(pos_filled.entry_price, 101_000 * PRICE_SCALAR);
+    assert_eq!(fixture.trading.get_user_pending(&user, &FEED_BTC), 0);
+    assert_eq!(fixture.trading.get_market_data(&FEED_BTC).l_notional, 10_000 * SCALAR_7);
     // col = 10B - open_fees(50_000_012) = 9_949_999_988
     assert_eq!(pos_filled.col, 9_949_999_988);
     // Keeper fill fee = floor(50_000_012 × 1_000_000 / S7) = 5_000_001
@@ -485,7 +564,7 @@ fn test_limit_order_cancel_refund() {
 }
 
 #[test]
-#[should_panic(expected = "Error(Contract, #731)")]
+#[should_panic(expected = "Error(Contract, #737)")]
 fn test_limit_order_not_fillable_at_price() {
     let fixture = setup_fixture();
     let user = Address::generate(&fixture.env);
@@ -523,6 +602,9 @@ fn test_open_blocked_when_frozen() {
         &(100_000 * PRICE_SCALAR),
         &0,
         &0,
+        &0,
+        &false,
+        &0,
     );
 }
 
@@ -608,7 +690,7 @@ fn test_loss_exceeds_collateral_clamped() {
 }
 
 // ==========================================
-// 6. Multi-User Isolation (1 test)
+// 6. Multi-User Isolation (2 tests)
 // ==========================================
 
 #[test]
@@ -624,6 +706,7 @@ fn test_multi_user_position_isolation() {
 
     assert_eq!(fixture.trading.get_user_counter(&user1), 1);
     assert_eq!(fixture.trading.get_user_counter(&user2), 1);
+    assert_eq!(fixture.trading.get_live_positions(), 2);
     // Positions are keyed by (user, id) — verify they exist and belong to the right market
     assert!(fixture.trading.get_position(&user1, &pos1).filled);
     assert!(fixture.trading.get_position(&user2, &pos2).filled);
@@ -634,4 +717,76 @@ fn test_multi_user_position_isolation() {
 
     assert!(!fixture.position_exists(&user1, pos1));
     assert!(fixture.position_exists(&user2, pos2));
+    assert_eq!(fixture.trading.get_live_positions(), 1);
+}
+
+#[test]
+fn test_next_position_id_matches_opened_id() {
+    let fixture = setup_fixture();
+    let user1 = Address::generate(&fixture.env);
+    let user2 = Address::generate(&fixture.env);
+    fixture.token.mint(&user1, &(100_000 * SCALAR_7));
+    fixture.token.mint(&user2, &(100_000 * SCALAR_7));
+
+    let next = fixture.trading.next_position_id(&user1);
+    assert_eq!(open_long(&fixture, &user1), next);
+    // Another user's position doesn't shift it
+    let next = fixture.trading.next_position_id(&user1);
+    open_short(&fixture, &user2);
+    assert_eq!(open_long(&fixture, &user1), next);
+}
+
+// ==========================================
+// 7. Delisted Market Recovery (2 tests)
+// ==========================================
+// A market whose oracle has died can't price closes or liquidations. Once the
+// owner disables it, positions are wound down either at an owner-set price
+// (force_settle_all) or, after del_market, by refunding collateral as-is.
+
+#[test]
+fn test_delisted_market_settles_at_last_price() {
+    let fixture = setup_zero_rate_fixture();
+    let user = Address::generate(&fixture.env);
+    fixture.token.mint(&user, &(100_000 * SCALAR_7));
+    let id = open_long(&fixture, &user);
+    let col = fixture.trading.get_position(&user, &id).col;
+
+    // The last price seen before the oracle went dark, a week ago
+    let last_seen = fixture.env.ledger().timestamp();
+    fixture.jump(SECONDS_PER_WEEK);
+    fixture.update_market(FEED_BTC, |m| m.enabled = false);
+
+    let before = fixture.token.balance(&user);
+    let last_price = PriceData {
+        feed_id: FEED_BTC,
+        price: 110_000 * PRICE_SCALAR,
+        exponent: -8,
+        publish_time: last_seen,
+    };
+    fixture.trading.force_settle_all(&FEED_BTC, &svec![&fixture.env, user.clone()], &svec![&fixture.env, id], &last_price);
+
+    // +10% on 10x: collateral plus ~1_000 of PnL, less the close fee
+    assert!(!fixture.position_exists(&user, id));
+    let paid = fixture.token.balance(&user) - before;
+    assert!(paid > col + 990 * SCALAR_7 && paid < col + 1_000 * SCALAR_7, "paid {paid}");
+    assert_eq!(fixture.trading.get_market_data(&FEED_BTC).l_notional, 0);
+}
+
+#[test]
+fn test_deleted_market_refunds_collateral() {
+    let fixture = setup_zero_rate_fixture();
+    let user = Address::generate(&fixture.env);
+    fixture.token.mint(&user, &(100_000 * SCALAR_7));
+    let id = open_long(&fixture, &user);
+    let col = fixture.trading.get_position(&user, &id).col;
+
+    fixture.jump(SECONDS_PER_WEEK);
+    fixture.update_market(FEED_BTC, |m| m.enabled = false);
+    fixture.trading.del_market(&FEED_BTC);
+
+    // No price needed: anyone can return the collateral, PnL ignored
+    let before = fixture.token.balance(&user);
+    assert_eq!(fixture.trading.cancel_position(&user, &id), col);
+    assert_eq!(fixture.token.balance(&user) - before, col);
+    assert!(!fixture.position_exists(&user, id));
 }
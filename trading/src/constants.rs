@@ -1,19 +1,34 @@
 pub const SCALAR_7: i128 = 10_000_000; // 7-decimal scalar: fees, ratios, utilization, margins
 pub const SCALAR_18: i128 = 1_000_000_000_000_000_000; // 18-decimal scalar: rates, cumulative indices (funding, borrowing, ADL)
 
+pub const STORAGE_VERSION: u32 = 1; // layout of stored entries, 0 = baseline; bump with a migration step when a released stored type changes
+
 pub const MAX_ENTRIES: u32 = 50; // max markets
+pub const MAX_BASKET: u32 = 10; // max constituents per basket market
+pub const MAX_TIERS: u32 = 10; // max margin or fee tiers per market
+pub const MAX_PAGE: u32 = 50; // max entries returned by a paginated view
 
 pub const UTIL_ONICE: i128 = 9_500_000; // enter OnIce when net PnL >= 95% of vault (SCALAR_7)
 pub const UTIL_ACTIVE: i128 = 9_000_000; // restore Active when net PnL < 90% of vault (SCALAR_7)
 
 pub const ONE_HOUR_SECONDS: u64 = 3600; // seconds per hour, for rate accrual conversion
 pub const MIN_OPEN_TIME: u64 = 30; // min seconds before user-initiated close (prevents same-block arbitrage)
+pub const BUMP_FEE_RATE: i128 = 1_000; // keeper fee for bump_position: 0.01% of collateral (SCALAR_7)
+pub const BUMP_MIN_IDLE: u64 = 90 * 24 * ONE_HOUR_SECONDS; // seconds a filled position must go unstored before bump_position applies (~30 days of TTL left)
 pub const MAX_CALLER_RATE: i128 = 5_000_000; // 50% of trading fees (SCALAR_7)
+pub const MAX_FEE_SHARE: i128 = 5_000_000; // 50% of trading fees to a market's partner (SCALAR_7)
+pub const MAX_REFERRAL_SHARE: i128 = 2_000_000; // 20% of a referred user's trading fees to their referrer (SCALAR_7)
+pub const MAX_TREASURY_RATE: i128 = 5_000_000; // highest rate the treasury accepts, reserved out of every fee split (SCALAR_7)
 pub const MAX_FEE_RATE: i128 = 100_000; // 1% of notional (SCALAR_7)
 pub const MAX_RATE_HOURLY: i128 = 100_000_000_000_000; // 0.01%/hr (~88% APR, SCALAR_18)
 pub const MAX_R_VAR: i128 = 100_000_000_000_000; // max vault/market variable rate: 0.01%/hr (SCALAR_18)
 pub const MAX_UTIL: i128 = 100_000_000; // 1000% global util cap (10 * SCALAR_7)
 pub const MIN_IMPACT: i128 = 100_000_000; // impact divisor floor: caps impact fee at 10% (10 * SCALAR_7)
 pub const MAX_MARGIN: i128 = 5_000_000; // 50% init margin = 2x min leverage (SCALAR_7)
+pub const MAX_LIQ_GRACE: u64 = ONE_HOUR_SECONDS; // max liquidation grace period (seconds)
+pub const MAX_MODIFY_INTERVAL: u64 = ONE_HOUR_SECONDS; // max cooldown between collateral modifications (seconds)
+pub const MAX_DURATION: u64 = 365 * 24 * ONE_HOUR_SECONDS; // longest expiry a dated market may set (seconds)
+pub const MAX_DUST: i128 = SCALAR_7; // most sweep_dust moves per call: 1 token (token_decimals)
+pub const MAX_SETTLE_DEV: i128 = 2_000_000; // force_settle price may sit at most 20% from the market's last stored price (SCALAR_7)
 pub const MAX_LIQ_FEE: i128 = 2_500_000; // 25% max liquidation fee/threshold (SCALAR_7)
 pub const MAX_R_VAR_MARKET: i128 = 100_000_000_000_000; // max per-market variable rate: 0.01%/hr (SCALAR_18)
@@ -1,12 +1,14 @@
 #![allow(clippy::too_many_arguments)]
 
-use crate::dependencies::PriceVerifierClient;
+use crate::constants::STORAGE_VERSION;
+use crate::dependencies::{PriceData, PriceVerifierClient};
 use crate::errors::TradingError;
-use crate::types::{MarketConfig, MarketData, Position, TradingConfig};
+use crate::types::{CloseQuote, MarketConfig, MarketData, Position, TradingConfig};
 use crate::{storage, trading, ContractStatus};
-use crate::validation::require_valid_config;
-use soroban_sdk::{contract, contractclient, contractimpl, panic_with_error, Address, Bytes, Env, Vec};
+use crate::validation::{require_valid_config, require_vault_asset};
+use soroban_sdk::{contract, contractclient, contractimpl, panic_with_error, Address, Bytes, Env, Map, Symbol, Vec};
 use soroban_sdk::unwrap::UnwrapOptimized;
+use soroban_sdk::token::TokenClient;
 use stellar_access::ownable::{self as ownable, Ownable};
 use stellar_contract_utils::upgradeable::{self as upgradeable, Upgradeable};
 use stellar_macros::only_owner;
@@ -22,6 +24,7 @@ pub trait Trading {
     /// - `config` - New [`TradingConfig`]
     ///
     /// # Panics
+    /// - `TradingError::MigrationPending` (743) if `migrate` has not run
     /// - `TradingError::InvalidConfig` (700) if bounds check fails
     /// - `TradingError::NegativeValueNotAllowed` (723) if any rate/fee is negative
     fn set_config(e: Env, config: TradingConfig);
@@ -29,18 +32,38 @@ pub trait Trading {
     /// (Owner only) Register a new market or update an existing market's configuration.
     ///
     /// On first call for a `market_id`, initializes `MarketData` with zero notional and
-    /// ADL indices at `SCALAR_18`. `config.feed_id` is immutable after creation.
+    /// ADL indices at `SCALAR_18`. `config.feed_id` and `config.basket` are immutable
+    /// after creation. A non-empty `basket` prices the market as a weighted index of
+    /// its constituent feeds; price payloads must then carry every constituent.
     ///
     /// # Parameters
     /// - `market_id` - Market identifier (u32)
-    /// - `config` - Per-market parameters (see [`MarketConfig`], includes `feed_id`)
+    /// - `config` - Per-market parameters (see [`MarketConfig`], includes `feed_id` and `basket`)
     ///
     /// # Panics
+    /// - `TradingError::MigrationPending` (743) if `migrate` has not run
     /// - `TradingError::MaxMarketsReached` (703) if `MAX_ENTRIES` markets exist
-    /// - `TradingError::InvalidConfig` (700) if market config bounds fail or feed_id changed
+    /// - `TradingError::InvalidConfig` (700) if market config bounds fail, basket invalid, or feed_id/basket changed
     /// - `TradingError::NegativeValueNotAllowed` (723) if any rate/fee is negative
     fn set_market(e: Env, market_id: u32, config: MarketConfig);
 
+    /// (Owner only) Update the config of an existing market. Open interest survives
+    /// the change and indices accrue under the old config first, so new rates only
+    /// price time after the update. Unlike `set_market`, never registers a market.
+    /// When the owner is the governance timelock, updates are queued and
+    /// cancellable there.
+    ///
+    /// # Parameters
+    /// - `market_id` - Registered market identifier
+    /// - `config` - New per-market parameters (`feed_id` and `basket` must be unchanged)
+    ///
+    /// # Panics
+    /// - `TradingError::MigrationPending` (743) if `migrate` has not run
+    /// - `TradingError::MarketNotFound` (701) if market_id not registered
+    /// - `TradingError::InvalidConfig` (700) if market config bounds fail or feed_id/basket changed
+    /// - `TradingError::NegativeValueNotAllowed` (723) if any rate/fee is negative
+    fn update_market(e: Env, market_id: u32, config: MarketConfig);
+
     /// (Owner only) Remove a market. Subtracts remaining OI from total_notional
     /// and cleans up market config and data storage.
     ///
@@ -48,6 +71,7 @@ pub trait Trading {
     /// - `market_id` - Market to remove
     ///
     /// # Panics
+    /// - `TradingError::MigrationPending` (743) if `migrate` has not run
     /// - `TradingError::MarketNotFound` (701) if market_id not registered
     fn del_market(e: Env, market_id: u32);
 
@@ -59,6 +83,107 @@ pub trait Trading {
     /// - `TradingError::InvalidStatus` (740) if status is `OnIce`
     fn set_status(e: Env, status: u32);
 
+    /// (Owner only) Force-settle positions on a disabled (delisted) market at an owner-set price.
+    ///
+    /// Filled positions are settled with full PnL and accrued fees; pending orders are
+    /// refunded. Pass positions in pages across multiple calls to respect the budget;
+    /// entries already settled or in another market are skipped. The price must lie
+    /// within 20% of the market's last stored (oracle-verified) price, which
+    /// force-settling never updates. When the last position is settled the
+    /// market's `MarketData` is reset.
+    ///
+    /// # Parameters
+    /// - `market_id` - Market to wind down (must be disabled)
+    /// - `users` - Position owner addresses (parallel with `ids`)
+    /// - `ids` - Position IDs, per-user sequence numbers (parallel with `users`)
+    /// - `price` - Settlement price set by the owner (`feed_id` must match the market)
+    ///
+    /// # Panics
+    /// - `TradingError::MigrationPending` (743) if `migrate` has not run
+    /// - `TradingError::MarketEnabled` (704) if the market is still enabled
+    /// - `TradingError::InvalidInput` (734) if `users` and `ids` lengths differ
+    /// - `TradingError::InvalidPrice` (710) on feed mismatch, if the market has no stored
+    ///   price, or a price outside the deviation bound
+    fn force_settle_all(e: Env, market_id: u32, users: Vec<Address>, ids: Vec<u32>, price: PriceData);
+
+    /// (Owner only) Freeze a single position, e.g. under a legal hold or while a bug
+    /// is investigated. Narrower than pausing a market: every user, keeper and owner
+    /// action on this position reverts with `PositionFrozen` (722) until
+    /// `unfreeze_position`. Funding and borrowing keep accruing while frozen.
+    ///
+    /// # Parameters
+    /// - `user` - Position owner
+    /// - `id` - Position ID
+    ///
+    /// # Panics
+    /// - `TradingError::PositionNotFound` (720) if the position doesn't exist
+    fn freeze_position(e: Env, user: Address, id: u32);
+
+    /// (Owner only) Lift a freeze set by `freeze_position`. A no-op if the position
+    /// isn't frozen.
+    ///
+    /// # Panics
+    /// - `TradingError::PositionNotFound` (720) if the position doesn't exist
+    fn unfreeze_position(e: Env, user: Address, id: u32);
+
+    /// (Owner only) Recover the contract's full balance of a token sent by mistake.
+    ///
+    /// # Parameters
+    /// - `token` - Token to sweep (must not be the collateral token)
+    /// - `to` - Recipient of the swept balance
+    ///
+    /// # Returns
+    /// Amount transferred (token's own decimals).
+    ///
+    /// # Panics
+    /// - `TradingError::TokenNotSweepable` (760) if `token` is the collateral token
+    fn sweep(e: Env, token: Address, to: Address) -> i128;
+
+    /// (Owner only) Move the contract's residual collateral-token balance (rounding
+    /// dust, stray transfers) to the vault once no positions are live, at most
+    /// 1 token per call.
+    ///
+    /// # Returns
+    /// Amount transferred (token_decimals).
+    ///
+    /// # Panics
+    /// - `TradingError::TokenNotSweepable` (760) if any pending or filled position
+    ///   exists or open interest is recorded
+    fn sweep_dust(e: Env) -> i128;
+
+    /// (Owner only) Register the vault's swap router for a secondary reward token.
+    /// The vault takes reward routing only from its strategy (this contract).
+    ///
+    /// # Parameters
+    /// - `reward` - Reward token held by the vault (must not be the vault asset)
+    /// - `router` - Swap router that sells `reward` for the vault asset
+    fn set_reward_router(e: Env, reward: Address, router: Address);
+
+    /// (Owner only) Swap the vault's full `reward` balance into the vault asset
+    /// through its registered router. No shares are minted, so the proceeds
+    /// raise the share price for all LPs.
+    ///
+    /// # Parameters
+    /// - `reward` - Reward token to sell
+    /// - `min_out` - Minimum vault asset to receive (token_decimals)
+    ///
+    /// # Returns
+    /// Asset amount received (token_decimals), 0 if the vault held no `reward`.
+    fn compound_reward(e: Env, reward: Address, min_out: i128) -> i128;
+
+    /// (Owner only) Migrate stored entries to the running code's layout after an
+    /// `upgrade` that bumps the storage version. Position and funding operations
+    /// are refused until this runs.
+    ///
+    /// # Parameters
+    /// - `live_positions` - Pending and filled positions open at the upgrade,
+    ///   counted off-chain; seeds the live-position count when migrating from the
+    ///   baseline layout, ignored otherwise
+    ///
+    /// # Panics
+    /// - `TradingError::NothingToMigrate` (761) if storage is already current
+    fn migrate(e: Env, live_positions: u32);
+
     /// Permissionless circuit breaker and ADL trigger.
     ///
     /// Anyone can call with current price data for all markets.
@@ -76,9 +201,30 @@ pub trait Trading {
     /// - `TradingError::InvalidPrice` (710) if feeds don't match registered markets
     fn update_status(e: Env, price: Bytes);
 
-    /// Place a pending limit order. Collateral is transferred to the contract immediately.
+    /// Protocol-wide solvency snapshot at the given prices. Read-only.
+    ///
+    /// # Parameters
+    /// - `price` - Binary-encoded price payload covering all registered markets
+    ///
+    /// # Returns
+    /// `(collateral, net_pnl, vault_balance, surplus)`: tokens held for traders
+    /// (the balance less escrowed fill bounties and unclaimed referral rewards),
+    /// net open price PnL owed to traders across all markets (the figure
+    /// `update_status` checks), the vault's total assets, and
+    /// `vault_balance - net_pnl`. A negative surplus means the vault can't cover
+    /// the net open profit.
+    ///
+    /// # Panics
+    /// - `TradingError::InvalidPrice` (710) if a market's feed is missing
+    fn solvency(e: Env, price: Bytes) -> (i128, i128, i128, i128);
+
+    /// Place a pending limit order. Collateral is transferred to the contract immediately,
+    /// along with the configured `fill_bounty`, which is paid to the keeper that fills
+    /// the order and refunded if it is cancelled.
     /// The order is filled later by a keeper via `execute` when the market price
-    /// reaches the specified `entry_price`.
+    /// reaches the specified `entry_price`. It opens at the market price at fill
+    /// time, which is at or better than `entry_price`. The exception is a gap past
+    /// `max_dev`, covered by `at_trigger` below.
     ///
     /// # Parameters
     /// - `user` - Position owner (must `require_auth`)
@@ -89,16 +235,27 @@ pub trait Trading {
     /// - `entry_price` - Desired fill price (price_scalar units)
     /// - `take_profit` - TP trigger price, 0 = not set (price_scalar units)
     /// - `stop_loss` - SL trigger price, 0 = not set (price_scalar units)
+    /// - `max_dev` - Max fill distance past `entry_price` on a price gap, 0 = unbounded (SCALAR_7)
+    /// - `at_trigger` - Past `max_dev`: `true` fills at `entry_price`, `false` reverts the fill
+    /// - `expiry` - Time from which the order no longer fills and anyone may cancel it
+    ///   via `cancel_expired`, 0 = never (seconds)
     ///
     /// # Returns
     /// Position ID.
     ///
     /// # Panics
     /// - `TradingError::ContractOnIce` (741) if contract is not Active
-    /// - `TradingError::NegativeValueNotAllowed` (723) if any value <= 0
+    /// - `TradingError::NegativeValueNotAllowed` (723) if any value <= 0 or `max_dev` < 0
+    /// - `TradingError::InvalidInput` (734) if `max_dev` > 100% or `expiry` is already past
     /// - `TradingError::NotionalBelowMinimum` (724) / `NotionalAboveMaximum` (725)
     /// - `TradingError::LeverageAboveMaximum` (726) if notional * margin > collateral
     /// - `TradingError::MarketDisabled` (702) if market is not enabled
+    /// - `TradingError::DirectionDisabled` (706) if the market takes no new positions on that side
+    /// - `TradingError::OpensPaused` (707) if the market has new opens paused
+    /// - `TradingError::TooManyPendingOrders` (738) if the user already holds
+    ///   `max_pending` orders on the market
+    /// - `TradingError::ProtocolPositionCapReached` (730) if `max_total_positions`
+    ///   positions are already live
     fn place_limit(
         e: Env,
         user: Address,
@@ -109,6 +266,9 @@ pub trait Trading {
         entry_price: i128,
         take_profit: i128,
         stop_loss: i128,
+        max_dev: i128,
+        at_trigger: bool,
+        expiry: u64,
     ) -> u32;
 
     /// Open a market order, filled immediately at the current oracle price.
@@ -136,6 +296,10 @@ pub trait Trading {
     /// - `TradingError::NotionalBelowMinimum` (724) / `NotionalAboveMaximum` (725)
     /// - `TradingError::LeverageAboveMaximum` (726) if notional * margin > collateral
     /// - `TradingError::MarketDisabled` (702) if market is not enabled
+    /// - `TradingError::DirectionDisabled` (706) if the market takes no new positions on that side
+    /// - `TradingError::OpensPaused` (707) if the market has new opens paused
+    /// - `TradingError::ProtocolPositionCapReached` (730) if `max_total_positions`
+    ///   positions are already live
     /// - `TradingError::InvalidPrice` (710) if feed_id mismatch
     /// - `TradingError::UtilizationExceeded` (751) if per-market or global cap exceeded
     fn open_market(
@@ -150,6 +314,28 @@ pub trait Trading {
         price: Bytes,
     ) -> u32;
 
+    /// Open a market order like `open_market`, returning the filled position.
+    ///
+    /// Saves a follow-up `get_position` read to learn the fill price, and that
+    /// read's exposure to state changes in between.
+    ///
+    /// # Returns
+    /// `(position_id, position)` as stored after the fill.
+    ///
+    /// # Panics
+    /// Same as `open_market`.
+    fn open_market_position(
+        e: Env,
+        user: Address,
+        market_id: u32,
+        collateral: i128,
+        notional_size: i128,
+        is_long: bool,
+        take_profit: i128,
+        stop_loss: i128,
+        price: Bytes,
+    ) -> (u32, Position);
+
     /// Cancel a position and refund collateral. No settlement or fees applied.
     ///
     /// - **Pending** (unfilled): requires user auth, cancels the limit order.
@@ -168,6 +354,50 @@ pub trait Trading {
     /// - `TradingError::PositionNotFound` (720) if position_id is invalid
     fn cancel_position(e: Env, user: Address, id: u32) -> i128;
 
+    /// Permissionless cleanup of another user's pending order once its `expiry`
+    /// is reached. Orders placed without an expiry never qualify. No owner auth required.
+    ///
+    /// The caller earns the keeper share (`caller_rate`) of the base fee the order
+    /// would have paid on fill; the rest of the collateral is refunded to `user`.
+    ///
+    /// # Parameters
+    /// - `caller` - Address receiving the cleanup reward
+    /// - `user` - Order owner address
+    /// - `id` - Position ID (per-user sequence number)
+    ///
+    /// # Returns
+    /// Collateral amount returned to the user (token_decimals).
+    ///
+    /// # Panics
+    /// - `TradingError::ActionNotAllowedForStatus` (733) if the position is filled
+    /// - `TradingError::OrderNotExpired` (735) if the order has no expiry or hasn't reached it
+    /// - `TradingError::ContractFrozen` (742) if contract is Frozen
+    /// - `TradingError::PositionNotFound` (720) if position_id is invalid
+    fn cancel_expired(e: Env, caller: Address, user: Address, id: u32) -> i128;
+
+    /// Permissionless TTL extension for an idle filled position, so its storage
+    /// entry (and the user's collateral record) isn't archived. Only applies once
+    /// the position has gone unstored for `BUMP_MIN_IDLE` (90 days).
+    ///
+    /// The caller earns `BUMP_FEE_RATE` (0.01%) of the position's collateral,
+    /// unless paying it would leave the position below initial margin.
+    ///
+    /// # Parameters
+    /// - `caller` - Address receiving the keeper fee
+    /// - `user` - Position owner address
+    /// - `id` - Position ID (per-user sequence number)
+    /// - `price` - Price update bytes for the position's market, for the margin check
+    ///
+    /// # Returns
+    /// Fee paid to the caller (token_decimals).
+    ///
+    /// # Panics
+    /// - `TradingError::ActionNotAllowedForStatus` (733) if the position is pending
+    /// - `TradingError::NotActionable` (731) if the position was stored within `BUMP_MIN_IDLE`
+    /// - `TradingError::ContractFrozen` (742) if contract is Frozen
+    /// - `TradingError::PositionNotFound` (720) if position_id is invalid
+    fn bump_position(e: Env, caller: Address, user: Address, id: u32, price: Bytes) -> i128;
+
     /// Close a filled position at the current oracle price with full settlement.
     ///
     /// # Parameters
@@ -180,10 +410,27 @@ pub trait Trading {
     ///
     /// # Panics
     /// - `TradingError::ContractFrozen` (742) if contract is Frozen
+    /// - `TradingError::PositionFrozen` (722) if the position is frozen
     /// - `TradingError::PositionTooNew` (732) if MIN_OPEN_TIME not elapsed (normal path only)
     /// - `TradingError::InvalidPrice` (710) if feed_id mismatch (normal path only)
     fn close_position(e: Env, user: Address, id: u32, price: Bytes) -> i128;
 
+    /// Close a filled position and pay out to `receiver` instead of the owner.
+    /// Requires the owner's auth; keepers cannot redirect payouts.
+    ///
+    /// # Parameters
+    /// - `user` - Position owner address
+    /// - `id` - Position ID (per-user sequence number)
+    /// - `receiver` - Address receiving the payout
+    /// - `price` - Binary-encoded price payload
+    ///
+    /// # Returns
+    /// Payout sent to `receiver` (token_decimals).
+    ///
+    /// # Panics
+    /// Same as `close_position`.
+    fn close_position_to(e: Env, user: Address, id: u32, receiver: Address, price: Bytes) -> i128;
+
     /// Add or withdraw collateral on an open (filled) position.
     ///
     /// Adding: transfers additional collateral from user to contract.
@@ -198,15 +445,20 @@ pub trait Trading {
     ///
     /// # Panics
     /// - `TradingError::ContractFrozen` (742) if contract is Frozen
+    /// - `TradingError::PositionFrozen` (722) if the position is frozen
     /// - `TradingError::ActionNotAllowedForStatus` (733) if position is not filled
     /// - `TradingError::CollateralUnchanged` (727) if new_collateral == current
     /// - `TradingError::WithdrawalBreaksMargin` (728) if withdrawal leaves insufficient margin
+    /// - `TradingError::ModifyTooSoon` (736) if within the market's `min_modify_interval` of the last modification
+    /// - `TradingError::BelowMinCollateral` (729) if withdrawal leaves collateral below `notional * margin`
     fn modify_collateral(e: Env, user: Address, id: u32, new_collateral: i128, price: Bytes);
 
     /// Update take-profit and stop-loss trigger prices on an existing position.
     ///
-    /// Set a trigger to 0 to clear it. TP/SL are pure price triggers — no
-    /// entry-price validation. Invalid values simply never fire.
+    /// Set a trigger to 0 to clear it. On a filled position TP/SL are pure price
+    /// triggers — no entry-price validation. Invalid values simply never fire.
+    /// On a pending order they are brackets armed at fill and must sit on the
+    /// profit and loss side of its `entry_price`.
     ///
     /// # Parameters
     /// - `user` - Position owner address
@@ -216,28 +468,159 @@ pub trait Trading {
     ///
     /// # Panics
     /// - `TradingError::ContractFrozen` (742) if contract is Frozen
+    /// - `TradingError::InvalidInput` (734) if a pending order's TP/SL would fire at its entry
     fn set_triggers(e: Env, user: Address, id: u32, take_profit: i128, stop_loss: i128);
 
+    /// Best-effort `set_triggers`: each trigger applies on its own, and one a
+    /// pending order's brackets reject keeps its previous value.
+    ///
+    /// # Returns
+    /// `(take_profit, stop_loss)` result codes: `0` if applied, otherwise the
+    /// error code `set_triggers` would have reverted with.
+    ///
+    /// # Panics
+    /// - `TradingError::ContractFrozen` (742) if contract is Frozen
+    fn set_triggers_each(e: Env, user: Address, id: u32, take_profit: i128, stop_loss: i128) -> (u32, u32);
+
+    /// Choose whether keepers may execute the position's TP/SL (default `true`).
+    /// When disabled, triggers fire only from an `execute` batch the user submits
+    /// as `caller`, e.g. to treat them as alerts and exit by hand. Liquidation and
+    /// expiry are unaffected.
+    ///
+    /// # Panics
+    /// - `TradingError::ContractFrozen` (742) if contract is Frozen
+    /// - `TradingError::PositionFrozen` (722) if the position is frozen
+    fn set_auto_trigger(e: Env, user: Address, id: u32, enabled: bool);
+
+    /// Bind a referrer to the caller. From then on the referrer accrues
+    /// `referral_share` of the user's trading fees, out of the vault's side.
+    ///
+    /// Write-once, and only before the user's first position.
+    ///
+    /// # Panics
+    /// - `TradingError::ContractFrozen` (742) if contract is Frozen
+    /// - `TradingError::ReferrerLocked` (762) if a referrer is already set, the user
+    ///   has opened or placed a position, or `referrer` is the user
+    fn set_referrer(e: Env, user: Address, referrer: Address);
+
+    /// Pay out the referrer's accrued rewards. Returns the amount paid.
+    ///
+    /// # Panics
+    /// - `TradingError::ContractFrozen` (742) if contract is Frozen
+    fn claim_referral(e: Env, referrer: Address) -> i128;
+
     /// Execute a batch of keeper actions for positions in a single market.
     ///
     /// The contract auto-detects the action for each position:
     /// - **Not filled** → fill limit order (if price crossed entry)
-    /// - **Filled** → priority: liquidate > stop-loss > take-profit
+    /// - **Filled** → priority: liquidate > stop-loss > take-profit > expiry (markets with `max_duration`)
     ///
-    /// All positions must be in the same market as the provided price.
+    /// All positions must be in the same market as the provided price. A caller
+    /// closing their own position earns no caller fee; that cut stays with the vault.
     ///
     /// # Parameters
-    /// - `caller` - Keeper address (receives `caller_rate` share of trading fees)
+    /// - `caller` - Keeper address (receives `caller_rate` share of trading fees); must
+    ///   sign when `keeper_min_shares` is set, as the stake is checked against it
     /// - `users` - Position owner addresses (parallel with `ids`)
     /// - `ids` - Position IDs, per-user sequence numbers (parallel with `users`)
     /// - `price` - Binary-encoded price payload (single feed)
     ///
     /// # Panics
     /// - `TradingError::ContractFrozen` (742) if contract is Frozen
+    /// - `TradingError::PositionFrozen` (722) if a position is frozen
     /// - `TradingError::InvalidPrice` (710) if position feed doesn't match price feed
     /// - `TradingError::NotActionable` (731) if no valid action for the position
+    /// - `TradingError::StalePrice` (711) if a liquidation's price is older than the
+    ///   market's `liq_price_age`
+    /// - `TradingError::KeeperStakeTooLow` (763) on a liquidation by a caller holding
+    ///   fewer vault shares than `keeper_min_shares`
+    /// - `TradingError::Unauthorized` (1) on a TP/SL of a position with `auto_trigger`
+    ///   off, unless `caller` is its user
+    /// - `TradingError::LimitOrderNotFillable` (737) if a pending order's limit price
+    ///   isn't reached yet, or the price gapped past its `max_dev`
+    /// - `TradingError::ContractOnIce` (741) on a fill during a fee-free wind-down
+    /// - `TradingError::DirectionDisabled` (706) on a fill whose side the market no
+    ///   longer accepts
     fn execute(e: Env, caller: Address, market_id: u32, users: Vec<Address>, ids: Vec<u32>, price: Bytes);
 
+    /// Best-effort `execute`: entries that can't act now are skipped instead of
+    /// reverting the batch, and the executed ones settle with netted transfers.
+    ///
+    /// # Returns
+    /// A result code per entry: `0` if executed, otherwise the `TradingError` code
+    /// `execute` would have reverted with for it (e.g. 737 for an unfillable order).
+    ///
+    /// # Panics
+    /// - `TradingError::ContractFrozen` (742) if contract is Frozen
+    /// - `TradingError::InvalidInput` (734) if `users` and `ids` lengths differ
+    fn execute_each(
+        e: Env,
+        caller: Address,
+        market_id: u32,
+        users: Vec<Address>,
+        ids: Vec<u32>,
+        price: Bytes,
+    ) -> Vec<u32>;
+
+    /// Mark a pending order as fillable at the given price, starting the clock on
+    /// the keeper fill bonus (`TradingConfig.fill_bonus`). Permissionless; only
+    /// the first mark counts until a later price shows the order out of range,
+    /// which clears the mark.
+    ///
+    /// # Returns
+    /// The order's `fillable_since` timestamp, or 0 if it can't fill at the price.
+    ///
+    /// # Panics
+    /// - `TradingError::PositionNotPending` (721) if the position is filled
+    /// - `TradingError::StalePrice` (711) if the price predates the order
+    fn mark_fillable(e: Env, user: Address, id: u32, price: Bytes) -> u64;
+
+    /// Dry run of `execute`: returns one reason tag per position describing what
+    /// the batch would do (`fill`, `liquidate`, `stop_loss`, ...) or why it would
+    /// be rejected (`not_fillable`, `too_new`, `not_actionable`, ...). Read-only.
+    ///
+    /// # Panics
+    /// - `TradingError::InvalidInput` (734) if `users` and `ids` lengths differ
+    /// - `TradingError::InvalidPrice` (710) if price verification fails
+    fn simulate_execute(e: Env, market_id: u32, users: Vec<Address>, ids: Vec<u32>, price: Bytes) -> Vec<Symbol>;
+
+    /// Dry run of `execute`'s token flows for `caller`: the net amount each address
+    /// would receive, with the vault negative when it funds payouts. Read-only, so
+    /// safe to call from other contracts.
+    ///
+    /// The batch is replayed in memory as `execute` runs it, closes before fills,
+    /// so fills that only fit once earlier closes free the caps are counted.
+    /// Entries the batch would reject add nothing; pair with `simulate_execute` to
+    /// see them.
+    ///
+    /// # Panics
+    /// - `TradingError::InvalidInput` (734) if `users` and `ids` lengths differ
+    /// - `TradingError::InvalidPrice` (710) if price verification fails
+    fn simulate_transfers(
+        e: Env,
+        caller: Address,
+        market_id: u32,
+        users: Vec<Address>,
+        ids: Vec<u32>,
+        price: Bytes,
+    ) -> Map<Address, i128>;
+
+    /// Returns the candidates a keeper batch would liquidate now, most underwater
+    /// (lowest health factor) first, at most `limit` (capped at 50). Positions are
+    /// not indexed per market, so the caller supplies `users` / `ids`. Read-only.
+    ///
+    /// # Panics
+    /// - `TradingError::InvalidInput` (734) if `users` and `ids` lengths differ
+    /// - `TradingError::InvalidPrice` (710) if price verification fails
+    fn get_liquidatable_sorted(
+        e: Env,
+        market_id: u32,
+        users: Vec<Address>,
+        ids: Vec<u32>,
+        price: Bytes,
+        limit: u32,
+    ) -> Vec<(Address, u32)>;
+
     /// Recalculate and store funding rates for all markets. Permissionless, callable
     /// once per hour.
     ///
@@ -251,18 +634,127 @@ pub trait Trading {
     /// Returns the position for the given user and position ID.
     fn get_position(e: Env, user: Address, id: u32) -> Position;
 
+    /// Returns the positions for parallel `users` / `ids` (at most 50) in one call,
+    /// `None` where a position no longer exists.
+    ///
+    /// # Panics
+    /// - `TradingError::InvalidInput` (734) if lengths differ or exceed 50
+    fn get_positions(e: Env, users: Vec<Address>, ids: Vec<u32>) -> Vec<Option<Position>>;
+
+    /// Returns the user's cumulative notional opened and closed, which selects
+    /// their fee tier on markets with `fee_tiers`. Closes count only when the
+    /// user submits them, not liquidations, keeper triggers or expiry.
+    fn get_user_volume(e: Env, user: Address) -> i128;
+
+    /// Returns the user's referrer, if one was set.
+    fn get_referrer(e: Env, user: Address) -> Option<Address>;
+
+    /// Returns the referrer's accrued, unclaimed rewards (token_decimals).
+    fn get_referral_rewards(e: Env, referrer: Address) -> i128;
+
+    /// Returns the position's health factor: equity / liquidation threshold (SCALAR_7).
+    /// Below `SCALAR_7` (1.0) the position is liquidatable; `i128::MAX` if the
+    /// threshold is zero.
+    ///
+    /// # Panics
+    /// - `TradingError::ActionNotAllowedForStatus` (733) if the position is pending
+    /// - `TradingError::InvalidPrice` (710) if price verification fails
+    fn health_factor(e: Env, user: Address, id: u32, price: Bytes) -> i128;
+
+    /// Returns what `close_position` would settle and transfer at the given price:
+    /// net PnL, fees, funding, borrowing interest and each party's share. Ignores
+    /// the minimum open time. Read-only.
+    ///
+    /// # Panics
+    /// - `TradingError::ActionNotAllowedForStatus` (733) if the position is pending
+    /// - `TradingError::InvalidPrice` (710) if price verification fails
+    fn quote_close(e: Env, user: Address, id: u32, price: Bytes) -> CloseQuote;
+
+    /// Returns true if the position is a pending order that has reached its
+    /// `expiry`, i.e. can no longer fill and is eligible for `cancel_expired`.
+    fn is_expired(e: Env, user: Address, id: u32) -> bool;
+
     /// Returns the next sequence number for the given user (number of positions created).
     fn get_user_counter(e: Env, user: Address) -> u32;
 
+    /// Returns the id the user's next `open_market`, `open_market_position` or
+    /// `place_limit` will be assigned. Ids are per-user sequence numbers, so the
+    /// value is stable until that user creates a position, and a client can
+    /// reference the position in follow-up calls in the same transaction.
+    ///
+    /// A keeper partial fill of one of the user's limit orders also takes an id
+    /// from the same sequence for the unfilled remainder, so the value can move
+    /// between transactions without the user acting.
+    fn next_position_id(e: Env, user: Address) -> u32;
+
+    /// Returns up to `limit` (capped at 50) of the user's position ids starting at
+    /// `start`, each paired with whether the position is still open or pending.
+    /// Ids are never reused, so paging `0..get_user_counter` yields the user's
+    /// full history including closed positions.
+    fn get_user_positions(e: Env, user: Address, start: u32, limit: u32) -> Vec<(u32, bool)>;
+
+    /// Returns the number of pending limit orders the user holds on the market,
+    /// counted against `max_pending`.
+    fn get_user_pending(e: Env, user: Address, market_id: u32) -> u32;
+
+    /// Returns the user's open notional on the market, counted against the
+    /// market's `max_user_notional`.
+    fn get_user_notional(e: Env, user: Address, market_id: u32) -> i128;
+
     /// Returns the market configuration for the given market.
     fn get_market_config(e: Env, market_id: u32) -> MarketConfig;
 
-    /// Returns the mutable market data (notionals, indices) for the given market.
+    /// Returns the mutable market data for the given market: side notionals and
+    /// entry weights, funding/borrowing/ADL indices, the current funding rate and
+    /// `last_update`. Indices are as of `last_update`, not accrued to now.
     fn get_market_data(e: Env, market_id: u32) -> MarketData;
 
+    /// Returns the market's open notional (both sides) / vault balance (SCALAR_7),
+    /// the utilization bounded by the market's `max_util`.
+    ///
+    /// # Panics
+    /// - `TradingError::MarketNotFound` (701) if the market does not exist
+    fn get_market_utilization(e: Env, market_id: u32) -> i128;
+
+    /// Returns the PnL-equivalent entry price of the market's `(long, short)` sides
+    /// in the market's price units, 0 for an empty side. This is the harmonic mean
+    /// of entries weighted by notional (`notional / Σ(notional / entry)`), not the
+    /// arithmetic one: 10k at $100k plus 10k at $50k gives $66.7k, not $75k.
+    ///
+    /// # Panics
+    /// - `TradingError::MarketNotFound` (701) if the market does not exist
+    fn get_avg_entry_prices(e: Env, market_id: u32) -> (i128, i128);
+
     /// Returns all registered market IDs.
     fn get_markets(e: Env) -> Vec<u32>;
 
+    /// Returns every registered market with its config and data in one call.
+    /// Bounded by `MAX_ENTRIES` markets.
+    fn get_all_markets(e: Env) -> Vec<(u32, MarketConfig, MarketData)>;
+
+    /// Audit check: recompute a market's long/short notional and entry weight from
+    /// the given open positions and compare against the stored `MarketData`, and
+    /// check their collateral plus unclaimed referral rewards fit in the contract's
+    /// token balance. Read-only.
+    ///
+    /// # Parameters
+    /// - `market_id` - Market to check
+    /// - `users` / `ids` - Every open position on the market (parallel vectors)
+    ///
+    /// # Returns
+    /// `false` on any mismatch, or if a listed position is missing, pending, on
+    /// another market, or listed twice.
+    fn check_invariants(e: Env, market_id: u32, users: Vec<Address>, ids: Vec<u32>) -> bool;
+
+    /// Returns the cumulative protocol fees collected from positions (token_decimals):
+    /// open/fill trading fees plus trading and borrowing fees settled on close or
+    /// liquidation, before the treasury and keeper splits. Funding is excluded (P2P).
+    fn total_fees_collected(e: Env) -> i128;
+
+    /// Returns the number of positions (pending or filled) across all users, the
+    /// count `TradingConfig.max_total_positions` caps.
+    fn get_live_positions(e: Env) -> u32;
+
     /// Returns the global trading configuration.
     fn get_config(e: Env) -> TradingConfig;
 
@@ -280,6 +772,10 @@ pub trait Trading {
 
     /// Returns the collateral token address.
     fn get_token(e: Env) -> Address;
+
+    /// Returns the contract's own collateral-token balance: live collateral plus
+    /// any dust awaiting `sweep_dust`.
+    fn get_token_balance(e: Env) -> i128;
 }
 
 #[contractimpl]
@@ -297,6 +793,7 @@ impl TradingContract {
     /// # Panics
     /// - `TradingError::InvalidConfig` (700) if config fails validation bounds
     /// - `TradingError::NegativeValueNotAllowed` (723) if any rate/fee is negative
+    /// - `TradingError::VaultAssetMismatch` (705) if the vault's asset is not `token`
     pub fn __constructor(
         e: Env,
         owner: Address,
@@ -307,6 +804,7 @@ impl TradingContract {
         config: TradingConfig,
     ) {
         require_valid_config(&e, &config);
+        require_vault_asset(&e, &vault, &token);
         ownable::set_owner(&e, &owner);
         storage::set_vault(&e, &vault);
         storage::set_token(&e, &token);
@@ -314,6 +812,7 @@ impl TradingContract {
         storage::set_treasury(&e, &treasury);
         storage::set_config(&e, &config);
         storage::set_status(&e, ContractStatus::Active as u32);
+        storage::set_storage_version(&e, STORAGE_VERSION);
     }
 }
 
@@ -331,6 +830,12 @@ impl Trading for TradingContract {
         trading::execute_set_market(&e, market_id, &config);
     }
 
+    #[only_owner]
+    fn update_market(e: Env, market_id: u32, config: MarketConfig) {
+        storage::extend_instance(&e);
+        trading::execute_update_market(&e, market_id, &config);
+    }
+
     #[only_owner]
     fn del_market(e: Env, market_id: u32) {
         storage::extend_instance(&e);
@@ -343,12 +848,66 @@ impl Trading for TradingContract {
         trading::execute_set_status(&e, status);
     }
 
+    #[only_owner]
+    fn force_settle_all(e: Env, market_id: u32, users: Vec<Address>, ids: Vec<u32>, price: PriceData) {
+        storage::extend_instance(&e);
+        let pd = trading::to_market_units(&e, market_id, price);
+        trading::execute_force_settle(&e, market_id, users, ids, &pd);
+    }
+
+    #[only_owner]
+    fn freeze_position(e: Env, user: Address, id: u32) {
+        storage::extend_instance(&e);
+        trading::execute_set_frozen(&e, &user, id, true);
+    }
+
+    #[only_owner]
+    fn unfreeze_position(e: Env, user: Address, id: u32) {
+        storage::extend_instance(&e);
+        trading::execute_set_frozen(&e, &user, id, false);
+    }
+
+    #[only_owner]
+    fn sweep(e: Env, token: Address, to: Address) -> i128 {
+        storage::extend_instance(&e);
+        trading::execute_sweep(&e, &token, &to)
+    }
+
+    #[only_owner]
+    fn sweep_dust(e: Env) -> i128 {
+        storage::extend_instance(&e);
+        trading::execute_sweep_dust(&e)
+    }
+
+    #[only_owner]
+    fn set_reward_router(e: Env, reward: Address, router: Address) {
+        storage::extend_instance(&e);
+        trading::execute_set_reward_router(&e, &reward, &router);
+    }
+
+    #[only_owner]
+    fn compound_reward(e: Env, reward: Address, min_out: i128) -> i128 {
+        storage::extend_instance(&e);
+        trading::execute_compound_reward(&e, &reward, min_out)
+    }
+
+    #[only_owner]
+    fn migrate(e: Env, live_positions: u32) {
+        storage::extend_instance(&e);
+        trading::execute_migrate(&e, live_positions);
+    }
+
     fn update_status(e: Env, price: Bytes) {
         storage::extend_instance(&e);
         let pv = PriceVerifierClient::new(&e, &storage::get_price_verifier(&e));
         trading::execute_update_status(&e, &pv.verify_prices(&price));
     }
 
+    fn solvency(e: Env, price: Bytes) -> (i128, i128, i128, i128) {
+        let pv = PriceVerifierClient::new(&e, &storage::get_price_verifier(&e));
+        trading::execute_solvency(&e, &pv.verify_prices(&price))
+    }
+
     fn place_limit(
         e: Env,
         user: Address,
@@ -359,11 +918,14 @@ impl Trading for TradingContract {
         entry_price: i128,
         take_profit: i128,
         stop_loss: i128,
+        max_dev: i128,
+        at_trigger: bool,
+        expiry: u64,
     ) -> u32 {
         storage::extend_instance(&e);
         trading::execute_create_limit(
             &e, &user, market_id, collateral, notional_size, is_long,
-            entry_price, take_profit, stop_loss,
+            entry_price, take_profit, stop_loss, max_dev, at_trigger, expiry,
         )
     }
 
@@ -379,28 +941,64 @@ impl Trading for TradingContract {
         price: Bytes,
     ) -> u32 {
         storage::extend_instance(&e);
-        let pv = PriceVerifierClient::new(&e, &storage::get_price_verifier(&e));
-        let pd = pv.verify_price(&price);
+        let pd = trading::verify_market_price(&e, market_id, &price);
         trading::execute_create_market(
             &e, &user, market_id, collateral, notional_size, is_long,
             take_profit, stop_loss, &pd,
         )
     }
 
+    fn open_market_position(
+        e: Env,
+        user: Address,
+        market_id: u32,
+        collateral: i128,
+        notional_size: i128,
+        is_long: bool,
+        take_profit: i128,
+        stop_loss: i128,
+        price: Bytes,
+    ) -> (u32, Position) {
+        storage::extend_instance(&e);
+        let pd = trading::verify_market_price(&e, market_id, &price);
+        trading::execute_create_market_position(
+            &e, &user, market_id, collateral, notional_size, is_long,
+            take_profit, stop_loss, &pd,
+        )
+    }
+
     fn cancel_position(e: Env, user: Address, id: u32) -> i128 {
         storage::extend_instance(&e);
         trading::execute_cancel_position(&e, &user, id)
     }
 
+    fn cancel_expired(e: Env, caller: Address, user: Address, id: u32) -> i128 {
+        storage::extend_instance(&e);
+        trading::execute_cancel_expired(&e, &caller, &user, id)
+    }
+
+    fn bump_position(e: Env, caller: Address, user: Address, id: u32, price: Bytes) -> i128 {
+        storage::extend_instance(&e);
+        let market_id = storage::get_position(&e, &user, id).market_id;
+        let pd = trading::verify_market_price(&e, market_id, &price);
+        trading::execute_bump_position(&e, &caller, &user, id, &pd)
+    }
+
     fn close_position(e: Env, user: Address, id: u32, price: Bytes) -> i128 {
         storage::extend_instance(&e);
         trading::execute_close_position(&e, &user, id, price)
     }
 
+    fn close_position_to(e: Env, user: Address, id: u32, receiver: Address, price: Bytes) -> i128 {
+        storage::extend_instance(&e);
+        trading::execute_close_position_to(&e, &user, id, &receiver, price)
+    }
+
     fn modify_collateral(e: Env, user: Address, id: u32, new_collateral: i128, price: Bytes) {
         storage::extend_instance(&e);
-        let pv = PriceVerifierClient::new(&e, &storage::get_price_verifier(&e));
-        trading::execute_modify_collateral(&e, &user, id, new_collateral, &pv.verify_price(&price));
+        let market_id = storage::get_position(&e, &user, id).market_id;
+        let pd = trading::verify_market_price(&e, market_id, &price);
+        trading::execute_modify_collateral(&e, &user, id, new_collateral, &pd);
     }
 
     fn set_triggers(e: Env, user: Address, id: u32, take_profit: i128, stop_loss: i128) {
@@ -408,10 +1006,79 @@ impl Trading for TradingContract {
         trading::execute_set_triggers(&e, &user, id, take_profit, stop_loss);
     }
 
+    fn set_triggers_each(e: Env, user: Address, id: u32, take_profit: i128, stop_loss: i128) -> (u32, u32) {
+        storage::extend_instance(&e);
+        trading::execute_set_triggers_each(&e, &user, id, take_profit, stop_loss)
+    }
+
+    fn set_auto_trigger(e: Env, user: Address, id: u32, enabled: bool) {
+        storage::extend_instance(&e);
+        trading::execute_set_auto_trigger(&e, &user, id, enabled);
+    }
+
+    fn set_referrer(e: Env, user: Address, referrer: Address) {
+        storage::extend_instance(&e);
+        trading::execute_set_referrer(&e, &user, &referrer);
+    }
+
+    fn claim_referral(e: Env, referrer: Address) -> i128 {
+        storage::extend_instance(&e);
+        trading::execute_claim_referral(&e, &referrer)
+    }
+
     fn execute(e: Env, caller: Address, market_id: u32, users: Vec<Address>, ids: Vec<u32>, price: Bytes) {
         storage::extend_instance(&e);
-        let pv = PriceVerifierClient::new(&e, &storage::get_price_verifier(&e));
-        trading::execute_trigger(&e, &caller, market_id, users, ids, &pv.verify_price(&price));
+        let pd = trading::verify_market_price(&e, market_id, &price);
+        trading::execute_trigger(&e, &caller, market_id, users, ids, &pd);
+    }
+
+    fn execute_each(
+        e: Env,
+        caller: Address,
+        market_id: u32,
+        users: Vec<Address>,
+        ids: Vec<u32>,
+        price: Bytes,
+    ) -> Vec<u32> {
+        storage::extend_instance(&e);
+        let pd = trading::verify_market_price(&e, market_id, &price);
+        trading::execute_trigger_each(&e, &caller, market_id, users, ids, &pd)
+    }
+
+    fn mark_fillable(e: Env, user: Address, id: u32, price: Bytes) -> u64 {
+        storage::extend_instance(&e);
+        let market_id = storage::get_position(&e, &user, id).market_id;
+        let pd = trading::verify_market_price(&e, market_id, &price);
+        trading::execute_mark_fillable(&e, &user, id, &pd)
+    }
+
+    fn simulate_execute(e: Env, market_id: u32, users: Vec<Address>, ids: Vec<u32>, price: Bytes) -> Vec<Symbol> {
+        let pd = trading::verify_market_price(&e, market_id, &price);
+        trading::execute_simulate_trigger(&e, market_id, users, ids, &pd)
+    }
+
+    fn simulate_transfers(
+        e: Env,
+        caller: Address,
+        market_id: u32,
+        users: Vec<Address>,
+        ids: Vec<u32>,
+        price: Bytes,
+    ) -> Map<Address, i128> {
+        let pd = trading::verify_market_price(&e, market_id, &price);
+        trading::execute_simulate_transfers(&e, &caller, market_id, users, ids, &pd)
+    }
+
+    fn get_liquidatable_sorted(
+        e: Env,
+        market_id: u32,
+        users: Vec<Address>,
+        ids: Vec<u32>,
+        price: Bytes,
+        limit: u32,
+    ) -> Vec<(Address, u32)> {
+        let pd = trading::verify_market_price(&e, market_id, &price);
+        trading::execute_liquidatable_sorted(&e, market_id, users, ids, &pd, limit)
     }
 
     fn apply_funding(e: Env) {
@@ -423,10 +1090,54 @@ impl Trading for TradingContract {
         storage::get_position(&e, &user, id)
     }
 
+    fn get_positions(e: Env, users: Vec<Address>, ids: Vec<u32>) -> Vec<Option<Position>> {
+        trading::execute_get_positions(&e, &users, &ids)
+    }
+
+    fn get_user_volume(e: Env, user: Address) -> i128 {
+        storage::get_user_volume(&e, &user)
+    }
+
+    fn get_referrer(e: Env, user: Address) -> Option<Address> {
+        storage::get_referrer(&e, &user)
+    }
+
+    fn get_referral_rewards(e: Env, referrer: Address) -> i128 {
+        storage::get_referral_rewards(&e, &referrer)
+    }
+
+    fn health_factor(e: Env, user: Address, id: u32, price: Bytes) -> i128 {
+        trading::execute_health_factor(&e, &user, id, price)
+    }
+
+    fn quote_close(e: Env, user: Address, id: u32, price: Bytes) -> CloseQuote {
+        trading::execute_quote_close(&e, &user, id, price)
+    }
+
+    fn is_expired(e: Env, user: Address, id: u32) -> bool {
+        storage::get_position(&e, &user, id).is_expired(&e)
+    }
+
     fn get_user_counter(e: Env, user: Address) -> u32 {
         storage::get_user_counter(&e, &user)
     }
 
+    fn next_position_id(e: Env, user: Address) -> u32 {
+        storage::get_user_counter(&e, &user)
+    }
+
+    fn get_user_positions(e: Env, user: Address, start: u32, limit: u32) -> Vec<(u32, bool)> {
+        trading::execute_user_positions(&e, &user, start, limit)
+    }
+
+    fn get_user_pending(e: Env, user: Address, market_id: u32) -> u32 {
+        storage::get_user_pending(&e, &user, market_id)
+    }
+
+    fn get_user_notional(e: Env, user: Address, market_id: u32) -> i128 {
+        storage::get_user_notional(&e, &user, market_id)
+    }
+
     fn get_market_config(e: Env, market_id: u32) -> MarketConfig {
         storage::get_market_config(&e, market_id)
     }
@@ -435,10 +1146,42 @@ impl Trading for TradingContract {
         storage::get_market_data(&e, market_id)
     }
 
+    fn get_market_utilization(e: Env, market_id: u32) -> i128 {
+        trading::execute_market_utilization(&e, market_id)
+    }
+
+    fn get_avg_entry_prices(e: Env, market_id: u32) -> (i128, i128) {
+        trading::execute_avg_entry_prices(&e, market_id)
+    }
+
     fn get_markets(e: Env) -> Vec<u32> {
         storage::get_markets(&e)
     }
 
+    fn get_all_markets(e: Env) -> Vec<(u32, MarketConfig, MarketData)> {
+        let mut markets = Vec::new(&e);
+        for market_id in storage::get_markets(&e).iter() {
+            markets.push_back((
+                market_id,
+                storage::get_market_config(&e, market_id),
+                storage::get_market_data(&e, market_id),
+            ));
+        }
+        markets
+    }
+
+    fn check_invariants(e: Env, market_id: u32, users: Vec<Address>, ids: Vec<u32>) -> bool {
+        trading::execute_check_invariants(&e, market_id, &users, &ids)
+    }
+
+    fn total_fees_collected(e: Env) -> i128 {
+        storage::get_total_fees(&e)
+    }
+
+    fn get_live_positions(e: Env) -> u32 {
+        storage::get_live_positions(&e)
+    }
+
     fn get_config(e: Env) -> TradingConfig {
         storage::get_config(&e)
     }
@@ -462,11 +1205,23 @@ impl Trading for TradingContract {
     fn get_token(e: Env) -> Address {
         storage::get_token(&e)
     }
+
+    fn get_token_balance(e: Env) -> i128 {
+        TokenClient::new(&e, &storage::get_token(&e)).balance(&e.current_contract_address())
+    }
 }
 
 #[contractimpl(contracttrait)]
 impl Ownable for TradingContract {}
 
+/// A new WASM that bumps `STORAGE_VERSION` leaves position and funding operations
+/// refused (`MigrationPending`) until the owner calls `migrate`.
+///
+/// With the governance contract as owner, an upgrade is queued there as
+/// `upgrade(new_wasm_hash, governance)` and only applies once its delay has
+/// passed, so it can be inspected and cancelled in between. Governance's own
+/// upgrade goes through the same delay (`queue_upgrade`), so it cannot be
+/// replaced by code that upgrades this contract at once.
 #[contractimpl]
 impl Upgradeable for TradingContract {
     fn upgrade(e: &Env, new_wasm_hash: soroban_sdk::BytesN<32>, operator: Address) {
@@ -15,4 +15,13 @@ pub trait VaultInterface {
 
     /// Strategy withdraws tokens from the vault (decreases total_assets and share price)
     fn strategy_withdraw(e: Env, strategy: Address, amount: i128);
+
+    /// Strategy registers the swap router used to compound `reward`
+    fn set_reward_router(e: Env, strategy: Address, reward: Address, router: Address);
+
+    /// Strategy swaps the vault's `reward` balance into the vault asset, returning the amount out
+    fn compound(e: Env, strategy: Address, reward: Address, min_out: i128) -> i128;
+
+    /// Returns the vault share balance of `account`
+    fn balance(e: Env, account: Address) -> i128;
 }
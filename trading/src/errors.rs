@@ -4,13 +4,17 @@ use soroban_sdk::contracterror;
 #[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
 #[repr(u32)]
 pub enum TradingError {
-    Unauthorized = 1, // caller is not the contract owner
+    Unauthorized = 1, // caller is not the contract owner, or not the user on a manual trigger
 
     // 700: Config & Market
     InvalidConfig = 700, // config parameter out of valid range
     MarketNotFound = 701, // no market registered for the given market_id
     MarketDisabled = 702, // market is disabled or deleted
     MaxMarketsReached = 703, // MAX_ENTRIES (50) markets already registered
+    MarketEnabled = 704, // action requires the market to be disabled first
+    VaultAssetMismatch = 705, // vault's underlying asset is not the settlement token
+    DirectionDisabled = 706, // market does not accept new positions on this side
+    OpensPaused = 707, // market has new opens paused (open_paused)
 
     // 710: Price
     InvalidPrice = 710, // price verification failed, feed_id mismatch, or missing feed
@@ -19,26 +23,43 @@ pub enum TradingError {
     // 720: Position
     PositionNotFound = 720, // position ID not found in storage
     PositionNotPending = 721, // position is filled; expected pending
+    PositionFrozen = 722, // position frozen by the owner; no actions until unfrozen
     NegativeValueNotAllowed = 723, // a parameter is <= 0 or negative
-    NotionalBelowMinimum = 724, // notional below TradingConfig.min_notional
+    NotionalBelowMinimum = 724, // notional below TradingConfig.min_notional or MarketConfig.min_notional
     NotionalAboveMaximum = 725, // notional above TradingConfig.max_notional
     LeverageAboveMaximum = 726, // effective leverage exceeds 1/margin
     CollateralUnchanged = 727, // modify_collateral called with unchanged amount
     WithdrawalBreaksMargin = 728, // collateral withdrawal would breach margin requirement
+    BelowMinCollateral = 729, // collateral withdrawal would leave col below notional * margin
+    ProtocolPositionCapReached = 730, // live positions across all users at TradingConfig.max_total_positions
     NotActionable = 731, // no valid action for this position
     PositionTooNew = 732, // close attempted before MIN_OPEN_TIME (30s)
     ActionNotAllowedForStatus = 733, // action not allowed for position status
     InvalidInput = 734, // malformed input (e.g. mismatched parallel vec lengths)
+    OrderNotExpired = 735, // pending order has no expiry or has not reached it
+    ModifyTooSoon = 736, // modify_collateral called within the market's min_modify_interval
+    LimitOrderNotFillable = 737, // limit price not reached, or gapped past max_dev without at_trigger
+    TooManyPendingOrders = 738, // user already holds TradingConfig.max_pending orders on the market
+    UserNotionalExceeded = 739, // user's open notional on the market would exceed MarketConfig.max_user_notional
 
     // 740: Contract Status
     InvalidStatus = 740, // invalid or disallowed contract status value
     ContractOnIce = 741, // new positions blocked (OnIce, AdminOnIce, or Frozen)
     ContractFrozen = 742, // all position management blocked (Frozen)
+    MigrationPending = 743, // storage predates STORAGE_VERSION; owner must call migrate
 
     // 750: Utilization & Funding
     ThresholdNotMet = 750, // net PnL below ADL threshold
     UtilizationExceeded = 751, // position would exceed notional/vault cap
     FundingTooEarly = 752, // apply_funding called < 1 hour since last call
+    InvalidMarketStats = 753, // an open would overflow open interest or entry weight
 
-    // 760-769: reserved for trading growth
+    // 760: Admin
+    TokenNotSweepable = 760, // sweep target is the collateral token, or sweep_dust with positions live
+    NothingToMigrate = 761, // storage is already at STORAGE_VERSION
+    ReferrerLocked = 762, // referrer already set, user has already traded, or self-referral
+    KeeperStakeTooLow = 763, // liquidating keeper holds fewer vault shares than TradingConfig.keeper_min_shares
+    OrderExpired = 764, // pending order is past its expiry and can no longer fill
+
+    // 765-769: reserved for trading growth
 }
@@ -13,6 +13,14 @@ pub struct SetMarket {
     pub market_id: u32,
 }
 
+/// Emitted when an existing market's config is updated via `update_market`.
+#[contractevent]
+#[derive(Clone)]
+pub struct UpdateMarket {
+    #[topic]
+    pub market_id: u32,
+}
+
 /// Emitted when the contract status changes (admin or circuit breaker).
 #[contractevent]
 #[derive(Clone)]
@@ -20,6 +28,14 @@ pub struct SetStatus {
     pub status: u32,
 }
 
+/// Emitted when stored entries are migrated to a new layout via `migrate`.
+#[contractevent]
+#[derive(Clone)]
+pub struct Migrate {
+    pub from: u32,
+    pub to: u32,
+}
+
 /// Emitted when a pending limit order is created via `place_limit`.
 #[contractevent]
 #[derive(Clone)]
@@ -30,6 +46,7 @@ pub struct PlaceLimit {
     pub user: Address,
     #[topic]
     pub position_id: u32,
+    pub event_seq: u32,
 }
 
 /// Emitted when a market order is opened and filled immediately via `open_market`.
@@ -42,6 +59,7 @@ pub struct OpenMarket {
     pub user: Address,
     #[topic]
     pub position_id: u32,
+    pub event_seq: u32,
     pub base_fee: i128,
     pub impact_fee: i128,
 }
@@ -56,6 +74,7 @@ pub struct FillLimit {
     pub user: Address,
     #[topic]
     pub position_id: u32,
+    pub event_seq: u32,
     pub base_fee: i128,
     pub impact_fee: i128,
 }
@@ -70,6 +89,7 @@ pub struct ClosePosition {
     pub user: Address,
     #[topic]
     pub position_id: u32,
+    pub event_seq: u32,
     pub price: i128,
     pub pnl: i128,
     pub base_fee: i128,
@@ -88,6 +108,7 @@ pub struct Liquidation {
     pub user: Address,
     #[topic]
     pub position_id: u32,
+    pub event_seq: u32,
     pub price: i128,
     pub base_fee: i128,
     pub impact_fee: i128,
@@ -96,6 +117,56 @@ pub struct Liquidation {
     pub liq_fee: i128,
 }
 
+/// Emitted when a position settles with losses and fees exceeding its collateral.
+/// The trader receives nothing and the vault absorbs the shortfall.
+#[contractevent]
+#[derive(Clone)]
+pub struct BadDebt {
+    #[topic]
+    pub market_id: u32,
+    #[topic]
+    pub user: Address,
+    #[topic]
+    pub position_id: u32,
+    pub event_seq: u32,
+    /// Shortfall absorbed by the vault (token_decimals).
+    pub amount: i128,
+}
+
+/// Emitted when a close removes more open interest or entry weight than a side
+/// holds on a market that was never ADL'd. The aggregate is floored at zero so
+/// the close goes through; any report is a drift to investigate.
+#[contractevent]
+#[derive(Clone)]
+pub struct StatsClamped {
+    #[topic]
+    pub market_id: u32,
+    pub long: bool,
+    /// Notional floored away (token_decimals).
+    pub notional: i128,
+    /// Entry weight floored away (price_scalar).
+    pub entry_wt: i128,
+}
+
+/// Emitted when a keeper settles a position past its market's `max_duration`.
+#[contractevent]
+#[derive(Clone)]
+pub struct SettleExpiry {
+    #[topic]
+    pub market_id: u32,
+    #[topic]
+    pub user: Address,
+    #[topic]
+    pub position_id: u32,
+    pub event_seq: u32,
+    pub price: i128,
+    pub pnl: i128,
+    pub base_fee: i128,
+    pub impact_fee: i128,
+    pub funding: i128,
+    pub borrowing_fee: i128,
+}
+
 /// Emitted when a take-profit trigger is executed by a keeper.
 #[contractevent]
 #[derive(Clone)]
@@ -106,6 +177,7 @@ pub struct TakeProfit {
     pub user: Address,
     #[topic]
     pub position_id: u32,
+    pub event_seq: u32,
     pub price: i128,
     pub pnl: i128,
     pub base_fee: i128,
@@ -124,6 +196,71 @@ pub struct StopLoss {
     pub user: Address,
     #[topic]
     pub position_id: u32,
+    pub event_seq: u32,
+    pub price: i128,
+    pub pnl: i128,
+    pub base_fee: i128,
+    pub impact_fee: i128,
+    pub funding: i128,
+    pub borrowing_fee: i128,
+}
+
+/// Emitted when a position is first seen below the liquidation threshold on a market
+/// with a liquidation grace period. Liquidation is deferred until the grace elapses.
+#[contractevent]
+#[derive(Clone)]
+pub struct MarginBreach {
+    #[topic]
+    pub market_id: u32,
+    #[topic]
+    pub user: Address,
+    #[topic]
+    pub position_id: u32,
+    pub event_seq: u32,
+    pub price: i128,
+}
+
+/// Emitted when the owner freezes or unfreezes a position via `freeze_position` /
+/// `unfreeze_position`.
+#[contractevent]
+#[derive(Clone)]
+pub struct SetFrozen {
+    #[topic]
+    pub market_id: u32,
+    #[topic]
+    pub user: Address,
+    #[topic]
+    pub position_id: u32,
+    pub event_seq: u32,
+    pub frozen: bool,
+}
+
+/// Emitted when a user lets keepers execute their TP/SL triggers, or takes that
+/// back, via `set_auto_trigger`.
+#[contractevent]
+#[derive(Clone)]
+pub struct SetAutoTrigger {
+    #[topic]
+    pub market_id: u32,
+    #[topic]
+    pub user: Address,
+    #[topic]
+    pub position_id: u32,
+    pub event_seq: u32,
+    pub enabled: bool,
+}
+
+/// Emitted when a position on a delisted market is settled by the owner via `force_settle_all`.
+#[contractevent]
+#[derive(Clone)]
+pub struct ForceClose {
+    #[topic]
+    pub market_id: u32,
+    #[topic]
+    pub user: Address,
+    #[topic]
+    pub position_id: u32,
+    pub event_seq: u32,
     pub price: i128,
     pub pnl: i128,
     pub base_fee: i128,
@@ -142,6 +279,7 @@ pub struct ModifyCollateral {
     pub user: Address,
     #[topic]
     pub position_id: u32,
+    pub event_seq: u32,
     /// Positive = deposit, negative = withdrawal (token_decimals).
     pub amount: i128,
 }
@@ -156,6 +294,7 @@ pub struct SetTriggers {
     pub user: Address,
     #[topic]
     pub position_id: u32,
+    pub event_seq: u32,
     pub take_profit: i128,
     pub stop_loss: i128,
 }
@@ -178,6 +317,7 @@ pub struct RefundPosition {
     pub user: Address,
     #[topic]
     pub position_id: u32,
+    pub event_seq: u32,
     pub amount: i128,
 }
 
@@ -186,6 +326,54 @@ pub struct RefundPosition {
 #[derive(Clone)]
 pub struct ApplyFunding {}
 
+/// Emitted when the owner recovers a stray token balance via `sweep`.
+#[contractevent]
+#[derive(Clone)]
+pub struct Sweep {
+    #[topic]
+    pub token: Address,
+    #[topic]
+    pub to: Address,
+    pub amount: i128,
+}
+
+/// Emitted per market by `apply_funding` (at most hourly), giving analytics a
+/// time series of indices and open interest without replaying position events.
+#[contractevent]
+#[derive(Clone)]
+pub struct FundingSnapshot {
+    #[topic]
+    pub market_id: u32,
+    pub fund_rate: i128,
+    pub l_fund_idx: i128,
+    pub s_fund_idx: i128,
+    pub l_borr_idx: i128,
+    pub s_borr_idx: i128,
+    pub l_notional: i128,
+    pub s_notional: i128,
+    /// Market notional / vault balance (SCALAR_7), 0 if the vault is empty.
+    pub util: i128,
+}
+
+/// Emitted by `MarketData::accrue_market` when accrual moves a market's funding or
+/// borrowing indices (on context loads, market updates and `apply_funding`), so
+/// indexers can attribute accrual between position touches.
+#[contractevent]
+#[derive(Clone)]
+pub struct IndexUpdate {
+    #[topic]
+    pub market_id: u32,
+    pub l_fund_idx: i128,
+    pub s_fund_idx: i128,
+    pub l_borr_idx: i128,
+    pub s_borr_idx: i128,
+    /// Vault-wide utilization the borrowing rate was priced at (SCALAR_7).
+    pub util_vault: i128,
+    /// Market utilization the borrowing rate was priced at (SCALAR_7).
+    pub util_market: i128,
+    pub timestamp: u64,
+}
+
 /// Emitted once when ADL is triggered, summarizing the overall reduction.
 #[contractevent]
 #[derive(Clone)]
@@ -195,3 +383,22 @@ pub struct ADLTriggered {
     /// Deficit amount: net_pnl - vault_balance (token_decimals).
     pub deficit: i128,
 }
+
+/// Emitted when a user binds a referrer via `set_referrer`.
+#[contractevent]
+#[derive(Clone)]
+pub struct SetReferrer {
+    #[topic]
+    pub user: Address,
+    #[topic]
+    pub referrer: Address,
+}
+
+/// Emitted when a referrer withdraws accrued rewards via `claim_referral`.
+#[contractevent]
+#[derive(Clone)]
+pub struct ClaimReferral {
+    #[topic]
+    pub referrer: Address,
+    pub amount: i128,
+}
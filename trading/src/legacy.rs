@@ -0,0 +1,167 @@
+//! Stored layouts from before `STORAGE_VERSION` 1, read once by `migrate`
+//! (configs) or lazily on access (positions).
+//!
+//! Version 0 is the baseline deployment. Every field added since has a default
+//! that keeps the baseline behavior, so entries convert without owner input.
+
+use crate::storage::{self, TradingStorageKey};
+use crate::types::{MarketConfig, Position, TradingConfig};
+use soroban_sdk::unwrap::UnwrapOptimized;
+use soroban_sdk::{contracttype, Env, Map, Symbol, TryFromVal, Val, Vec};
+
+#[contracttype(export = false)]
+#[derive(Clone, Debug)]
+pub(crate) struct TradingConfigV0 {
+    pub caller_rate:  i128,
+    pub min_notional: i128,
+    pub max_notional: i128,
+    pub fee_dom:      i128,
+    pub fee_non_dom:  i128,
+    pub max_util:     i128,
+    pub r_funding:    i128,
+    pub r_base:       i128,
+    pub r_var:        i128,
+}
+
+#[contracttype(export = false)]
+#[derive(Clone, Debug)]
+pub(crate) struct MarketConfigV0 {
+    pub feed_id:  u32,
+    pub enabled:  bool,
+    pub max_util: i128,
+    pub r_var_market: i128,
+    pub margin:   i128,
+    pub liq_fee:  i128,
+    pub impact:   i128,
+}
+
+#[contracttype(export = false)]
+#[derive(Clone, Debug)]
+pub(crate) struct PositionV0 {
+    pub filled:      bool,
+    pub market_id:   u32,
+    pub long:        bool,
+    pub sl:          i128,
+    pub tp:          i128,
+    pub entry_price: i128,
+    pub col:         i128,
+    pub notional:    i128,
+    pub fund_idx:    i128,
+    pub borr_idx:    i128,
+    pub adl_idx:     i128,
+    pub created_at:  u64,
+}
+
+impl From<TradingConfigV0> for TradingConfig {
+    fn from(c: TradingConfigV0) -> Self {
+        TradingConfig {
+            caller_rate: c.caller_rate,
+            min_notional: c.min_notional,
+            max_notional: c.max_notional,
+            fee_dom: c.fee_dom,
+            fee_non_dom: c.fee_non_dom,
+            max_util: c.max_util,
+            r_funding: c.r_funding,
+            r_base: c.r_base,
+            r_var: c.r_var,
+            fee_free_wind_down: false,
+            max_pending: 0,
+            fill_bonus: 0,
+            liq_caller_cap: 0,
+            referral_share: 0,
+            max_total_positions: 0,
+            fill_bounty: 0,
+            keeper_min_shares: 0,
+        }
+    }
+}
+
+impl MarketConfigV0 {
+    fn upgrade(self, e: &Env) -> MarketConfig {
+        MarketConfig {
+            feed_id: self.feed_id,
+            basket: Vec::new(e),
+            enabled: self.enabled,
+            allow_long: true,
+            allow_short: true,
+            open_paused: false,
+            max_util: self.max_util,
+            r_var_market: self.r_var_market,
+            margin: self.margin,
+            liq_fee: self.liq_fee,
+            impact: self.impact,
+            min_notional: 0,
+            max_user_notional: 0,
+            liq_grace: 0,
+            liq_price_age: 0,
+            max_duration: 0,
+            tiers: Vec::new(e),
+            min_modify_interval: 0,
+            fee_tiers: Vec::new(e),
+            fee_recipient: None,
+            fee_share: 0,
+            min_side: 0,
+        }
+    }
+}
+
+impl From<PositionV0> for Position {
+    fn from(p: PositionV0) -> Self {
+        Position {
+            filled: p.filled,
+            market_id: p.market_id,
+            long: p.long,
+            sl: p.sl,
+            tp: p.tp,
+            entry_price: p.entry_price,
+            col: p.col,
+            notional: p.notional,
+            fund_idx: p.fund_idx,
+            borr_idx: p.borr_idx,
+            adl_idx: p.adl_idx,
+            created_at: p.created_at,
+            breach_at: 0,
+            max_dev: 0,
+            at_trigger: false,
+            expiry: 0,
+            last_modified_at: 0,
+            touched_at: p.created_at,
+            fillable_since: 0,
+            bounty: 0,
+            frozen: false,
+            auto_trigger: true,
+            event_seq: 0,
+        }
+    }
+}
+
+/// 0 -> 1: rewrite the global and every market's config in the current layout.
+/// `MarketData` is unchanged; positions convert on access via [`decode_position`].
+pub(crate) fn migrate_v0(e: &Env) {
+    let config: TradingConfigV0 = e
+        .storage()
+        .instance()
+        .get(&TradingStorageKey::Config)
+        .unwrap_optimized();
+    storage::set_config(e, &config.into());
+
+    for market_id in storage::get_markets(e).iter() {
+        let config: MarketConfigV0 = e
+            .storage()
+            .persistent()
+            .get(&TradingStorageKey::MarketConfig(market_id))
+            .unwrap_optimized();
+        storage::set_market_config(e, market_id, &config.upgrade(e));
+    }
+}
+
+/// Decode a stored position in either layout. Positions are keyed per user and
+/// can't be enumerated, so baseline entries stay as written until next stored.
+pub(crate) fn decode_position(e: &Env, raw: Val) -> Position {
+    let fields = Map::<Symbol, Val>::try_from_val(e, &raw).unwrap_optimized();
+    if fields.contains_key(Symbol::new(e, "event_seq")) {
+        Position::try_from_val(e, &raw).unwrap_optimized()
+    } else {
+        PositionV0::try_from_val(e, &raw).unwrap_optimized().into()
+    }
+}
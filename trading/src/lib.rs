@@ -7,6 +7,7 @@ mod contract;
 mod dependencies;
 mod errors;
 mod events;
+mod legacy;
 mod types;
 mod validation;
 
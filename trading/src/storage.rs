@@ -1,9 +1,10 @@
 use crate::{
     errors::TradingError,
+    legacy,
     types::{MarketConfig, MarketData, Position, TradingConfig},
 };
 use soroban_sdk::{
-    contracttype, panic_with_error, unwrap::UnwrapOptimized, Address, Env, Vec,
+    contracttype, panic_with_error, unwrap::UnwrapOptimized, Address, Env, Val, Vec,
 };
 
 
@@ -12,8 +13,11 @@ use soroban_sdk::{
 //   is fine because it's extended on every call (hourly at least for funding updates).
 // - Market (45/52d): Config and data are touched on every position action but not
 //   every tx. Longer threshold provides buffer for idle markets.
-// - Position (14/21d): Perp positions are short-lived (most close within days).
-//   Shorter TTL avoids paying rent for abandoned/expired positions.
+// - Position (14/21d): Pending orders are short-lived (filled, cancelled or past expiry).
+//   Shorter TTL avoids paying rent for abandoned/expired orders.
+// - Open position (100/120d): Filled positions hold user collateral and can stay
+//   untouched for months, so they get the longest tier, matching the factory's pool
+//   records. Idle ones can be kept alive by keepers via `bump_position`.
 
 const ONE_DAY_LEDGERS: u32 = 17280; // assumes ~5s per ledger
 
@@ -26,6 +30,9 @@ const LEDGER_BUMP_MARKET: u32 = LEDGER_THRESHOLD_MARKET + 7 * ONE_DAY_LEDGERS; /
 const LEDGER_THRESHOLD_POSITION: u32 = ONE_DAY_LEDGERS * 14;      // ~14 days
 const LEDGER_BUMP_POSITION: u32 = LEDGER_THRESHOLD_POSITION + 7 * ONE_DAY_LEDGERS; // ~21 days
 
+const LEDGER_THRESHOLD_POSITION_OPEN: u32 = ONE_DAY_LEDGERS * 100; // ~100 days
+const LEDGER_BUMP_POSITION_OPEN: u32 = LEDGER_THRESHOLD_POSITION_OPEN + 20 * ONE_DAY_LEDGERS; // ~120 days
+
 #[derive(Clone)]
 #[contracttype]
 pub enum TradingStorageKey {
@@ -37,12 +44,24 @@ pub enum TradingStorageKey {
     Config,
     Treasury,
     TotalNotional,
+    TotalFees,
     LastFundingUpdate,
+    LivePositions, // pending + filled positions in storage
+    ReferralOwed, // unclaimed referral rewards across all referrers, excluded from sweep_dust
+    BountyEscrow, // fill bounties escrowed by pending orders
+    StorageVersion, // layout version of stored entries, see STORAGE_VERSION
     // Persistent storage (per-entity)
     Markets, // Accessed during ADL, apply_funding, and market management.
     MarketConfig(u32),
     MarketData(u32),
+    PriceExponent(u32), // oracle exponent pinned on the market's first fill; later prices are rescaled to it
+    LastPrice(u32), // price the market's state was last stored at, bounds force_settle prices
     UserCounter(Address),
+    UserVolume(Address), // cumulative notional opened and closed, for fee tiers
+    UserPending(Address, u32), // pending limit orders per (user, market_id)
+    UserNotional(Address, u32), // open notional per (user, market_id), for max_user_notional
+    Referrer(Address), // referrer bound to a user before their first position
+    ReferralRewards(Address), // unclaimed referral rewards per referrer, held by this contract
     Position(Address, u32),
 }
 
@@ -131,10 +150,26 @@ pub fn set_status(e: &Env, status: u32) {
         .set(&TradingStorageKey::Status, &status);
 }
 
+/// Layout version the stored entries were written with. Deployments that
+/// predate versioning have no entry and read as 0.
+pub fn get_storage_version(e: &Env) -> u32 {
+    e.storage()
+        .instance()
+        .get(&TradingStorageKey::StorageVersion)
+        .unwrap_or(0)
+}
+
+pub fn set_storage_version(e: &Env, version: u32) {
+    e.storage()
+        .instance()
+        .set(&TradingStorageKey::StorageVersion, &version);
+}
+
 pub fn next_position_id(e: &Env, user: &Address) -> u32 {
     let key = TradingStorageKey::UserCounter(user.clone());
     let current: u32 = e.storage().persistent().get(&key).unwrap_or(0);
     e.storage().persistent().set(&key, &(current + 1));
+    set_live_positions(e, get_live_positions(e) + 1);
     // Market-tier TTL: counter must outlive all positions to prevent ID reuse
     e.storage()
         .persistent()
@@ -153,6 +188,147 @@ pub fn get_user_counter(e: &Env, user: &Address) -> u32 {
     result
 }
 
+pub fn get_user_volume(e: &Env, user: &Address) -> i128 {
+    e.storage()
+        .persistent()
+        .get(&TradingStorageKey::UserVolume(user.clone()))
+        .unwrap_or(0)
+}
+
+pub fn set_user_volume(e: &Env, user: &Address, volume: i128) {
+    let key = TradingStorageKey::UserVolume(user.clone());
+    e.storage().persistent().set(&key, &volume);
+    e.storage()
+        .persistent()
+        .extend_ttl(&key, LEDGER_THRESHOLD_MARKET, LEDGER_BUMP_MARKET);
+}
+
+/// Number of pending limit orders `user` holds on `market_id`.
+pub fn get_user_pending(e: &Env, user: &Address, market_id: u32) -> u32 {
+    e.storage()
+        .persistent()
+        .get(&TradingStorageKey::UserPending(user.clone(), market_id))
+        .unwrap_or(0)
+}
+
+pub fn set_user_pending(e: &Env, user: &Address, market_id: u32, count: u32) {
+    let key = TradingStorageKey::UserPending(user.clone(), market_id);
+    if count == 0 {
+        e.storage().persistent().remove(&key);
+        return;
+    }
+    e.storage().persistent().set(&key, &count);
+    // Market-tier TTL: the count must outlive the pending orders it tracks
+    e.storage()
+        .persistent()
+        .extend_ttl(&key, LEDGER_THRESHOLD_MARKET, LEDGER_BUMP_MARKET);
+}
+
+/// Decrement the pending order count once an order is filled or refunded.
+pub fn release_pending(e: &Env, user: &Address, market_id: u32) {
+    set_user_pending(e, user, market_id, get_user_pending(e, user, market_id).saturating_sub(1));
+}
+
+pub fn get_user_notional(e: &Env, user: &Address, market_id: u32) -> i128 {
+    e.storage()
+        .persistent()
+        .get(&TradingStorageKey::UserNotional(user.clone(), market_id))
+        .unwrap_or(0)
+}
+
+/// Adjust the user's open notional on the market by `delta`, floored at 0 so
+/// positions opened before tracking began release cleanly.
+pub fn add_user_notional(e: &Env, user: &Address, market_id: u32, delta: i128) {
+    let key = TradingStorageKey::UserNotional(user.clone(), market_id);
+    let notional = (get_user_notional(e, user, market_id) + delta).max(0);
+    if notional == 0 {
+        e.storage().persistent().remove(&key);
+        return;
+    }
+    e.storage().persistent().set(&key, &notional);
+    // Market-tier TTL: the total must outlive the positions it tracks
+    e.storage()
+        .persistent()
+        .extend_ttl(&key, LEDGER_THRESHOLD_MARKET, LEDGER_BUMP_MARKET);
+}
+
+pub fn get_referrer(e: &Env, user: &Address) -> Option<Address> {
+    e.storage()
+        .persistent()
+        .get(&TradingStorageKey::Referrer(user.clone()))
+}
+
+pub fn set_referrer(e: &Env, user: &Address, referrer: &Address) {
+    let key = TradingStorageKey::Referrer(user.clone());
+    e.storage().persistent().set(&key, referrer);
+    e.storage()
+        .persistent()
+        .extend_ttl(&key, LEDGER_THRESHOLD_MARKET, LEDGER_BUMP_MARKET);
+}
+
+pub fn get_referral_rewards(e: &Env, referrer: &Address) -> i128 {
+    e.storage()
+        .persistent()
+        .get(&TradingStorageKey::ReferralRewards(referrer.clone()))
+        .unwrap_or(0)
+}
+
+/// Add `delta` to `referrer`'s unclaimed rewards and to the contract-wide total owed.
+pub fn add_referral_rewards(e: &Env, referrer: &Address, delta: i128) {
+    let key = TradingStorageKey::ReferralRewards(referrer.clone());
+    let rewards = get_referral_rewards(e, referrer) + delta;
+    if rewards == 0 {
+        e.storage().persistent().remove(&key);
+    } else {
+        e.storage().persistent().set(&key, &rewards);
+        e.storage()
+            .persistent()
+            .extend_ttl(&key, LEDGER_THRESHOLD_MARKET, LEDGER_BUMP_MARKET);
+    }
+    e.storage()
+        .instance()
+        .set(&TradingStorageKey::ReferralOwed, &(get_referral_owed(e) + delta));
+}
+
+/// Unclaimed referral rewards across all referrers, held in this contract's balance.
+pub fn get_referral_owed(e: &Env) -> i128 {
+    e.storage()
+        .instance()
+        .get(&TradingStorageKey::ReferralOwed)
+        .unwrap_or(0)
+}
+
+/// Fill bounties escrowed by pending orders, held in this contract's balance.
+pub fn get_bounty_escrow(e: &Env) -> i128 {
+    e.storage()
+        .instance()
+        .get(&TradingStorageKey::BountyEscrow)
+        .unwrap_or(0)
+}
+
+/// Adjust the escrowed bounty total by `delta`: positive when an order escrows
+/// its bounty, negative when it is paid to a keeper or refunded.
+pub fn add_bounty_escrow(e: &Env, delta: i128) {
+    e.storage()
+        .instance()
+        .set(&TradingStorageKey::BountyEscrow, &(get_bounty_escrow(e) + delta));
+}
+
+/// Number of positions (pending or filled) currently holding collateral.
+/// Incremented on ID allocation, decremented on removal.
+pub fn get_live_positions(e: &Env) -> u32 {
+    e.storage()
+        .instance()
+        .get(&TradingStorageKey::LivePositions)
+        .unwrap_or(0)
+}
+
+pub fn set_live_positions(e: &Env, count: u32) {
+    e.storage()
+        .instance()
+        .set(&TradingStorageKey::LivePositions, &count);
+}
+
 pub fn get_total_notional(e: &Env) -> i128 {
     e.storage()
         .instance()
@@ -166,6 +342,19 @@ pub fn set_total_notional(e: &Env, total: i128) {
         .set(&TradingStorageKey::TotalNotional, &total);
 }
 
+pub fn get_total_fees(e: &Env) -> i128 {
+    e.storage()
+        .instance()
+        .get(&TradingStorageKey::TotalFees)
+        .unwrap_or(0)
+}
+
+pub fn set_total_fees(e: &Env, total: i128) {
+    e.storage()
+        .instance()
+        .set(&TradingStorageKey::TotalFees, &total);
+}
+
 pub fn get_last_funding_update(e: &Env) -> u64 {
     e.storage()
         .instance()
@@ -259,28 +448,90 @@ pub fn remove_market_data(e: &Env, market_id: u32) {
     e.storage().persistent().remove(&key);
 }
 
+pub fn get_price_exponent(e: &Env, market_id: u32) -> Option<i32> {
+    let key = TradingStorageKey::PriceExponent(market_id);
+    let result = e.storage().persistent().get(&key);
+    if result.is_some() {
+        e.storage()
+            .persistent()
+            .extend_ttl(&key, LEDGER_THRESHOLD_MARKET, LEDGER_BUMP_MARKET);
+    }
+    result
+}
+
+pub fn set_price_exponent(e: &Env, market_id: u32, exponent: i32) {
+    let key = TradingStorageKey::PriceExponent(market_id);
+    e.storage().persistent().set(&key, &exponent);
+    e.storage()
+        .persistent()
+        .extend_ttl(&key, LEDGER_THRESHOLD_MARKET, LEDGER_BUMP_MARKET);
+}
+
+pub fn remove_price_exponent(e: &Env, market_id: u32) {
+    let key = TradingStorageKey::PriceExponent(market_id);
+    e.storage().persistent().remove(&key);
+}
+
+/// Price (in the market's pinned units) the market's state was last stored at,
+/// `None` before its first state-changing action.
+pub fn get_last_price(e: &Env, market_id: u32) -> Option<i128> {
+    e.storage()
+        .persistent()
+        .get(&TradingStorageKey::LastPrice(market_id))
+}
+
+pub fn set_last_price(e: &Env, market_id: u32, price: i128) {
+    let key = TradingStorageKey::LastPrice(market_id);
+    e.storage().persistent().set(&key, &price);
+    e.storage()
+        .persistent()
+        .extend_ttl(&key, LEDGER_THRESHOLD_MARKET, LEDGER_BUMP_MARKET);
+}
+
+pub fn remove_last_price(e: &Env, market_id: u32) {
+    e.storage().persistent().remove(&TradingStorageKey::LastPrice(market_id));
+}
+
 pub fn get_position(e: &Env, user: &Address, id: u32) -> Position {
     let key = TradingStorageKey::Position(user.clone(), id);
-    let result = e
+    let raw: Val = e
         .storage()
         .persistent()
         .get(&key)
         .unwrap_or_else(|| panic_with_error!(e, TradingError::PositionNotFound));
+    let result = legacy::decode_position(e, raw);
+    extend_position(e, &key, &result);
+    result
+}
+
+/// Read a position without extending its TTL; `None` if it does not exist.
+pub fn try_get_position(e: &Env, user: &Address, id: u32) -> Option<Position> {
     e.storage()
         .persistent()
-        .extend_ttl(&key, LEDGER_THRESHOLD_POSITION, LEDGER_BUMP_POSITION);
-    result
+        .get::<_, Val>(&TradingStorageKey::Position(user.clone(), id))
+        .map(|raw| legacy::decode_position(e, raw))
 }
 
+/// Store a position, stamping `touched_at` with the current time.
 pub fn set_position(e: &Env, user: &Address, id: u32, position: &Position) {
     let key = TradingStorageKey::Position(user.clone(), id);
-    e.storage().persistent().set(&key, position);
-    e.storage()
-        .persistent()
-        .extend_ttl(&key, LEDGER_THRESHOLD_POSITION, LEDGER_BUMP_POSITION);
+    let mut stored = position.clone();
+    stored.touched_at = e.ledger().timestamp();
+    e.storage().persistent().set(&key, &stored);
+    extend_position(e, &key, &stored);
+}
+
+fn extend_position(e: &Env, key: &TradingStorageKey, position: &Position) {
+    let (threshold, bump) = if position.filled {
+        (LEDGER_THRESHOLD_POSITION_OPEN, LEDGER_BUMP_POSITION_OPEN)
+    } else {
+        (LEDGER_THRESHOLD_POSITION, LEDGER_BUMP_POSITION)
+    };
+    e.storage().persistent().extend_ttl(key, threshold, bump);
 }
 
 pub fn remove_position(e: &Env, user: &Address, id: u32) {
     let key = TradingStorageKey::Position(user.clone(), id);
     e.storage().persistent().remove(&key);
+    set_live_positions(e, get_live_positions(e).saturating_sub(1));
 }
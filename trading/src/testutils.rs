@@ -126,6 +126,30 @@ impl MockVault {
         soroban_sdk::token::TokenClient::new(&e, &token)
             .transfer(&e.current_contract_address(), &strategy, &amount);
     }
+
+    pub fn set_reward_router(e: Env, strategy: Address, reward: Address, router: Address) {
+        strategy.require_auth();
+        e.storage().instance().set(&(soroban_sdk::Symbol::new(&e, "router"), reward), &router);
+    }
+
+    /// Echoes `min_out` once `reward` has a router, so callers see what was forwarded.
+    pub fn compound(e: Env, strategy: Address, reward: Address, min_out: i128) -> i128 {
+        strategy.require_auth();
+        let key = (soroban_sdk::Symbol::new(&e, "router"), reward);
+        if !e.storage().instance().has(&key) {
+            panic!("reward not registered");
+        }
+        min_out
+    }
+
+    /// Set the share balance reported for `account`.
+    pub fn set_shares(e: Env, account: Address, amount: i128) {
+        e.storage().instance().set(&account, &amount);
+    }
+
+    pub fn balance(e: Env, account: Address) -> i128 {
+        e.storage().instance().get(&account).unwrap_or(0)
+    }
 }
 
 //************************************************
@@ -213,18 +237,41 @@ pub fn default_config() -> TradingConfig {
         r_funding: 10_000_000_000_000,             // 0.001% per hour in SCALAR_18
         r_base: 10_000_000_000_000,                // 0.001% per hour in SCALAR_18
         r_var: 10_000_000_000_000,                 // 0.001%/hr vault variable rate (SCALAR_18)
+        fee_free_wind_down: false,
+        max_pending: 0,
+        fill_bonus: 0,
+        liq_caller_cap: 0,
+        referral_share: 0,
+        max_total_positions: 0,
+        fill_bounty: 0,
+        keeper_min_shares: 0,
     }
 }
 
-pub fn default_market(_e: &Env) -> MarketConfig {
+pub fn default_market(e: &Env) -> MarketConfig {
     MarketConfig {
         feed_id: FEED_BTC,
+        basket: Vec::new(e),
         enabled: true,
+        allow_long: true,
+        allow_short: true,
+        open_paused: false,
         max_util: 5 * SCALAR_7,                           // 5x vault per market
         r_var_market: 10_000_000_000_000,           // 0.001%/hr per-market variable rate (SCALAR_18)
         margin: 100_000,                           // 1%
         liq_fee: 50_000,                           // 0.5%
         impact: 8_000_000_000 * SCALAR_7,
+        min_notional: 0,
+        max_user_notional: 0,
+        liq_grace: 0,
+        liq_price_age: 0,
+        max_duration: 0,
+        tiers: Vec::new(e),
+        min_modify_interval: 0,
+        fee_tiers: Vec::new(e),
+        fee_recipient: None,
+        fee_share: 0,
+        min_side: 0,
     }
 }
 
@@ -291,7 +338,7 @@ pub fn setup_contract(e: &Env) -> (Address, StellarAssetClient<'_>) {
 
         storage::set_last_funding_update(e, e.ledger().timestamp());
         let mut data = storage::get_market_data(e, FEED_BTC);
-        data.update_funding_rate(e, config.r_funding);
+        data.update_funding_rate(e, config.r_funding, 0);
         storage::set_market_data(e, FEED_BTC, &data);
     });
 
@@ -300,6 +347,33 @@ pub fn setup_contract(e: &Env) -> (Address, StellarAssetClient<'_>) {
     (contract, token_client)
 }
 
+/// Apply `f` to the stored trading config, skipping `set_config` validation.
+pub fn update_config(e: &Env, contract: &Address, f: impl FnOnce(&mut TradingConfig)) {
+    e.as_contract(contract, || {
+        let mut config = storage::get_config(e);
+        f(&mut config);
+        storage::set_config(e, &config);
+    });
+}
+
+/// Apply `f` to the stored config of `market_id`, skipping `set_market`
+/// validation. A market not yet stored starts from `default_market` on its own
+/// feed with fresh market data.
+pub fn update_market(e: &Env, contract: &Address, market_id: u32, f: impl FnOnce(&mut MarketConfig)) {
+    e.as_contract(contract, || {
+        let mut config = if storage::has_market(e, market_id) {
+            storage::get_market_config(e, market_id)
+        } else {
+            let mut data = default_market_data();
+            data.last_update = e.ledger().timestamp();
+            storage::set_market_data(e, market_id, &data);
+            MarketConfig { feed_id: market_id, ..default_market(e) }
+        };
+        f(&mut config);
+        storage::set_market_config(e, market_id, &config);
+    });
+}
+
 //************************************************
 //           Fuzz / Property Test Wrappers
 //************************************************
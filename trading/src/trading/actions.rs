@@ -1,20 +1,38 @@
-use crate::constants::{ONE_HOUR_SECONDS, SCALAR_7};
+use crate::constants::{BUMP_FEE_RATE, BUMP_MIN_IDLE, MAX_PAGE, ONE_HOUR_SECONDS, SCALAR_7};
 use crate::dependencies::VaultClient;
 use crate::errors::TradingError;
-use crate::events::{ApplyFunding, ClosePosition, ModifyCollateral, OpenMarket, PlaceLimit, RefundPosition, SetTriggers};
+use crate::events::{ApplyFunding, ClosePosition, FundingSnapshot, ModifyCollateral, OpenMarket, PlaceLimit, RefundPosition, SetAutoTrigger, SetTriggers};
 use crate::storage;
 use crate::trading::context::Context;
-use crate::trading::position::Position;
-use crate::dependencies::PriceData;
-use crate::validation::{require_active, require_can_manage};
+use crate::trading::position::{Position, Settlement};
+use crate::trading::price::verify_market_price;
+use crate::dependencies::{scalar_from_exponent, PriceData};
+use crate::types::CloseQuote;
+use crate::validation::{require_active, require_can_manage, require_migrated, require_position_slot};
 use soroban_fixed_point_math::SorobanFixedPoint;
 use soroban_sdk::token::TokenClient;
-use soroban_sdk::{panic_with_error, Address, Env};
+use soroban_sdk::{panic_with_error, Address, Env, Vec};
 
 /// Create a pending limit order. Validates parameters, stores position, transfers collateral.
 ///
 /// The order is not filled immediately, a keeper calls `execute` with the position ID
 /// when the market price reaches `entry_price`.
+///
+/// `max_dev` bounds how far past `entry_price` a gapped fill may land (SCALAR_7
+/// fraction of `entry_price`, 0 = unbounded). Beyond it the fill either happens at
+/// `entry_price` (`at_trigger`) or reverts, leaving the order pending.
+///
+/// # Panics
+/// - `TradingError::NegativeValueNotAllowed` (723) if `max_dev` is negative
+/// - `TradingError::InvalidInput` (734) if `max_dev` exceeds 100%, or TP/SL sit on
+///   the wrong side of `entry_price`
+/// - `TradingError::TooManyPendingOrders` (738) if the user already holds
+///   `max_pending` orders on the market
+/// - `TradingError::DirectionDisabled` (706) if the market takes no new positions
+///   on that side
+/// - `TradingError::OpensPaused` (707) if the market has new opens paused
+/// - `TradingError::ProtocolPositionCapReached` (730) if `max_total_positions`
+///   positions are already live
 #[allow(clippy::too_many_arguments)]
 pub fn execute_create_limit(
     e: &Env,
@@ -26,37 +44,72 @@ pub fn execute_create_limit(
     entry_price: i128,
     take_profit: i128,
     stop_loss: i128,
+    max_dev: i128,
+    at_trigger: bool,
+    expiry: u64,
 ) -> u32 {
     require_active(e);
     user.require_auth();
 
+    if max_dev < 0 {
+        panic_with_error!(e, TradingError::NegativeValueNotAllowed);
+    }
+    if max_dev > SCALAR_7 || (expiry != 0 && expiry <= e.ledger().timestamp()) {
+        panic_with_error!(e, TradingError::InvalidInput);
+    }
+
     let config = storage::get_config(e);
+    require_position_slot(e, &config);
+    let pending = storage::get_user_pending(e, user, market_id);
+    if config.max_pending > 0 && pending >= config.max_pending {
+        panic_with_error!(e, TradingError::TooManyPendingOrders);
+    }
+    storage::set_user_pending(e, user, market_id, pending + 1);
+
     let market_config = storage::get_market_config(e, market_id);
-    let (id, position) = Position::create(e, user, market_id, is_long, entry_price, collateral, notional_size, stop_loss, take_profit);
-    position.validate(e, market_config.enabled, config.min_notional, config.max_notional, market_config.margin);
+    market_config.require_opens(e);
+    let (id, mut position) = Position::create(e, user, market_id, is_long, entry_price, collateral, notional_size, stop_loss, take_profit);
+    position.max_dev = max_dev;
+    position.at_trigger = at_trigger;
+    position.expiry = expiry;
+    position.bounty = config.fill_bounty;
+    position.require_valid_brackets(e);
+    position.validate(
+        e,
+        market_config.enabled,
+        market_config.notional_floor(config.min_notional),
+        config.max_notional,
+        market_config.margin_for(notional_size),
+    );
+    market_config.require_direction(e, is_long);
+    position.next_seq();
     storage::set_position(e, user, id, &position);
 
     let token_client = TokenClient::new(e, &storage::get_token(e));
-    token_client.transfer(user, e.current_contract_address(), &collateral);
+    token_client.transfer(user, e.current_contract_address(), &(collateral + position.bounty));
+    storage::add_bounty_escrow(e, position.bounty);
 
     PlaceLimit {
         market_id,
         user: user.clone(),
         position_id: id,
+        event_seq: position.event_seq,
     }
     .publish(e);
 
     id
 }
 
-/// Cancel a position and refund collateral. No settlement or fees applied.
+/// Cancel a position and refund collateral, plus a pending order's fill bounty.
+/// No settlement or fees applied.
 ///
 /// - **Pending** (not filled): requires user auth, cancels the limit order.
 /// - **Filled + market deleted**: permissionless (anyone can clean up stranded positions).
 /// - **Filled + market exists**: panics (use `close_position` for settlement).
 pub fn execute_cancel_position(e: &Env, user: &Address, id: u32) -> i128 {
     require_can_manage(e);
-    let position = storage::get_position(e, user, id);
+    let mut position = storage::get_position(e, user, id);
+    position.require_unfrozen(e);
 
     if position.filled {
         // Filled positions can only be cancelled if the market was deleted
@@ -64,11 +117,14 @@ pub fn execute_cancel_position(e: &Env, user: &Address, id: u32) -> i128 {
             panic_with_error!(e, TradingError::PositionNotPending);
         }
         // Permissionless: anyone can clean up stranded positions on deleted markets
+        storage::add_user_notional(e, user, position.market_id, -position.notional);
     } else {
         user.require_auth();
+        storage::release_pending(e, user, position.market_id);
     }
 
-    let payout = position.col;
+    storage::add_bounty_escrow(e, -position.bounty);
+    let payout = position.col + position.bounty;
     if payout > 0 {
         let token_client = TokenClient::new(e, &storage::get_token(e));
         token_client.transfer(&e.current_contract_address(), user, &payout);
@@ -80,6 +136,57 @@ pub fn execute_cancel_position(e: &Env, user: &Address, id: u32) -> i128 {
         market_id: position.market_id,
         user: user.clone(),
         position_id: id,
+        event_seq: position.next_seq(),
+        amount: payout,
+    }
+    .publish(e);
+
+    payout
+}
+
+/// Cancel another user's expired pending order. Permissionless once the order's
+/// `expiry` is reached; orders without one never expire.
+///
+/// The caller earns the keeper share (`caller_rate`) of the base fee the order would
+/// have paid on fill, taken from the collateral. The remainder and the order's fill
+/// bounty are refunded to the owner.
+pub fn execute_cancel_expired(e: &Env, caller: &Address, user: &Address, id: u32) -> i128 {
+    require_can_manage(e);
+    let mut position = storage::get_position(e, user, id);
+    position.require_unfrozen(e);
+
+    if position.filled {
+        panic_with_error!(e, TradingError::ActionNotAllowedForStatus);
+    }
+    if !position.is_expired(e) {
+        panic_with_error!(e, TradingError::OrderNotExpired);
+    }
+    storage::release_pending(e, user, position.market_id);
+
+    let config = storage::get_config(e);
+    let caller_fee = position
+        .notional
+        .fixed_mul_floor(e, &config.fee_non_dom, &SCALAR_7)
+        .fixed_mul_floor(e, &config.caller_rate, &SCALAR_7)
+        .min(position.col);
+    let payout = position.col - caller_fee + position.bounty;
+    storage::add_bounty_escrow(e, -position.bounty);
+
+    let token_client = TokenClient::new(e, &storage::get_token(e));
+    if caller_fee > 0 {
+        token_client.transfer(&e.current_contract_address(), caller, &caller_fee);
+    }
+    if payout > 0 {
+        token_client.transfer(&e.current_contract_address(), user, &payout);
+    }
+
+    storage::remove_position(e, user, id);
+
+    RefundPosition {
+        market_id: position.market_id,
+        user: user.clone(),
+        position_id: id,
+        event_seq: position.next_seq(),
         amount: payout,
     }
     .publish(e);
@@ -106,10 +213,34 @@ pub fn execute_create_market(
     stop_loss: i128,
     price_data: &PriceData,
 ) -> u32 {
+    execute_create_market_position(
+        e, user, market_id, collateral, notional_size, is_long, take_profit, stop_loss, price_data,
+    )
+    .0
+}
+
+/// Open a market order and return the filled position alongside its ID.
+///
+/// Same as `execute_create_market`, but callers get the fill price and index
+/// snapshots without a follow-up read.
+#[allow(clippy::too_many_arguments)]
+pub fn execute_create_market_position(
+    e: &Env,
+    user: &Address,
+    market_id: u32,
+    collateral: i128,
+    notional_size: i128,
+    is_long: bool,
+    take_profit: i128,
+    stop_loss: i128,
+    price_data: &PriceData,
+) -> (u32, Position) {
     require_active(e);
     user.require_auth();
 
     let mut ctx = Context::load(e, market_id, price_data);
+    ctx.config.require_opens(e);
+    require_position_slot(e, &ctx.trading_config);
 
     let (id, mut position) = Position::create(e, user, market_id, is_long, ctx.price, collateral, notional_size, stop_loss, take_profit);
     let (base_fee, impact_fee) = ctx.open(e, &mut position, user, id);
@@ -117,7 +248,10 @@ pub fn execute_create_market(
 
     let total_fee = base_fee + impact_fee;
     let treasury_fee = ctx.treasury_fee(e, total_fee);
-    let vault_fee = total_fee - treasury_fee;
+    let partner_fee = ctx.partner_fee(e, total_fee);
+    // the referrer's cut stays in this contract until claimed
+    let referral_fee = ctx.referral_fee(e, user, total_fee);
+    let vault_fee = total_fee - treasury_fee - partner_fee - referral_fee;
 
     let token_client = TokenClient::new(e, &ctx.token);
     token_client.transfer(user, e.current_contract_address(), &collateral);
@@ -127,17 +261,66 @@ pub fn execute_create_market(
     if treasury_fee > 0 {
         token_client.transfer(&e.current_contract_address(), &ctx.treasury, &treasury_fee);
     }
+    pay_partner(e, &ctx, &token_client, partner_fee);
 
     OpenMarket {
         market_id: ctx.market_id,
         user: user.clone(),
         position_id: id,
+        event_seq: position.event_seq,
         base_fee,
         impact_fee,
     }
     .publish(e);
 
-    id
+    (id, position)
+}
+
+/// Transfer the market partner's fee cut, if the market has a fee recipient.
+fn pay_partner(e: &Env, ctx: &Context, token_client: &TokenClient, amount: i128) {
+    if let Some(recipient) = &ctx.config.fee_recipient {
+        if amount > 0 {
+            token_client.transfer(&e.current_contract_address(), recipient, &amount);
+        }
+    }
+}
+
+/// Extend the storage TTL of an idle filled position so it isn't archived.
+///
+/// Permissionless, but only once nothing has stored the position for
+/// `BUMP_MIN_IDLE`: every write already extends the TTL. The bump is itself a
+/// write, so a position pays at most once per idle period.
+///
+/// The caller earns BUMP_FEE_RATE of the collateral, under the same floors as a
+/// withdrawal: if paying it would leave collateral or equity below initial
+/// margin, the bump is free.
+pub fn execute_bump_position(e: &Env, caller: &Address, user: &Address, id: u32, price_data: &PriceData) -> i128 {
+    require_can_manage(e);
+    let mut position = storage::get_position(e, user, id);
+    position.require_unfrozen(e);
+    if !position.filled {
+        panic_with_error!(e, TradingError::ActionNotAllowedForStatus);
+    }
+    if e.ledger().timestamp() < position.touched_at.saturating_add(BUMP_MIN_IDLE) {
+        panic_with_error!(e, TradingError::NotActionable);
+    }
+
+    let ctx = Context::load(e, position.market_id, price_data);
+    let mut fee = position.col.fixed_mul_floor(e, &BUMP_FEE_RATE, &SCALAR_7);
+    let floor = position.notional.fixed_mul_ceil(e, &ctx.config.margin_for(position.notional), &SCALAR_7);
+    let equity = position.clone().settle(e, &ctx).equity(position.col - fee);
+    if position.col - fee < floor || equity < floor {
+        fee = 0;
+    }
+    ctx.store(e);
+
+    position.col -= fee;
+    storage::set_position(e, user, id, &position);
+    if fee > 0 {
+        TokenClient::new(e, &ctx.token).transfer(&e.current_contract_address(), caller, &fee);
+    }
+
+    fee
 }
 
 /// Close a filled position at the current oracle price with full settlement.
@@ -148,21 +331,30 @@ pub fn execute_create_market(
 /// # Returns
 /// User payout amount (token_decimals), >= 0.
 pub fn execute_close_position(e: &Env, user: &Address, id: u32, price: soroban_sdk::Bytes) -> i128 {
-    require_can_manage(e);
-    let pv = crate::dependencies::PriceVerifierClient::new(e, &storage::get_price_verifier(e));
-    let price_data = pv.verify_price(&price);
+    execute_close_position_to(e, user, id, user, price)
+}
 
+/// Close a filled position and send the payout to `receiver` instead of the owner.
+///
+/// Requires the owner's auth, so only the owner can redirect the payout.
+pub fn execute_close_position_to(
+    e: &Env,
+    user: &Address,
+    id: u32,
+    receiver: &Address,
+    price: soroban_sdk::Bytes,
+) -> i128 {
+    require_can_manage(e);
     let mut position = storage::get_position(e, user, id);
+    position.require_unfrozen(e);
     user.require_auth();
     position.require_closable(e);
+    let price_data = verify_market_price(e, position.market_id, &price);
 
     let mut ctx = Context::load(e, position.market_id, &price_data);
     let col = position.col;
-    let s = ctx.close(e, &mut position, user, id);
-
-    let user_payout = s.equity(col).max(0);
-    let treasury_fee = ctx.treasury_fee(e, s.protocol_fee());
-    let vault_transfer = col - user_payout - treasury_fee;
+    let s = ctx.close(e, &mut position, user, id, true);
+    let (user_payout, treasury_fee, partner_fee, _, vault_transfer) = close_split(e, &ctx, user, &s, col);
 
     let token_client = TokenClient::new(e, &ctx.token);
     if vault_transfer < 0 {
@@ -174,8 +366,9 @@ pub fn execute_close_position(e: &Env, user: &Address, id: u32, price: soroban_s
     if treasury_fee > 0 {
         token_client.transfer(&e.current_contract_address(), &ctx.treasury, &treasury_fee);
     }
+    pay_partner(e, &ctx, &token_client, partner_fee);
     if user_payout > 0 {
-        token_client.transfer(&e.current_contract_address(), user, &user_payout);
+        token_client.transfer(&e.current_contract_address(), receiver, &user_payout);
     }
 
     ctx.store(e);
@@ -184,6 +377,7 @@ pub fn execute_close_position(e: &Env, user: &Address, id: u32, price: soroban_s
         market_id: position.market_id,
         user: user.clone(),
         position_id: id,
+        event_seq: position.next_seq(),
         price: ctx.price,
         pnl: s.net_pnl(col),
         base_fee: s.base_fee,
@@ -196,14 +390,151 @@ pub fn execute_close_position(e: &Env, user: &Address, id: u32, price: soroban_s
     user_payout
 }
 
+/// Token split of a user close:
+/// `(user_payout, treasury_fee, partner_fee, referral_fee, vault_transfer)`.
+/// `vault_transfer` is negative when the vault funds the payout; `referral_fee`
+/// stays in this contract.
+fn close_split(e: &Env, ctx: &Context, user: &Address, s: &Settlement, col: i128) -> (i128, i128, i128, i128, i128) {
+    let user_payout = s.equity(col).max(0);
+    let treasury_fee = ctx.treasury_fee(e, s.collectible(col, s.protocol_fee()));
+    let partner_fee = ctx.partner_fee(e, s.collectible(col, s.trading_fee()));
+    let referral_fee = ctx.referral_fee(e, user, s.collectible(col, s.trading_fee()));
+    let vault_transfer = col - user_payout - treasury_fee - partner_fee - referral_fee;
+    (user_payout, treasury_fee, partner_fee, referral_fee, vault_transfer)
+}
+
+/// Preview closing a filled position at the given price: what
+/// [`execute_close_position`] would settle and transfer. Read-only.
+///
+/// Ignores `MIN_OPEN_TIME`, so a position can be quoted before it may close.
+///
+/// # Panics
+/// - `TradingError::ActionNotAllowedForStatus` (733) if the position is pending
+pub fn execute_quote_close(e: &Env, user: &Address, id: u32, price: soroban_sdk::Bytes) -> CloseQuote {
+    let position = storage::get_position(e, user, id);
+    if !position.filled {
+        panic_with_error!(e, TradingError::ActionNotAllowedForStatus);
+    }
+    let price_data = verify_market_price(e, position.market_id, &price);
+    let ctx = Context::load(e, position.market_id, &price_data);
+
+    let col = position.col;
+    let s = ctx.settle(e, &mut position.clone(), user, true);
+    let (user_payout, treasury_fee, partner_fee, referral_fee, vault_transfer) = close_split(e, &ctx, user, &s, col);
+    CloseQuote {
+        pnl: s.net_pnl(col),
+        trading_fee: s.trading_fee(),
+        funding: s.funding,
+        borrowing_fee: s.borrowing_fee,
+        user_payout,
+        vault_transfer,
+        treasury_fee,
+        partner_fee,
+        referral_fee,
+    }
+}
+
+/// Health factor of a filled position: equity / liquidation threshold (SCALAR_7).
+///
+/// Uses the same settlement preview and threshold as keeper liquidation, so a
+/// value below `SCALAR_7` (1.0) means the position is liquidatable. Returns
+/// `i128::MAX` when the threshold rounds to zero. Read-only.
+///
+/// # Panics
+/// - `TradingError::ActionNotAllowedForStatus` (733) if the position is pending
+pub fn execute_health_factor(e: &Env, user: &Address, id: u32, price: soroban_sdk::Bytes) -> i128 {
+    let position = storage::get_position(e, user, id);
+    if !position.filled {
+        panic_with_error!(e, TradingError::ActionNotAllowedForStatus);
+    }
+    let price_data = verify_market_price(e, position.market_id, &price);
+    let ctx = Context::load(e, position.market_id, &price_data);
+    position.health_factor(e, &ctx)
+}
+
+/// Current utilization of a market against the vault (SCALAR_7). Read-only.
+///
+/// Opens are rejected once this would exceed the market's `max_util`. Uses the
+/// stored notionals, so it reflects the last position action on the market.
+///
+/// # Panics
+/// - `TradingError::MarketNotFound` (701) if the market does not exist
+pub fn execute_market_utilization(e: &Env, market_id: u32) -> i128 {
+    let data = storage::get_market_data(e, market_id);
+    let vault_balance = VaultClient::new(e, &storage::get_vault(e)).total_assets();
+    data.utilization(e, vault_balance)
+}
+
+/// PnL-equivalent (notional-weighted harmonic mean) entry price of each side of a
+/// market, as `(long, short)` in the market's pinned price units, 0 for an empty
+/// side. Read-only.
+///
+/// Derived from the entry-weight aggregates, so together with the side's notional
+/// it gives the side's unrealized PnL at any price in one read.
+///
+/// # Panics
+/// - `TradingError::MarketNotFound` (701) if the market does not exist
+pub fn execute_avg_entry_prices(e: &Env, market_id: u32) -> (i128, i128) {
+    let data = storage::get_market_data(e, market_id);
+    // No price pinned yet: nothing has opened on the market
+    let Some(exponent) = storage::get_price_exponent(e, market_id) else { return (0, 0) };
+    let price_scalar = scalar_from_exponent(exponent);
+    (data.avg_entry_price(e, true, price_scalar), data.avg_entry_price(e, false, price_scalar))
+}
+
+/// Page through every position id the user has ever created. Read-only.
+///
+/// Ids are assigned sequentially from the user's counter and never reused, so
+/// `0..get_user_counter` is the user's full history. Each entry is paired with
+/// whether the position is still stored (open or pending); closed, cancelled
+/// and liquidated positions report `false`. At most `MAX_PAGE` entries are
+/// returned, starting at id `start`.
+pub fn execute_user_positions(e: &Env, user: &Address, start: u32, limit: u32) -> Vec<(u32, bool)> {
+    let end = storage::get_user_counter(e, user).min(start.saturating_add(limit.min(MAX_PAGE)));
+    let mut page = Vec::new(e);
+    for id in start..end {
+        page.push_back((id, storage::try_get_position(e, user, id).is_some()));
+    }
+    page
+}
+
+/// Read a watchlist of positions in one call. Read-only.
+///
+/// Entries are `(users[i], ids[i])` pairs; closed, cancelled or never-created
+/// positions come back as `None` in their slot, so the result stays parallel
+/// with the input.
+///
+/// # Panics
+/// - `TradingError::InvalidInput` (734) if `users` and `ids` lengths differ or
+///   exceed `MAX_PAGE`
+pub fn execute_get_positions(e: &Env, users: &Vec<Address>, ids: &Vec<u32>) -> Vec<Option<Position>> {
+    if users.len() != ids.len() || ids.len() > MAX_PAGE {
+        panic_with_error!(e, TradingError::InvalidInput);
+    }
+    let mut positions = Vec::new(e);
+    for (user, id) in users.iter().zip(ids.iter()) {
+        positions.push_back(storage::try_get_position(e, &user, id));
+    }
+    positions
+}
+
 /// Add or withdraw collateral on an open (filled) position.
 ///
 /// For withdrawals, a margin check is performed: the position's equity after
 /// settlement must remain above `notional * margin`. This prevents users from
 /// extracting collateral to a point where the position would be immediately liquidatable.
+///
+/// Collateral itself may not drop below `notional * margin` either (the same floor
+/// `validate` enforces on open), so unrealized profit cannot be used to shrink a
+/// position's collateral to dust.
+///
+/// Markets with a `min_modify_interval` rate-limit modifications per position:
+/// a second modification within the interval of the previous one is rejected.
+/// Closes and keeper actions are not affected.
 pub fn execute_modify_collateral(e: &Env, user: &Address, id: u32, new_collateral: i128, price_data: &PriceData) {
     require_can_manage(e);
     let mut position = storage::get_position(e, user, id);
+    position.require_unfrozen(e);
     user.require_auth();
 
     if !position.filled {
@@ -214,29 +545,57 @@ pub fn execute_modify_collateral(e: &Env, user: &Address, id: u32, new_collatera
     if collateral_diff == 0 {
         panic_with_error!(e, TradingError::CollateralUnchanged);
     }
+
+    let now = e.ledger().timestamp();
+    let config = storage::get_market_config(e, position.market_id);
+    if position.last_modified_at > 0 && now < position.last_modified_at + config.min_modify_interval {
+        panic_with_error!(e, TradingError::ModifyTooSoon);
+    }
     position.col = new_collateral;
+    position.last_modified_at = now;
 
     if collateral_diff > 0 {
         let token_client = TokenClient::new(e, &storage::get_token(e));
         token_client.transfer(user, e.current_contract_address(), &collateral_diff);
+        // A top-up that lifts the position back over the threshold ends its breach
+        if position.breach_at != 0 {
+            let ctx = Context::load(e, position.market_id, price_data);
+            let equity = position.clone().settle(e, &ctx).equity(position.col);
+            if equity >= position.liq_threshold(e, ctx.config.liq_fee) {
+                position.breach_at = 0;
+            }
+            ctx.store(e);
+        }
     } else {
+        let margin = config.margin_for(position.notional);
+        if new_collateral < position.notional.fixed_mul_ceil(e, &margin, &SCALAR_7) {
+            panic_with_error!(e, TradingError::BelowMinCollateral);
+        }
+
         let ctx = Context::load(e, position.market_id, price_data);
         let token_client = TokenClient::new(e, &ctx.token);
+        let tracked = position.notional;
         let s = position.settle(e, &ctx);
+        // Settlement stores any ADL reduction, so release it from the user's total
+        storage::add_user_notional(e, user, position.market_id, position.notional - tracked);
         let equity = position.col + s.pnl - s.total_fee();
-        if equity < position.notional.fixed_mul_ceil(e, &ctx.config.margin, &SCALAR_7) {
+        if equity < position.notional.fixed_mul_ceil(e, &ctx.config.margin_for(position.notional), &SCALAR_7) {
             panic_with_error!(e, TradingError::WithdrawalBreaksMargin);
         }
+        // Above initial margin, so above the liquidation threshold
+        position.breach_at = 0;
 
         ctx.store(e);
         token_client.transfer(&e.current_contract_address(), user, &-collateral_diff);
     }
 
+    position.next_seq();
     storage::set_position(e, user, id, &position);
     ModifyCollateral {
         market_id: position.market_id,
         user: user.clone(),
         position_id: id,
+        event_seq: position.event_seq,
         amount: collateral_diff,
     }
     .publish(e);
@@ -244,36 +603,116 @@ pub fn execute_modify_collateral(e: &Env, user: &Address, id: u32, new_collatera
 
 /// Update take-profit and stop-loss trigger prices on a position.
 ///
-/// Set to 0 to clear a trigger. TP/SL are pure price triggers — no
-/// entry-price validation. Invalid values simply never fire.
+/// Set to 0 to clear a trigger. On a filled position TP/SL are pure price
+/// triggers — no entry-price validation. Invalid values simply never fire.
+///
+/// On a pending order they are brackets armed at fill, so they must sit on the
+/// profit and loss side of the order's `entry_price`.
+///
+/// # Panics
+/// - `TradingError::InvalidInput` (734) if a pending order's TP/SL would fire at its entry
 pub fn execute_set_triggers(e: &Env, user: &Address, id: u32, take_profit: i128, stop_loss: i128) {
     require_can_manage(e);
     let mut position = storage::get_position(e, user, id);
+    position.require_unfrozen(e);
     user.require_auth();
 
     position.tp = take_profit;
     position.sl = stop_loss;
+    if !position.filled {
+        position.require_valid_brackets(e);
+    }
+    position.next_seq();
     storage::set_position(e, user, id, &position);
 
     SetTriggers {
         market_id: position.market_id,
         user: user.clone(),
         position_id: id,
+        event_seq: position.event_seq,
         take_profit,
         stop_loss,
     }
     .publish(e);
 }
 
+/// Let keepers execute the position's TP/SL triggers (the default), or keep them
+/// for the user alone. With `enabled = false` a trigger still fires, but only from
+/// a keeper batch the user submits as `caller`; liquidation and expiry are unaffected.
+pub fn execute_set_auto_trigger(e: &Env, user: &Address, id: u32, enabled: bool) {
+    require_can_manage(e);
+    let mut position = storage::get_position(e, user, id);
+    position.require_unfrozen(e);
+    user.require_auth();
+
+    position.auto_trigger = enabled;
+    position.next_seq();
+    storage::set_position(e, user, id, &position);
+
+    SetAutoTrigger {
+        market_id: position.market_id,
+        user: user.clone(),
+        position_id: id,
+        event_seq: position.event_seq,
+        enabled,
+    }
+    .publish(e);
+}
+
+/// Best-effort variant of [`execute_set_triggers`]: each trigger is applied on its
+/// own, and one that a pending order's brackets reject keeps its previous value
+/// instead of reverting the call.
+///
+/// # Returns
+/// `(take_profit, stop_loss)` result codes: `0` if applied, otherwise the
+/// `TradingError` code [`execute_set_triggers`] would have reverted with.
+pub fn execute_set_triggers_each(e: &Env, user: &Address, id: u32, take_profit: i128, stop_loss: i128) -> (u32, u32) {
+    require_can_manage(e);
+    let mut position = storage::get_position(e, user, id);
+    position.require_unfrozen(e);
+    user.require_auth();
+
+    let (prev_tp, prev_sl) = (position.tp, position.sl);
+    position.tp = take_profit;
+    position.sl = stop_loss;
+    let (tp_err, sl_err) = if position.filled { (None, None) } else { position.bracket_rejections() };
+    if tp_err.is_some() {
+        position.tp = prev_tp;
+    }
+    if sl_err.is_some() {
+        position.sl = prev_sl;
+    }
+    let (tp_code, sl_code) = (tp_err.map_or(0, |c| c as u32), sl_err.map_or(0, |c| c as u32));
+    if tp_code != 0 && sl_code != 0 {
+        return (tp_code, sl_code);
+    }
+
+    position.next_seq();
+    storage::set_position(e, user, id, &position);
+    SetTriggers {
+        market_id: position.market_id,
+        user: user.clone(),
+        position_id: id,
+        event_seq: position.event_seq,
+        take_profit: position.tp,
+        stop_loss: position.sl,
+    }
+    .publish(e);
+
+    (tp_code, sl_code)
+}
+
 /// Apply funding rate updates across all markets. Permissionless, callable once per hour.
 ///
 /// For each market: accrues borrowing + funding indices, then recalculates the
 /// funding rate based on current OI imbalance. The new rate takes effect for the
-/// next accrual period.
+/// next accrual period. A `FundingSnapshot` event is emitted per market.
 ///
 /// # Panics
+/// - `TradingError::MigrationPending` (743) if `migrate` has not run
 /// - `TradingError::FundingTooEarly` (752) if < 1 hour since last call
 pub fn execute_apply_funding(e: &Env) {
+    require_migrated(e);
     let last_funding_update = storage::get_last_funding_update(e);
     let elapsed = e.ledger().timestamp() - last_funding_update;
     if elapsed < ONE_HOUR_SECONDS {
@@ -289,19 +728,24 @@ pub fn execute_apply_funding(e: &Env) {
         let market_config = storage::get_market_config(e, market_id);
         let mut data = storage::get_market_data(e, market_id);
 
-        data.accrue(
-            e,
-            config.r_base,
-            config.r_var,
-            market_config.r_var_market,
-            vault_balance,
-            total_notional,
-            config.max_util,
-            market_config.max_util,
-        );
-        data.update_funding_rate(e, config.r_funding);
+        data.accrue_market(e, market_id, &config, &market_config, vault_balance, total_notional);
+        data.update_funding_rate(e, config.r_funding, market_config.min_side);
 
         storage::set_market_data(e, market_id, &data);
+
+        let util = data.utilization(e, vault_balance);
+        FundingSnapshot {
+            market_id,
+            fund_rate: data.fund_rate,
+            l_fund_idx: data.l_fund_idx,
+            s_fund_idx: data.s_fund_idx,
+            l_borr_idx: data.l_borr_idx,
+            s_borr_idx: data.s_borr_idx,
+            l_notional: data.l_notional,
+            s_notional: data.s_notional,
+            util,
+        }
+        .publish(e);
     }
 
     (ApplyFunding {}).publish(e);
@@ -312,10 +756,11 @@ pub fn execute_apply_funding(e: &Env) {
 
 #[cfg(test)]
 mod tests {
-    use crate::constants::SCALAR_7;
+    use crate::constants::{BUMP_MIN_IDLE, SCALAR_7};
     use crate::storage;
     use crate::testutils::{
-        setup_contract, setup_env, FEED_BTC, BTC_PRICE,
+        jump, setup_contract, setup_env, update_config, update_market, BTC_PRICE, FEED_BTC, FEED_ETH,
+        PRICE_SCALAR,
     };
     use crate::dependencies::PriceData;
     use soroban_sdk::testutils::Address as _;
@@ -338,7 +783,15 @@ mod tests {
                 notional,
                 true,
                 BTC_PRICE,
-                0, 0,
+                0, 0, 0, false, 0,
+            )
+        })
+    }
+
+    fn place_limit_expiring(e: &soroban_sdk::Env, contract: &Address, user: &Address, expiry: u64) -> u32 {
+        e.as_contract(contract, || {
+            super::execute_create_limit(
+                e, user, FEED_BTC, 1_000 * SCALAR_7, 10_000 * SCALAR_7, true, BTC_PRICE, 0, 0, 0, false, expiry,
             )
         })
     }
@@ -353,7 +806,7 @@ mod tests {
                 notional,
                 false,
                 BTC_PRICE,
-                0, 0,
+                0, 0, 0, false, 0,
             )
         })
     }
@@ -432,89 +885,924 @@ mod tests {
     }
 
     #[test]
-    #[should_panic(expected = "Error(Contract, #723)")]
-    fn test_create_limit_zero_collateral() {
+    fn test_create_market_fees_come_out_of_collateral() {
         let e = setup_env();
         let (contract, token_client) = setup_contract(&e);
         let user = Address::generate(&e);
-        token_client.mint(&user, &(100_000 * SCALAR_7));
+        token_client.mint(&user, &(1_000 * SCALAR_7));
 
-        place_limit_long(&e, &contract, &user, 0, 10_000 * SCALAR_7);
+        // The user holds exactly the collateral, nothing extra for fees
+        let pd = btc_price(&e);
+        let id = e.as_contract(&contract, || {
+            super::execute_create_market(&e, &user, FEED_BTC, 1_000 * SCALAR_7, 10_000 * SCALAR_7, true, 0, 0, &pd)
+        });
+
+        assert_eq!(token_client.balance(&user), 0);
+        e.as_contract(&contract, || {
+            let pos = storage::get_position(&e, &user, id);
+            // base = 10_000 × 0.05% = 5 tokens, impact = floor(1e11 × S7 / 8e16) = 12
+            assert_eq!(pos.col, 1_000 * SCALAR_7 - 5 * SCALAR_7 - 12);
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #726)")]
+    fn test_create_market_fees_below_margin_panics() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(1_000 * SCALAR_7));
+
+        // Exactly 1% margin before fees: the open fee leaves it short
+        let pd = btc_price(&e);
+        e.as_contract(&contract, || {
+            super::execute_create_market(&e, &user, FEED_BTC, 100 * SCALAR_7, 10_000 * SCALAR_7, true, 0, 0, &pd)
+        });
     }
 
+
     #[test]
     #[should_panic(expected = "Error(Contract, #724)")]
-    fn test_create_limit_below_min_notional() {
+    fn test_create_market_below_market_min_notional_panics() {
         let e = setup_env();
         let (contract, token_client) = setup_contract(&e);
         let user = Address::generate(&e);
         token_client.mint(&user, &(100_000 * SCALAR_7));
+        update_market(&e, &contract, FEED_BTC, |m| m.min_notional = 1_000 * SCALAR_7);
 
-        // min_notional = 10 * SCALAR_7, try with 5
-        place_limit_long(&e, &contract, &user, SCALAR_7, 5 * SCALAR_7);
+        // Well above the global 10-token floor, one stroop under the market's
+        let pd = btc_price(&e);
+        e.as_contract(&contract, || {
+            super::execute_create_market(&e, &user, FEED_BTC, 500 * SCALAR_7, 1_000 * SCALAR_7 - 1, true, 0, 0, &pd)
+        });
     }
 
     #[test]
-    fn test_apply_funding_rate() {
-        use crate::testutils::jump;
-
+    fn test_create_at_market_min_notional() {
         let e = setup_env();
-        let (contract, _token_client) = setup_contract(&e);
-
-        jump(&e, 1000 + 3601);
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+        update_market(&e, &contract, FEED_BTC, |m| m.min_notional = 1_000 * SCALAR_7);
 
+        let pd = btc_price(&e);
         e.as_contract(&contract, || {
-            super::execute_apply_funding(&e);
-            let last = storage::get_last_funding_update(&e);
-            assert_eq!(last, 1000 + 3601);
+            super::execute_create_market(&e, &user, FEED_BTC, 500 * SCALAR_7, 1_000 * SCALAR_7, true, 0, 0, &pd)
         });
+        place_limit_long(&e, &contract, &user, 500 * SCALAR_7, 1_000 * SCALAR_7);
     }
 
     #[test]
-    #[should_panic(expected = "Error(Contract, #752)")]
-    fn test_apply_funding_too_early() {
-        use crate::testutils::jump;
-
+    fn test_volume_tier_discounts_base_fee() {
         let e = setup_env();
-        let (contract, _token_client) = setup_contract(&e);
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
 
-        jump(&e, 1000 + 1800);
+        update_market(&e, &contract, FEED_BTC, |m| {
+            m.fee_tiers = soroban_sdk::vec![
+                &e,
+                crate::types::FeeTier { volume: 10_000 * SCALAR_7, discount: 5_000_000 }, // 50%
+            ];
+        });
+
+        let first = open_btc_long(&e, &contract, &user);
+        let second = open_btc_long(&e, &contract, &user);
 
         e.as_contract(&contract, || {
-            super::execute_apply_funding(&e);
+            assert_eq!(storage::get_user_volume(&e, &user), 20_000 * SCALAR_7);
+            let first = storage::get_position(&e, &user, first);
+            let second = storage::get_position(&e, &user, second);
+            // Both opens are dominant (fee_dom 0.05% of 10_000 = 5); the second is at the 50% tier
+            assert_eq!(second.col - first.col, 25_000_000);
         });
     }
 
     #[test]
-    fn test_cancel_position() {
+    fn test_partner_fee_split() {
         let e = setup_env();
         let (contract, token_client) = setup_contract(&e);
         let user = Address::generate(&e);
+        let partner = Address::generate(&e);
         token_client.mint(&user, &(100_000 * SCALAR_7));
 
-        let balance_before = token_client.balance(&user);
-        let id = place_limit_long(&e, &contract, &user, 1_000 * SCALAR_7, 10_000 * SCALAR_7);
+        update_market(&e, &contract, FEED_BTC, |m| {
+            m.fee_recipient = Some(partner.clone());
+            m.fee_share = 2_000_000; // 20%
+        });
+
+        let token = soroban_sdk::token::TokenClient::new(&e, &token_client.address);
+        let (vault, treasury) = e.as_contract(&contract, || (storage::get_vault(&e), storage::get_treasury(&e)));
+        let vault_before = token.balance(&vault);
+
+        let id = open_btc_long(&e, &contract, &user);
+        let open_fee = 1_000 * SCALAR_7 - e.as_contract(&contract, || storage::get_position(&e, &user, id).col);
+        assert_eq!(token.balance(&partner), open_fee / 5);
+        assert_eq!(
+            token.balance(&vault) - vault_before,
+            open_fee - open_fee / 5 - token.balance(&treasury)
+        );
 
+        jump(&e, 1000 + 31);
         e.as_contract(&contract, || {
-            super::execute_cancel_position(&e, &user, id);
+            super::execute_close_position(&e, &user, id, dummy_price_bytes(&e));
         });
-
-        // User gets full collateral back (no fees charged for limits)
-        let balance_after = token_client.balance(&user);
-        assert_eq!(balance_after, balance_before);
+        assert!(token.balance(&partner) > open_fee / 5);
     }
 
     #[test]
-    #[should_panic(expected = "Error(Contract, #721)")]
-    fn test_cancel_position_filled_panics() {
+    fn test_create_market_position_matches_stored() {
         let e = setup_env();
         let (contract, token_client) = setup_contract(&e);
         let user = Address::generate(&e);
         token_client.mint(&user, &(100_000 * SCALAR_7));
 
-        let pd = PriceData {
-            feed_id: FEED_BTC,
-            price: BTC_PRICE,
+        let pd = btc_price(&e);
+        e.as_contract(&contract, || {
+            let (id, pos) = super::execute_create_market_position(
+                &e, &user, FEED_BTC, 1_000 * SCALAR_7, 10_000 * SCALAR_7, true, 0, 0, &pd,
+            );
+            assert!(pos.filled);
+            assert_eq!(pos.entry_price, BTC_PRICE);
+            assert_eq!(pos, storage::get_position(&e, &user, id));
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #723)")]
+    fn test_create_limit_zero_collateral() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        place_limit_long(&e, &contract, &user, 0, 10_000 * SCALAR_7);
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #723)")]
+    fn test_create_limit_zero_notional() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        place_limit_long(&e, &contract, &user, 1_000 * SCALAR_7, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #723)")]
+    fn test_create_limit_zero_entry_price() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        e.as_contract(&contract, || {
+            super::execute_create_limit(
+                &e, &user, FEED_BTC, 1_000 * SCALAR_7, 10_000 * SCALAR_7, true, 0, 0, 0, 0, false, 0,
+            )
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #723)")]
+    fn test_create_market_zero_notional() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        let pd = btc_price(&e);
+        e.as_contract(&contract, || {
+            super::execute_create_market(&e, &user, FEED_BTC, 1_000 * SCALAR_7, 0, true, 0, 0, &pd)
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #723)")]
+    fn test_create_market_zero_collateral() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        let pd = btc_price(&e);
+        e.as_contract(&contract, || {
+            super::execute_create_market(&e, &user, FEED_BTC, 0, 10_000 * SCALAR_7, true, 0, 0, &pd)
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #724)")]
+    fn test_create_limit_below_min_notional() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        // min_notional = 10 * SCALAR_7, try with 5
+        place_limit_long(&e, &contract, &user, SCALAR_7, 5 * SCALAR_7);
+    }
+
+    #[test]
+    fn test_apply_funding_rate() {
+        use crate::testutils::jump;
+
+        let e = setup_env();
+        let (contract, _token_client) = setup_contract(&e);
+
+        jump(&e, 1000 + 3601);
+
+        e.as_contract(&contract, || {
+            super::execute_apply_funding(&e);
+            let last = storage::get_last_funding_update(&e);
+            assert_eq!(last, 1000 + 3601);
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #752)")]
+    fn test_apply_funding_too_early() {
+        use crate::testutils::jump;
+
+        let e = setup_env();
+        let (contract, _token_client) = setup_contract(&e);
+
+        jump(&e, 1000 + 1800);
+
+        e.as_contract(&contract, || {
+            super::execute_apply_funding(&e);
+        });
+    }
+
+    #[test]
+    fn test_cancel_position() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        let balance_before = token_client.balance(&user);
+        let id = place_limit_long(&e, &contract, &user, 1_000 * SCALAR_7, 10_000 * SCALAR_7);
+
+        e.as_contract(&contract, || {
+            super::execute_cancel_position(&e, &user, id);
+        });
+
+        // User gets full collateral back (no fees charged for limits)
+        let balance_after = token_client.balance(&user);
+        assert_eq!(balance_after, balance_before);
+    }
+
+    #[test]
+    fn test_cancel_position_refunds_fill_bounty() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+        update_config(&e, &contract, |c| c.fill_bounty = 5 * SCALAR_7);
+
+        let id = place_limit_long(&e, &contract, &user, 1_000 * SCALAR_7, 10_000 * SCALAR_7);
+        assert_eq!(token_client.balance(&user), 100_000 * SCALAR_7 - 1_005 * SCALAR_7);
+
+        let payout = e.as_contract(&contract, || super::execute_cancel_position(&e, &user, id));
+        assert_eq!(payout, 1_005 * SCALAR_7);
+        assert_eq!(token_client.balance(&user), 100_000 * SCALAR_7);
+    }
+
+    #[test]
+    fn test_cancel_expired_rewards_caller() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        let caller = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        let id = place_limit_expiring(&e, &contract, &user, 1_000 + 3600);
+        let balance_before = token_client.balance(&user);
+
+        jump(&e, 1_000 + 3600);
+        let payout = e.as_contract(&contract, || {
+            super::execute_cancel_expired(&e, &caller, &user, id)
+        });
+
+        // caller_fee = notional * fee_non_dom * caller_rate
+        let config = e.as_contract(&contract, || storage::get_config(&e));
+        let caller_fee = 10_000 * SCALAR_7 * config.fee_non_dom / SCALAR_7 * config.caller_rate / SCALAR_7;
+        assert!(caller_fee > 0);
+        assert_eq!(token_client.balance(&caller), caller_fee);
+        assert_eq!(payout, 1_000 * SCALAR_7 - caller_fee);
+        assert_eq!(token_client.balance(&user) - balance_before, payout);
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #735)")]
+    fn test_cancel_expired_before_expiry_panics() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        let caller = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        let id = place_limit_expiring(&e, &contract, &user, 1_000 + 3600);
+
+        jump(&e, 1_000 + 3600 - 1);
+        e.as_contract(&contract, || {
+            super::execute_cancel_expired(&e, &caller, &user, id);
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #735)")]
+    fn test_cancel_expired_without_expiry_panics() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        let caller = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        let id = place_limit_long(&e, &contract, &user, 1_000 * SCALAR_7, 10_000 * SCALAR_7);
+
+        // Orders placed without an expiry rest until filled or cancelled by the owner
+        jump(&e, 1_000 + 365 * 24 * 3600);
+        e.as_contract(&contract, || {
+            super::execute_cancel_expired(&e, &caller, &user, id);
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #734)")]
+    fn test_create_limit_past_expiry_panics() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        place_limit_expiring(&e, &contract, &user, 1_000);
+    }
+
+    fn btc_price(e: &soroban_sdk::Env) -> PriceData {
+        PriceData {
+            feed_id: FEED_BTC,
+            price: BTC_PRICE,
+            exponent: -8,
+            publish_time: e.ledger().timestamp(),
+        }
+    }
+
+    /// Helper: open a 1_000 collateral / 10_000 notional BTC long at BTC_PRICE
+    fn open_btc_long(e: &soroban_sdk::Env, contract: &Address, user: &Address) -> u32 {
+        let pd = btc_price(e);
+        e.as_contract(contract, || {
+            super::execute_create_market(
+                e, user, FEED_BTC, 1_000 * SCALAR_7, 10_000 * SCALAR_7, true, 0, 0, &pd,
+            )
+        })
+    }
+
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #739)")]
+    fn test_create_market_above_max_user_notional_panics() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+        update_market(&e, &contract, FEED_BTC, |m| m.max_user_notional = 15_000 * SCALAR_7);
+
+        // Each position fits on its own; together they exceed the cap
+        open_btc_long(&e, &contract, &user);
+        open_btc_long(&e, &contract, &user);
+    }
+
+    #[test]
+    fn test_max_user_notional_frees_on_close() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        let other = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+        token_client.mint(&other, &(100_000 * SCALAR_7));
+        update_market(&e, &contract, FEED_BTC, |m| m.max_user_notional = 15_000 * SCALAR_7);
+
+        let id = open_btc_long(&e, &contract, &user);
+        // The cap is per user: another trader is unaffected
+        open_btc_long(&e, &contract, &other);
+        let tracked = e.as_contract(&contract, || storage::get_user_notional(&e, &user, FEED_BTC));
+        assert_eq!(tracked, 10_000 * SCALAR_7);
+
+        jump(&e, 1000 + 31);
+        e.as_contract(&contract, || {
+            super::execute_close_position(&e, &user, id, dummy_price_bytes(&e));
+        });
+        let tracked = e.as_contract(&contract, || storage::get_user_notional(&e, &user, FEED_BTC));
+        assert_eq!(tracked, 0);
+        open_btc_long(&e, &contract, &user);
+    }
+
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #706)")]
+    fn test_create_market_short_on_long_only_market_panics() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+        update_market(&e, &contract, FEED_BTC, |m| m.allow_short = false);
+
+        let pd = btc_price(&e);
+        e.as_contract(&contract, || {
+            super::execute_create_market(&e, &user, FEED_BTC, 1_000 * SCALAR_7, 10_000 * SCALAR_7, false, 0, 0, &pd)
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #706)")]
+    fn test_place_limit_on_disabled_direction_panics() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+        update_market(&e, &contract, FEED_BTC, |m| m.allow_long = false);
+
+        place_limit_long(&e, &contract, &user, 1_000 * SCALAR_7, 10_000 * SCALAR_7);
+    }
+
+    #[test]
+    fn test_disabled_direction_still_closes() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        let id = open_btc_long(&e, &contract, &user);
+        update_market(&e, &contract, FEED_BTC, |m| m.allow_long = false);
+
+        // Shorts still open; the existing long can still be closed
+        let pd = btc_price(&e);
+        e.as_contract(&contract, || {
+            super::execute_create_market(&e, &user, FEED_BTC, 1_000 * SCALAR_7, 10_000 * SCALAR_7, false, 0, 0, &pd)
+        });
+        jump(&e, 1000 + 31);
+        e.as_contract(&contract, || {
+            super::execute_close_position(&e, &user, id, dummy_price_bytes(&e));
+            assert!(storage::try_get_position(&e, &user, id).is_none());
+        });
+    }
+
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #707)")]
+    fn test_create_market_while_opens_paused_panics() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+        update_market(&e, &contract, FEED_BTC, |m| m.open_paused = true);
+
+        open_btc_long(&e, &contract, &user);
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #707)")]
+    fn test_place_limit_while_opens_paused_panics() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+        update_market(&e, &contract, FEED_BTC, |m| m.open_paused = true);
+
+        place_limit_long(&e, &contract, &user, 1_000 * SCALAR_7, 10_000 * SCALAR_7);
+    }
+
+    #[test]
+    fn test_opens_paused_keeps_fills_and_closes() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        let keeper = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        let open = open_btc_long(&e, &contract, &user);
+        let pending = place_limit_long(&e, &contract, &user, 1_000 * SCALAR_7, 10_000 * SCALAR_7);
+        update_market(&e, &contract, FEED_BTC, |m| m.open_paused = true);
+
+        // A keeper still fills the order placed before the pause
+        let pd = btc_price(&e);
+        e.as_contract(&contract, || {
+            let (users, ids) = (soroban_sdk::vec![&e, user.clone()], soroban_sdk::vec![&e, pending]);
+            crate::trading::execute_trigger(&e, &keeper, FEED_BTC, users, ids, &pd);
+            assert!(storage::get_position(&e, &user, pending).filled);
+        });
+
+        jump(&e, 1000 + 31);
+        e.as_contract(&contract, || {
+            super::execute_close_position(&e, &user, open, dummy_price_bytes(&e));
+            assert!(storage::try_get_position(&e, &user, open).is_none());
+        });
+    }
+
+    #[test]
+    fn test_market_utilization_rises_with_open_interest() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        let util = || e.as_contract(&contract, || super::execute_market_utilization(&e, FEED_BTC));
+        assert_eq!(util(), 0);
+
+        open_btc_long(&e, &contract, &user);
+        let first = util();
+        // 10_000 notional against a ~100M vault: ~0.01%
+        assert!(first > 0 && first <= 1_000);
+
+        open_btc_long(&e, &contract, &user);
+        assert!(util() > first);
+    }
+
+    #[test]
+    fn test_bump_position_extends_ttl() {
+        use soroban_sdk::testutils::storage::Persistent as _;
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        let caller = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        let id = open_btc_long(&e, &contract, &user);
+
+        // Idle long enough for the TTL to fall below the open-position threshold
+        jump(&e, 1000 + BUMP_MIN_IDLE);
+        let key = storage::TradingStorageKey::Position(user.clone(), id);
+        let ttl_before = e.as_contract(&contract, || e.storage().persistent().get_ttl(&key));
+        let col_before = e.as_contract(&contract, || storage::try_get_position(&e, &user, id).unwrap().col);
+
+        let fee = e.as_contract(&contract, || {
+            super::execute_bump_position(&e, &caller, &user, id, &btc_price(&e))
+        });
+
+        let ttl_after = e.as_contract(&contract, || e.storage().persistent().get_ttl(&key));
+        assert!(ttl_after > ttl_before);
+        assert_eq!(fee, col_before / 10_000);
+        assert_eq!(token_client.balance(&caller), fee);
+        let col_after = e.as_contract(&contract, || storage::get_position(&e, &user, id).col);
+        assert_eq!(col_after, col_before - fee);
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #731)")]
+    fn test_bump_position_recently_stored_panics() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        let caller = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        let id = open_btc_long(&e, &contract, &user);
+
+        // A week-old position's TTL is nowhere near expiry
+        jump(&e, 1000 + 7 * 24 * 3600);
+        e.as_contract(&contract, || {
+            super::execute_bump_position(&e, &caller, &user, id, &btc_price(&e));
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #731)")]
+    fn test_bump_position_twice_in_idle_period_panics() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        let caller = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        let id = open_btc_long(&e, &contract, &user);
+
+        jump(&e, 1000 + BUMP_MIN_IDLE);
+        e.as_contract(&contract, || {
+            super::execute_bump_position(&e, &caller, &user, id, &btc_price(&e));
+        });
+        jump(&e, 1000 + BUMP_MIN_IDLE + 7 * 24 * 3600);
+        e.as_contract(&contract, || {
+            super::execute_bump_position(&e, &caller, &user, id, &btc_price(&e));
+        });
+    }
+
+    #[test]
+    fn test_bump_position_at_margin_is_free() {
+        use soroban_sdk::testutils::storage::Persistent as _;
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        let caller = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        let id = open_btc_long(&e, &contract, &user);
+
+        // 10% margin puts the 10x position's collateral (net of the open fee) under the floor
+        update_market(&e, &contract, FEED_BTC, |m| m.margin = 1_000_000);
+
+        jump(&e, 1000 + BUMP_MIN_IDLE);
+        let key = storage::TradingStorageKey::Position(user.clone(), id);
+        let ttl_before = e.as_contract(&contract, || e.storage().persistent().get_ttl(&key));
+        let col_before = e.as_contract(&contract, || storage::try_get_position(&e, &user, id).unwrap().col);
+        let fee = e.as_contract(&contract, || {
+            super::execute_bump_position(&e, &caller, &user, id, &btc_price(&e))
+        });
+
+        assert_eq!(fee, 0);
+        assert_eq!(token_client.balance(&caller), 0);
+        let ttl_after = e.as_contract(&contract, || e.storage().persistent().get_ttl(&key));
+        assert!(ttl_after > ttl_before);
+        let position = e.as_contract(&contract, || storage::get_position(&e, &user, id));
+        assert_eq!(position.col, col_before);
+        assert_eq!(position.touched_at, 1000 + BUMP_MIN_IDLE);
+    }
+
+    #[test]
+    fn test_total_fees_collected_accumulates() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        let id1 = open_btc_long(&e, &contract, &user);
+        let id2 = open_btc_long(&e, &contract, &user);
+
+        let (open_fees, treasury) = e.as_contract(&contract, || {
+            let fees = 2 * 1_000 * SCALAR_7
+                - storage::get_position(&e, &user, id1).col
+                - storage::get_position(&e, &user, id2).col;
+            assert_eq!(storage::get_total_fees(&e), fees);
+            (fees, storage::get_treasury(&e))
+        });
+
+        jump(&e, 1000 + 3600);
+        let treasury_before = token_client.balance(&treasury);
+        e.as_contract(&contract, || {
+            super::execute_close_position(&e, &user, id1, dummy_price_bytes(&e));
+        });
+
+        // Close adds the protocol fee, of which the treasury takes its 5% cut
+        let close_fees = e.as_contract(&contract, || storage::get_total_fees(&e)) - open_fees;
+        assert!(close_fees > 0);
+        assert_eq!(token_client.balance(&treasury) - treasury_before, close_fees * 500_000 / SCALAR_7);
+    }
+
+    #[test]
+    fn test_health_factor_tracks_liquidation_threshold() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        let id = open_btc_long(&e, &contract, &user);
+        let pv = e.as_contract(&contract, || storage::get_price_verifier(&e));
+        let pv_client = crate::testutils::MockPriceVerifierClient::new(&e, &pv);
+
+        // ~995 equity against a 50 threshold (0.5% of 10k)
+        let healthy = e.as_contract(&contract, || {
+            super::execute_health_factor(&e, &user, id, dummy_price_bytes(&e))
+        });
+        assert!(healthy > 19 * SCALAR_7 && healthy < 20 * SCALAR_7);
+
+        // -9.7% on 10x: equity below the threshold but still positive
+        pv_client.set_price(&FEED_BTC, &9_030_000_000_000);
+        let breached = e.as_contract(&contract, || {
+            super::execute_health_factor(&e, &user, id, dummy_price_bytes(&e))
+        });
+        assert!(breached > 0 && breached < SCALAR_7);
+    }
+
+    #[test]
+    fn test_user_positions_keeps_closed_ids() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        let first = place_limit_long(&e, &contract, &user, 1_000 * SCALAR_7, 10_000 * SCALAR_7);
+        let second = place_limit_long(&e, &contract, &user, 1_000 * SCALAR_7, 10_000 * SCALAR_7);
+        e.as_contract(&contract, || super::execute_cancel_position(&e, &user, first));
+
+        let history = e.as_contract(&contract, || super::execute_user_positions(&e, &user, 0, 10));
+        assert_eq!(history, soroban_sdk::vec![&e, (first, false), (second, true)]);
+
+        // Pages past the counter are empty; the page is clamped to MAX_PAGE
+        let tail = e.as_contract(&contract, || super::execute_user_positions(&e, &user, 1, u32::MAX));
+        assert_eq!(tail, soroban_sdk::vec![&e, (second, true)]);
+        let past = e.as_contract(&contract, || super::execute_user_positions(&e, &user, 2, 10));
+        assert!(past.is_empty());
+    }
+
+    #[test]
+    fn test_get_positions_marks_missing_entries() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let (user, other) = (Address::generate(&e), Address::generate(&e));
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        let open = open_btc_long(&e, &contract, &user);
+        let pending = place_limit_long(&e, &contract, &user, 1_000 * SCALAR_7, 10_000 * SCALAR_7);
+        let users = soroban_sdk::vec![&e, user.clone(), other.clone(), user.clone()];
+        let ids = soroban_sdk::vec![&e, pending, open, open];
+
+        e.as_contract(&contract, || {
+            let positions = super::execute_get_positions(&e, &users, &ids);
+            assert_eq!(positions.len(), 3);
+            assert_eq!(positions.get(0).unwrap(), Some(storage::get_position(&e, &user, pending)));
+            assert_eq!(positions.get(1).unwrap(), None);
+            assert_eq!(positions.get(2).unwrap(), Some(storage::get_position(&e, &user, open)));
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #734)")]
+    fn test_get_positions_over_page_panics() {
+        let e = setup_env();
+        let (contract, _) = setup_contract(&e);
+        let user = Address::generate(&e);
+        let mut users = soroban_sdk::Vec::new(&e);
+        let mut ids = soroban_sdk::Vec::new(&e);
+        for id in 0..=crate::constants::MAX_PAGE {
+            users.push_back(user.clone());
+            ids.push_back(id);
+        }
+        e.as_contract(&contract, || super::execute_get_positions(&e, &users, &ids));
+    }
+
+    #[test]
+    fn test_quote_close_matches_close_transfers() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+        let (vault, treasury) = e.as_contract(&contract, || (storage::get_vault(&e), storage::get_treasury(&e)));
+
+        let id = open_btc_long(&e, &contract, &user);
+        // A day of borrowing interest
+        jump(&e, 1000 + 86_400);
+
+        let quote = e.as_contract(&contract, || super::execute_quote_close(&e, &user, id, dummy_price_bytes(&e)));
+        assert!(quote.borrowing_fee > 0);
+        // Read-only: the position is still open
+        e.as_contract(&contract, || assert!(storage::try_get_position(&e, &user, id).is_some()));
+
+        let before = (token_client.balance(&user), token_client.balance(&vault), token_client.balance(&treasury));
+        let payout = e.as_contract(&contract, || super::execute_close_position(&e, &user, id, dummy_price_bytes(&e)));
+        assert_eq!(payout, quote.user_payout);
+        assert_eq!(token_client.balance(&user) - before.0, quote.user_payout);
+        assert_eq!(token_client.balance(&vault) - before.1, quote.vault_transfer);
+        assert_eq!(token_client.balance(&treasury) - before.2, quote.treasury_fee);
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #733)")]
+    fn test_health_factor_pending_panics() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        let id = place_limit_long(&e, &contract, &user, 1_000 * SCALAR_7, 10_000 * SCALAR_7);
+        e.as_contract(&contract, || {
+            super::execute_health_factor(&e, &user, id, dummy_price_bytes(&e))
+        });
+    }
+
+    #[test]
+    fn test_close_fee_free_during_wind_down() {
+        use crate::types::ContractStatus;
+
+        // Same position closed with and without the wind-down waiver
+        let close_payout = |fee_free: bool| {
+            let e = setup_env();
+            let (contract, token_client) = setup_contract(&e);
+            let user = Address::generate(&e);
+            token_client.mint(&user, &(100_000 * SCALAR_7));
+
+            let id = open_btc_long(&e, &contract, &user);
+            jump(&e, 1000 + 31);
+            update_config(&e, &contract, |c| c.fee_free_wind_down = fee_free);
+            e.as_contract(&contract, || {
+                storage::set_status(&e, ContractStatus::OnIce as u32);
+                super::execute_close_position(&e, &user, id, dummy_price_bytes(&e))
+            })
+        };
+
+        // Closing the only long leaves it non-dominant: 0.05% base fee + 10k / 8e9 impact
+        let base_fee = 5 * SCALAR_7;
+        let impact_fee = 12;
+        assert_eq!(close_payout(true) - close_payout(false), base_fee + impact_fee);
+    }
+
+    #[test]
+    fn test_close_funding_rebate_exceeds_fees() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        let id = open_btc_long(&e, &contract, &user);
+        let (col, vault, treasury) = e.as_contract(&contract, || {
+            // 2% funding earned by longs since the position opened
+            let mut data = storage::get_market_data(&e, FEED_BTC);
+            data.l_fund_idx -= crate::constants::SCALAR_18 / 50;
+            storage::set_market_data(&e, FEED_BTC, &data);
+            (storage::get_position(&e, &user, id).col, storage::get_vault(&e), storage::get_treasury(&e))
+        });
+        jump(&e, 1000 + 31);
+
+        let vault_before = token_client.balance(&vault);
+        let treasury_before = token_client.balance(&treasury);
+        let contract_before = token_client.balance(&contract);
+        let payout = e.as_contract(&contract, || {
+            super::execute_close_position(&e, &user, id, dummy_price_bytes(&e))
+        });
+
+        // 200 rebate against a 5 base fee and dust borrowing: trader nets a credit
+        assert!(payout > col + 190 * SCALAR_7);
+        let treasury_fee = token_client.balance(&treasury) - treasury_before;
+        assert_eq!(vault_before - token_client.balance(&vault), payout + treasury_fee - col);
+        assert_eq!(token_client.balance(&contract), contract_before - col);
+    }
+
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #726)")]
+    fn test_open_above_tier_leverage_panics() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+        update_market(&e, &contract, FEED_BTC, |m| {
+            m.tiers = soroban_sdk::vec![
+                &e,
+                crate::types::MarginTier { notional: 5_000 * SCALAR_7, margin: 2_000_000 },
+            ];
+        });
+
+        // 10x is within the flat 1% margin but above the 5x tier cap
+        open_btc_long(&e, &contract, &user);
+    }
+
+    #[test]
+    fn test_open_below_tier_uses_flat_margin() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+        update_market(&e, &contract, FEED_BTC, |m| {
+            m.tiers = soroban_sdk::vec![
+                &e,
+                crate::types::MarginTier { notional: 5_000 * SCALAR_7, margin: 2_000_000 },
+            ];
+        });
+
+        // 4_000 notional on 100 collateral (40x) stays under the tier threshold
+        let pd = btc_price(&e);
+        let id = e.as_contract(&contract, || {
+            super::execute_create_market(
+                &e, &user, FEED_BTC, 100 * SCALAR_7, 4_000 * SCALAR_7, true, 0, 0, &pd,
+            )
+        });
+        e.as_contract(&contract, || assert!(storage::get_position(&e, &user, id).filled));
+    }
+
+    #[test]
+    fn test_close_position_to_receiver() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        let receiver = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        let id = open_btc_long(&e, &contract, &user);
+        jump(&e, 1000 + 31);
+
+        let user_before = token_client.balance(&user);
+        let payout = e.as_contract(&contract, || {
+            super::execute_close_position_to(&e, &user, id, &receiver, dummy_price_bytes(&e))
+        });
+
+        assert!(payout > 0);
+        assert_eq!(token_client.balance(&receiver), payout);
+        assert_eq!(token_client.balance(&user), user_before);
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #721)")]
+    fn test_cancel_position_filled_panics() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        let pd = PriceData {
+            feed_id: FEED_BTC,
+            price: BTC_PRICE,
             exponent: -8,
             publish_time: e.ledger().timestamp(),
         };
@@ -624,6 +1912,68 @@ mod tests {
         });
     }
 
+    #[test]
+    #[should_panic(expected = "Error(Contract, #729)")]
+    fn test_modify_collateral_below_min_collateral_panics() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        let id = open_btc_long(&e, &contract, &user);
+
+        e.as_contract(&contract, || {
+            // Min collateral = notional * margin = 10_000 * 1% = 100 tokens
+            super::execute_modify_collateral(&e, &user, id, 100 * SCALAR_7 - 1, &btc_price(&e));
+        });
+    }
+
+    fn open_btc_long_with_interval(e: &soroban_sdk::Env, contract: &Address, user: &Address, interval: u64) -> u32 {
+        update_market(e, contract, FEED_BTC, |m| m.min_modify_interval = interval);
+        open_btc_long(e, contract, user)
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #736)")]
+    fn test_modify_collateral_within_interval_panics() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        let id = open_btc_long_with_interval(&e, &contract, &user, 60);
+
+        e.as_contract(&contract, || {
+            super::execute_modify_collateral(&e, &user, id, 2_000 * SCALAR_7, &btc_price(&e));
+        });
+        jump(&e, 1059);
+        e.as_contract(&contract, || {
+            super::execute_modify_collateral(&e, &user, id, 3_000 * SCALAR_7, &btc_price(&e));
+        });
+    }
+
+    #[test]
+    fn test_modify_collateral_after_interval() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        let id = open_btc_long_with_interval(&e, &contract, &user, 60);
+
+        e.as_contract(&contract, || {
+            super::execute_modify_collateral(&e, &user, id, 2_000 * SCALAR_7, &btc_price(&e));
+            assert_eq!(storage::get_position(&e, &user, id).last_modified_at, 1000);
+        });
+        jump(&e, 1060);
+        e.as_contract(&contract, || {
+            super::execute_modify_collateral(&e, &user, id, 3_000 * SCALAR_7, &btc_price(&e));
+            let pos = storage::get_position(&e, &user, id);
+            assert_eq!(pos.col, 3_000 * SCALAR_7);
+            assert_eq!(pos.last_modified_at, 1060);
+        });
+    }
+
     #[test]
     #[should_panic(expected = "Error(Contract, #727)")]
     fn test_modify_collateral_unchanged_panics() {
@@ -682,6 +2032,62 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_set_triggers_on_pending_order() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        // Long limit at BTC_PRICE: TP above, SL below
+        let id = place_limit_long(&e, &contract, &user, 1_000 * SCALAR_7, 10_000 * SCALAR_7);
+        let (tp, sl) = (BTC_PRICE + 10_000 * PRICE_SCALAR, BTC_PRICE - 5_000 * PRICE_SCALAR);
+        e.as_contract(&contract, || {
+            super::execute_set_triggers(&e, &user, id, tp, sl);
+            let pos = storage::get_position(&e, &user, id);
+            assert!(!pos.filled);
+            assert_eq!((pos.tp, pos.sl), (tp, sl));
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #734)")]
+    fn test_set_triggers_on_pending_order_wrong_side_panics() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        // SL above a long entry would stop the position out as soon as it fills
+        let id = place_limit_long(&e, &contract, &user, 1_000 * SCALAR_7, 10_000 * SCALAR_7);
+        e.as_contract(&contract, || {
+            super::execute_set_triggers(&e, &user, id, 0, BTC_PRICE + PRICE_SCALAR);
+        });
+    }
+
+    #[test]
+    fn test_set_triggers_each_applies_valid_and_reports_rejected() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        let id = place_limit_long(&e, &contract, &user, 1_000 * SCALAR_7, 10_000 * SCALAR_7);
+        let prev_sl = BTC_PRICE - 5_000 * PRICE_SCALAR;
+        e.as_contract(&contract, || super::execute_set_triggers(&e, &user, id, 0, prev_sl));
+
+        // Valid TP above entry, invalid SL above a long entry
+        let tp = BTC_PRICE + 10_000 * PRICE_SCALAR;
+        let codes = e.as_contract(&contract, || {
+            super::execute_set_triggers_each(&e, &user, id, tp, BTC_PRICE + PRICE_SCALAR)
+        });
+        assert_eq!(codes, (0, 734));
+        e.as_contract(&contract, || {
+            let pos = storage::get_position(&e, &user, id);
+            assert_eq!((pos.tp, pos.sl), (tp, prev_sl));
+        });
+    }
+
     #[test]
     fn test_set_triggers_clear() {
         let e = setup_env();
@@ -720,11 +2126,7 @@ mod tests {
         let user = Address::generate(&e);
         token_client.mint(&user, &(100_000 * SCALAR_7));
 
-        e.as_contract(&contract, || {
-            let mut mc = storage::get_market_config(&e, FEED_BTC);
-            mc.enabled = false;
-            storage::set_market_config(&e, FEED_BTC, &mc);
-        });
+        update_market(&e, &contract, FEED_BTC, |m| m.enabled = false);
 
         place_limit_long(&e, &contract, &user, 1_000 * SCALAR_7, 10_000 * SCALAR_7);
     }
@@ -737,11 +2139,7 @@ mod tests {
         let user = Address::generate(&e);
         token_client.mint(&user, &(100_000 * SCALAR_7));
 
-        e.as_contract(&contract, || {
-            let mut mc = storage::get_market_config(&e, FEED_BTC);
-            mc.enabled = false;
-            storage::set_market_config(&e, FEED_BTC, &mc);
-        });
+        update_market(&e, &contract, FEED_BTC, |m| m.enabled = false);
 
         let pd = PriceData {
             feed_id: FEED_BTC,
@@ -780,11 +2178,7 @@ mod tests {
         });
 
         // Disable market
-        e.as_contract(&contract, || {
-            let mut mc = storage::get_market_config(&e, FEED_BTC);
-            mc.enabled = false;
-            storage::set_market_config(&e, FEED_BTC, &mc);
-        });
+        update_market(&e, &contract, FEED_BTC, |m| m.enabled = false);
 
         jump(&e, 1000 + 31);
 
@@ -799,6 +2193,55 @@ mod tests {
         assert!(balance_after > balance_before);
     }
 
+    #[test]
+    fn test_close_position_bad_debt() {
+        use crate::testutils::jump;
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        // 1%/hr base borrowing rate so fees outgrow collateral within hours
+        update_config(&e, &contract, |c| c.r_base = 10_000_000_000_000_000);
+
+        let pd = PriceData {
+            feed_id: FEED_BTC,
+            price: BTC_PRICE,
+            exponent: -8,
+            publish_time: e.ledger().timestamp(),
+        };
+
+        let id = e.as_contract(&contract, || {
+            super::execute_create_market(
+                &e, &user, FEED_BTC, 200 * SCALAR_7, 10_000 * SCALAR_7, true, 0, 0, &pd,
+            )
+        });
+        let (col, vault, treasury) = e.as_contract(&contract, || {
+            (
+                storage::get_position(&e, &user, id).col,
+                storage::get_vault(&e),
+                storage::get_treasury(&e),
+            )
+        });
+
+        // 3 hours of borrowing on 10k notional (~300 tokens) exceeds collateral (~199 tokens)
+        jump(&e, 1000 + 3 * 3600);
+
+        let user_before = token_client.balance(&user);
+        let vault_before = token_client.balance(&vault);
+        let treasury_before = token_client.balance(&treasury);
+        e.as_contract(&contract, || {
+            let payout = super::execute_close_position(&e, &user, id, dummy_price_bytes(&e));
+            assert_eq!(payout, 0);
+        });
+
+        // Trader gets nothing, treasury cut is capped at collateral, vault keeps the rest
+        let treasury_fee = col * 500_000 / SCALAR_7;
+        assert_eq!(token_client.balance(&user), user_before);
+        assert_eq!(token_client.balance(&treasury) - treasury_before, treasury_fee);
+        assert_eq!(token_client.balance(&vault) - vault_before, col - treasury_fee);
+    }
+
     #[test]
     fn test_cancel_position_deleted_market_refund() {
         let e = setup_env();
@@ -850,11 +2293,7 @@ mod tests {
         let id = place_limit_long(&e, &contract, &user, collateral, 10_000 * SCALAR_7);
 
         // Disable market — pending position can still be cancelled
-        e.as_contract(&contract, || {
-            let mut mc = storage::get_market_config(&e, FEED_BTC);
-            mc.enabled = false;
-            storage::set_market_config(&e, FEED_BTC, &mc);
-        });
+        update_market(&e, &contract, FEED_BTC, |m| m.enabled = false);
 
         let balance_before = token_client.balance(&user);
         e.as_contract(&contract, || {
@@ -866,4 +2305,159 @@ mod tests {
         assert_eq!(balance_after - balance_before, collateral);
     }
 
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #738)")]
+    fn test_create_limit_above_max_pending_panics() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+        update_config(&e, &contract, |c| c.max_pending = 2);
+        update_market(&e, &contract, FEED_ETH, |_| {});
+
+        place_limit_long(&e, &contract, &user, 1_000 * SCALAR_7, 10_000 * SCALAR_7);
+        place_limit_long(&e, &contract, &user, 1_000 * SCALAR_7, 10_000 * SCALAR_7);
+        place_limit_long(&e, &contract, &user, 1_000 * SCALAR_7, 10_000 * SCALAR_7);
+    }
+
+    #[test]
+    fn test_max_pending_is_per_market() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+        update_config(&e, &contract, |c| c.max_pending = 2);
+        update_market(&e, &contract, FEED_ETH, |_| {});
+
+        let first = place_limit_long(&e, &contract, &user, 1_000 * SCALAR_7, 10_000 * SCALAR_7);
+        place_limit_long(&e, &contract, &user, 1_000 * SCALAR_7, 10_000 * SCALAR_7);
+
+        // BTC is full, ETH is not
+        e.as_contract(&contract, || {
+            super::execute_create_limit(
+                &e, &user, FEED_ETH, 1_000 * SCALAR_7, 10_000 * SCALAR_7, true, 2_000 * PRICE_SCALAR, 0, 0, 0, false, 0,
+            );
+            assert_eq!(storage::get_user_pending(&e, &user, FEED_BTC), 2);
+            assert_eq!(storage::get_user_pending(&e, &user, FEED_ETH), 1);
+        });
+
+        // Cancelling a BTC order frees its slot
+        e.as_contract(&contract, || super::execute_cancel_position(&e, &user, first));
+        place_limit_long(&e, &contract, &user, 1_000 * SCALAR_7, 10_000 * SCALAR_7);
+        e.as_contract(&contract, || {
+            assert_eq!(storage::get_user_pending(&e, &user, FEED_BTC), 2);
+        });
+    }
+
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #730)")]
+    fn test_total_position_cap_blocks_any_user() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let (a, b, c) = (Address::generate(&e), Address::generate(&e), Address::generate(&e));
+        for user in [&a, &b, &c] {
+            token_client.mint(user, &(100_000 * SCALAR_7));
+        }
+        update_config(&e, &contract, |c| c.max_total_positions = 2);
+
+        open_btc_long(&e, &contract, &a);
+        place_limit_long(&e, &contract, &b, 1_000 * SCALAR_7, 10_000 * SCALAR_7);
+
+        // A third user, with no positions of their own, is still over the cap
+        open_btc_long(&e, &contract, &c);
+    }
+
+    #[test]
+    fn test_total_position_cap_frees_on_close() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let (a, b) = (Address::generate(&e), Address::generate(&e));
+        token_client.mint(&a, &(100_000 * SCALAR_7));
+        token_client.mint(&b, &(100_000 * SCALAR_7));
+        update_config(&e, &contract, |c| c.max_total_positions = 1);
+
+        let id = place_limit_long(&e, &contract, &a, 1_000 * SCALAR_7, 10_000 * SCALAR_7);
+        e.as_contract(&contract, || super::execute_cancel_position(&e, &a, id));
+        open_btc_long(&e, &contract, &b);
+        e.as_contract(&contract, || assert_eq!(storage::get_live_positions(&e), 1));
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #722)")]
+    fn test_close_frozen_position_fails() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        let id = open_btc_long(&e, &contract, &user);
+        jump(&e, 1000 + 31);
+        e.as_contract(&contract, || {
+            crate::trading::execute_set_frozen(&e, &user, id, true);
+            super::execute_close_position(&e, &user, id, dummy_price_bytes(&e));
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #722)")]
+    fn test_modify_frozen_position_fails() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        let id = open_btc_long(&e, &contract, &user);
+        e.as_contract(&contract, || {
+            crate::trading::execute_set_frozen(&e, &user, id, true);
+            super::execute_modify_collateral(&e, &user, id, 2_000 * SCALAR_7, &btc_price(&e));
+        });
+    }
+
+    #[test]
+    fn test_unfrozen_position_can_modify_and_close() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        let id = open_btc_long(&e, &contract, &user);
+        jump(&e, 1000 + 31);
+        e.as_contract(&contract, || {
+            crate::trading::execute_set_frozen(&e, &user, id, true);
+            crate::trading::execute_set_frozen(&e, &user, id, false);
+            assert!(!storage::get_position(&e, &user, id).frozen);
+
+        });
+        e.as_contract(&contract, || {
+            super::execute_modify_collateral(&e, &user, id, 2_000 * SCALAR_7, &btc_price(&e))
+        });
+        e.as_contract(&contract, || {
+            super::execute_close_position(&e, &user, id, dummy_price_bytes(&e));
+            assert!(storage::try_get_position(&e, &user, id).is_none());
+        });
+    }
+
+    #[test]
+    fn test_avg_entry_prices() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        let avg = || e.as_contract(&contract, || super::execute_avg_entry_prices(&e, FEED_BTC));
+        assert_eq!(avg(), (0, 0));
+
+        open_btc_long(&e, &contract, &user);
+        let pd = PriceData { price: BTC_PRICE / 2, ..btc_price(&e) };
+        e.as_contract(&contract, || {
+            super::execute_create_market(&e, &user, FEED_BTC, 1_000 * SCALAR_7, 10_000 * SCALAR_7, true, 0, 0, &pd)
+        });
+
+        // Equal notionals at $100k and $50k: harmonic mean $66,666.66
+        let (long, short) = avg();
+        assert!((long - 66_666 * PRICE_SCALAR).abs() < PRICE_SCALAR, "long avg {long}");
+        assert_eq!(short, 0);
+    }
 }
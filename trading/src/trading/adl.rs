@@ -3,11 +3,56 @@ use crate::dependencies::VaultClient;
 use crate::errors::TradingError;
 use crate::events::{ADLTriggered, SetStatus};
 use crate::storage;
+use crate::trading::price::{feed_map, load_price, to_market_units};
 use crate::dependencies::{scalar_from_exponent, PriceData};
 use crate::types::{ContractStatus, MarketConfig, MarketData};
+use crate::validation::require_migrated;
 use soroban_fixed_point_math::SorobanFixedPoint;
+use soroban_sdk::token::TokenClient;
 use soroban_sdk::{panic_with_error, Env, Map, Vec};
 
+/// Open price PnL of each side of a market, `(long_pnl, short_pnl)`, from its
+/// entry-weighted aggregates. Positive = traders are owed.
+fn side_pnls(e: &Env, f: &PriceData, data: &MarketData) -> (i128, i128) {
+    let ps = scalar_from_exponent(f.exponent);
+    let long_pnl = f.price.fixed_mul_floor(e, &data.l_entry_wt, &ps) - data.l_notional;
+    let short_pnl = data.s_notional - f.price.fixed_mul_floor(e, &data.s_entry_wt, &ps);
+    (long_pnl, short_pnl)
+}
+
+/// Protocol-wide solvency snapshot at the given prices. Read-only.
+///
+/// # Returns
+/// `(collateral, net_pnl, vault_balance, surplus)`:
+/// - `collateral` - tokens held for traders (open positions and pending orders):
+///   the contract's balance less escrowed fill bounties and unclaimed referral
+///   rewards, which it holds for keepers and referrers. Fees accrued on open
+///   positions are still part of it until they settle
+/// - `net_pnl` - open price PnL owed to traders across all markets, the same
+///   figure `update_status` compares with the vault (negative = traders owe)
+/// - `vault_balance` - the vault's total assets
+/// - `surplus` - `vault_balance - net_pnl`; negative means the vault can't cover
+///   the net open profit at these prices
+///
+/// # Panics
+/// - `TradingError::InvalidPrice` (710) if a market's feed is missing
+pub fn execute_solvency(e: &Env, feeds: &Vec<PriceData>) -> (i128, i128, i128, i128) {
+    let feeds = feed_map(e, feeds);
+    let mut net_pnl: i128 = 0;
+    for market_id in storage::get_markets(e).iter() {
+        let config = storage::get_market_config(e, market_id);
+        let f = to_market_units(e, market_id, load_price(e, &config, &feeds));
+        let (long_pnl, short_pnl) = side_pnls(e, &f, &storage::get_market_data(e, market_id));
+        net_pnl += long_pnl + short_pnl;
+    }
+
+    let collateral = TokenClient::new(e, &storage::get_token(e)).balance(&e.current_contract_address())
+        - storage::get_bounty_escrow(e)
+        - storage::get_referral_owed(e);
+    let vault_balance = VaultClient::new(e, &storage::get_vault(e)).total_assets();
+    (collateral, net_pnl, vault_balance, vault_balance - net_pnl)
+}
+
 /// Permissionless circuit breaker and auto-deleveraging (ADL) trigger.
 ///
 /// Computes net trader PnL across all markets using entry-weighted aggregates
@@ -26,6 +71,7 @@ use soroban_sdk::{panic_with_error, Env, Map, Vec};
 /// - `TradingError::InvalidStatus` (740) if contract is Frozen
 /// - `TradingError::InvalidPrice` (710) if feeds length doesn't match markets
 pub fn execute_update_status(e: &Env, feeds: &Vec<PriceData>) {
+    require_migrated(e);
     let current = ContractStatus::from_u32(e, storage::get_status(e));
     let vault = storage::get_vault(e);
     let markets = storage::get_markets(e);
@@ -34,10 +80,7 @@ pub fn execute_update_status(e: &Env, feeds: &Vec<PriceData>) {
 
     // Build a feed_id -> PriceData lookup from the input feeds.
     // feeds.len() can be <= markets.len() when multiple markets share a feed.
-    let mut feed_map: Map<u32, PriceData> = Map::new(e);
-    for f in feeds.iter() {
-        feed_map.set(f.feed_id, f);
-    }
+    let feeds = feed_map(e, feeds);
 
     let mut cached: Map<u32, (MarketConfig, MarketData, i128, i128)> = Map::new(e);
     let mut net_pnl: i128 = 0;
@@ -45,13 +88,9 @@ pub fn execute_update_status(e: &Env, feeds: &Vec<PriceData>) {
 
     for market_id in markets.iter() {
         let config = storage::get_market_config(e, market_id);
-        let f = feed_map.get(config.feed_id)
-            .unwrap_or_else(|| panic_with_error!(e, TradingError::InvalidPrice));
+        let f = to_market_units(e, market_id, load_price(e, &config, &feeds));
         let data = storage::get_market_data(e, market_id);
-        let ps = scalar_from_exponent(f.exponent);
-
-        let long_pnl = f.price.fixed_mul_floor(e, &data.l_entry_wt, &ps) - data.l_notional;
-        let short_pnl = data.s_notional - f.price.fixed_mul_floor(e, &data.s_entry_wt, &ps);
+        let (long_pnl, short_pnl) = side_pnls(e, &f, &data);
 
         net_pnl += long_pnl + short_pnl;
         if long_pnl > 0 { total_winner_pnl += long_pnl; }
@@ -139,16 +178,7 @@ fn do_adl(
     let mut new_total: i128 = 0;
     for (market_id, (config, mut data, long_pnl, short_pnl)) in cached.iter() {
         // Accrue indices against pre-ADL notionals before reducing them
-        data.accrue(
-            e,
-            trading_config.r_base,
-            trading_config.r_var,
-            config.r_var_market,
-            vault_balance,
-            total_notional,
-            trading_config.max_util,
-            config.max_util,
-        );
+        data.accrue_market(e, market_id, &trading_config, &config, vault_balance, total_notional);
 
         let mut changed = false;
 
@@ -265,6 +295,53 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_solvency_reports_deficit() {
+        let e = Env::default();
+        e.mock_all_auths();
+        jump(&e, 1000);
+
+        let contract = setup_small_vault(&e, 100 * SCALAR_7);
+        // Longs opened at half today's price are owed their notional again
+        set_market_positions(&e, &contract, 1000 * SCALAR_7, 0, 50_000 * PRICE_SCALAR);
+
+        e.as_contract(&contract, || {
+            let (collateral, net_pnl, vault_balance, surplus) =
+                super::execute_solvency(&e, &vec![&e, btc_feed(&e)]);
+            assert_eq!(collateral, 0);
+            assert_eq!(net_pnl, 1000 * SCALAR_7);
+            assert_eq!(vault_balance, 100 * SCALAR_7);
+            assert_eq!(surplus, -900 * SCALAR_7);
+            // Read-only: status is untouched
+            assert_eq!(storage::get_status(&e), ContractStatus::Active as u32);
+        });
+    }
+
+    #[test]
+    fn test_solvency_collateral_excludes_bounties_and_referrals() {
+        use crate::testutils::{setup_contract, setup_env, update_config};
+        use soroban_sdk::testutils::Address as _;
+
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+        update_config(&e, &contract, |c| c.fill_bounty = SCALAR_7 / 10);
+        e.as_contract(&contract, || {
+            storage::add_referral_rewards(&e, &Address::generate(&e), 3 * SCALAR_7);
+        });
+
+        let balance = token_client.balance(&contract);
+        e.as_contract(&contract, || {
+            crate::trading::execute_create_limit(
+                &e, &user, FEED_BTC, 1_000 * SCALAR_7, 10_000 * SCALAR_7, true, 90_000 * PRICE_SCALAR, 0, 0, 0, false, 0,
+            );
+            assert_eq!(storage::get_bounty_escrow(&e), SCALAR_7 / 10);
+            let (collateral, _, _, _) = super::execute_solvency(&e, &vec![&e, btc_feed(&e)]);
+            assert_eq!(collateral, balance + 1_000 * SCALAR_7 - 3 * SCALAR_7);
+        });
+    }
+
     #[test]
     #[should_panic(expected = "Error(Contract, #750)")]
     fn test_update_status_threshold_not_met() {
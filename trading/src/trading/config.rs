@@ -1,14 +1,29 @@
-use crate::constants::MAX_ENTRIES;
+use crate::constants::{MAX_DUST, MAX_ENTRIES, STORAGE_VERSION};
+use crate::dependencies::VaultClient;
 use crate::errors::TradingError;
-use crate::events::{DelMarket, SetConfig, SetMarket, SetStatus};
+use crate::legacy;
+use crate::events::{DelMarket, Migrate, SetConfig, SetFrozen, SetMarket, SetStatus, Sweep, UpdateMarket};
 use crate::types::{ContractStatus, MarketConfig, TradingConfig};
-use crate::validation::{require_valid_config, require_valid_market_config};
+use crate::validation::{
+    require_fee_split, require_migrated, require_notional_range, require_valid_basket, require_valid_config,
+    require_valid_market_config,
+};
 use crate::{storage, MarketData};
-use soroban_sdk::{panic_with_error, Env};
+use soroban_sdk::token::TokenClient;
+use soroban_sdk::{panic_with_error, Address, Env};
 
 /// Validate and store a new global trading configuration.
+///
+/// Like every owner writer of configs and market state, refused with
+/// `MigrationPending` until `migrate` has rewritten the stored layout.
 pub fn execute_set_config(e: &Env, config: &TradingConfig) {
+    require_migrated(e);
     require_valid_config(e, config);
+    for market_id in storage::get_markets(e).iter() {
+        let market = storage::get_market_config(e, market_id);
+        require_fee_split(e, config, market.fee_share);
+        require_notional_range(e, config, &market);
+    }
     storage::set_config(e, config);
     (SetConfig {}).publish(e);
 }
@@ -19,10 +34,17 @@ pub fn execute_set_config(e: &Env, config: &TradingConfig) {
 /// and `last_update` at current timestamp. Also seeds `last_funding_update` for the
 /// first market to establish the funding cadence.
 ///
-/// `config.feed_id` is immutable after creation: updating an existing market with a
-/// different `feed_id` panics with `InvalidConfig`.
+/// On an existing market this is `execute_update_market`: indices accrue under
+/// the old config first. `config.feed_id` and `config.basket` are immutable after
+/// creation: updating an existing market with a different value panics with
+/// `InvalidConfig`.
 pub fn execute_set_market(e: &Env, market_id: u32, config: &MarketConfig) {
+    require_migrated(e);
     require_valid_market_config(e, config);
+    require_valid_basket(e, &config.basket);
+    let trading_config = storage::get_config(e);
+    require_fee_split(e, &trading_config, config.fee_share);
+    require_notional_range(e, &trading_config, config);
 
     let mut markets = storage::get_markets(e);
     let is_new = !markets.contains(market_id);
@@ -40,20 +62,54 @@ pub fn execute_set_market(e: &Env, market_id: u32, config: &MarketConfig) {
         };
         storage::set_market_data(e, market_id, &initial_data);
     } else {
-        // feed_id is immutable after creation
-        let existing = storage::get_market_config(e, market_id);
-        if config.feed_id != existing.feed_id {
-            panic_with_error!(e, TradingError::InvalidConfig);
-        }
+        accrue_for_update(e, market_id, config);
     }
 
     storage::set_market_config(e, market_id, config);
     SetMarket { market_id }.publish(e);
 }
 
+/// Update the configuration of an existing market.
+///
+/// Unlike `execute_set_market`, this never registers a market, so a queued
+/// retune can't create one if its target was deleted meanwhile. Open interest
+/// and entry weights are untouched and indices accrue under the old config
+/// first, so live markets can be retuned with positions open.
+pub fn execute_update_market(e: &Env, market_id: u32, config: &MarketConfig) {
+    require_migrated(e);
+    if !storage::has_market(e, market_id) {
+        panic_with_error!(e, TradingError::MarketNotFound);
+    }
+    require_valid_market_config(e, config);
+    let trading_config = storage::get_config(e);
+    require_fee_split(e, &trading_config, config.fee_share);
+    require_notional_range(e, &trading_config, config);
+    accrue_for_update(e, market_id, config);
+
+    storage::set_market_config(e, market_id, config);
+    UpdateMarket { market_id }.publish(e);
+}
+
+/// Accrue a live market's indices up to now under its stored config, so the
+/// new rates only apply from the update on. `feed_id` and `basket` are
+/// immutable after creation.
+fn accrue_for_update(e: &Env, market_id: u32, config: &MarketConfig) {
+    let existing = storage::get_market_config(e, market_id);
+    if config.feed_id != existing.feed_id || config.basket != existing.basket {
+        panic_with_error!(e, TradingError::InvalidConfig);
+    }
+
+    let trading_config = storage::get_config(e);
+    let vault_balance = VaultClient::new(e, &storage::get_vault(e)).total_assets();
+    let mut data = storage::get_market_data(e, market_id);
+    data.accrue_market(e, market_id, &trading_config, &existing, vault_balance, storage::get_total_notional(e));
+    storage::set_market_data(e, market_id, &data);
+}
+
 /// Remove a market. Subtracts remaining OI from total_notional and cleans up
 /// market storage. Existing positions are refunded via cancel_position.
 pub fn execute_del_market(e: &Env, market_id: u32) {
+    require_migrated(e);
     let mut markets = storage::get_markets(e);
     let idx = markets
         .iter()
@@ -72,6 +128,8 @@ pub fn execute_del_market(e: &Env, market_id: u32) {
     storage::set_markets(e, &markets);
     storage::remove_market_config(e, market_id);
     storage::remove_market_data(e, market_id);
+    storage::remove_price_exponent(e, market_id);
+    storage::remove_last_price(e, market_id);
     DelMarket { market_id }.publish(e);
 }
 
@@ -89,12 +147,129 @@ pub fn execute_set_status(e: &Env, status: u32) {
     SetStatus { status }.publish(e);
 }
 
+/// Freeze or unfreeze a single position. While frozen, every action against it
+/// reverts with `PositionFrozen` (see `Position::require_unfrozen`).
+pub fn execute_set_frozen(e: &Env, user: &Address, id: u32, frozen: bool) {
+    let mut position = storage::get_position(e, user, id);
+    if position.frozen == frozen {
+        return;
+    }
+    position.frozen = frozen;
+    position.next_seq();
+    storage::set_position(e, user, id, &position);
+
+    SetFrozen {
+        market_id: position.market_id,
+        user: user.clone(),
+        position_id: id,
+        event_seq: position.event_seq,
+        frozen,
+    }
+    .publish(e);
+}
+
+/// Transfer the contract's full balance of a stray token to `to`.
+///
+/// The collateral token is refused: position collateral is not aggregated on-chain,
+/// so no excess over outstanding obligations can be computed safely.
+pub fn execute_sweep(e: &Env, token: &Address, to: &Address) -> i128 {
+    if *token == storage::get_token(e) {
+        panic_with_error!(e, TradingError::TokenNotSweepable);
+    }
+
+    let token_client = TokenClient::new(e, token);
+    let amount = token_client.balance(&e.current_contract_address());
+    if amount > 0 {
+        token_client.transfer(&e.current_contract_address(), to, &amount);
+    }
+
+    Sweep {
+        token: token.clone(),
+        to: to.clone(),
+        amount,
+    }
+    .publish(e);
+
+    amount
+}
+
+/// Transfer the contract's residual collateral-token balance to the vault, at
+/// most `MAX_DUST` per call.
+///
+/// Only callable once no position (pending or filled) holds collateral and no
+/// open interest is recorded, so the balance beyond unclaimed referral rewards
+/// is rounding dust or stray transfers and belongs to LPs. The cap bounds what
+/// a miscounted live-position total could release.
+pub fn execute_sweep_dust(e: &Env) -> i128 {
+    if storage::get_live_positions(e) > 0 || storage::get_total_notional(e) > 0 {
+        panic_with_error!(e, TradingError::TokenNotSweepable);
+    }
+
+    let token = storage::get_token(e);
+    let vault = storage::get_vault(e);
+    let token_client = TokenClient::new(e, &token);
+    let residual = token_client.balance(&e.current_contract_address()) - storage::get_referral_owed(e);
+    let amount = residual.clamp(0, MAX_DUST);
+    if amount > 0 {
+        token_client.transfer(&e.current_contract_address(), &vault, &amount);
+    }
+
+    Sweep { token, to: vault, amount }.publish(e);
+
+    amount
+}
+
+/// Register the vault's swap router for `reward`, acting as its strategy.
+///
+/// The vault only accepts reward routing from its strategy, so this contract
+/// is the one path that can set it up.
+pub fn execute_set_reward_router(e: &Env, reward: &Address, router: &Address) {
+    VaultClient::new(e, &storage::get_vault(e)).set_reward_router(&e.current_contract_address(), reward, router);
+}
+
+/// Have the vault swap its `reward` balance into the vault asset, acting as
+/// its strategy. Returns the asset amount received.
+pub fn execute_compound_reward(e: &Env, reward: &Address, min_out: i128) -> i128 {
+    VaultClient::new(e, &storage::get_vault(e)).compound(&e.current_contract_address(), reward, &min_out)
+}
+
+/// Bring stored entries up to `STORAGE_VERSION` after an upgrade.
+///
+/// Runs one step per version, each rewriting entries written under `v` into
+/// the `v + 1` layout, then records the new version so operations resume.
+/// Positions are keyed per user and can't be enumerated on-chain, so a step
+/// that changes `Position` must read the old layout lazily on access instead.
+///
+/// The live-position count gates `sweep_dust` and can't be rebuilt on-chain, so
+/// the baseline step seeds it with `live_positions`, the pending and filled
+/// positions open at the upgrade as counted off-chain from events. The other
+/// counters introduced since the baseline (pending orders and open notional per
+/// user) start at zero and don't count positions opened before the upgrade; they
+/// only loosen the caps they feed until those positions close.
+///
+/// # Panics
+/// - `TradingError::NothingToMigrate` (761) if storage is already current
+pub fn execute_migrate(e: &Env, live_positions: u32) {
+    let from = storage::get_storage_version(e);
+    if from >= STORAGE_VERSION {
+        panic_with_error!(e, TradingError::NothingToMigrate);
+    }
+
+    if from == 0 {
+        legacy::migrate_v0(e);
+        storage::set_live_positions(e, live_positions);
+    }
+
+    storage::set_storage_version(e, STORAGE_VERSION);
+    Migrate { from, to: STORAGE_VERSION }.publish(e);
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::constants::SCALAR_18;
+    use crate::constants::{SCALAR_18, SCALAR_7};
     use crate::storage;
     use crate::testutils::{
-        create_trading, default_market, jump, FEED_BTC,
+        create_trading, default_market, jump, FEED_BTC, FEED_ETH, FEED_XLM,
     };
     use crate::types::ContractStatus;
     use soroban_sdk::Env;
@@ -116,6 +291,32 @@ mod tests {
         });
     }
 
+    #[test]
+    #[should_panic(expected = "Error(Contract, #705)")]
+    fn test_constructor_vault_asset_mismatch_panics() {
+        use crate::contract::TradingContract;
+        use crate::testutils::{create_price_verifier, create_token, create_treasury, create_vault, default_config};
+        use soroban_sdk::{testutils::Address as _, Address};
+
+        let e = Env::default();
+        e.mock_all_auths();
+        jump(&e, 1000);
+
+        let owner = Address::generate(&e);
+        let (token, _) = create_token(&e, &owner);
+        let (other_token, _) = create_token(&e, &owner);
+        let vault = create_vault(&e, &other_token, 0);
+        let (price_verifier, _) = create_price_verifier(&e);
+        e.register(TradingContract {}, (
+            owner,
+            token,
+            vault,
+            price_verifier,
+            create_treasury(&e),
+            default_config(),
+        ));
+    }
+
     #[test]
     fn test_set_config() {
         let e = Env::default();
@@ -150,7 +351,7 @@ mod tests {
             assert_eq!(markets.len(), 1);
             assert_eq!(markets.get(0).unwrap(), FEED_BTC);
             let stored = storage::get_market_config(&e, FEED_BTC);
-            assert_eq!(stored.enabled, true);
+            assert!(stored.enabled);
 
             let data = storage::get_market_data(&e, FEED_BTC);
             assert_eq!(data.l_notional, 0);
@@ -160,6 +361,398 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_update_market_preserves_market_data() {
+        let e = Env::default();
+        e.mock_all_auths();
+        jump(&e, 1000);
+
+        let (contract, _owner) = create_trading(&e);
+
+        e.as_contract(&contract, || {
+            super::execute_set_market(&e, FEED_BTC, &default_market(&e));
+            let mut data = storage::get_market_data(&e, FEED_BTC);
+            data.l_notional = 5_000;
+            data.l_fund_idx = 42;
+            storage::set_market_data(&e, FEED_BTC, &data);
+
+            jump(&e, 2000);
+            let mut config = default_market(&e);
+            config.margin *= 2;
+            super::execute_update_market(&e, FEED_BTC, &config);
+
+            assert_eq!(storage::get_market_config(&e, FEED_BTC).margin, config.margin);
+            let after = storage::get_market_data(&e, FEED_BTC);
+            assert_eq!(after.l_notional, 5_000);
+            assert_eq!(after.l_fund_idx, 42);
+            assert_eq!(after.last_update, 2000);
+        });
+    }
+
+    #[test]
+    fn test_update_market_rate_change_only_prices_later_time() {
+        let e = Env::default();
+        e.mock_all_auths();
+        jump(&e, 1000);
+
+        let (contract, _owner) = create_trading(&e);
+
+        e.as_contract(&contract, || {
+            let old = default_market(&e);
+            super::execute_set_market(&e, FEED_BTC, &old);
+            let mut data = storage::get_market_data(&e, FEED_BTC);
+            data.l_notional = 50_000 * SCALAR_7;
+            storage::set_market_data(&e, FEED_BTC, &data);
+            storage::set_total_notional(&e, 50_000 * SCALAR_7);
+
+            // One hour at the old rate, then the market rate jumps to the max
+            jump(&e, 1000 + 3600);
+            let mut config = old.clone();
+            config.r_var_market = crate::constants::MAX_R_VAR_MARKET;
+            super::execute_update_market(&e, FEED_BTC, &config);
+
+            let after = storage::get_market_data(&e, FEED_BTC);
+            let first_hour = after.l_borr_idx;
+            assert_eq!(after.last_update, 1000 + 3600);
+
+            // Same hour priced under the old config
+            let trading_config = storage::get_config(&e);
+            let vault_balance = crate::dependencies::VaultClient::new(&e, &storage::get_vault(&e)).total_assets();
+            data.accrue(
+                &e,
+                trading_config.r_base,
+                trading_config.r_var,
+                old.r_var_market,
+                vault_balance,
+                50_000 * SCALAR_7,
+                trading_config.max_util,
+                old.max_util,
+                old.min_side,
+            );
+            assert_eq!(first_hour, data.l_borr_idx);
+
+            // The next hour prices at the new, higher rate
+            jump(&e, 1000 + 7200);
+            super::execute_update_market(&e, FEED_BTC, &config);
+            let second_hour = storage::get_market_data(&e, FEED_BTC).l_borr_idx - first_hour;
+            assert!(second_hour > first_hour);
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #700)")]
+    fn test_set_market_descending_tiers_panics() {
+        use crate::types::MarginTier;
+        let e = Env::default();
+        e.mock_all_auths();
+        jump(&e, 1000);
+
+        let (contract, _owner) = create_trading(&e);
+
+        e.as_contract(&contract, || {
+            let mut config = default_market(&e);
+            config.tiers = soroban_sdk::vec![
+                &e,
+                MarginTier { notional: 100_000, margin: 2_000_000 },
+                MarginTier { notional: 200_000, margin: 1_000_000 },
+            ];
+            super::execute_set_market(&e, FEED_BTC, &config);
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #700)")]
+    fn test_set_market_margin_at_liq_fee_panics() {
+        let e = Env::default();
+        e.mock_all_auths();
+        jump(&e, 1000);
+
+        let (contract, _owner) = create_trading(&e);
+
+        // Equity at max leverage would already sit at the liquidation threshold
+        e.as_contract(&contract, || {
+            let mut config = default_market(&e);
+            config.liq_fee = config.margin;
+            super::execute_set_market(&e, FEED_BTC, &config);
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #700)")]
+    fn test_set_market_duration_above_max_panics() {
+        let e = Env::default();
+        e.mock_all_auths();
+        jump(&e, 1000);
+
+        let (contract, _owner) = create_trading(&e);
+
+        e.as_contract(&contract, || {
+            let mut config = default_market(&e);
+            config.max_duration = u64::MAX;
+            super::execute_set_market(&e, FEED_BTC, &config);
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #700)")]
+    fn test_set_market_zero_impact_panics() {
+        let e = Env::default();
+        e.mock_all_auths();
+        jump(&e, 1000);
+
+        let (contract, _owner) = create_trading(&e);
+
+        e.as_contract(&contract, || {
+            let mut config = default_market(&e);
+            config.impact = 0;
+            super::execute_set_market(&e, FEED_BTC, &config);
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #700)")]
+    fn test_set_market_user_cap_below_min_notional_panics() {
+        let e = Env::default();
+        e.mock_all_auths();
+        jump(&e, 1000);
+
+        let (contract, _owner) = create_trading(&e);
+
+        e.as_contract(&contract, || {
+            let mut config = default_market(&e);
+            config.min_notional = 1_000 * SCALAR_7;
+            config.max_user_notional = 999 * SCALAR_7;
+            super::execute_set_market(&e, FEED_BTC, &config);
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #700)")]
+    fn test_set_market_min_notional_above_global_max_panics() {
+        let e = Env::default();
+        e.mock_all_auths();
+        jump(&e, 1000);
+
+        let (contract, _owner) = create_trading(&e);
+
+        e.as_contract(&contract, || {
+            let mut config = default_market(&e);
+            config.min_notional = storage::get_config(&e).max_notional + 1;
+            super::execute_set_market(&e, FEED_BTC, &config);
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #700)")]
+    fn test_set_config_max_notional_below_market_floor_panics() {
+        let e = Env::default();
+        e.mock_all_auths();
+        jump(&e, 1000);
+
+        let (contract, _owner) = create_trading(&e);
+
+        e.as_contract(&contract, || {
+            let mut market = default_market(&e);
+            market.min_notional = 50_000 * SCALAR_7;
+            super::execute_set_market(&e, FEED_BTC, &market);
+
+            let mut config = storage::get_config(&e);
+            config.max_notional = 40_000 * SCALAR_7;
+            super::execute_set_config(&e, &config);
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #700)")]
+    fn test_set_config_min_notional_above_user_cap_panics() {
+        let e = Env::default();
+        e.mock_all_auths();
+        jump(&e, 1000);
+
+        let (contract, _owner) = create_trading(&e);
+
+        e.as_contract(&contract, || {
+            let mut market = default_market(&e);
+            market.max_user_notional = 100 * SCALAR_7;
+            super::execute_set_market(&e, FEED_BTC, &market);
+
+            let mut config = storage::get_config(&e);
+            config.min_notional = 200 * SCALAR_7;
+            super::execute_set_config(&e, &config);
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #700)")]
+    fn test_set_config_fill_bounty_above_max_fee_panics() {
+        let e = Env::default();
+        e.mock_all_auths();
+        jump(&e, 1000);
+
+        let (contract, _owner) = create_trading(&e);
+
+        e.as_contract(&contract, || {
+            // 1% of the 10 token minimum order is 0.1 token
+            let mut config = storage::get_config(&e);
+            config.fill_bounty = SCALAR_7 / 10;
+            super::execute_set_config(&e, &config);
+            config.fill_bounty += 1;
+            super::execute_set_config(&e, &config);
+        });
+    }
+
+    #[test]
+    fn test_set_market_basket() {
+        use crate::types::FeedWeight;
+        let e = Env::default();
+        e.mock_all_auths();
+        jump(&e, 1000);
+
+        let (contract, _owner) = create_trading(&e);
+
+        e.as_contract(&contract, || {
+            let mut config = default_market(&e);
+            config.feed_id = 100;
+            config.basket = soroban_sdk::vec![
+                &e,
+                FeedWeight { feed_id: FEED_BTC, weight: 6_000_000 },
+                FeedWeight { feed_id: FEED_ETH, weight: 4_000_000 },
+            ];
+            super::execute_set_market(&e, 100, &config);
+            assert_eq!(storage::get_market_config(&e, 100).basket, config.basket);
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #700)")]
+    fn test_set_market_basket_weights_not_full_panics() {
+        use crate::types::FeedWeight;
+        let e = Env::default();
+        e.mock_all_auths();
+        jump(&e, 1000);
+
+        let (contract, _owner) = create_trading(&e);
+
+        // 60% + 30%: the index would read 10% below its constituents
+        e.as_contract(&contract, || {
+            let mut config = default_market(&e);
+            config.feed_id = 100;
+            config.basket = soroban_sdk::vec![
+                &e,
+                FeedWeight { feed_id: FEED_BTC, weight: 6_000_000 },
+                FeedWeight { feed_id: FEED_ETH, weight: 3_000_000 },
+            ];
+            super::execute_set_market(&e, 100, &config);
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #700)")]
+    fn test_set_market_single_constituent_basket_panics() {
+        use crate::types::FeedWeight;
+        let e = Env::default();
+        e.mock_all_auths();
+        jump(&e, 1000);
+
+        let (contract, _owner) = create_trading(&e);
+
+        e.as_contract(&contract, || {
+            let mut config = default_market(&e);
+            config.feed_id = 100;
+            config.basket = soroban_sdk::vec![&e, FeedWeight { feed_id: FEED_BTC, weight: SCALAR_7 }];
+            super::execute_set_market(&e, 100, &config);
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #700)")]
+    fn test_set_market_fee_share_without_recipient_panics() {
+        let e = Env::default();
+        e.mock_all_auths();
+        jump(&e, 1000);
+
+        let (contract, _owner) = create_trading(&e);
+
+        e.as_contract(&contract, || {
+            let mut config = default_market(&e);
+            config.fee_share = 2_000_000;
+            super::execute_set_market(&e, FEED_BTC, &config);
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #700)")]
+    fn test_set_market_fee_share_past_treasury_headroom_panics() {
+        use soroban_sdk::{testutils::Address as _, Address};
+        let e = Env::default();
+        e.mock_all_auths();
+        jump(&e, 1000);
+
+        let (contract, _owner) = create_trading(&e);
+
+        // 10% caller + 45% partner leaves less than the treasury's 50% cap
+        e.as_contract(&contract, || {
+            let mut config = default_market(&e);
+            config.fee_recipient = Some(Address::generate(&e));
+            config.fee_share = 4_500_000;
+            super::execute_set_market(&e, FEED_BTC, &config);
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #700)")]
+    fn test_set_config_overruns_market_fee_share_panics() {
+        use soroban_sdk::{testutils::Address as _, Address};
+        let e = Env::default();
+        e.mock_all_auths();
+        jump(&e, 1000);
+
+        let (contract, _owner) = create_trading(&e);
+
+        e.as_contract(&contract, || {
+            let mut market = default_market(&e);
+            market.fee_recipient = Some(Address::generate(&e));
+            market.fee_share = 3_000_000;
+            super::execute_set_market(&e, FEED_BTC, &market);
+
+            // 30% partner + 10% caller + 20% referral overruns the split
+            let mut config = crate::testutils::default_config();
+            config.referral_share = 2_000_000;
+            super::execute_set_config(&e, &config);
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #701)")]
+    fn test_update_market_unknown_panics() {
+        let e = Env::default();
+        e.mock_all_auths();
+        jump(&e, 1000);
+
+        let (contract, _owner) = create_trading(&e);
+
+        e.as_contract(&contract, || {
+            super::execute_update_market(&e, FEED_BTC, &default_market(&e));
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #700)")]
+    fn test_update_market_feed_change_panics() {
+        let e = Env::default();
+        e.mock_all_auths();
+        jump(&e, 1000);
+
+        let (contract, _owner) = create_trading(&e);
+
+        e.as_contract(&contract, || {
+            super::execute_set_market(&e, FEED_BTC, &default_market(&e));
+            let mut config = default_market(&e);
+            config.feed_id = FEED_BTC + 1;
+            super::execute_update_market(&e, FEED_BTC, &config);
+        });
+    }
+
     #[test]
     fn test_del_market() {
         let e = Env::default();
@@ -188,6 +781,37 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_get_all_markets() {
+        let e = Env::default();
+        e.mock_all_auths();
+        jump(&e, 1000);
+
+        let (contract, _owner) = create_trading(&e);
+        let client = crate::TradingClient::new(&e, &contract);
+
+        for feed_id in [FEED_BTC, FEED_ETH, FEED_XLM] {
+            let mut config = default_market(&e);
+            config.feed_id = feed_id;
+            client.set_market(&feed_id, &config);
+        }
+        e.as_contract(&contract, || {
+            let mut data = storage::get_market_data(&e, FEED_ETH);
+            data.s_notional = 7_000;
+            storage::set_market_data(&e, FEED_ETH, &data);
+        });
+
+        let all = client.get_all_markets();
+        assert_eq!(all.len(), 3);
+        for (i, feed_id) in [FEED_BTC, FEED_ETH, FEED_XLM].into_iter().enumerate() {
+            let (market_id, config, data) = all.get(i as u32).unwrap();
+            assert_eq!(market_id, feed_id);
+            assert_eq!(config.feed_id, feed_id);
+            assert_eq!(data.last_update, 1000);
+            assert_eq!(data.s_notional, if feed_id == FEED_ETH { 7_000 } else { 0 });
+        }
+    }
+
     #[test]
     fn test_set_status() {
         let e = Env::default();
@@ -304,4 +928,256 @@ mod tests {
             assert!(storage::get_market_config(&e, FEED_BTC).enabled);
         });
     }
+
+    #[test]
+    fn test_sweep_stray_token() {
+        use crate::testutils::{create_token, setup_contract, setup_env};
+        use soroban_sdk::testutils::Address as _;
+        use soroban_sdk::Address;
+
+        let e = setup_env();
+        let (contract, _token_client) = setup_contract(&e);
+        let (stray, stray_client) = create_token(&e, &Address::generate(&e));
+        let to = Address::generate(&e);
+        stray_client.mint(&contract, &1_000);
+
+        let swept = e.as_contract(&contract, || super::execute_sweep(&e, &stray, &to));
+        assert_eq!(swept, 1_000);
+        assert_eq!(stray_client.balance(&to), 1_000);
+        assert_eq!(stray_client.balance(&contract), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #760)")]
+    fn test_sweep_collateral_token_panics() {
+        use crate::testutils::{setup_contract, setup_env};
+        use soroban_sdk::testutils::Address as _;
+        use soroban_sdk::Address;
+
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let to = Address::generate(&e);
+
+        e.as_contract(&contract, || {
+            super::execute_sweep(&e, &token_client.address, &to);
+        });
+    }
+
+    #[test]
+    fn test_sweep_dust_to_vault_after_odd_trades() {
+        use crate::constants::SCALAR_7;
+        use crate::dependencies::PriceData;
+        use crate::testutils::{setup_contract, setup_env, BTC_PRICE};
+        use crate::trading::{execute_close_position, execute_create_market};
+        use soroban_sdk::testutils::Address as _;
+        use soroban_sdk::{Address, Bytes};
+
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        let pd = PriceData { feed_id: FEED_BTC, price: BTC_PRICE, exponent: -8, publish_time: 1000 };
+        let ids = [
+            (1_234_567_891, 9_876_543_211, true),
+            (777_777_777, 3_333_333_337, false),
+        ]
+        .map(|(col, notional, long)| {
+            e.as_contract(&contract, || {
+                execute_create_market(&e, &user, FEED_BTC, col, notional, long, 0, 0, &pd)
+            })
+        });
+        jump(&e, 1031);
+        for id in ids {
+            e.as_contract(&contract, || execute_close_position(&e, &user, id, Bytes::new(&e)));
+        }
+        // Stray transfer on top of any rounding dust left by the trades
+        token_client.mint(&contract, &7);
+
+        let vault = e.as_contract(&contract, || storage::get_vault(&e));
+        let token = soroban_sdk::token::TokenClient::new(&e, &token_client.address);
+        let residual = token.balance(&contract);
+        let vault_before = token.balance(&vault);
+        assert!(residual >= 7);
+
+        // The setup's contract float is far above the cap: one call moves MAX_DUST
+        let swept = e.as_contract(&contract, || super::execute_sweep_dust(&e));
+        assert_eq!(swept, crate::constants::MAX_DUST);
+        assert_eq!(token.balance(&contract), residual - swept);
+        assert_eq!(token.balance(&vault) - vault_before, swept);
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #760)")]
+    fn test_sweep_dust_with_live_position_panics() {
+        use crate::constants::SCALAR_7;
+        use crate::testutils::{setup_contract, setup_env, BTC_PRICE};
+        use soroban_sdk::testutils::Address as _;
+        use soroban_sdk::Address;
+
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        e.as_contract(&contract, || {
+            crate::trading::execute_create_limit(
+                &e, &user, FEED_BTC, 1_000 * SCALAR_7, 10_000 * SCALAR_7, true, BTC_PRICE, 0, 0, 0, false, 0,
+            );
+            super::execute_sweep_dust(&e);
+        });
+    }
+
+    #[test]
+    fn test_compound_reward_forwards_to_vault_as_strategy() {
+        use crate::testutils::{setup_contract, setup_env};
+        use soroban_sdk::testutils::Address as _;
+        use soroban_sdk::Address;
+
+        let e = setup_env();
+        let (contract, _token_client) = setup_contract(&e);
+        let client = crate::TradingClient::new(&e, &contract);
+        let (reward, router) = (Address::generate(&e), Address::generate(&e));
+
+        // The mock vault requires the strategy's auth and rejects unregistered rewards
+        assert!(client.try_compound_reward(&reward, &5).is_err());
+        client.set_reward_router(&reward, &router);
+        assert_eq!(client.compound_reward(&reward, &5), 5);
+    }
+
+    #[test]
+    fn test_migrate_converts_baseline_layout() {
+        use crate::constants::{SCALAR_18, SCALAR_7, STORAGE_VERSION};
+        use crate::dependencies::PriceData;
+        use crate::legacy::{MarketConfigV0, PositionV0, TradingConfigV0};
+        use crate::storage::TradingStorageKey;
+        use crate::testutils::{setup_contract, setup_env, BTC_PRICE};
+        use crate::trading::{execute_cancel_position, execute_create_market};
+        use soroban_sdk::testutils::Address as _;
+        use soroban_sdk::Address;
+
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let client = crate::TradingClient::new(&e, &contract);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        // Storage as the baseline WASM wrote it: fewer fields, no version entry
+        let legacy_order = PositionV0 {
+            filled: false,
+            market_id: FEED_BTC,
+            long: true,
+            sl: 0,
+            tp: 0,
+            entry_price: BTC_PRICE,
+            col: 500 * SCALAR_7,
+            notional: 5_000 * SCALAR_7,
+            fund_idx: 0,
+            borr_idx: 0,
+            adl_idx: SCALAR_18,
+            created_at: 900,
+        };
+        e.as_contract(&contract, || {
+            let c = storage::get_config(&e);
+            let old = TradingConfigV0 {
+                caller_rate: c.caller_rate,
+                min_notional: c.min_notional,
+                max_notional: c.max_notional,
+                fee_dom: 7_000,
+                fee_non_dom: c.fee_non_dom,
+                max_util: c.max_util,
+                r_funding: c.r_funding,
+                r_base: c.r_base,
+                r_var: c.r_var,
+            };
+            e.storage().instance().set(&TradingStorageKey::Config, &old);
+            let m = storage::get_market_config(&e, FEED_BTC);
+            let old = MarketConfigV0 {
+                feed_id: m.feed_id,
+                enabled: m.enabled,
+                max_util: m.max_util,
+                r_var_market: m.r_var_market,
+                margin: 200_000,
+                liq_fee: m.liq_fee,
+                impact: m.impact,
+            };
+            e.storage().persistent().set(&TradingStorageKey::MarketConfig(FEED_BTC), &old);
+            e.storage().persistent().set(&TradingStorageKey::Position(user.clone(), 0), &legacy_order);
+            e.storage().persistent().set(&TradingStorageKey::UserCounter(user.clone()), &1u32);
+            e.storage().instance().remove(&TradingStorageKey::StorageVersion);
+        });
+
+        client.migrate(&1);
+        assert_eq!(e.as_contract(&contract, || storage::get_storage_version(&e)), STORAGE_VERSION);
+        // The baseline order is counted, so sweep_dust stays closed until it is gone
+        assert_eq!(client.try_sweep_dust(), Err(Ok(crate::errors::TradingError::TokenNotSweepable.into())));
+        e.as_contract(&contract, || {
+            let config = storage::get_config(&e);
+            assert_eq!((config.fee_dom, config.max_pending, config.keeper_min_shares), (7_000, 0, 0));
+            let market = storage::get_market_config(&e, FEED_BTC);
+            assert_eq!(market.margin, 200_000);
+            assert!(market.allow_long && market.allow_short && market.basket.is_empty());
+
+            // Positions convert on read, with defaults for the new fields
+            let order = storage::get_position(&e, &user, 0);
+            assert_eq!((order.col, order.created_at, order.entry_price), (500 * SCALAR_7, 900, BTC_PRICE));
+            assert!(order.auto_trigger && !order.frozen && order.bounty == 0);
+        });
+
+        let before = token_client.balance(&user);
+        e.as_contract(&contract, || execute_cancel_position(&e, &user, 0));
+        assert_eq!(token_client.balance(&user), before + 500 * SCALAR_7);
+        assert_eq!(e.as_contract(&contract, || storage::get_live_positions(&e)), 0);
+
+        let pd = PriceData { feed_id: FEED_BTC, price: BTC_PRICE, exponent: -8, publish_time: 1000 };
+        e.as_contract(&contract, || {
+            execute_create_market(&e, &user, FEED_BTC, 1_000 * SCALAR_7, 10_000 * SCALAR_7, true, 0, 0, &pd)
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #743)")]
+    fn test_operations_blocked_until_migrated() {
+        use crate::constants::{SCALAR_7, STORAGE_VERSION};
+        use crate::dependencies::PriceData;
+        use crate::testutils::{setup_contract, setup_env, BTC_PRICE};
+        use crate::trading::execute_create_market;
+        use soroban_sdk::testutils::Address as _;
+        use soroban_sdk::Address;
+
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        e.as_contract(&contract, || storage::set_storage_version(&e, STORAGE_VERSION - 1));
+        let pd = PriceData { feed_id: FEED_BTC, price: BTC_PRICE, exponent: -8, publish_time: 1000 };
+        e.as_contract(&contract, || {
+            execute_create_market(&e, &user, FEED_BTC, 1_000 * SCALAR_7, 10_000 * SCALAR_7, true, 0, 0, &pd)
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #743)")]
+    fn test_set_config_blocked_until_migrated() {
+        use crate::constants::STORAGE_VERSION;
+        use crate::testutils::{default_config, setup_contract, setup_env};
+
+        let e = setup_env();
+        let (contract, _) = setup_contract(&e);
+
+        // Writing the current layout now would leave migrate unable to decode it
+        e.as_contract(&contract, || {
+            storage::set_storage_version(&e, STORAGE_VERSION - 1);
+            super::execute_set_config(&e, &default_config());
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #761)")]
+    fn test_migrate_when_current_panics() {
+        let e = Env::default();
+        let (contract, _owner) = create_trading(&e);
+        crate::TradingClient::new(&e, &contract).migrate(&0);
+    }
 }
@@ -1,9 +1,12 @@
 use crate::constants::SCALAR_7;
 use crate::dependencies::{VaultClient, TreasuryClient};
 use crate::errors::TradingError;
+use crate::events::{BadDebt, StatsClamped};
 use crate::storage;
 use crate::trading::position::{Position, Settlement};
-use crate::types::{MarketConfig, MarketData, TradingConfig};
+use crate::trading::price::pin_exponent;
+use crate::trading::referral::credit_referral;
+use crate::types::{ContractStatus, MarketConfig, MarketData, TradingConfig};
 use crate::dependencies::{PriceData, scalar_from_exponent};
 use soroban_fixed_point_math::SorobanFixedPoint;
 use soroban_sdk::{panic_with_error, Address, Env};
@@ -23,6 +26,7 @@ pub struct Context {
     pub feed_id:      u32,
     pub price:        i128,
     pub price_scalar: i128,
+    pub exponent:     i32,
     pub publish_time: u64,
     pub config:       MarketConfig,
     pub data:         MarketData,
@@ -33,6 +37,9 @@ pub struct Context {
     pub token:          Address,
     pub treasury:       Address,
     pub total_notional: i128,
+    pub total_fees:     i128,
+    pub fee_free:       bool, // OnIce with `fee_free_wind_down`: user closes skip base/impact fees
+    pub caller_signed:  bool, // the batch `caller` has signed, see `require_caller_auth`
 }
 
 impl Context {
@@ -43,7 +50,9 @@ impl Context {
     /// - `price_data` - Verified price data from the oracle (contains feed_id, price, exponent)
     ///
     /// # Side effects
-    /// - Calls `MarketData::accrue()` to advance borrowing and funding indices
+    /// - Calls `MarketData::accrue_market()` to advance borrowing and funding indices
+    ///   (a no-op on repeat loads within a transaction: same timestamp, no time elapsed)
+    /// - Emits `IndexUpdate` if any index moved
     /// - Computes `price_scalar = 10^(-exponent)` from Pyth exponent
     ///
     /// # Panics
@@ -55,26 +64,21 @@ impl Context {
         let token = storage::get_token(e);
         let treasury = storage::get_treasury(e);
         let total_notional = storage::get_total_notional(e);
+        let total_fees = storage::get_total_fees(e);
+        let fee_free = trading_config.fee_free_wind_down
+            && storage::get_status(e) == ContractStatus::OnIce as u32;
         let config = storage::get_market_config(e, market_id);
         if price_data.feed_id != config.feed_id {
             panic_with_error!(e, TradingError::InvalidPrice);
         }
         let mut data = storage::get_market_data(e, market_id);
-        data.accrue(
-            e,
-            trading_config.r_base,
-            trading_config.r_var,
-            config.r_var_market,
-            vault_balance,
-            total_notional,
-            trading_config.max_util,
-            config.max_util,
-        );
+        data.accrue_market(e, market_id, &trading_config, &config, vault_balance, total_notional);
         Context {
             market_id,
             feed_id: config.feed_id,
             price: price_data.price,
             price_scalar: scalar_from_exponent(price_data.exponent),
+            exponent: price_data.exponent,
             publish_time: price_data.publish_time,
             config,
             data,
@@ -84,6 +88,18 @@ impl Context {
             token,
             treasury,
             total_notional,
+            total_fees,
+            fee_free,
+            caller_signed: false,
+        }
+    }
+
+    /// Require the keeper batch's `caller` to sign, once per call: the host
+    /// rejects a second `require_auth` for the same address in one frame.
+    pub fn require_caller_auth(&mut self, caller: &Address) {
+        if !self.caller_signed {
+            caller.require_auth();
+            self.caller_signed = true;
         }
     }
 
@@ -107,11 +123,20 @@ impl Context {
         }
     }
 
+    /// Additional notional that fits under both the per-market and global
+    /// utilization caps, i.e. the most `require_within_util` would accept.
+    pub(crate) fn util_headroom(&self, e: &Env) -> i128 {
+        let market_cap = self.vault_balance.fixed_mul_floor(e, &self.config.max_util, &SCALAR_7);
+        let global_cap = self.vault_balance.fixed_mul_floor(e, &self.trading_config.max_util, &SCALAR_7);
+        let market_notional = self.data.l_notional + self.data.s_notional;
+        (market_cap - market_notional).min(global_cap - self.total_notional).max(0)
+    }
+
     /// Compute the treasury's cut from a revenue amount.
     ///
     /// Returns `floor(revenue × rate / SCALAR_7)` where rate is queried from
     /// the treasury contract (SCALAR_7 fraction, e.g. 500_000 = 5%).
-    /// Returns 0 when revenue <= 0 or rate is 0.
+    /// Returns 0 when revenue <= 0 or rate is 0. Floor leaves rounding dust with the vault.
     pub(crate) fn treasury_fee(&self, e: &Env, revenue: i128) -> i128 {
         if revenue > 0 {
             let rate = TreasuryClient::new(e, &self.treasury).get_rate();
@@ -140,70 +165,206 @@ impl Context {
     ///   (SCALAR_7 fraction of notional). Opening on the dominant side worsens
     ///   market imbalance, so the higher fee disincentivizes that.
     /// - `impact_fee`: `notional / impact` (SCALAR_7), simulates price impact.
+    /// - Markets with `fee_tiers` discount the base fee by the user's volume tier.
     ///
     /// # Panics
     /// - `TradingError::UtilizationExceeded` (751) if position pushes utilization past caps
+    /// - `TradingError::UserNotionalExceeded` (739) if the user's open notional on the
+    ///   market would exceed `max_user_notional`
+    /// - `TradingError::DirectionDisabled` (706) if the market takes no new positions
+    ///   on the position's side
     /// - All panics from `Position::validate()`
     pub fn open(&mut self, e: &Env, position: &mut Position, user: &Address, id: u32) -> (i128, i128) {
-        let base_fee = if self.data.is_dominant(position.long, position.notional) {
-            position.notional.fixed_mul_ceil(e, &self.trading_config.fee_dom, &SCALAR_7)
-        } else {
-            position.notional.fixed_mul_ceil(e, &self.trading_config.fee_non_dom, &SCALAR_7)
-        };
-        let impact_fee = position.notional.fixed_div_floor(e, &self.config.impact, &SCALAR_7);
+        let (base_fee, impact_fee) = self.open_fees(e, position, user);
+        record_volume(e, user, position.notional);
 
         // fees deducted from collateral before validation, ensures post-fee
         // collateral still meets margin requirements, preventing under-collateralized positions.
         position.col -= base_fee + impact_fee;
-        position.validate(e, self.config.enabled, self.trading_config.min_notional, self.trading_config.max_notional, self.config.margin);
+        let margin = self.config.margin_for(position.notional);
+        let min_notional = self.config.notional_floor(self.trading_config.min_notional);
+        position.validate(e, self.config.enabled, min_notional, self.trading_config.max_notional, margin);
+        self.config.require_direction(e, position.long);
+        let user_notional = storage::get_user_notional(e, user, self.market_id) + position.notional;
+        if self.config.max_user_notional > 0 && user_notional > self.config.max_user_notional {
+            panic_with_error!(e, TradingError::UserNotionalExceeded);
+        }
+        storage::add_user_notional(e, user, self.market_id, position.notional);
+        pin_exponent(e, self.market_id, self.exponent);
         position.fill(e, &self.data);
+        position.next_seq(); // carried by the caller's OpenMarket or FillLimit event
         storage::set_position(e, user, id, position);
 
-        // entry_wt (entry-weighted aggregate) tracks Sigma(notional/entry_price) per side.
-        // This enables O(1) estimate PnL calculation for the entire side during ADL checks,
-        // without iterating over every position.
-        // floor rounding on entry_wt, conservative (slightly understates aggregate weight).
-        let ew_delta = position.notional.fixed_div_floor(e, &position.entry_price, &self.price_scalar);
-        self.data.update_stats(position.long, position.notional, ew_delta);
-        self.total_notional += position.notional;
+        self.track(e, position, true);
+        self.total_fees += base_fee + impact_fee;
         self.require_within_util(e);
+        credit_referral(e, user, self.referral_fee(e, user, base_fee + impact_fee));
+
+        (base_fee, impact_fee)
+    }
 
+    /// Opening fees for `position` as `(base_fee, impact_fee)`, before they are
+    /// deducted from collateral. Read-only: the user's volume is not recorded.
+    pub(crate) fn open_fees(&self, e: &Env, position: &Position, user: &Address) -> (i128, i128) {
+        let dominant = self.data.is_dominant(position.long, position.notional);
+        let (base_fee, impact_fee) = self.trading_fees(e, position.notional, dominant);
+        (self.volume_discount(e, user, base_fee), impact_fee)
+    }
+
+    /// `(base_fee, impact_fee)` on `notional`, the base fee at `fee_dom` if the
+    /// trade is charged as `dominant`, else at `fee_non_dom`.
+    ///
+    /// Base fee: ceil (protocol never under-collects). Impact is the one floored
+    /// fee: the dust is sub-unit and floor keeps `impact = i128::MAX` as an exact
+    /// zero-fee setting.
+    pub(crate) fn trading_fees(&self, e: &Env, notional: i128, dominant: bool) -> (i128, i128) {
+        let rate = if dominant { self.trading_config.fee_dom } else { self.trading_config.fee_non_dom };
+        let base_fee = notional.fixed_mul_ceil(e, &rate, &SCALAR_7);
+        let impact_fee = notional.fixed_div_floor(e, &self.config.impact, &SCALAR_7);
         (base_fee, impact_fee)
     }
 
+    /// Settle `position` as [`Position::settle`], with the base fee discounted by
+    /// the user's volume tier. A close the user submits during a fee-free
+    /// wind-down (`by_user`) pays no base or impact fee; keeper and owner
+    /// settlements pay them in full.
+    pub(crate) fn settle(&self, e: &Env, position: &mut Position, user: &Address, by_user: bool) -> Settlement {
+        let mut s = position.settle(e, self);
+        s.base_fee = self.volume_discount(e, user, s.base_fee);
+        // Wind-down: user exits only settle PnL and accrued interest
+        if by_user && self.fee_free {
+            s.base_fee = 0;
+            s.impact_fee = 0;
+        }
+        s
+    }
+
     /// Close a position: settle PnL and all accrued fees, update market stats, remove from storage.
     ///
     /// # Parameters
     /// - `position` - Mutable position to settle (notional may be reduced by ADL)
     /// - `user` - Position owner address (storage key)
     /// - `id` - Position ID (per-user sequence number)
+    /// - `by_user` - The owner submitted the close (see [`Context::settle`]); only
+    ///   such closes count toward the user's volume
     ///
     /// # Returns
     /// [`Settlement`] with broken-down PnL and fee components.
-    pub fn close(&mut self, e: &Env, position: &mut Position, user: &Address, id: u32) -> Settlement {
-        let s = position.settle(e, self);
-        let ew_delta = position.notional.fixed_div_floor(e, &position.entry_price, &self.price_scalar);
-        self.data.update_stats(position.long, -position.notional, ew_delta);
-        self.total_notional -= position.notional;
+    ///
+    /// # Side effects
+    /// - Adds the collectible protocol fee (trading + borrowing) to `total_fees`
+    /// - Emits `BadDebt` if losses plus fees exceed the position's collateral
+    /// - Emits `StatsClamped` if the side's aggregates held less than the position
+    pub fn close(&mut self, e: &Env, position: &mut Position, user: &Address, id: u32, by_user: bool) -> Settlement {
+        // Release the notional as tracked, before ADL scales it down
+        storage::add_user_notional(e, user, self.market_id, -position.notional);
+        let s = self.settle(e, position, user, by_user);
+        // Keeper-driven exits (liquidation, triggers, expiry) don't earn fee tiers
+        if by_user {
+            record_volume(e, user, position.notional);
+        }
+        let (notional, entry_wt) = self.track(e, position, false);
+        if notional > 0 || entry_wt > 0 {
+            StatsClamped { market_id: self.market_id, long: position.long, notional, entry_wt }.publish(e);
+        }
+        self.total_fees += s.collectible(position.col, s.protocol_fee());
+        credit_referral(e, user, self.referral_fee(e, user, s.collectible(position.col, s.trading_fee())));
         storage::remove_position(e, user, id);
+
+        let bad_debt = s.bad_debt(position.col);
+        if bad_debt > 0 {
+            BadDebt {
+                market_id: self.market_id,
+                user: user.clone(),
+                position_id: id,
+                event_seq: position.next_seq(),
+                amount: bad_debt,
+            }
+            .publish(e);
+        }
         s
     }
 
-    /// Write mutable state back to storage.
+    /// Add `position` to (`opening`) or remove it from its side's aggregates and the
+    /// global open notional. In memory only, until [`Context::store`].
+    ///
+    /// # Returns
+    /// A removal's shortfall, see [`MarketData::update_stats`].
+    pub(crate) fn track(&mut self, e: &Env, position: &Position, opening: bool) -> (i128, i128) {
+        // entry_wt (entry-weighted aggregate) tracks Sigma(notional/entry_price) per side.
+        // This enables O(1) estimate PnL calculation for the entire side during ADL checks,
+        // without iterating over every position.
+        // floor rounding on entry_wt, conservative (slightly understates aggregate weight).
+        let ew_delta = position.notional.fixed_div_floor(e, &position.entry_price, &self.price_scalar);
+        let delta = if opening { position.notional } else { -position.notional };
+        self.total_notional += delta;
+        self.data.update_stats(e, position.long, delta, ew_delta)
+    }
+
+    /// Partner's cut of collected trading `fees` on markets with a `fee_recipient`:
+    /// `floor(fees × fee_share / SCALAR_7)`, paid out of the vault's side.
+    pub(crate) fn partner_fee(&self, e: &Env, fees: i128) -> i128 {
+        if self.config.fee_recipient.is_some() && fees > 0 {
+            fees.fixed_mul_floor(e, &self.config.fee_share, &SCALAR_7)
+        } else {
+            0
+        }
+    }
+
+    /// Referrer's cut of `user`'s collected trading `fees`, if they were referred:
+    /// `floor(fees × referral_share / SCALAR_7)`, paid out of the vault's side.
+    pub(crate) fn referral_fee(&self, e: &Env, user: &Address, fees: i128) -> i128 {
+        let share = self.trading_config.referral_share;
+        if share > 0 && fees > 0 && storage::get_referrer(e, user).is_some() {
+            fees.fixed_mul_floor(e, &share, &SCALAR_7)
+        } else {
+            0
+        }
+    }
+
+    /// Discount `base_fee` by the user's volume tier, as of before this trade.
+    pub(crate) fn volume_discount(&self, e: &Env, user: &Address, base_fee: i128) -> i128 {
+        let volume = storage::get_user_volume(e, user);
+        // floor: the discount never exceeds the tier rate
+        base_fee - base_fee.fixed_mul_floor(e, &self.config.fee_discount_for(volume), &SCALAR_7)
+    }
+
+    /// Write mutable state back to storage, with the price it was settled at.
     pub fn store(&self, e: &Env) {
+        self.store_state(e);
+        storage::set_last_price(e, self.market_id, self.price);
+    }
+
+    /// Write mutable state back to storage, leaving the market's last price as is.
+    /// For unverified prices, which must not become the next reference.
+    pub fn store_state(&self, e: &Env) {
         storage::set_market_data(e, self.market_id, &self.data);
         storage::set_total_notional(e, self.total_notional);
+        storage::set_total_fees(e, self.total_fees);
     }
 }
 
+/// Add `notional` to the user's cumulative volume, after their fee is priced.
+/// Volume only feeds fee tiers, so it stops at `i128::MAX` rather than
+/// reverting the trade.
+fn record_volume(e: &Env, user: &Address, notional: i128) {
+    let volume = storage::get_user_volume(e, user).checked_add(notional).unwrap_or(i128::MAX);
+    storage::set_user_volume(e, user, volume);
+}
+
 #[cfg(test)]
 mod tests {
     use crate::constants::SCALAR_7;
-    use crate::testutils::{default_config, default_market, default_market_data, FEED_BTC};
+    use crate::dependencies::PriceData;
+    use crate::events::{IndexUpdate, StatsClamped};
+    use crate::storage;
+    use crate::testutils::{
+        default_config, default_market, default_market_data, jump, setup_contract, setup_env, BTC_PRICE, FEED_BTC,
+    };
     use crate::types::MarketData;
     use super::Context;
-    use soroban_sdk::testutils::Address as _;
-    use soroban_sdk::{Address, Env};
+    use soroban_sdk::testutils::{Address as _, Events as _};
+    use soroban_sdk::{Address, Env, Event as _};
 
     fn test_ctx(e: &Env, vault_balance: i128, market_data: MarketData, total_notional: i128) -> Context {
         Context {
@@ -211,6 +372,7 @@ mod tests {
             feed_id: FEED_BTC,
             price: 0,
             price_scalar: SCALAR_7,
+            exponent: -7,
             publish_time: 0,
             config: default_market(e),
             data: market_data,
@@ -220,9 +382,130 @@ mod tests {
             token: Address::generate(e),
             treasury: Address::generate(e),
             total_notional,
+            total_fees: 0,
+            fee_free: false,
+            caller_signed: false,
         }
     }
 
+    #[test]
+    fn test_load_emits_index_update_once_per_accrual() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+        let pd = |e: &Env| PriceData {
+            feed_id: FEED_BTC,
+            price: BTC_PRICE,
+            exponent: -8,
+            publish_time: e.ledger().timestamp(),
+        };
+
+        e.as_contract(&contract, || {
+            crate::trading::execute_create_market(
+                &e, &user, FEED_BTC, 1_000 * SCALAR_7, 10_000 * SCALAR_7, true, 0, 0, &pd(&e),
+            )
+        });
+        jump(&e, 1000 + 3600);
+
+        e.as_contract(&contract, || {
+            let ctx = Context::load(&e, FEED_BTC, &pd(&e));
+            // Longs are the dominant side and alone accrue borrowing
+            assert!(ctx.data.l_borr_idx > 0);
+            let (util_vault, util_market) = ctx.data.borrow_utilization(
+                &e,
+                ctx.vault_balance,
+                ctx.total_notional,
+                ctx.trading_config.max_util,
+                ctx.config.max_util,
+            );
+            assert!(util_market > 0);
+            let expected = IndexUpdate {
+                market_id: FEED_BTC,
+                l_fund_idx: ctx.data.l_fund_idx,
+                s_fund_idx: ctx.data.s_fund_idx,
+                l_borr_idx: ctx.data.l_borr_idx,
+                s_borr_idx: ctx.data.s_borr_idx,
+                util_vault,
+                util_market,
+                timestamp: 1000 + 3600,
+            };
+            let events = e.events().all().filter_by_contract(&contract);
+            assert_eq!(events.events().last(), Some(&expected.to_xdr(&e, &contract)));
+            ctx.store(&e);
+
+            // Same ledger: nothing accrues, so nothing is emitted
+            let before = e.events().all().events().len();
+            Context::load(&e, FEED_BTC, &pd(&e));
+            assert_eq!(e.events().all().events().len(), before);
+        });
+
+        // Market updates and funding accrue outside a context and report it too
+        let stored_update = |e: &Env| {
+            e.as_contract(&contract, || {
+                let data = storage::get_market_data(e, FEED_BTC);
+                let config = storage::get_market_config(e, FEED_BTC);
+                let trading_config = storage::get_config(e);
+                let vault_balance = crate::dependencies::VaultClient::new(e, &storage::get_vault(e)).total_assets();
+                let (util_vault, util_market) = data.borrow_utilization(
+                    e,
+                    vault_balance,
+                    storage::get_total_notional(e),
+                    trading_config.max_util,
+                    config.max_util,
+                );
+                IndexUpdate {
+                    market_id: FEED_BTC,
+                    l_fund_idx: data.l_fund_idx,
+                    s_fund_idx: data.s_fund_idx,
+                    l_borr_idx: data.l_borr_idx,
+                    s_borr_idx: data.s_borr_idx,
+                    util_vault,
+                    util_market,
+                    timestamp: data.last_update,
+                }
+                .to_xdr(e, &contract)
+            })
+        };
+        jump(&e, 1000 + 2 * 3600);
+        e.as_contract(&contract, || {
+            crate::trading::execute_update_market(&e, FEED_BTC, &storage::get_market_config(&e, FEED_BTC))
+        });
+        assert!(e.events().all().filter_by_contract(&contract).events().contains(&stored_update(&e)));
+
+        jump(&e, 1000 + 3 * 3600);
+        e.as_contract(&contract, || crate::trading::execute_apply_funding(&e));
+        assert!(e.events().all().filter_by_contract(&contract).events().contains(&stored_update(&e)));
+    }
+
+    #[test]
+    fn test_close_floors_drifted_stats_and_reports() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+        let pd = PriceData { feed_id: FEED_BTC, price: BTC_PRICE, exponent: -8, publish_time: 1000 };
+
+        let id = e.as_contract(&contract, || {
+            crate::trading::execute_create_market(&e, &user, FEED_BTC, 1_000 * SCALAR_7, 10_000 * SCALAR_7, true, 0, 0, &pd)
+        });
+        e.as_contract(&contract, || {
+            // The long aggregate has drifted 4k below the one open position
+            let mut data = crate::storage::get_market_data(&e, FEED_BTC);
+            data.l_notional -= 4_000 * SCALAR_7;
+            crate::storage::set_market_data(&e, FEED_BTC, &data);
+
+            let mut ctx = Context::load(&e, FEED_BTC, &pd);
+            let mut position = crate::storage::get_position(&e, &user, id);
+            ctx.close(&e, &mut position, &user, id, false);
+            assert_eq!((ctx.data.l_notional, ctx.data.l_entry_wt), (0, 0));
+
+            let expected = StatsClamped { market_id: FEED_BTC, long: true, notional: 4_000 * SCALAR_7, entry_wt: 0 };
+            let events = e.events().all().filter_by_contract(&contract);
+            assert_eq!(events.events().last(), Some(&expected.to_xdr(&e, &contract)));
+        });
+    }
+
     #[test]
     fn test_util_within_caps() {
         let e = Env::default();
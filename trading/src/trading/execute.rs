@@ -1,14 +1,20 @@
-use crate::constants::SCALAR_7;
+use crate::constants::{
+    MAX_CALLER_RATE, MAX_PAGE, MAX_SETTLE_DEV, MAX_TREASURY_RATE, MIN_OPEN_TIME, ONE_HOUR_SECONDS, SCALAR_7,
+};
 use crate::errors::TradingError;
-use crate::events::{FillLimit, Liquidation, StopLoss, TakeProfit};
+use crate::events::{
+    FillLimit, ForceClose, Liquidation, MarginBreach, PlaceLimit, RefundPosition, SettleExpiry, StopLoss,
+    TakeProfit,
+};
 use crate::storage;
 use crate::trading::context::Context;
 use crate::trading::position::{Position, Settlement};
-use crate::dependencies::PriceData;
-use crate::validation::require_can_manage;
+use crate::types::MarketData;
+use crate::dependencies::{PriceData, VaultClient};
+use crate::validation::{has_position_slot, require_can_manage, require_migrated};
 use soroban_fixed_point_math::SorobanFixedPoint;
 use soroban_sdk::token::TokenClient;
-use soroban_sdk::{panic_with_error, Address, Env, Map, Vec};
+use soroban_sdk::{panic_with_error, Address, Env, Map, Symbol, Vec};
 
 /// Accumulate a transfer amount for an address (batches multiple payouts).
 fn add_transfer(map: &mut Map<Address, i128>, address: &Address, amount: i128) {
@@ -18,11 +24,20 @@ fn add_transfer(map: &mut Map<Address, i128>, address: &Address, amount: i128) {
     );
 }
 
+/// Accumulate the market partner's fee cut, if the market has a fee recipient.
+fn add_partner_transfer(map: &mut Map<Address, i128>, ctx: &Context, amount: i128) {
+    if let Some(recipient) = &ctx.config.fee_recipient {
+        if amount > 0 {
+            add_transfer(map, recipient, amount);
+        }
+    }
+}
+
 /// Execute a batch of keeper triggers for a single market.
 ///
 /// Auto-detects the action for each position:
 /// - **Not filled** → fill limit order (if price crossed entry)
-/// - **Filled** → priority order: liquidate > stop-loss > take-profit
+/// - **Filled** → priority order: liquidate > stop-loss > take-profit > expiry
 pub fn execute_trigger(
     e: &Env,
     caller: &Address,
@@ -37,10 +52,636 @@ pub fn execute_trigger(
     }
 
     let mut ctx = Context::load(e, market_id, price_data);
+    require_keeper_auth(&mut ctx, caller);
     let transfers = process_positions(e, &mut ctx, caller, users, ids);
+    pay_transfers(e, &ctx, &transfers);
+    ctx.store(e);
+}
+
+/// Best-effort variant of [`execute_trigger`]: entries that can't act are skipped
+/// instead of reverting the batch, so a keeper can submit a mixed batch of fills,
+/// stops and liquidations without one stale entry sinking the rest.
+///
+/// Entries run in the same order as [`execute_trigger`] (closes, then fills) and are
+/// screened against the market state left by the entries before them, with the
+/// rejections [`execute_simulate_trigger`] tags. Transfers for every executed entry
+/// are netted and paid once.
+///
+/// The batch shares one verified price, so a bad payload still reverts up front;
+/// a price too old for one entry (a liquidation past `liq_price_age`, or one
+/// published before the position opened) only skips that entry with `StalePrice`.
+///
+/// Fills are screened for everything opening them checks, including the size,
+/// margin, per-user notional and utilization caps, so no entry reverts the batch.
+///
+/// # Returns
+/// A result code per entry: `0` if executed, otherwise the `TradingError` code the
+/// entry would have reverted [`execute_trigger`] with.
+///
+/// # Panics
+/// - `TradingError::InvalidInput` (734) if `users` and `ids` lengths differ
+pub fn execute_trigger_each(
+    e: &Env,
+    caller: &Address,
+    market_id: u32,
+    users: Vec<Address>,
+    ids: Vec<u32>,
+    price_data: &PriceData,
+) -> Vec<u32> {
+    require_can_manage(e);
+    if users.len() != ids.len() {
+        panic_with_error!(e, TradingError::InvalidInput);
+    }
+
+    let mut ctx = Context::load(e, market_id, price_data);
+    require_keeper_auth(&mut ctx, caller);
+    let mut t: Map<Address, i128> = Map::new(e);
+    let mut codes: Vec<u32> = Vec::new(e);
+    let mut fills: Vec<u32> = Vec::new(e);
+
+    for i in 0..users.len() {
+        let user = users.get(i).unwrap();
+        let id = ids.get(i).unwrap();
+        let code = match storage::try_get_position(e, &user, id) {
+            None => Decision::NotFound.rejection(),
+            Some(p) if p.market_id != market_id => Decision::WrongMarket.rejection(),
+            Some(p) if !p.filled => {
+                fills.push_back(i);
+                None
+            }
+            Some(mut p) => {
+                let rejected = caller_decision(e, &ctx, &p, caller, &user).rejection();
+                if rejected.is_none() {
+                    apply_close(e, &mut t, &mut ctx, caller, &mut p, &user, id);
+                }
+                rejected
+            }
+        };
+        codes.push_back(code.map_or(0, |c| c as u32));
+    }
+
+    for i in fills.iter() {
+        let user = users.get(i).unwrap();
+        let id = ids.get(i).unwrap();
+        let mut position = storage::get_position(e, &user, id);
+        // Listed twice in the batch and already filled by the first entry
+        let rejected = if position.filled {
+            Some(TradingError::NotActionable)
+        } else {
+            classify(e, &ctx, &position, &user).rejection()
+        };
+        match rejected {
+            None => apply_fill(e, &mut t, &mut ctx, caller, &mut position, &user, id),
+            Some(err) => {
+                if err == TradingError::LimitOrderNotFillable {
+                    clear_fillable(e, &mut position, &user, id, ctx.publish_time);
+                }
+                codes.set(i, err as u32)
+            }
+        }
+    }
+
+    pay_transfers(e, &ctx, &t);
+    ctx.store(e);
+    codes
+}
+
+/// What a keeper batch does with a position at the context's price. Decided in
+/// one place, [`classify`]: `apply_fill` and `apply_close` act on it and the dry
+/// runs report it by [`Decision::tag`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Decision {
+    // Actions
+    Fill,
+    Liquidate,
+    MarginBreach,
+    ClearBreach,
+    StopLoss,
+    TakeProfit,
+    Expiry,
+    // Rejections
+    NotFound,
+    WrongMarket,
+    Frozen,
+    OnIce,
+    DirectionDisabled,
+    NotFillable,
+    Expired,
+    StalePrice,
+    InGrace,
+    TooNew,
+    ManualOnly,
+    NotActionable,
+    Unstaked,
+    // Fill rejections from opening checks
+    NegativeValue,
+    MarketDisabled,
+    BelowMin,
+    AboveMax,
+    OverLeverage,
+    UserNotional,
+    Utilization,
+}
+
+impl Decision {
+    /// The error a keeper batch reverts with, or `None` if this is an action.
+    fn rejection(self) -> Option<TradingError> {
+        match self {
+            Decision::Fill
+            | Decision::Liquidate
+            | Decision::MarginBreach
+            | Decision::ClearBreach
+            | Decision::StopLoss
+            | Decision::TakeProfit
+            | Decision::Expiry => None,
+            Decision::NotFound => Some(TradingError::PositionNotFound),
+            Decision::WrongMarket => Some(TradingError::InvalidPrice),
+            Decision::Frozen => Some(TradingError::PositionFrozen),
+            Decision::ManualOnly => Some(TradingError::Unauthorized),
+            Decision::OnIce => Some(TradingError::ContractOnIce),
+            Decision::DirectionDisabled => Some(TradingError::DirectionDisabled),
+            Decision::NotFillable => Some(TradingError::LimitOrderNotFillable),
+            Decision::Expired => Some(TradingError::OrderExpired),
+            Decision::StalePrice => Some(TradingError::StalePrice),
+            Decision::TooNew => Some(TradingError::PositionTooNew),
+            Decision::InGrace | Decision::NotActionable => Some(TradingError::NotActionable),
+            Decision::Unstaked => Some(TradingError::KeeperStakeTooLow),
+            Decision::NegativeValue => Some(TradingError::NegativeValueNotAllowed),
+            Decision::MarketDisabled => Some(TradingError::MarketDisabled),
+            Decision::BelowMin => Some(TradingError::NotionalBelowMinimum),
+            Decision::AboveMax => Some(TradingError::NotionalAboveMaximum),
+            Decision::OverLeverage => Some(TradingError::LeverageAboveMaximum),
+            Decision::UserNotional => Some(TradingError::UserNotionalExceeded),
+            Decision::Utilization => Some(TradingError::UtilizationExceeded),
+        }
+    }
+
+    /// The reason tag [`execute_simulate_trigger`] reports.
+    fn tag(self, e: &Env) -> Symbol {
+        let tag = match self {
+            Decision::Fill => "fill",
+            Decision::Liquidate => "liquidate",
+            Decision::MarginBreach => "margin_breach",
+            Decision::ClearBreach => "clear_breach",
+            Decision::StopLoss => "stop_loss",
+            Decision::TakeProfit => "take_profit",
+            Decision::Expiry => "expiry",
+            Decision::NotFound => "not_found",
+            Decision::WrongMarket => "wrong_market",
+            Decision::Frozen => "frozen",
+            Decision::OnIce => "on_ice",
+            Decision::DirectionDisabled => "direction_disabled",
+            Decision::NotFillable => "not_fillable",
+            Decision::Expired => "expired",
+            Decision::StalePrice => "stale_price",
+            Decision::InGrace => "in_grace",
+            Decision::TooNew => "too_new",
+            Decision::ManualOnly => "manual_only",
+            Decision::NotActionable => "not_actionable",
+            Decision::Unstaked => "unstaked",
+            Decision::NegativeValue => "negative_value",
+            Decision::MarketDisabled => "market_disabled",
+            Decision::BelowMin => "below_min",
+            Decision::AboveMax => "above_max",
+            Decision::OverLeverage => "over_leverage",
+            Decision::UserNotional => "user_notional",
+            Decision::Utilization => "utilization",
+        };
+        Symbol::new(e, tag)
+    }
+}
+
+/// Record that a pending order is fillable at a verified price, starting its
+/// keeper fill bonus clock (see `TradingConfig.fill_bonus`). Permissionless.
+///
+/// The mark lasts one fillable episode: the first mark counts and later marks leave
+/// `fillable_since` unchanged, until a price published after the mark shows the
+/// order out of range. That clears the mark, so the bonus only counts time the
+/// order has been fillable since it was last seen fillable without a break.
+///
+/// # Returns
+/// The order's `fillable_since` timestamp, or 0 if it can't fill at the price.
+///
+/// # Panics
+/// - `TradingError::PositionNotPending` (721) if the position is filled
+/// - `TradingError::StalePrice` (711) if the price predates the order
+pub fn execute_mark_fillable(e: &Env, user: &Address, id: u32, price_data: &PriceData) -> u64 {
+    require_can_manage(e);
+    let mut position = storage::get_position(e, user, id);
+    position.require_unfrozen(e);
+    if position.filled {
+        panic_with_error!(e, TradingError::PositionNotPending);
+    }
+    if price_data.publish_time < position.created_at {
+        panic_with_error!(e, TradingError::StalePrice);
+    }
+    if !position.can_fill_at(e, price_data.price) {
+        clear_fillable(e, &mut position, user, id, price_data.publish_time);
+        return 0;
+    }
+    if position.fillable_since == 0 {
+        position.fillable_since = e.ledger().timestamp();
+        storage::set_position(e, user, id, &position);
+    }
+    position.fillable_since
+}
+
+/// End an order's fillable episode on a price showing it out of range. Prices
+/// published up to the mark say nothing about the episode and are ignored.
+fn clear_fillable(e: &Env, position: &mut Position, user: &Address, id: u32, publish_time: u64) {
+    if position.fillable_since != 0 && publish_time > position.fillable_since {
+        position.fillable_since = 0;
+        storage::set_position(e, user, id, position);
+    }
+}
+
+/// Dry run of [`execute_trigger`]: what a keeper batch would do to each position,
+/// as a reason tag per entry. Read-only, nothing is settled or stored.
+///
+/// Tags name the `Decision` from `classify` that `apply_fill` / `apply_close` act on:
+/// - actions: `fill`, `liquidate`, `margin_breach`, `clear_breach`, `stop_loss`,
+///   `take_profit`, `expiry`
+/// - rejections: `not_found`, `wrong_market`, `frozen`, `on_ice`, `direction_disabled`, `not_fillable`,
+///   `stale_price`, `in_grace`, `too_new`, `manual_only`, `not_actionable`
+/// - fill rejections from opening checks: `negative_value`, `market_disabled`,
+///   `below_min`, `above_max`, `over_leverage`, `user_notional`, `utilization`
+///
+/// `manual_only` is a TP/SL on a position with `auto_trigger` off, which only its
+/// user may execute.
+///
+/// Entries are judged independently against the same market state, so margin and
+/// utilization checks that depend on earlier entries in the batch are not predicted.
+///
+/// # Panics
+/// - `TradingError::InvalidInput` (734) if `users` and `ids` lengths differ
+pub fn execute_simulate_trigger(
+    e: &Env,
+    market_id: u32,
+    users: Vec<Address>,
+    ids: Vec<u32>,
+    price_data: &PriceData,
+) -> Vec<Symbol> {
+    if users.len() != ids.len() {
+        panic_with_error!(e, TradingError::InvalidInput);
+    }
+
+    let ctx = Context::load(e, market_id, price_data);
+    let mut tags = Vec::new(e);
+    for (user, id) in users.iter().zip(ids.iter()) {
+        let decision = match storage::try_get_position(e, &user, id) {
+            None => Decision::NotFound,
+            Some(p) if p.market_id != market_id => Decision::WrongMarket,
+            Some(p) => classify(e, &ctx, &p, &user),
+        };
+        tags.push_back(decision.tag(e));
+    }
+    tags
+}
+
+/// Dry run of [`execute_trigger`]'s token flows: the net amount each address would
+/// receive from the batch, with the vault negative when it funds payouts. Read-only,
+/// nothing is settled, stored or transferred.
+///
+/// The batch is replayed on its own copy of the market context: closes before fills,
+/// each entry acting on the decision `caller_decision` gives it against the state the entries
+/// before it left, with its transfers from the same helpers the batch pays through.
+/// Entries the batch would reject add nothing, though [`execute_trigger`] reverts as
+/// a whole, so check the tags first. An entry listed twice counts once. Fees are at
+/// each user's volume tier from before the batch.
+///
+/// # Panics
+/// - `TradingError::InvalidInput` (734) if `users` and `ids` lengths differ
+pub fn execute_simulate_transfers(
+    e: &Env,
+    caller: &Address,
+    market_id: u32,
+    users: Vec<Address>,
+    ids: Vec<u32>,
+    price_data: &PriceData,
+) -> Map<Address, i128> {
+    if users.len() != ids.len() {
+        panic_with_error!(e, TradingError::InvalidInput);
+    }
+
+    let mut ctx = Context::load(e, market_id, price_data);
+    let mut t: Map<Address, i128> = Map::new(e);
+    let mut seen: Map<(Address, u32), bool> = Map::new(e);
+    let mut fills: Vec<(Address, Position)> = Vec::new(e);
+    for (user, id) in users.iter().zip(ids.iter()) {
+        let Some(mut position) = storage::try_get_position(e, &user, id) else { continue };
+        if position.market_id != market_id || seen.contains_key((user.clone(), id)) {
+            continue;
+        }
+        seen.set((user.clone(), id), true);
+        if !position.filled {
+            fills.push_back((user, position));
+            continue;
+        }
+
+        let decision = caller_decision(e, &ctx, &position, caller, &user);
+        if !matches!(decision, Decision::Liquidate | Decision::StopLoss | Decision::TakeProfit | Decision::Expiry) {
+            continue;
+        }
+        let col = position.col;
+        // Liquidation equity is judged before the volume discount, as in apply_close
+        let equity = position.clone().settle(e, &ctx).equity(col);
+        let s = ctx.settle(e, &mut position, &user, false);
+        ctx.track(e, &position, false);
+        if decision == Decision::Liquidate {
+            liquidation_transfers(e, &mut t, &ctx, caller, &user, col, &s, equity);
+        } else {
+            settle_close(e, &mut t, &ctx, caller, &user, col, &s);
+        }
+    }
+
+    for (user, mut position) in fills.iter() {
+        if classify(e, &ctx, &position, &user) != Decision::Fill {
+            continue;
+        }
+        let caller_rate = fill_caller_rate(e, &ctx, &position);
+        fill_part(e, &ctx, &mut position);
+        let (base_fee, impact_fee) = ctx.open_fees(e, &position, &user);
+        ctx.track(e, &position, true);
+        fill_transfers(e, &mut t, &ctx, caller, &user, caller_rate, base_fee + impact_fee, position.bounty);
+    }
+    t
+}
+
+/// The positions among `users` / `ids` a keeper batch would liquidate now, most
+/// underwater (lowest health factor) first, at most `limit` (capped at `MAX_PAGE`).
+/// Read-only.
+///
+/// Positions are not indexed per market on-chain, so the caller supplies the
+/// candidates. Entries in grace or priced too stale to liquidate are left out,
+/// matching the `liquidate` tag of [`execute_simulate_trigger`].
+///
+/// # Panics
+/// - `TradingError::InvalidInput` (734) if `users` and `ids` lengths differ
+pub fn execute_liquidatable_sorted(
+    e: &Env,
+    market_id: u32,
+    users: Vec<Address>,
+    ids: Vec<u32>,
+    price_data: &PriceData,
+    limit: u32,
+) -> Vec<(Address, u32)> {
+    if users.len() != ids.len() {
+        panic_with_error!(e, TradingError::InvalidInput);
+    }
+
+    let ctx = Context::load(e, market_id, price_data);
+    // Insertion sort by health: batches are keeper-sized, not market-sized
+    let mut sorted: Vec<(i128, Address, u32)> = Vec::new(e);
+    for (user, id) in users.iter().zip(ids.iter()) {
+        let Some(position) = storage::try_get_position(e, &user, id) else { continue };
+        if position.market_id != market_id || !position.filled || classify(e, &ctx, &position, &user) != Decision::Liquidate {
+            continue;
+        }
+        let health = position.health_factor(e, &ctx);
+        let at = sorted.iter().position(|(h, _, _)| h > health).unwrap_or(sorted.len() as usize);
+        sorted.insert(at as u32, (health, user, id));
+    }
+
+    let mut page = Vec::new(e);
+    for (_, user, id) in sorted.iter().take(limit.min(MAX_PAGE) as usize) {
+        page.push_back((user, id));
+    }
+    page
+}
+
+/// What a keeper batch would do to `position` at the context's price. The one
+/// place the fill and close decisions are made: `apply_fill` and `apply_close`
+/// act on it, and the dry runs report it.
+fn classify(e: &Env, ctx: &Context, position: &Position, user: &Address) -> Decision {
+    if position.filled {
+        classify_close(e, ctx, position)
+    } else {
+        classify_fill(e, ctx, position, user)
+    }
+}
+
+/// [`classify`] for a batch submitted by `caller`: a manual-only TP/SL is the
+/// user's own to execute, and a liquidation needs a staked keeper.
+fn caller_decision(e: &Env, ctx: &Context, position: &Position, caller: &Address, user: &Address) -> Decision {
+    match classify(e, ctx, position, user) {
+        Decision::ManualOnly if caller == user => {
+            if position.check_stop_loss(ctx.price) { Decision::StopLoss } else { Decision::TakeProfit }
+        }
+        Decision::Liquidate if !keeper_staked(e, ctx, caller) => Decision::Unstaked,
+        decision => decision,
+    }
+}
+
+fn classify_fill(e: &Env, ctx: &Context, position: &Position, user: &Address) -> Decision {
+    if position.frozen {
+        return Decision::Frozen;
+    }
+    if position.is_expired(e) {
+        return Decision::Expired;
+    }
+    // Fee-free wind-down: no new exposure, or fills could round-trip without fees
+    if ctx.fee_free {
+        return Decision::OnIce;
+    }
+    if !ctx.config.allows(position.long) {
+        return Decision::DirectionDisabled;
+    }
+    if !position.can_fill_at(e, ctx.price) {
+        return Decision::NotFillable;
+    }
+
+    // What `Context::open` would reject, judged on the part a split would fill
+    let mut part = position.clone();
+    if let Some((notional, col, _)) = headroom_part(e, ctx, position) {
+        part.notional = notional;
+        part.col = col;
+    }
+    let (base_fee, impact_fee) = ctx.open_fees(e, &part, user);
+    part.col -= base_fee + impact_fee;
+    let min_notional = ctx.config.notional_floor(ctx.trading_config.min_notional);
+    let margin = ctx.config.margin_for(part.notional);
+    let invalid = part.validation_error(e, ctx.config.enabled, min_notional, ctx.trading_config.max_notional, margin);
+    match invalid {
+        None => {}
+        Some(TradingError::NegativeValueNotAllowed) => return Decision::NegativeValue,
+        Some(TradingError::MarketDisabled) => return Decision::MarketDisabled,
+        Some(TradingError::NotionalBelowMinimum) => return Decision::BelowMin,
+        Some(TradingError::NotionalAboveMaximum) => return Decision::AboveMax,
+        Some(TradingError::LeverageAboveMaximum) => return Decision::OverLeverage,
+        // Any other opening check reverts as `Context::open` would
+        Some(err) => panic_with_error!(e, err),
+    }
+    let max_user_notional = ctx.config.max_user_notional;
+    if max_user_notional > 0 && storage::get_user_notional(e, user, ctx.market_id) + part.notional > max_user_notional {
+        return Decision::UserNotional;
+    }
+    // Market stats can't fail here: a fill within the caps can't overflow them
+    if part.notional > ctx.util_headroom(e) {
+        return Decision::Utilization;
+    }
+    Decision::Fill
+}
+
+fn classify_close(e: &Env, ctx: &Context, position: &Position) -> Decision {
+    if position.frozen {
+        return Decision::Frozen;
+    }
+    let now = e.ledger().timestamp();
+    let equity = position.clone().settle(e, ctx).equity(position.col);
+    if equity < position.liq_threshold(e, ctx.config.liq_fee) {
+        if ctx.publish_time < position.created_at
+            || (ctx.config.liq_price_age > 0 && now > ctx.publish_time.saturating_add(ctx.config.liq_price_age))
+        {
+            return Decision::StalePrice;
+        }
+        if equity >= 0 && ctx.config.liq_grace > 0 {
+            if position.breach_at == 0 {
+                return Decision::MarginBreach;
+            }
+            if now < position.breach_at + ctx.config.liq_grace {
+                return Decision::InGrace;
+            }
+        }
+        return Decision::Liquidate;
+    }
+
+    let too_new = now < position.created_at.saturating_add(MIN_OPEN_TIME);
+    let trigger = |decision| {
+        if too_new {
+            Decision::TooNew
+        } else if !position.auto_trigger {
+            Decision::ManualOnly
+        } else {
+            decision
+        }
+    };
+    if position.check_stop_loss(ctx.price) {
+        trigger(Decision::StopLoss)
+    } else if position.check_take_profit(ctx.price) {
+        trigger(Decision::TakeProfit)
+    } else if ctx.config.max_duration > 0 && now >= position.created_at.saturating_add(ctx.config.max_duration) {
+        Decision::Expiry
+    } else if position.breach_at != 0 {
+        Decision::ClearBreach
+    } else {
+        Decision::NotActionable
+    }
+}
+
+/// Force-settle a page of positions on a disabled market at an owner-set price.
+///
+/// Filled positions settle like a user close (PnL + all accrued fees, no keeper cut).
+/// Pending orders are refunded in full. There is no on-chain index of positions per
+/// market, so the owner pages through them off-chain (from `PlaceLimit`/`OpenMarket`
+/// events) across as many calls as needed to stay within the transaction budget.
+///
+/// The owner's price is not oracle-verified, so it must lie within `MAX_SETTLE_DEV`
+/// of the price the market last stored its state at. Entries that no longer exist
+/// (settled in an earlier page) or belong to another market are skipped.
+///
+/// Once both sides are empty the market's indices and aggregates are reset, leaving
+/// clean `MarketData` behind for `del_market`.
+///
+/// # Panics
+/// - `TradingError::MigrationPending` (743) if `migrate` has not run
+/// - `TradingError::MarketEnabled` (704) if the market is still enabled
+/// - `TradingError::InvalidInput` (734) if `users` and `ids` lengths differ
+/// - `TradingError::InvalidPrice` (710) on a feed mismatch, if the market has no
+///   stored price, or a price outside `MAX_SETTLE_DEV` of it
+pub fn execute_force_settle(
+    e: &Env,
+    market_id: u32,
+    users: Vec<Address>,
+    ids: Vec<u32>,
+    price_data: &PriceData,
+) {
+    require_migrated(e);
+    if users.len() != ids.len() {
+        panic_with_error!(e, TradingError::InvalidInput);
+    }
+
+    let mut ctx = Context::load(e, market_id, price_data);
+    if ctx.config.enabled {
+        panic_with_error!(e, TradingError::MarketEnabled);
+    }
+    let last = storage::get_last_price(e, market_id)
+        .unwrap_or_else(|| panic_with_error!(e, TradingError::InvalidPrice));
+    if ctx.price <= 0 || (ctx.price - last).abs() > last.fixed_mul_floor(e, &MAX_SETTLE_DEV, &SCALAR_7) {
+        panic_with_error!(e, TradingError::InvalidPrice);
+    }
+
+    let mut t: Map<Address, i128> = Map::new(e);
+    for i in 0..users.len() {
+        let user = users.get(i).unwrap();
+        let id = ids.get(i).unwrap();
+        let Some(mut position) = storage::try_get_position(e, &user, id) else { continue };
+        if position.market_id != market_id {
+            continue;
+        }
+        position.require_unfrozen(e);
+
+        if !position.filled {
+            storage::remove_position(e, &user, id);
+            storage::release_pending(e, &user, market_id);
+            storage::add_bounty_escrow(e, -position.bounty);
+            add_transfer(&mut t, &user, position.col + position.bounty);
+            RefundPosition {
+                market_id,
+                user: user.clone(),
+                position_id: id,
+                event_seq: position.next_seq(),
+                amount: position.col + position.bounty,
+            }
+            .publish(e);
+            continue;
+        }
+
+        let col = position.col;
+        let s = ctx.close(e, &mut position, &user, id, false);
+        let user_payout = s.equity(col).max(0);
+        let treasury_fee = ctx.treasury_fee(e, s.collectible(col, s.protocol_fee()));
+        let partner_fee = ctx.partner_fee(e, s.collectible(col, s.trading_fee()));
+        let referral_fee = ctx.referral_fee(e, &user, s.collectible(col, s.trading_fee()));
+        let vault_transfer = col - user_payout - treasury_fee - partner_fee - referral_fee;
+
+        if user_payout > 0 { add_transfer(&mut t, &user, user_payout); }
+        if vault_transfer != 0 { add_transfer(&mut t, &ctx.vault, vault_transfer); }
+        if treasury_fee > 0 { add_transfer(&mut t, &ctx.treasury, treasury_fee); }
+        add_partner_transfer(&mut t, &ctx, partner_fee);
+
+        ForceClose {
+            market_id,
+            user: user.clone(),
+            position_id: id,
+            event_seq: position.next_seq(),
+            price: ctx.price,
+            pnl: s.net_pnl(col),
+            base_fee: s.base_fee,
+            impact_fee: s.impact_fee,
+            funding: s.funding,
+            borrowing_fee: s.borrowing_fee,
+        }
+        .publish(e);
+    }
+
+    pay_transfers(e, &ctx, &t);
+
+    // Market fully wound down: drop accumulated indices and entry-weight dust
+    if ctx.data.l_notional == 0 && ctx.data.s_notional == 0 {
+        ctx.data = MarketData {
+            last_update: e.ledger().timestamp(),
+            ..Default::default()
+        };
+    }
+    // The owner's price is never the reference for the next page
+    ctx.store_state(e);
+}
 
+/// Settle a batch of accumulated transfers. The vault is paid last so that
+/// a net withdrawal from the vault funds every other payout first.
+fn pay_transfers(e: &Env, ctx: &Context, transfers: &Map<Address, i128>) {
     let token_client = TokenClient::new(e, &ctx.token);
-    let vault_client = crate::dependencies::VaultClient::new(e, &ctx.vault);
+    let vault_client = VaultClient::new(e, &ctx.vault);
 
     // STEP 1: Vault pays to contract (if needed)
     let vault_transfer = transfers.get(ctx.vault.clone()).unwrap_or(0);
@@ -59,10 +700,14 @@ pub fn execute_trigger(
     if vault_transfer > 0 {
         token_client.transfer(&e.current_contract_address(), &ctx.vault, &vault_transfer);
     }
-
-    ctx.store(e);
 }
 
+/// Apply every close in the batch before any fill.
+///
+/// Closes (liquidations included) shrink open interest before fills are checked
+/// against utilization caps. Vault flows are netted across the whole batch in
+/// `pay_transfers`, so liquidation inflows fund profitable closes regardless of
+/// their order within the batch.
 fn process_positions(
     e: &Env,
     ctx: &mut Context,
@@ -71,6 +716,7 @@ fn process_positions(
     ids: Vec<u32>,
 ) -> Map<Address, i128> {
     let mut t: Map<Address, i128> = Map::new(e);
+    let mut fills: Vec<(Address, u32)> = Vec::new(e);
 
     for i in 0..users.len() {
         let user = users.get(i).unwrap();
@@ -82,20 +728,41 @@ fn process_positions(
         }
 
         if !position.filled {
-            apply_fill(e, &mut t, ctx, caller, &mut position, &user, id);
+            fills.push_back((user, id));
         } else {
             apply_close(e, &mut t, ctx, caller, &mut position, &user, id);
         }
     }
 
+    for (user, id) in fills.iter() {
+        let mut position = storage::get_position(e, &user, id);
+        // Listed twice in the batch and already filled by the first entry
+        if position.filled {
+            panic_with_error!(e, TradingError::NotActionable);
+        }
+        apply_fill(e, &mut t, ctx, caller, &mut position, &user, id);
+    }
+
     t
 }
 
-/// Close a filled position, auto-detecting the action:
-/// liquidate (equity < threshold) > stop-loss > take-profit.
+/// Close a filled position, acting on its [`caller_decision`]:
+/// liquidate (equity < threshold) > stop-loss > take-profit > expiry.
+///
+/// Expiry applies to markets with a `max_duration`: once `created_at + max_duration`
+/// has passed, the position settles at the current price like a SL/TP close.
 ///
 /// Liquidation bypasses MIN_OPEN_TIME (only requires fresh price).
-/// SL/TP require MIN_OPEN_TIME via require_closable.
+/// SL/TP require MIN_OPEN_TIME.
+///
+/// With a market `liq_grace`, the first breach only records `breach_at`; the
+/// position is liquidated once it is still below threshold after the grace period.
+/// A breach only ends on an observed recovery: a healthy position with a recorded
+/// breach has it cleared here, as does a `modify_collateral` that restores equity. Positions with
+/// negative equity skip the grace period so bad debt doesn't grow during it.
+///
+/// # Panics
+/// - the `TradingError` [`Decision::rejection`] of a non-close decision
 fn apply_close(
     e: &Env,
     t: &mut Map<Address, i128>,
@@ -106,51 +773,135 @@ fn apply_close(
     id: u32,
 ) {
     let col = position.col;
-    let s = ctx.close(e, position, user, id);
-    let liq_threshold = position.notional.fixed_mul_floor(e, &ctx.config.liq_fee, &SCALAR_7);
-    let equity = s.equity(col);
-
-    // Priority 1: Liquidation if under collateralized, regardless of open time or SL/TP
-    if equity < liq_threshold {
-        position.require_liquidatable(e, ctx.publish_time);
-        settle_liquidation(e, t, ctx, caller, position, user, id, col, &s, equity);
-    }
-    // Priority 2: Stop-loss if trigger price hit, requires open time
-    else if position.check_stop_loss(ctx.price) {
-        position.require_closable(e);
-        settle_close(e, t, ctx, caller, user, col, &s);
-        StopLoss {
-            market_id: position.market_id,
-            user: user.clone(),
-            position_id: id,
-            price: ctx.price,
-            pnl: s.net_pnl(col),
-            base_fee: s.base_fee,
-            impact_fee: s.impact_fee,
-            funding: s.funding,
-            borrowing_fee: s.borrowing_fee,
+    match caller_decision(e, ctx, position, caller, user) {
+        Decision::MarginBreach => {
+            position.breach_at = e.ledger().timestamp();
+            position.next_seq();
+            storage::set_position(e, user, id, position);
+            MarginBreach {
+                market_id: position.market_id,
+                user: user.clone(),
+                position_id: id,
+                event_seq: position.event_seq,
+                price: ctx.price,
+            }
+            .publish(e);
         }
-        .publish(e);
-    }
-    // Priority 3: Take-profit if trigger price hit, requires open time
-    else if position.check_take_profit(ctx.price) {
-        position.require_closable(e);
-        settle_close(e, t, ctx, caller, user, col, &s);
-        TakeProfit {
-            market_id: position.market_id,
-            user: user.clone(),
-            position_id: id,
-            price: ctx.price,
-            pnl: s.net_pnl(col),
-            base_fee: s.base_fee,
-            impact_fee: s.impact_fee,
-            funding: s.funding,
-            borrowing_fee: s.borrowing_fee,
+        Decision::Liquidate => {
+            // Judged on a copy: the stored position is only settled by the close
+            let equity = position.clone().settle(e, ctx).equity(col);
+            let s = ctx.close(e, position, user, id, false);
+            settle_liquidation(e, t, ctx, caller, position, user, id, col, &s, equity);
         }
-        .publish(e);
-    } else {
-        panic_with_error!(e, TradingError::NotActionable);
+        Decision::StopLoss => {
+            if !position.auto_trigger {
+                ctx.require_caller_auth(caller);
+            }
+            let s = ctx.close(e, position, user, id, false);
+            settle_close(e, t, ctx, caller, user, col, &s);
+            StopLoss {
+                market_id: position.market_id,
+                user: user.clone(),
+                position_id: id,
+                event_seq: position.next_seq(),
+                price: ctx.price,
+                pnl: s.net_pnl(col),
+                base_fee: s.base_fee,
+                impact_fee: s.impact_fee,
+                funding: s.funding,
+                borrowing_fee: s.borrowing_fee,
+            }
+            .publish(e);
+        }
+        Decision::TakeProfit => {
+            if !position.auto_trigger {
+                ctx.require_caller_auth(caller);
+            }
+            let s = ctx.close(e, position, user, id, false);
+            settle_close(e, t, ctx, caller, user, col, &s);
+            TakeProfit {
+                market_id: position.market_id,
+                user: user.clone(),
+                position_id: id,
+                event_seq: position.next_seq(),
+                price: ctx.price,
+                pnl: s.net_pnl(col),
+                base_fee: s.base_fee,
+                impact_fee: s.impact_fee,
+                funding: s.funding,
+                borrowing_fee: s.borrowing_fee,
+            }
+            .publish(e);
+        }
+        Decision::Expiry => {
+            let s = ctx.close(e, position, user, id, false);
+            settle_close(e, t, ctx, caller, user, col, &s);
+            SettleExpiry {
+                market_id: position.market_id,
+                user: user.clone(),
+                position_id: id,
+                event_seq: position.next_seq(),
+                price: ctx.price,
+                pnl: s.net_pnl(col),
+                base_fee: s.base_fee,
+                impact_fee: s.impact_fee,
+                funding: s.funding,
+                borrowing_fee: s.borrowing_fee,
+            }
+            .publish(e);
+        }
+        // Recovered from a grace-period breach: clear the marker
+        Decision::ClearBreach => {
+            position.breach_at = 0;
+            storage::set_position(e, user, id, position);
+        }
+        decision => panic_with_error!(e, decision.rejection().unwrap_or(TradingError::NotActionable)),
+    }
+}
+
+/// Keeper's share of a limit fill's fees: `caller_rate`, plus `fill_bonus` per
+/// hour since the order was marked fillable, capped at `MAX_CALLER_RATE` and at
+/// what the fee split leaves after the treasury's headroom, partner and referrer.
+/// Orders neglected while fillable pay keepers progressively more to fill.
+fn fill_caller_rate(e: &Env, ctx: &Context, position: &Position) -> i128 {
+    let rate = ctx.trading_config.caller_rate;
+    if position.fillable_since == 0 || ctx.trading_config.fill_bonus == 0 {
+        return rate;
+    }
+    let waited = e.ledger().timestamp().saturating_sub(position.fillable_since) as i128;
+    let bonus = ctx.trading_config.fill_bonus.fixed_mul_floor(e, &waited, &(ONE_HOUR_SECONDS as i128));
+    // The bonus only grows into what the treasury, partner and referrer leave
+    let headroom = SCALAR_7 - MAX_TREASURY_RATE - ctx.trading_config.referral_share - ctx.config.fee_share;
+    (rate + bonus).min(MAX_CALLER_RATE).min(headroom)
+}
+
+/// Whether `caller` holds the vault shares `keeper_min_shares` requires to
+/// liquidate. Keepers with skin in the vault are the ones hurt by griefing it.
+fn keeper_staked(e: &Env, ctx: &Context, caller: &Address) -> bool {
+    let min = ctx.trading_config.keeper_min_shares;
+    min == 0 || VaultClient::new(e, &ctx.vault).balance(caller) >= min
+}
+
+/// With a `keeper_min_shares` requirement the stake is checked against `caller`,
+/// so `caller` must sign; otherwise any submitter could name a staked address.
+fn require_keeper_auth(ctx: &mut Context, caller: &Address) {
+    if ctx.trading_config.keeper_min_shares > 0 {
+        ctx.require_caller_auth(caller);
+    }
+}
+
+/// Keeper's `rate` cut of `fees`. Owners triggering or filling their own positions
+/// earn nothing, so self-liquidation can't recover the liquidation fee and a
+/// self-fill can't rebate its own fees; the cut stays with the vault.
+///
+/// This only stops the trivial case: a user submitting from a second address they
+/// control still earns the cut, as any keeper would.
+fn caller_fee(e: &Env, caller: &Address, user: &Address, fees: i128, rate: i128) -> i128 {
+    if caller == user {
+        return 0;
     }
+    // floor: caller never over-paid, remainder stays with the vault.
+    fees.fixed_mul_floor(e, &rate, &SCALAR_7)
 }
 
 /// Distribute transfers for a normal close (SL/TP).
@@ -164,47 +915,72 @@ fn settle_close(
     s: &Settlement,
 ) {
     let user_payout = s.equity(col).max(0);
-    let treasury_fee = ctx.treasury_fee(e, s.protocol_fee());
-    let caller_fee = s.trading_fee()
-        .fixed_mul_floor(e, &ctx.trading_config.caller_rate, &SCALAR_7);
-    let vault_transfer = col - user_payout - treasury_fee - caller_fee;
+    let treasury_fee = ctx.treasury_fee(e, s.collectible(col, s.protocol_fee()));
+    let caller_fee = caller_fee(e, caller, user, s.collectible(col, s.trading_fee()), ctx.trading_config.caller_rate);
+    let partner_fee = ctx.partner_fee(e, s.collectible(col, s.trading_fee()));
+    let referral_fee = ctx.referral_fee(e, user, s.collectible(col, s.trading_fee()));
+    let vault_transfer = col - user_payout - treasury_fee - caller_fee - partner_fee - referral_fee;
 
     if user_payout > 0 { add_transfer(t, user, user_payout); }
     if vault_transfer != 0 { add_transfer(t, &ctx.vault, vault_transfer); }
     if treasury_fee > 0 { add_transfer(t, &ctx.treasury, treasury_fee); }
     if caller_fee > 0 { add_transfer(t, caller, caller_fee); }
+    add_partner_transfer(t, ctx, partner_fee);
 }
 
-/// Distribute transfers for a liquidation.
-fn settle_liquidation(
+/// Accumulate the transfers of a liquidation. Returns the liquidation fee.
+#[allow(clippy::too_many_arguments)]
+fn liquidation_transfers(
     e: &Env,
     t: &mut Map<Address, i128>,
     ctx: &Context,
     caller: &Address,
-    position: &Position,
     user: &Address,
-    id: u32,
     col: i128,
     s: &Settlement,
     equity: i128,
-) {
+) -> i128 {
     // liq_fee is the residual equity at liquidation (clamped to 0 from below).
     // The configured liq_fee threshold gates the liquidation path above; this
     // gives the keeper whatever equity remains. Underwater positions yield 0.
     let liq_fee = equity.max(0);
     let revenue = (s.protocol_fee() + liq_fee).min(col);
     let treasury_fee = ctx.treasury_fee(e, revenue);
-    let caller_fee = (s.trading_fee() + liq_fee).min(col)
-        .fixed_mul_floor(e, &ctx.trading_config.caller_rate, &SCALAR_7);
+    let mut caller_fee = caller_fee(e, caller, user, (s.trading_fee() + liq_fee).min(col), ctx.trading_config.caller_rate);
+    if ctx.trading_config.liq_caller_cap > 0 {
+        caller_fee = caller_fee.min(col.fixed_mul_floor(e, &ctx.trading_config.liq_caller_cap, &SCALAR_7));
+    }
+    let partner_fee = ctx.partner_fee(e, s.collectible(col, s.trading_fee()));
+    let referral_fee = ctx.referral_fee(e, user, s.collectible(col, s.trading_fee()));
 
-    add_transfer(t, &ctx.vault, col - treasury_fee - caller_fee);
+    add_transfer(t, &ctx.vault, col - treasury_fee - caller_fee - partner_fee - referral_fee);
     if treasury_fee > 0 { add_transfer(t, &ctx.treasury, treasury_fee); }
     if caller_fee > 0 { add_transfer(t, caller, caller_fee); }
+    add_partner_transfer(t, ctx, partner_fee);
+    liq_fee
+}
 
-    Liquidation {
-        market_id: position.market_id,
-        user: user.clone(),
+/// Distribute transfers for a liquidation.
+#[allow(clippy::too_many_arguments)]
+fn settle_liquidation(
+    e: &Env,
+    t: &mut Map<Address, i128>,
+    ctx: &Context,
+    caller: &Address,
+    position: &mut Position,
+    user: &Address,
+    id: u32,
+    col: i128,
+    s: &Settlement,
+    equity: i128,
+) {
+    let liq_fee = liquidation_transfers(e, t, ctx, caller, user, col, s, equity);
+
+    Liquidation {
+        market_id: position.market_id,
+        user: user.clone(),
         position_id: id,
+        event_seq: position.next_seq(),
         price: ctx.price,
         base_fee: s.base_fee,
         impact_fee: s.impact_fee,
@@ -215,7 +991,116 @@ fn settle_liquidation(
     .publish(e);
 }
 
-/// Fill a pending limit order.
+/// The `(notional, collateral, bounty)` of a pending order the utilization caps can
+/// take, if that is only part of it. Collateral and bounty are cut pro rata.
+///
+/// `None` when the whole order fits, or unless both the part and the remainder
+/// clear the market's notional floor; the whole fill then reverts on the caps.
+/// Also `None` when the remainder couldn't be placed as a new order: with opens
+/// paused or no room under `max_total_positions`. `max_pending` needs no check,
+/// as the filled part releases the slot the remainder takes.
+fn headroom_part(e: &Env, ctx: &Context, position: &Position) -> Option<(i128, i128, i128)> {
+    if ctx.config.open_paused || !has_position_slot(e, &ctx.trading_config) {
+        return None;
+    }
+    let headroom = ctx.util_headroom(e);
+    let rest = position.notional - headroom;
+    let floor = ctx.config.notional_floor(ctx.trading_config.min_notional);
+    if rest <= 0 || headroom < floor || rest < floor {
+        return None;
+    }
+    let col = position.col.fixed_mul_floor(e, &headroom, &position.notional);
+    let bounty = position.bounty.fixed_mul_floor(e, &headroom, &position.notional);
+    Some((headroom, col, bounty))
+}
+
+/// Cut a pending order down to the part a fill executes now: what the utilization
+/// caps can take (see [`headroom_part`]), at the price it fills at.
+///
+/// # Returns
+/// The remainder's `(notional, collateral, bounty)` if the order was split.
+fn fill_part(e: &Env, ctx: &Context, position: &mut Position) -> Option<(i128, i128, i128)> {
+    let rest = headroom_part(e, ctx, position).map(|(notional, col, bounty)| {
+        let rest = (position.notional - notional, position.col - col, position.bounty - bounty);
+        position.notional = notional;
+        position.col = col;
+        position.bounty = bounty;
+        rest
+    });
+    // Gapped past the order's deviation bound (with at_trigger): fill at the trigger
+    if !position.gapped(e, ctx.price) {
+        position.entry_price = ctx.price;
+    }
+    rest
+}
+
+/// Place the `(notional, collateral, bounty)` left over from a split fill of `order`
+/// as a new pending order with the same terms, expiry and fillable mark.
+fn place_remainder(e: &Env, order: &Position, user: &Address, (notional, col, bounty): (i128, i128, i128)) {
+    let (rest_id, mut rest) = Position::create(
+        e, user, order.market_id, order.long, order.entry_price, col, notional, order.sl, order.tp,
+    );
+    rest.max_dev = order.max_dev;
+    rest.at_trigger = order.at_trigger;
+    rest.expiry = order.expiry;
+    rest.auto_trigger = order.auto_trigger;
+    rest.fillable_since = order.fillable_since;
+    rest.bounty = bounty;
+    rest.next_seq();
+    storage::set_position(e, user, rest_id, &rest);
+    // The filled part releases its slot on fill; the remainder takes its own
+    let pending = storage::get_user_pending(e, user, order.market_id);
+    storage::set_user_pending(e, user, order.market_id, pending + 1);
+
+    PlaceLimit {
+        market_id: order.market_id,
+        user: user.clone(),
+        position_id: rest_id,
+        event_seq: rest.event_seq,
+    }
+    .publish(e);
+}
+
+/// Accumulate the transfers of `user`'s limit fill `total_fee`, the keeper earning
+/// `caller_rate` of it (see [`caller_fee`]) and the order's escrowed `bounty`.
+#[allow(clippy::too_many_arguments)]
+fn fill_transfers(
+    e: &Env,
+    t: &mut Map<Address, i128>,
+    ctx: &Context,
+    caller: &Address,
+    user: &Address,
+    caller_rate: i128,
+    total_fee: i128,
+    bounty: i128,
+) {
+    let treasury_fee = ctx.treasury_fee(e, total_fee);
+    let caller_fee = caller_fee(e, caller, user, total_fee, caller_rate);
+    let partner_fee = ctx.partner_fee(e, total_fee);
+    let referral_fee = ctx.referral_fee(e, user, total_fee);
+    let vault_fee = total_fee - treasury_fee - caller_fee - partner_fee - referral_fee;
+
+    add_transfer(t, &ctx.vault, vault_fee);
+    if treasury_fee > 0 { add_transfer(t, &ctx.treasury, treasury_fee); }
+    if caller_fee + bounty > 0 { add_transfer(t, caller, caller_fee + bounty); }
+    add_partner_transfer(t, ctx, partner_fee);
+}
+
+/// Fill a pending limit order, if [`classify_fill`] decides `Fill`; any other
+/// decision reverts with its [`Decision::rejection`].
+///
+/// If the utilization caps only have room for part of the order, that part fills
+/// and the rest stays pending as a new order (see `fill_part`).
+///
+/// The keeper earns the order's escrowed `bounty` on top of its fee share, so
+/// orders paying little or no fee are still worth filling. A split order pays the
+/// bounty pro rata to the notional filled; the remainder carries the rest.
+///
+/// Fill semantics: the order executes at the keeper's market price, which is at or
+/// better than `entry_price` for the trader. Within `max_dev` of `entry_price` (or
+/// with `max_dev` = 0) the position opens at that market price. Past `max_dev` an
+/// `at_trigger` order opens at `entry_price` instead, and any other order reverts
+/// and stays pending.
 fn apply_fill(
     e: &Env,
     t: &mut Map<Address, i128>,
@@ -228,35 +1113,28 @@ fn apply_fill(
     if position.filled {
         panic_with_error!(e, TradingError::PositionNotPending);
     }
-
-    // Long limit: fills when market price falls to or below the entry (buy at or better).
-    // Short limit: fills when market price rises to or above the entry (sell at or better).
-    let can_fill = if position.long {
-        ctx.price <= position.entry_price
-    } else {
-        ctx.price >= position.entry_price
-    };
-    if !can_fill {
-        panic_with_error!(e, TradingError::NotActionable);
+    let decision = classify_fill(e, ctx, position, user);
+    if decision != Decision::Fill {
+        panic_with_error!(e, decision.rejection().unwrap_or(TradingError::NotActionable));
+    }
+    let order = position.clone();
+    if let Some(rest) = fill_part(e, ctx, position) {
+        place_remainder(e, &order, user, rest);
     }
 
-    position.entry_price = ctx.price;
-
+    let caller_rate = fill_caller_rate(e, ctx, position);
+    let bounty = position.bounty;
+    position.bounty = 0;
+    storage::add_bounty_escrow(e, -bounty);
     let (base_fee, impact_fee) = ctx.open(e, position, user, id);
-    let total_fee = base_fee + impact_fee;
-    let treasury_fee = ctx.treasury_fee(e, total_fee);
-    let caller_fee = total_fee
-        .fixed_mul_floor(e, &ctx.trading_config.caller_rate, &SCALAR_7);
-    let vault_fee = total_fee - treasury_fee - caller_fee;
-
-    add_transfer(t, &ctx.vault, vault_fee);
-    if treasury_fee > 0 { add_transfer(t, &ctx.treasury, treasury_fee); }
-    if caller_fee > 0 { add_transfer(t, caller, caller_fee); }
+    storage::release_pending(e, user, position.market_id);
+    fill_transfers(e, t, ctx, caller, user, caller_rate, base_fee + impact_fee, bounty);
 
     FillLimit {
         market_id: position.market_id,
         user: user.clone(),
         position_id: id,
+        event_seq: position.event_seq,
         base_fee,
         impact_fee,
     }
@@ -268,7 +1146,8 @@ mod tests {
     use crate::constants::SCALAR_7;
     use crate::storage;
     use crate::testutils::{
-        setup_contract, setup_env, FEED_BTC, BTC_PRICE, PRICE_SCALAR,
+        dummy_price, jump, setup_contract, setup_env, update_config, update_market, FEED_BTC, FEED_ETH,
+        BTC_PRICE, PRICE_SCALAR,
     };
     use crate::dependencies::PriceData;
     use soroban_sdk::testutils::Address as _;
@@ -293,7 +1172,7 @@ mod tests {
     ) -> u32 {
         e.as_contract(contract, || {
             crate::trading::execute_create_limit(
-                e, user, FEED_BTC, collateral, notional, true, entry_price, 0, 0,
+                e, user, FEED_BTC, collateral, notional, true, entry_price, 0, 0, 0, false, 0,
             )
         })
     }
@@ -308,7 +1187,7 @@ mod tests {
     ) -> u32 {
         e.as_contract(contract, || {
             crate::trading::execute_create_limit(
-                e, user, FEED_BTC, collateral, notional, false, entry_price, 0, 0,
+                e, user, FEED_BTC, collateral, notional, false, entry_price, 0, 0, 0, false, 0,
             )
         })
     }
@@ -318,6 +1197,36 @@ mod tests {
         (vec![e, user.clone()], vec![e, id])
     }
 
+    #[test]
+    fn test_event_seq_increases_across_position_lifecycle() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        let caller = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+        let seq = |id| e.as_contract(&contract, || storage::get_position(&e, &user, id).event_seq);
+
+        // PlaceLimit -> SetTriggers -> FillLimit -> ModifyCollateral, all in one ledger
+        let id = create_pending_long(&e, &contract, &user, 1_000 * SCALAR_7, 10_000 * SCALAR_7, BTC_PRICE);
+        assert_eq!(seq(id), 1);
+        e.as_contract(&contract, || {
+            crate::trading::execute_set_triggers(&e, &user, id, 110_000 * PRICE_SCALAR, 0)
+        });
+        assert_eq!(seq(id), 2);
+
+        let pd = btc_price_data(&e, BTC_PRICE);
+        e.as_contract(&contract, || {
+            let (users, ids) = trigger_one(&e, &user, id);
+            super::execute_trigger(&e, &caller, FEED_BTC, users, ids, &pd);
+        });
+        assert_eq!(seq(id), 3);
+
+        e.as_contract(&contract, || {
+            crate::trading::execute_modify_collateral(&e, &user, id, 1_500 * SCALAR_7, &pd)
+        });
+        assert_eq!(seq(id), 4);
+    }
+
     #[test]
     fn test_fill_long_limit_order() {
         let e = setup_env();
@@ -342,7 +1251,7 @@ mod tests {
     }
 
     #[test]
-    #[should_panic(expected = "Error(Contract, #731)")]
+    #[should_panic(expected = "Error(Contract, #737)")]
     fn test_fill_long_limit_not_fillable() {
         let e = setup_env();
         let (contract, token_client) = setup_contract(&e);
@@ -364,49 +1273,1097 @@ mod tests {
     }
 
     #[test]
-    fn test_fill_short_limit_order() {
+    fn test_simulate_trigger_reason_tags() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        let fillable = create_pending_long(&e, &contract, &user, 1_000 * SCALAR_7, 10_000 * SCALAR_7, BTC_PRICE);
+        let resting = create_pending_long(&e, &contract, &user, 1_000 * SCALAR_7, 10_000 * SCALAR_7, 90_000 * PRICE_SCALAR);
+        let pd = btc_price_data(&e, BTC_PRICE);
+        let open = e.as_contract(&contract, || {
+            crate::trading::execute_create_market(&e, &user, FEED_BTC, 1_000 * SCALAR_7, 10_000 * SCALAR_7, true, 0, 0, &pd)
+        });
+
+        let tags = e.as_contract(&contract, || {
+            let users = vec![&e, user.clone(), user.clone(), user.clone(), user.clone()];
+            let ids = vec![&e, fillable, resting, open, open + 1];
+            super::execute_simulate_trigger(&e, FEED_BTC, users, ids, &pd)
+        });
+        let expected = ["fill", "not_fillable", "not_actionable", "not_found"]
+            .map(|tag| soroban_sdk::Symbol::new(&e, tag));
+        assert_eq!(tags, soroban_sdk::Vec::from_array(&e, expected));
+
+        // Nothing was filled
+        e.as_contract(&contract, || assert!(!storage::get_position(&e, &user, fillable).filled));
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #764)")]
+    fn test_fill_past_expiry_panics() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        let caller = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        let id = e.as_contract(&contract, || {
+            crate::trading::execute_create_limit(
+                &e, &user, FEED_BTC, 1_000 * SCALAR_7, 10_000 * SCALAR_7, true, BTC_PRICE, 0, 0, 0, false, 2_000,
+            )
+        });
+        jump(&e, 2_000);
+        let pd = btc_price_data(&e, BTC_PRICE);
+        e.as_contract(&contract, || {
+            let (users, ids) = trigger_one(&e, &user, id);
+            super::execute_trigger(&e, &caller, FEED_BTC, users, ids, &pd);
+        });
+    }
+
+    #[test]
+    fn test_expired_order_tagged_and_skipped() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        let caller = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        let id = e.as_contract(&contract, || {
+            crate::trading::execute_create_limit(
+                &e, &user, FEED_BTC, 1_000 * SCALAR_7, 10_000 * SCALAR_7, true, BTC_PRICE, 0, 0, 0, false, 2_000,
+            )
+        });
+        jump(&e, 2_000);
+        let pd = btc_price_data(&e, BTC_PRICE);
+        e.as_contract(&contract, || {
+            let (users, ids) = trigger_one(&e, &user, id);
+            let tags = super::execute_simulate_trigger(&e, FEED_BTC, users.clone(), ids.clone(), &pd);
+            assert_eq!(tags, vec![&e, soroban_sdk::Symbol::new(&e, "expired")]);
+
+            let codes = super::execute_trigger_each(&e, &caller, FEED_BTC, users, ids, &pd);
+            assert_eq!(codes, vec![&e, 764]);
+            assert!(!storage::get_position(&e, &user, id).filled);
+        });
+    }
+
+    #[test]
+    fn test_fill_splits_order_at_utilization_cap() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        let caller = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+        // 100M vault: the market can take 5_000 of notional
+        update_market(&e, &contract, FEED_BTC, |m| m.max_util = 500);
+
+        let id = e.as_contract(&contract, || {
+            crate::trading::execute_create_limit(
+                &e, &user, FEED_BTC, 1_000 * SCALAR_7, 10_000 * SCALAR_7, true, BTC_PRICE, 0, 0, 0, false, 5_000,
+            )
+        });
+        jump(&e, 1_100);
+        let pd = btc_price_data(&e, BTC_PRICE);
+        e.as_contract(&contract, || {
+            let (users, ids) = trigger_one(&e, &user, id);
+            super::execute_trigger(&e, &caller, FEED_BTC, users, ids, &pd);
+
+            let filled = storage::get_position(&e, &user, id);
+            assert!(filled.filled);
+            assert_eq!(filled.notional, 5_000 * SCALAR_7);
+            assert!(filled.col < 500 * SCALAR_7 && filled.col > 490 * SCALAR_7);
+
+            let rest = storage::get_position(&e, &user, id + 1);
+            assert!(!rest.filled);
+            assert_eq!(rest.notional, 5_000 * SCALAR_7);
+            assert_eq!(rest.col, 500 * SCALAR_7);
+            assert_eq!(rest.entry_price, BTC_PRICE);
+            // A new order: cancellable at the parent's expiry, not from its placement
+            assert_eq!(rest.created_at, 1_100);
+            assert_eq!(rest.expiry, 5_000);
+            assert!(!rest.is_expired(&e));
+            assert_eq!(storage::get_user_pending(&e, &user, FEED_BTC), 1);
+        });
+        assert!(token_client.balance(&caller) > 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #751)")]
+    fn test_fill_not_split_while_opens_paused() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        let caller = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        let id = create_pending_long(&e, &contract, &user, 1_000 * SCALAR_7, 10_000 * SCALAR_7, BTC_PRICE);
+        update_market(&e, &contract, FEED_BTC, |m| {
+            m.max_util = 500;
+            m.open_paused = true;
+        });
+        jump(&e, 1_100);
+        let pd = btc_price_data(&e, BTC_PRICE);
+        e.as_contract(&contract, || {
+            let (users, ids) = trigger_one(&e, &user, id);
+            super::execute_trigger(&e, &caller, FEED_BTC, users, ids, &pd);
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #751)")]
+    fn test_fill_not_split_at_position_cap() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        let caller = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        let id = create_pending_long(&e, &contract, &user, 1_000 * SCALAR_7, 10_000 * SCALAR_7, BTC_PRICE);
+        update_market(&e, &contract, FEED_BTC, |m| m.max_util = 500);
+        // The order itself is the only live position and fills the cap
+        update_config(&e, &contract, |c| c.max_total_positions = 1);
+        jump(&e, 1_100);
+        let pd = btc_price_data(&e, BTC_PRICE);
+        e.as_contract(&contract, || {
+            let (users, ids) = trigger_one(&e, &user, id);
+            super::execute_trigger(&e, &caller, FEED_BTC, users, ids, &pd);
+        });
+    }
+
+    #[test]
+    fn test_split_remainder_keeps_auto_trigger_off() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        let caller = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+        update_market(&e, &contract, FEED_BTC, |m| m.max_util = 500);
+
+        let id = create_pending_long(&e, &contract, &user, 1_000 * SCALAR_7, 10_000 * SCALAR_7, BTC_PRICE);
+        e.as_contract(&contract, || crate::trading::execute_set_auto_trigger(&e, &user, id, false));
+        jump(&e, 1_100);
+        let pd = btc_price_data(&e, BTC_PRICE);
+        e.as_contract(&contract, || {
+            let (users, ids) = trigger_one(&e, &user, id);
+            super::execute_trigger(&e, &caller, FEED_BTC, users, ids, &pd);
+
+            assert!(!storage::get_position(&e, &user, id).auto_trigger);
+            let rest = storage::get_position(&e, &user, id + 1);
+            assert!(!rest.filled);
+            assert!(!rest.auto_trigger);
+        });
+    }
+
+    #[test]
+    fn test_simulate_transfers_matches_execute() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let token = soroban_sdk::token::TokenClient::new(&e, &token_client.address);
+        let (a, b) = (Address::generate(&e), Address::generate(&e));
+        let caller = Address::generate(&e);
+        token_client.mint(&a, &(100_000 * SCALAR_7));
+        token_client.mint(&b, &(100_000 * SCALAR_7));
+
+        let pending = create_pending_long(&e, &contract, &a, 1_000 * SCALAR_7, 10_000 * SCALAR_7, BTC_PRICE);
+        let open = e.as_contract(&contract, || {
+            crate::trading::execute_create_market(
+                &e, &b, FEED_BTC, 1_000 * SCALAR_7, 10_000 * SCALAR_7, true, 0, 0, &btc_price_data(&e, BTC_PRICE),
+            )
+        });
+
+        // -9.5% on 10x: the order fills and the open long is liquidated with equity left
+        let pd = btc_price_data(&e, 90_500 * PRICE_SCALAR);
+        let users = vec![&e, a.clone(), b.clone()];
+        let ids = vec![&e, pending, open];
+        let (t, vault) = e.as_contract(&contract, || {
+            let t = super::execute_simulate_transfers(&e, &caller, FEED_BTC, users.clone(), ids.clone(), &pd);
+            (t, storage::get_vault(&e))
+        });
+        // Nothing settled by the dry run
+        e.as_contract(&contract, || assert!(storage::try_get_position(&e, &b, open).is_some()));
+
+        let vault_before = token.balance(&vault);
+        e.as_contract(&contract, || super::execute_trigger(&e, &caller, FEED_BTC, users, ids, &pd));
+        e.as_contract(&contract, || assert!(storage::try_get_position(&e, &b, open).is_none()));
+        assert!(t.get(caller.clone()).unwrap() > 0);
+        assert_eq!(token.balance(&caller), t.get(caller).unwrap());
+        assert_eq!(token.balance(&vault) - vault_before, t.get(vault).unwrap());
+    }
+
+    #[test]
+    fn test_simulate_transfers_replays_closes_before_fills() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let (a, b) = (Address::generate(&e), Address::generate(&e));
+        let caller = Address::generate(&e);
+        token_client.mint(&a, &(100_000 * SCALAR_7));
+        token_client.mint(&b, &(100_000 * SCALAR_7));
+        update_market(&e, &contract, FEED_BTC, |m| {
+            m.max_util = 1_000; // 10_000 of notional on the 100M vault
+        });
+
+        let pending = create_pending_long(&e, &contract, &a, 1_000 * SCALAR_7, 10_000 * SCALAR_7, BTC_PRICE);
+        let open = e.as_contract(&contract, || {
+            crate::trading::execute_create_market(
+                &e, &b, FEED_BTC, 1_000 * SCALAR_7, 10_000 * SCALAR_7, true, 0, 0, &btc_price_data(&e, BTC_PRICE),
+            )
+        });
+
+        // The order only fits once the liquidation listed after it frees the cap
+        let pd = btc_price_data(&e, 90_500 * PRICE_SCALAR);
+        let users = vec![&e, a.clone(), b.clone()];
+        let ids = vec![&e, pending, open];
+        let t = e.as_contract(&contract, || {
+            super::execute_simulate_transfers(&e, &caller, FEED_BTC, users.clone(), ids.clone(), &pd)
+        });
+        e.as_contract(&contract, || super::execute_trigger(&e, &caller, FEED_BTC, users, ids, &pd));
+        e.as_contract(&contract, || assert!(storage::get_position(&e, &a, pending).filled));
+        assert_eq!(token_client.balance(&caller), t.get(caller).unwrap());
+    }
+
+    #[test]
+    fn test_liquidatable_sorted_most_underwater_first() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        let open = |col: i128| {
+            e.as_contract(&contract, || {
+                crate::trading::execute_create_market(
+                    &e, &user, FEED_BTC, col * SCALAR_7, 10_000 * SCALAR_7, true, 0, 0, &btc_price_data(&e, BTC_PRICE),
+                )
+            })
+        };
+        let mid = open(800);
+        let healthy = open(3_000);
+        let worst = open(700);
+        let best = open(900);
+
+        // -9.5%: every long but the 3_000 one is past its liquidation threshold
+        let pd = btc_price_data(&e, 90_500 * PRICE_SCALAR);
+        let users = vec![&e, user.clone(), user.clone(), user.clone(), user.clone()];
+        let ids = vec![&e, mid, healthy, worst, best];
+        let sorted = e.as_contract(&contract, || {
+            super::execute_liquidatable_sorted(&e, FEED_BTC, users.clone(), ids.clone(), &pd, 10)
+        });
+        assert_eq!(sorted, vec![&e, (user.clone(), worst), (user.clone(), mid), (user.clone(), best)]);
+
+        let capped = e.as_contract(&contract, || super::execute_liquidatable_sorted(&e, FEED_BTC, users, ids, &pd, 2));
+        assert_eq!(capped, vec![&e, (user.clone(), worst), (user, mid)]);
+    }
+
+    /// Opens a liquidatable long, a stop-loss long and a fillable order for `a`, plus
+    /// an unfillable order and an idle short for `b`, then moves past MIN_OPEN_TIME.
+    /// Returns `[liq, stop, fill, unfillable, idle]`.
+    fn mixed_batch(e: &soroban_sdk::Env, contract: &Address, a: &Address, b: &Address) -> [u32; 5] {
+        let pd = btc_price_data(e, BTC_PRICE);
+        let open = |user: &Address, col: i128, long: bool, sl: i128| {
+            e.as_contract(contract, || {
+                crate::trading::execute_create_market(
+                    e, user, FEED_BTC, col * SCALAR_7, 10_000 * SCALAR_7, long, 0, sl, &pd,
+                )
+            })
+        };
+        let liq = open(a, 1_000, true, 0);
+        let stop = open(a, 5_000, true, 95_000 * PRICE_SCALAR);
+        let fill = create_pending_long(e, contract, a, 1_000 * SCALAR_7, 10_000 * SCALAR_7, BTC_PRICE);
+        let unfillable = create_pending_long(e, contract, b, 1_000 * SCALAR_7, 10_000 * SCALAR_7, 80_000 * PRICE_SCALAR);
+        let idle = open(b, 1_000, false, 0);
+        jump(e, 1000 + 31);
+        [liq, stop, fill, unfillable, idle]
+    }
+
+    #[test]
+    fn test_execute_each_skips_failures_and_nets_transfers() {
+        // Reference: the three actionable entries through the all-or-nothing batch
+        let (vault_ref, caller_ref, a_ref) = {
+            let e = setup_env();
+            let (contract, token_client) = setup_contract(&e);
+            let (a, b, caller) = (Address::generate(&e), Address::generate(&e), Address::generate(&e));
+            token_client.mint(&a, &(100_000 * SCALAR_7));
+            token_client.mint(&b, &(100_000 * SCALAR_7));
+            let [liq, stop, fill, _, _] = mixed_batch(&e, &contract, &a, &b);
+
+            let vault = e.as_contract(&contract, || storage::get_vault(&e));
+            let (vault_before, a_before) = (token_client.balance(&vault), token_client.balance(&a));
+            let pd = btc_price_data(&e, 90_500 * PRICE_SCALAR);
+            let users = vec![&e, a.clone(), a.clone(), a.clone()];
+            e.as_contract(&contract, || {
+                super::execute_trigger(&e, &caller, FEED_BTC, users, vec![&e, fill, stop, liq], &pd)
+            });
+            (
+                token_client.balance(&vault) - vault_before,
+                token_client.balance(&caller),
+                token_client.balance(&a) - a_before,
+            )
+        };
+
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let (a, b, caller) = (Address::generate(&e), Address::generate(&e), Address::generate(&e));
+        token_client.mint(&a, &(100_000 * SCALAR_7));
+        token_client.mint(&b, &(100_000 * SCALAR_7));
+        let [liq, stop, fill, unfillable, idle] = mixed_batch(&e, &contract, &a, &b);
+
+        let vault = e.as_contract(&contract, || storage::get_vault(&e));
+        let (vault_before, a_before) = (token_client.balance(&vault), token_client.balance(&a));
+        // -9.5%: liq is liquidated, stop hits its stop-loss, fill fills, the rest can't act
+        let pd = btc_price_data(&e, 90_500 * PRICE_SCALAR);
+        let users = vec![&e, a.clone(), b.clone(), a.clone(), b.clone(), a.clone(), a.clone()];
+        let ids = vec![&e, fill, unfillable, stop, idle, liq, liq];
+        let codes = e.as_contract(&contract, || super::execute_trigger_each(&e, &caller, FEED_BTC, users, ids, &pd));
+        // The repeated liq entry finds the position already gone
+        assert_eq!(codes, vec![&e, 0, 737, 0, 731, 0, 720]);
+
+        e.as_contract(&contract, || {
+            assert!(storage::get_position(&e, &a, fill).filled);
+            assert!(storage::try_get_position(&e, &a, stop).is_none());
+            assert!(storage::try_get_position(&e, &a, liq).is_none());
+            assert!(!storage::get_position(&e, &b, unfillable).filled);
+            assert!(storage::try_get_position(&e, &b, idle).is_some());
+        });
+        assert_eq!(token_client.balance(&vault) - vault_before, vault_ref);
+        assert_eq!(token_client.balance(&caller), caller_ref);
+        assert_eq!(token_client.balance(&a) - a_before, a_ref);
+        assert!(caller_ref > 0);
+    }
+
+    #[test]
+    fn test_execute_each_skips_fills_over_caps() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let (a, b, c) = (Address::generate(&e), Address::generate(&e), Address::generate(&e));
+        let caller = Address::generate(&e);
+        for user in [&a, &b, &c] {
+            token_client.mint(user, &(100_000 * SCALAR_7));
+        }
+
+        let id_a = create_pending_long(&e, &contract, &a, 1_000 * SCALAR_7, 10_000 * SCALAR_7, BTC_PRICE);
+        let id_b = create_pending_long(&e, &contract, &b, 500 * SCALAR_7, 5_000 * SCALAR_7, BTC_PRICE);
+        let id_c = create_pending_long(&e, &contract, &c, 500 * SCALAR_7, 5_000 * SCALAR_7, BTC_PRICE);
+        update_market(&e, &contract, FEED_BTC, |m| {
+            m.max_user_notional = 5_000 * SCALAR_7;
+            // Room for b's order only, and no split of c's: the remainder is under the floor
+            m.max_util = 501;
+            m.min_notional = 1_000 * SCALAR_7;
+        });
+
+        let pd = btc_price_data(&e, BTC_PRICE);
+        let users = vec![&e, a.clone(), b.clone(), c.clone()];
+        let ids = vec![&e, id_a, id_b, id_c];
+        let tags = e.as_contract(&contract, || {
+            super::execute_simulate_trigger(&e, FEED_BTC, users.clone(), ids.clone(), &pd)
+        });
+        assert_eq!(tags.get(0), Some(soroban_sdk::Symbol::new(&e, "user_notional")));
+
+        let codes = e.as_contract(&contract, || super::execute_trigger_each(&e, &caller, FEED_BTC, users, ids, &pd));
+        assert_eq!(codes, vec![&e, 739, 0, 751]);
+        e.as_contract(&contract, || {
+            assert!(!storage::get_position(&e, &a, id_a).filled);
+            assert!(storage::get_position(&e, &b, id_b).filled);
+            assert!(!storage::get_position(&e, &c, id_c).filled);
+        });
+    }
+
+    #[test]
+    fn test_execute_each_stale_price_skips_only_liquidation() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let (a, b, caller) = (Address::generate(&e), Address::generate(&e), Address::generate(&e));
+        token_client.mint(&a, &(100_000 * SCALAR_7));
+        token_client.mint(&b, &(100_000 * SCALAR_7));
+        update_market(&e, &contract, FEED_BTC, |m| m.liq_price_age = 10);
+
+        let open = e.as_contract(&contract, || {
+            crate::trading::execute_create_market(
+                &e, &a, FEED_BTC, 1_000 * SCALAR_7, 10_000 * SCALAR_7, true, 0, 0, &btc_price_data(&e, BTC_PRICE),
+            )
+        });
+        let pending = create_pending_long(&e, &contract, &b, 1_000 * SCALAR_7, 10_000 * SCALAR_7, BTC_PRICE);
+
+        // A -9.5% price published 20s ago: too old to liquidate on, fine to fill at
+        jump(&e, 1000 + 40);
+        let mut pd = btc_price_data(&e, 90_500 * PRICE_SCALAR);
+        pd.publish_time -= 20;
+        let users = vec![&e, a.clone(), b.clone()];
+        let ids = vec![&e, open, pending];
+        let codes = e.as_contract(&contract, || super::execute_trigger_each(&e, &caller, FEED_BTC, users, ids, &pd));
+
+        assert_eq!(codes, vec![&e, 711, 0]);
+        e.as_contract(&contract, || {
+            assert!(storage::try_get_position(&e, &a, open).is_some());
+            assert!(storage::get_position(&e, &b, pending).filled);
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #737)")]
+    fn test_fill_gapped_through_stop_loss_reverts() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        let caller = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        // Long limit at 99k bracketed with a 98k stop; price gaps to 97k
+        let id = e.as_contract(&contract, || {
+            crate::trading::execute_create_limit(
+                &e, &user, FEED_BTC, 1_000 * SCALAR_7, 10_000 * SCALAR_7, true,
+                99_000 * PRICE_SCALAR, 0, 98_000 * PRICE_SCALAR, 0, false, 0,
+            )
+        });
+
+        let pd = btc_price_data(&e, 97_000 * PRICE_SCALAR);
+        e.as_contract(&contract, || {
+            let (users, ids) = trigger_one(&e, &user, id);
+            super::execute_trigger(&e, &caller, FEED_BTC, users, ids, &pd);
+        });
+    }
+
+    #[test]
+    fn test_fill_bonus_grows_while_marked_fillable() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let (a, b) = (Address::generate(&e), Address::generate(&e));
+        let caller = Address::generate(&e);
+        token_client.mint(&a, &(100_000 * SCALAR_7));
+        token_client.mint(&b, &(100_000 * SCALAR_7));
+        update_config(&e, &contract, |c| {
+            c.fill_bonus = 1_000_000; // +10% of fill fees per hour
+        });
+
+        let id_a = create_pending_long(&e, &contract, &a, 1_000 * SCALAR_7, 10_000 * SCALAR_7, BTC_PRICE);
+        let id_b = create_pending_long(&e, &contract, &b, 1_000 * SCALAR_7, 10_000 * SCALAR_7, BTC_PRICE);
+        let pd = btc_price_data(&e, BTC_PRICE);
+        e.as_contract(&contract, || {
+            assert_eq!(super::execute_mark_fillable(&e, &a, id_a, &pd), 1000);
+            assert_eq!(super::execute_mark_fillable(&e, &b, id_b, &pd), 1000);
+        });
+
+        let fill = |user: &Address, id: u32| {
+            let before = token_client.balance(&caller);
+            let pd = btc_price_data(&e, BTC_PRICE);
+            e.as_contract(&contract, || {
+                let (users, ids) = trigger_one(&e, user, id);
+                super::execute_trigger(&e, &caller, FEED_BTC, users, ids, &pd);
+            });
+            token_client.balance(&caller) - before
+        };
+        let prompt = fill(&a, id_a);
+
+        // Left fillable for 2h: 10% + 2 × 10% = 30% of the same fees
+        jump(&e, 1000 + 2 * 3600);
+        let neglected = fill(&b, id_b);
+        assert!(neglected >= 3 * prompt && neglected <= 3 * prompt + 3);
+    }
+
+    #[test]
+    fn test_mark_fillable_before_price_reached_is_unmarked() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        let id = create_pending_long(&e, &contract, &user, 1_000 * SCALAR_7, 10_000 * SCALAR_7, 90_000 * PRICE_SCALAR);
+        let pd = btc_price_data(&e, BTC_PRICE);
+        e.as_contract(&contract, || {
+            assert_eq!(super::execute_mark_fillable(&e, &user, id, &pd), 0);
+            assert_eq!(storage::get_position(&e, &user, id).fillable_since, 0);
+        });
+    }
+
+    #[test]
+    fn test_fillable_mark_cleared_when_price_leaves_range() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let (user, caller) = (Address::generate(&e), Address::generate(&e));
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        let id = create_pending_long(&e, &contract, &user, 1_000 * SCALAR_7, 10_000 * SCALAR_7, BTC_PRICE);
+        let in_range = btc_price_data(&e, BTC_PRICE);
+        e.as_contract(&contract, || super::execute_mark_fillable(&e, &user, id, &in_range));
+
+        // A price from before the mark can't end the episode
+        jump(&e, 1000 + 3600);
+        let early = PriceData { publish_time: 1000, ..btc_price_data(&e, BTC_PRICE + PRICE_SCALAR) };
+        e.as_contract(&contract, || {
+            assert_eq!(super::execute_mark_fillable(&e, &user, id, &early), 0);
+            assert_eq!(storage::get_position(&e, &user, id).fillable_since, 1000);
+        });
+
+        // The batch sees the order out of range and clears the mark
+        let out_of_range = btc_price_data(&e, BTC_PRICE + PRICE_SCALAR);
+        e.as_contract(&contract, || {
+            let (users, ids) = trigger_one(&e, &user, id);
+            let codes = super::execute_trigger_each(&e, &caller, FEED_BTC, users, ids, &out_of_range);
+            assert_eq!(codes, vec![&e, 737]);
+            assert_eq!(storage::get_position(&e, &user, id).fillable_since, 0);
+        });
+
+        // Back in range: a fresh episode starts from the new mark
+        jump(&e, 1000 + 2 * 3600);
+        let back = btc_price_data(&e, BTC_PRICE);
+        e.as_contract(&contract, || {
+            assert_eq!(super::execute_mark_fillable(&e, &user, id, &back), 1000 + 2 * 3600);
+        });
+    }
+
+    /// Helper: 99k long limit bounded to a 1% gap, filled at `fill_price`.
+    fn fill_gapped_long(e: &soroban_sdk::Env, at_trigger: bool, fill_price: i128) -> i128 {
+        let (contract, token_client) = setup_contract(e);
+        let user = Address::generate(e);
+        let caller = Address::generate(e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        let entry = 99_000 * PRICE_SCALAR;
+        let id = e.as_contract(&contract, || {
+            crate::trading::execute_create_limit(
+                e, &user, FEED_BTC, 1_000 * SCALAR_7, 10_000 * SCALAR_7, true, entry, 0, 0, 100_000, at_trigger, 0,
+            )
+        });
+
+        let pd = btc_price_data(e, fill_price);
+        e.as_contract(&contract, || {
+            let (users, ids) = trigger_one(e, &user, id);
+            super::execute_trigger(e, &caller, FEED_BTC, users, ids, &pd);
+            storage::get_position(e, &user, id).entry_price
+        })
+    }
+
+    #[test]
+    fn test_fill_within_max_dev_uses_market_price() {
+        let e = setup_env();
+        let price = 98_500 * PRICE_SCALAR;
+        assert_eq!(fill_gapped_long(&e, false, price), price);
+    }
+
+    #[test]
+    fn test_fill_past_max_dev_at_trigger() {
+        let e = setup_env();
+        assert_eq!(fill_gapped_long(&e, true, 90_000 * PRICE_SCALAR), 99_000 * PRICE_SCALAR);
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #737)")]
+    fn test_fill_past_max_dev_reverts() {
+        let e = setup_env();
+        fill_gapped_long(&e, false, 90_000 * PRICE_SCALAR);
+    }
+
+    #[test]
+    fn test_fill_gapped_short_at_trigger() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        let caller = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        // 101k short limit bounded to a 1% gap; price gaps up to 110k
+        let entry = 101_000 * PRICE_SCALAR;
+        let id = e.as_contract(&contract, || {
+            crate::trading::execute_create_limit(
+                &e, &user, FEED_BTC, 1_000 * SCALAR_7, 10_000 * SCALAR_7, false, entry, 0, 0, 100_000, true, 0,
+            )
+        });
+
+        let pd = btc_price_data(&e, 110_000 * PRICE_SCALAR);
+        e.as_contract(&contract, || {
+            let (users, ids) = trigger_one(&e, &user, id);
+            super::execute_trigger(&e, &caller, FEED_BTC, users, ids, &pd);
+            let position = storage::get_position(&e, &user, id);
+            assert!(position.filled);
+            assert_eq!(position.entry_price, entry);
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #741)")]
+    fn test_fill_blocked_during_fee_free_wind_down() {
+        use crate::types::ContractStatus;
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        let caller = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        let id = create_pending_long(&e, &contract, &user, 1_000 * SCALAR_7, 10_000 * SCALAR_7, BTC_PRICE);
+        update_config(&e, &contract, |c| c.fee_free_wind_down = true);
+        e.as_contract(&contract, || {
+            storage::set_status(&e, ContractStatus::OnIce as u32);
+        });
+
+        let pd = btc_price_data(&e, BTC_PRICE);
+        e.as_contract(&contract, || {
+            let (users, ids) = trigger_one(&e, &user, id);
+            super::execute_trigger(&e, &caller, FEED_BTC, users, ids, &pd);
+        });
+    }
+
+    #[test]
+    fn test_fill_pays_escrowed_bounty_without_fee_share() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        let caller = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+        update_config(&e, &contract, |c| {
+            c.caller_rate = 0; // the fill fee alone pays the keeper nothing
+            c.fill_bounty = 5 * SCALAR_7;
+        });
+
+        let id = create_pending_long(&e, &contract, &user, 1_000 * SCALAR_7, 10_000 * SCALAR_7, BTC_PRICE);
+        assert_eq!(token_client.balance(&user), 100_000 * SCALAR_7 - 1_005 * SCALAR_7);
+
+        let pd = btc_price_data(&e, BTC_PRICE);
+        e.as_contract(&contract, || {
+            let (users, ids) = trigger_one(&e, &user, id);
+            super::execute_trigger(&e, &caller, FEED_BTC, users, ids, &pd);
+        });
+
+        assert_eq!(token_client.balance(&caller), 5 * SCALAR_7);
+        e.as_contract(&contract, || {
+            let position = storage::get_position(&e, &user, id);
+            assert!(position.filled);
+            assert_eq!(position.bounty, 0);
+        });
+    }
+
+    #[test]
+    fn test_split_fill_pays_bounty_pro_rata() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        let caller = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+        update_config(&e, &contract, |c| {
+            c.caller_rate = 0;
+            c.fill_bounty = SCALAR_7 / 10;
+        });
+        update_market(&e, &contract, FEED_BTC, |m| m.max_util = 500); // room for half the order
+
+        let id = create_pending_long(&e, &contract, &user, 1_000 * SCALAR_7, 10_000 * SCALAR_7, BTC_PRICE);
+        let pd = btc_price_data(&e, BTC_PRICE);
+        e.as_contract(&contract, || {
+            let (users, ids) = trigger_one(&e, &user, id);
+            super::execute_trigger(&e, &caller, FEED_BTC, users, ids, &pd);
+            assert_eq!(storage::get_position(&e, &user, id).bounty, 0);
+            assert_eq!(storage::get_position(&e, &user, id + 1).bounty, SCALAR_7 / 20);
+        });
+        assert_eq!(token_client.balance(&caller), SCALAR_7 / 20);
+    }
+
+    #[test]
+    fn test_self_fill_earns_no_caller_fee() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        let id = create_pending_long(&e, &contract, &user, 1_000 * SCALAR_7, 10_000 * SCALAR_7, BTC_PRICE);
+        let user_before = token_client.balance(&user);
+        e.as_contract(&contract, || {
+            let (users, ids) = trigger_one(&e, &user, id);
+            super::execute_trigger(&e, &user, FEED_BTC, users, ids, &btc_price_data(&e, BTC_PRICE));
+            assert!(storage::get_position(&e, &user, id).filled);
+        });
+
+        // The keeper share a third party would earn (5_000_001) stays with the vault
+        assert_eq!(token_client.balance(&user), user_before);
+    }
+
+    #[test]
+    fn test_fill_short_limit_order() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        let caller = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        let id = create_pending_short(&e, &contract, &user, 1_000 * SCALAR_7, 10_000 * SCALAR_7, BTC_PRICE);
+
+        let pd = btc_price_data(&e, BTC_PRICE);
+        e.as_contract(&contract, || {
+            let (users, ids) = trigger_one(&e, &user, id);
+            super::execute_trigger(&e, &caller, FEED_BTC, users, ids, &pd);
+
+            let pos = storage::get_position(&e, &user, id);
+            assert!(pos.filled);
+            assert_eq!(pos.col, 9_949_999_988);
+        });
+    }
+
+    #[test]
+    fn test_liquidation_underwater_position() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        let caller = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        let id = create_pending_long(&e, &contract, &user, 1_100 * SCALAR_7, 100_000 * SCALAR_7, BTC_PRICE);
+
+        let balance_after_create = token_client.balance(&user);
+        let pd = btc_price_data(&e, BTC_PRICE);
+        e.as_contract(&contract, || {
+            let (users, ids) = trigger_one(&e, &user, id);
+            super::execute_trigger(&e, &caller, FEED_BTC, users, ids, &pd);
+
+            // Price crashes -2% on 100x leverage → underwater
+            let crash_pd = btc_price_data(&e, 9_800_000_000_000_i128);
+            let (users, ids) = trigger_one(&e, &user, id);
+            super::execute_trigger(&e, &caller, FEED_BTC, users, ids, &crash_pd);
+            // The fill counts toward the user's volume, the liquidation does not
+            assert_eq!(storage::get_user_volume(&e, &user), 100_000 * SCALAR_7);
+        });
+        // User gets nothing back (underwater liquidation)
+        assert_eq!(token_client.balance(&user), balance_after_create);
+    }
+
+    #[test]
+    fn test_liquidation_caller_fee_capped_by_collateral() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        let caller = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+        update_config(&e, &contract, |c| {
+            c.liq_caller_cap = 1_000; // 0.01% of collateral
+        });
+
+        let id = e.as_contract(&contract, || {
+            crate::trading::execute_create_market(
+                &e, &user, FEED_BTC, 1_000 * SCALAR_7, 10_000 * SCALAR_7, true, 0, 0, &btc_price_data(&e, BTC_PRICE),
+            )
+        });
+        let col = e.as_contract(&contract, || storage::get_position(&e, &user, id).col);
+
+        // Left untouched for two years: borrowing alone sinks it at an unchanged price
+        jump(&e, 1000 + 2 * 365 * 86_400);
+        let pd = btc_price_data(&e, BTC_PRICE);
+        e.as_contract(&contract, || {
+            let (users, ids) = trigger_one(&e, &user, id);
+            super::execute_trigger(&e, &caller, FEED_BTC, users, ids, &pd);
+            assert!(storage::try_get_position(&e, &user, id).is_none());
+        });
+        assert_eq!(token_client.balance(&caller), col / 10_000);
+    }
+
+    #[test]
+    fn test_self_liquidation_earns_no_caller_fee() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        let keeper = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+        let id = fill_long(&e, &contract, &user, &keeper);
+
+        let (vault, treasury) = e.as_contract(&contract, || (storage::get_vault(&e), storage::get_treasury(&e)));
+        let user_before = token_client.balance(&user);
+        let vault_before = token_client.balance(&vault);
+        let treasury_before = token_client.balance(&treasury);
+        e.as_contract(&contract, || {
+            let (users, ids) = trigger_one(&e, &user, id);
+            super::execute_trigger(&e, &user, FEED_BTC, users, ids, &btc_price_data(&e, BREACH_PRICE));
+        });
+
+        // Owner as caller: no keeper cut, everything but the treasury share goes to the vault
+        assert_eq!(token_client.balance(&user), user_before);
+        let treasury_fee = token_client.balance(&treasury) - treasury_before;
+        assert_eq!(token_client.balance(&vault) - vault_before, 9_949_999_988 - treasury_fee);
+    }
+
+    /// Helper: fill a 1_000 / 10_000 long through `caller`.
+    fn fill_long(e: &soroban_sdk::Env, contract: &Address, user: &Address, caller: &Address) -> u32 {
+        let id = create_pending_long(e, contract, user, 1_000 * SCALAR_7, 10_000 * SCALAR_7, BTC_PRICE);
+        let pd = btc_price_data(e, BTC_PRICE);
+        e.as_contract(contract, || {
+            let (users, ids) = trigger_one(e, user, id);
+            super::execute_trigger(e, caller, FEED_BTC, users, ids, &pd);
+        });
+        id
+    }
+
+    // -9.7% on 10x: equity below the 0.5% threshold but still positive
+    const BREACH_PRICE: i128 = 9_030_000_000_000;
+
+    #[test]
+    fn test_liquidation_grace_marks_breach() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        let caller = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+        update_market(&e, &contract, FEED_BTC, |m| m.liq_grace = 600);
+        let id = fill_long(&e, &contract, &user, &caller);
+
+        let pd = btc_price_data(&e, BREACH_PRICE);
+        e.as_contract(&contract, || {
+            let (users, ids) = trigger_one(&e, &user, id);
+            super::execute_trigger(&e, &caller, FEED_BTC, users, ids, &pd);
+
+            let pos = storage::get_position(&e, &user, id);
+            assert_eq!(pos.breach_at, e.ledger().timestamp());
+            assert_eq!(pos.col, 9_949_999_988);
+        });
+    }
+
+    #[test]
+    fn test_liquidation_grace_recovery_clears_breach() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        let caller = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+        update_market(&e, &contract, FEED_BTC, |m| m.liq_grace = 600);
+        let id = fill_long(&e, &contract, &user, &caller);
+
+        let pd = btc_price_data(&e, BREACH_PRICE);
+        e.as_contract(&contract, || {
+            let (users, ids) = trigger_one(&e, &user, id);
+            super::execute_trigger(&e, &caller, FEED_BTC, users, ids, &pd);
+        });
+
+        jump(&e, e.ledger().timestamp() + 60);
+        let pd = btc_price_data(&e, BTC_PRICE);
+        e.as_contract(&contract, || {
+            let (users, ids) = trigger_one(&e, &user, id);
+            super::execute_trigger(&e, &caller, FEED_BTC, users, ids, &pd);
+
+            assert_eq!(storage::get_position(&e, &user, id).breach_at, 0);
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #731)")]
+    fn test_liquidation_within_grace_panics() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        let caller = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+        update_market(&e, &contract, FEED_BTC, |m| m.liq_grace = 600);
+        let id = fill_long(&e, &contract, &user, &caller);
+
+        e.as_contract(&contract, || {
+            let (users, ids) = trigger_one(&e, &user, id);
+            super::execute_trigger(&e, &caller, FEED_BTC, users, ids, &btc_price_data(&e, BREACH_PRICE));
+        });
+
+        jump(&e, e.ledger().timestamp() + 599);
+        e.as_contract(&contract, || {
+            let (users, ids) = trigger_one(&e, &user, id);
+            super::execute_trigger(&e, &caller, FEED_BTC, users, ids, &btc_price_data(&e, BREACH_PRICE));
+        });
+    }
+
+    #[test]
+    fn test_liquidation_after_grace() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        let caller = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+        update_market(&e, &contract, FEED_BTC, |m| m.liq_grace = 600);
+        let id = fill_long(&e, &contract, &user, &caller);
+
+        e.as_contract(&contract, || {
+            let (users, ids) = trigger_one(&e, &user, id);
+            super::execute_trigger(&e, &caller, FEED_BTC, users, ids, &btc_price_data(&e, BREACH_PRICE));
+        });
+
+        jump(&e, e.ledger().timestamp() + 600);
+        let caller_before = token_client.balance(&caller);
+        e.as_contract(&contract, || {
+            let (users, ids) = trigger_one(&e, &user, id);
+            super::execute_trigger(&e, &caller, FEED_BTC, users, ids, &btc_price_data(&e, BREACH_PRICE));
+
+            let key = storage::TradingStorageKey::Position(user.clone(), id);
+            assert!(!e.storage().persistent().has(&key));
+        });
+        assert!(token_client.balance(&caller) > caller_before);
+    }
+
+    #[test]
+    fn test_top_up_clears_breach_and_next_dip_gets_full_grace() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        let caller = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+        update_market(&e, &contract, FEED_BTC, |m| m.liq_grace = 600);
+        let id = fill_long(&e, &contract, &user, &caller);
+
+        e.as_contract(&contract, || {
+            let (users, ids) = trigger_one(&e, &user, id);
+            super::execute_trigger(&e, &caller, FEED_BTC, users, ids, &btc_price_data(&e, BREACH_PRICE));
+        });
+        jump(&e, e.ledger().timestamp() + 60);
+        e.as_contract(&contract, || {
+            let pd = btc_price_data(&e, BREACH_PRICE);
+            crate::trading::execute_modify_collateral(&e, &user, id, 9_949_999_988 + 500 * SCALAR_7, &pd);
+        });
+        assert_eq!(e.as_contract(&contract, || storage::get_position(&e, &user, id).breach_at), 0);
+
+        // Past the old breach's grace: a new dip records a new breach, not a liquidation
+        jump(&e, e.ledger().timestamp() + 600);
+        e.as_contract(&contract, || {
+            let (users, ids) = trigger_one(&e, &user, id);
+            super::execute_trigger(&e, &caller, FEED_BTC, users, ids, &btc_price_data(&e, 8_550_000_000_000));
+            assert_eq!(storage::get_position(&e, &user, id).breach_at, e.ledger().timestamp());
+        });
+    }
+
+    #[test]
+    fn test_late_keeper_still_liquidates_breach() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        let caller = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+        update_market(&e, &contract, FEED_BTC, |m| m.liq_grace = 600);
+        let id = fill_long(&e, &contract, &user, &caller);
+
+        e.as_contract(&contract, || {
+            let (users, ids) = trigger_one(&e, &user, id);
+            super::execute_trigger(&e, &caller, FEED_BTC, users, ids, &btc_price_data(&e, BREACH_PRICE));
+        });
+
+        // Keepers come back hours later to a position that never recovered: the
+        // breach is not restarted, the position is liquidated
+        jump(&e, e.ledger().timestamp() + 2 * 3600);
+        e.as_contract(&contract, || {
+            let (users, ids) = trigger_one(&e, &user, id);
+            let tags = super::execute_simulate_trigger(&e, FEED_BTC, users.clone(), ids.clone(), &btc_price_data(&e, BREACH_PRICE));
+            assert_eq!(tags, vec![&e, soroban_sdk::Symbol::new(&e, "liquidate")]);
+            super::execute_trigger(&e, &caller, FEED_BTC, users, ids, &btc_price_data(&e, BREACH_PRICE));
+            assert!(storage::try_get_position(&e, &user, id).is_none());
+        });
+    }
+
+    #[test]
+    fn test_submitted_recovery_clears_breach_for_next_dip() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        let caller = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+        update_market(&e, &contract, FEED_BTC, |m| m.liq_grace = 600);
+        let id = fill_long(&e, &contract, &user, &caller);
+
+        e.as_contract(&contract, || {
+            let (users, ids) = trigger_one(&e, &user, id);
+            super::execute_trigger(&e, &caller, FEED_BTC, users, ids, &btc_price_data(&e, BREACH_PRICE));
+        });
+
+        // A keeper submits the recovered position, then it dips again past the old grace
+        jump(&e, e.ledger().timestamp() + 60);
+        e.as_contract(&contract, || {
+            let (users, ids) = trigger_one(&e, &user, id);
+            super::execute_trigger(&e, &caller, FEED_BTC, users, ids, &btc_price_data(&e, BTC_PRICE));
+            assert_eq!(storage::get_position(&e, &user, id).breach_at, 0);
+        });
+        jump(&e, e.ledger().timestamp() + 2 * 3600);
+        e.as_contract(&contract, || {
+            let (users, ids) = trigger_one(&e, &user, id);
+            super::execute_trigger(&e, &caller, FEED_BTC, users, ids, &btc_price_data(&e, BREACH_PRICE));
+            assert_eq!(storage::get_position(&e, &user, id).breach_at, e.ledger().timestamp());
+        });
+    }
+
+    #[test]
+    fn test_settle_expiry_after_max_duration() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        let caller = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+        update_market(&e, &contract, FEED_BTC, |m| m.max_duration = 7 * 86_400);
+        let id = fill_long(&e, &contract, &user, &caller);
+
+        jump(&e, e.ledger().timestamp() + 7 * 86_400);
+        let user_before = token_client.balance(&user);
+        let caller_before = token_client.balance(&caller);
+        e.as_contract(&contract, || {
+            let (users, ids) = trigger_one(&e, &user, id);
+            super::execute_trigger(&e, &caller, FEED_BTC, users, ids, &btc_price_data(&e, BTC_PRICE));
+
+            let key = storage::TradingStorageKey::Position(user.clone(), id);
+            assert!(!e.storage().persistent().has(&key));
+        });
+        assert!(token_client.balance(&user) > user_before);
+        assert!(token_client.balance(&caller) > caller_before);
+    }
+
+    #[test]
+    fn test_settle_expiry_with_funding_rebate_conserves_tokens() {
         let e = setup_env();
         let (contract, token_client) = setup_contract(&e);
+        let whale = Address::generate(&e);
         let user = Address::generate(&e);
         let caller = Address::generate(&e);
+        token_client.mint(&whale, &(100_000 * SCALAR_7));
         token_client.mint(&user, &(100_000 * SCALAR_7));
 
-        let id = create_pending_short(&e, &contract, &user, 1_000 * SCALAR_7, 10_000 * SCALAR_7, BTC_PRICE);
-
+        // 10k long vs 1k short: the minority short receives 10x the per-unit funding
+        update_market(&e, &contract, FEED_BTC, |m| m.max_duration = 7 * 86_400);
+        fill_long(&e, &contract, &whale, &caller);
+        let id = create_pending_short(&e, &contract, &user, 100 * SCALAR_7, 1_000 * SCALAR_7, BTC_PRICE);
         let pd = btc_price_data(&e, BTC_PRICE);
         e.as_contract(&contract, || {
             let (users, ids) = trigger_one(&e, &user, id);
             super::execute_trigger(&e, &caller, FEED_BTC, users, ids, &pd);
+        });
+        jump(&e, e.ledger().timestamp() + 3600);
+        e.as_contract(&contract, || crate::trading::execute_apply_funding(&e));
 
-            let pos = storage::get_position(&e, &user, id);
-            assert!(pos.filled);
-            assert_eq!(pos.col, 9_949_999_988);
+        // Flat price at expiry: the only PnL is the funding rebate
+        jump(&e, 1000 + 7 * 86_400);
+        let pd = btc_price_data(&e, BTC_PRICE);
+        let (col, s) = e.as_contract(&contract, || {
+            let position = storage::get_position(&e, &user, id);
+            let ctx = crate::trading::context::Context::load(&e, FEED_BTC, &pd);
+            (position.col, position.clone().settle(&e, &ctx))
+        });
+        assert_eq!(s.pnl, 0);
+        assert_eq!(s.borrowing_fee, 0); // non-dominant side
+        assert!(-s.funding > s.trading_fee(), "rebate should exceed the close fees");
+
+        let user_before = token_client.balance(&user);
+        let caller_before = token_client.balance(&caller);
+        let contract_before = token_client.balance(&contract);
+        e.as_contract(&contract, || {
+            let (users, ids) = trigger_one(&e, &user, id);
+            super::execute_trigger(&e, &caller, FEED_BTC, users, ids, &pd);
         });
+
+        // User: collateral + rebate - close fees (the caller's cut comes out of the fees)
+        let payout = col - s.funding - s.trading_fee();
+        assert_eq!(token_client.balance(&user) - user_before, payout);
+        assert_eq!(
+            token_client.balance(&caller) - caller_before,
+            s.trading_fee() * 1_000_000 / SCALAR_7,
+        );
+        // Only the closed position's collateral leaves; the vault funds the rebate
+        assert_eq!(contract_before - token_client.balance(&contract), col);
     }
 
     #[test]
-    fn test_liquidation_underwater_position() {
+    #[should_panic(expected = "Error(Contract, #731)")]
+    fn test_settle_expiry_before_max_duration_panics() {
         let e = setup_env();
         let (contract, token_client) = setup_contract(&e);
         let user = Address::generate(&e);
         let caller = Address::generate(&e);
         token_client.mint(&user, &(100_000 * SCALAR_7));
+        update_market(&e, &contract, FEED_BTC, |m| m.max_duration = 7 * 86_400);
+        let id = fill_long(&e, &contract, &user, &caller);
 
-        let id = create_pending_long(&e, &contract, &user, 1_100 * SCALAR_7, 100_000 * SCALAR_7, BTC_PRICE);
-
-        let balance_after_create = token_client.balance(&user);
-        let pd = btc_price_data(&e, BTC_PRICE);
+        jump(&e, e.ledger().timestamp() + 7 * 86_400 - 1);
         e.as_contract(&contract, || {
             let (users, ids) = trigger_one(&e, &user, id);
-            super::execute_trigger(&e, &caller, FEED_BTC, users, ids, &pd);
-
-            // Price crashes -2% on 100x leverage → underwater
-            let crash_pd = btc_price_data(&e, 9_800_000_000_000_i128);
-            let (users, ids) = trigger_one(&e, &user, id);
-            super::execute_trigger(&e, &caller, FEED_BTC, users, ids, &crash_pd);
+            super::execute_trigger(&e, &caller, FEED_BTC, users, ids, &btc_price_data(&e, BTC_PRICE));
         });
-        // User gets nothing back (underwater liquidation)
-        assert_eq!(token_client.balance(&user), balance_after_create);
     }
 
     #[test]
@@ -449,6 +2406,9 @@ mod tests {
                 BTC_PRICE,
                 0,
                 95_000 * PRICE_SCALAR,
+                0,
+                false,
+                0,
             )
         });
 
@@ -488,6 +2448,9 @@ mod tests {
                 BTC_PRICE,
                 110_000 * PRICE_SCALAR,
                 0,
+                0,
+                false,
+                0,
             )
         });
 
@@ -510,6 +2473,57 @@ mod tests {
             "TP payout should exceed original collateral");
     }
 
+    #[test]
+    fn test_manual_stop_loss_only_executes_for_user() {
+        use crate::testutils::jump;
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        let caller = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        let id = e.as_contract(&contract, || {
+            crate::trading::execute_create_limit(
+                &e, &user, FEED_BTC,
+                1_000 * SCALAR_7,
+                10_000 * SCALAR_7,
+                true,
+                BTC_PRICE,
+                0,
+                95_000 * PRICE_SCALAR,
+                0,
+                false,
+                0,
+            )
+        });
+        let pd = btc_price_data(&e, BTC_PRICE);
+        e.as_contract(&contract, || {
+            let (users, ids) = trigger_one(&e, &user, id);
+            super::execute_trigger(&e, &caller, FEED_BTC, users, ids, &pd);
+        });
+        e.as_contract(&contract, || crate::trading::execute_set_auto_trigger(&e, &user, id, false));
+
+        jump(&e, 1000 + 31);
+        let sl_pd = btc_price_data(&e, 9_400_000_000_000_i128);
+        e.as_contract(&contract, || {
+            let (users, ids) = trigger_one(&e, &user, id);
+            let tags = super::execute_simulate_trigger(&e, FEED_BTC, users.clone(), ids.clone(), &sl_pd);
+            assert_eq!(tags, vec![&e, soroban_sdk::Symbol::new(&e, "manual_only")]);
+            let codes = super::execute_trigger_each(&e, &caller, FEED_BTC, users, ids, &sl_pd);
+            assert_eq!(codes, vec![&e, 1]);
+            assert!(storage::try_get_position(&e, &user, id).is_some());
+        });
+
+        // The user can still execute their own trigger
+        let balance_before = token_client.balance(&user);
+        e.as_contract(&contract, || {
+            let (users, ids) = trigger_one(&e, &user, id);
+            super::execute_trigger(&e, &user, FEED_BTC, users, ids, &sl_pd);
+            assert!(storage::try_get_position(&e, &user, id).is_none());
+        });
+        assert!(token_client.balance(&user) > balance_before);
+    }
+
     #[test]
     fn test_batch_multiple_requests() {
         let e = setup_env();
@@ -537,6 +2551,52 @@ mod tests {
         assert!(token_client.balance(&caller) > caller_before);
     }
 
+    #[test]
+    fn test_batch_liquidation_funds_winner_under_tight_liquidity() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let winner = Address::generate(&e);
+        let loser = Address::generate(&e);
+        let caller = Address::generate(&e);
+        token_client.mint(&winner, &(100_000 * SCALAR_7));
+        token_client.mint(&loser, &(100_000 * SCALAR_7));
+
+        let pd = btc_price_data(&e, BTC_PRICE);
+        let (win_id, lose_id) = e.as_contract(&contract, || {
+            let win_id = crate::trading::execute_create_market(
+                &e, &winner, FEED_BTC, 1_000 * SCALAR_7, 10_000 * SCALAR_7, true,
+                105_000 * PRICE_SCALAR, 0, &pd,
+            );
+            let lose_id = crate::trading::execute_create_market(
+                &e, &loser, FEED_BTC, 1_100 * SCALAR_7, 100_000 * SCALAR_7, false, 0, 0, &pd,
+            );
+            (win_id, lose_id)
+        });
+
+        // Drain the vault below the winner's profit
+        let vault = e.as_contract(&contract, || storage::get_vault(&e));
+        let token = soroban_sdk::token::TokenClient::new(&e, &token_client.address);
+        let sink = Address::generate(&e);
+        token.transfer(&vault, &sink, &(token.balance(&vault) - 100 * SCALAR_7));
+
+        jump(&e, 1000 + 31);
+        let winner_before = token.balance(&winner);
+        let vault_before = token.balance(&vault);
+        e.as_contract(&contract, || {
+            // Winner listed first: the loser's collateral still covers its profit
+            let users = vec![&e, winner.clone(), loser.clone()];
+            let ids = vec![&e, win_id, lose_id];
+            super::execute_trigger(&e, &caller, FEED_BTC, users, ids, &btc_price_data(&e, 10_600_000_000_000_i128));
+        });
+
+        assert!(token.balance(&winner) - winner_before > 1_000 * SCALAR_7);
+        assert!(token.balance(&vault) > vault_before);
+        e.as_contract(&contract, || {
+            assert!(storage::try_get_position(&e, &winner, win_id).is_none());
+            assert!(storage::try_get_position(&e, &loser, lose_id).is_none());
+        });
+    }
+
     #[test]
     #[should_panic(expected = "Error(Contract, #731)")]
     fn test_fill_already_filled_panics() {
@@ -559,4 +2619,330 @@ mod tests {
         });
     }
 
+
+    #[test]
+    fn test_force_settle_all_winds_down_market() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        let caller = Address::generate(&e);
+        token_client.mint(&user, &(1_000_000 * SCALAR_7));
+
+        let filled = create_pending_long(&e, &contract, &user, 1_000 * SCALAR_7, 10_000 * SCALAR_7, BTC_PRICE);
+        let pending = create_pending_short(&e, &contract, &user, 1_000 * SCALAR_7, 10_000 * SCALAR_7, 200_000 * PRICE_SCALAR);
+
+        let pd = btc_price_data(&e, BTC_PRICE);
+        e.as_contract(&contract, || {
+            let (users, ids) = trigger_one(&e, &user, filled);
+            super::execute_trigger(&e, &caller, FEED_BTC, users, ids, &pd);
+        });
+        update_market(&e, &contract, FEED_BTC, |m| m.enabled = false);
+
+        let balance_before = token_client.balance(&user);
+        let contract_before = token_client.balance(&contract);
+        e.as_contract(&contract, || {
+            let users = vec![&e, user.clone(), user.clone()];
+            let ids = vec![&e, filled, pending];
+            super::execute_force_settle(&e, FEED_BTC, users, ids, &pd);
+
+            let data = storage::get_market_data(&e, FEED_BTC);
+            assert_eq!(data.l_notional, 0);
+            assert_eq!(data.s_notional, 0);
+            assert_eq!(data.l_entry_wt, 0);
+            assert_eq!(data.s_entry_wt, 0);
+            assert_eq!(storage::get_total_notional(&e), 0);
+        });
+        // Pending refunded in full, filled returned less fees
+        let received = token_client.balance(&user) - balance_before;
+        assert!(received > 1_000 * SCALAR_7);
+        assert!(received < 2_000 * SCALAR_7);
+        // Both collaterals (filled net of open fees) leave the contract
+        assert_eq!(token_client.balance(&contract), contract_before - 9_949_999_988 - 1_000 * SCALAR_7);
+    }
+
+    #[test]
+    fn test_force_settle_all_skips_other_market_and_settled_entries() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let (alice, bob, caller) = (Address::generate(&e), Address::generate(&e), Address::generate(&e));
+        token_client.mint(&alice, &(1_000_000 * SCALAR_7));
+        token_client.mint(&bob, &(1_000_000 * SCALAR_7));
+        update_market(&e, &contract, FEED_ETH, |_| {});
+
+        let filled = create_pending_long(&e, &contract, &alice, 1_000 * SCALAR_7, 10_000 * SCALAR_7, BTC_PRICE);
+        let pending = create_pending_short(&e, &contract, &bob, 1_000 * SCALAR_7, 10_000 * SCALAR_7, 200_000 * PRICE_SCALAR);
+        let eth = e.as_contract(&contract, || {
+            crate::trading::execute_create_limit(
+                &e, &bob, FEED_ETH, 1_000 * SCALAR_7, 10_000 * SCALAR_7, true, 2_000 * PRICE_SCALAR, 0, 0, 0, false, 0,
+            )
+        });
+        let pd = btc_price_data(&e, BTC_PRICE);
+        e.as_contract(&contract, || {
+            let (users, ids) = trigger_one(&e, &alice, filled);
+            super::execute_trigger(&e, &caller, FEED_BTC, users, ids, &pd);
+        });
+        update_market(&e, &contract, FEED_BTC, |m| m.enabled = false);
+
+        // Settle a first page, then replay it alongside the ETH order: settled and
+        // foreign entries are skipped, not fatal.
+        let settle_pd = btc_price_data(&e, 95_000 * PRICE_SCALAR);
+        e.as_contract(&contract, || {
+            let (users, ids) = trigger_one(&e, &alice, filled);
+            super::execute_force_settle(&e, FEED_BTC, users, ids, &settle_pd);
+
+            let users = vec![&e, alice.clone(), bob.clone(), bob.clone()];
+            let ids = vec![&e, filled, eth, pending];
+            super::execute_force_settle(&e, FEED_BTC, users, ids, &settle_pd);
+
+            assert!(storage::try_get_position(&e, &alice, filled).is_none());
+            assert!(storage::try_get_position(&e, &bob, pending).is_none());
+            assert!(storage::try_get_position(&e, &bob, eth).is_some());
+            let data = storage::get_market_data(&e, FEED_BTC);
+            assert_eq!(data.l_notional + data.s_notional, 0);
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #710)")]
+    fn test_force_settle_all_price_beyond_deviation_panics() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let (user, caller) = (Address::generate(&e), Address::generate(&e));
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        let id = create_pending_long(&e, &contract, &user, 1_000 * SCALAR_7, 10_000 * SCALAR_7, BTC_PRICE);
+        e.as_contract(&contract, || {
+            let (users, ids) = trigger_one(&e, &user, id);
+            super::execute_trigger(&e, &caller, FEED_BTC, users, ids, &btc_price_data(&e, BTC_PRICE));
+        });
+        update_market(&e, &contract, FEED_BTC, |m| m.enabled = false);
+
+        // 25% above the last stored price, past MAX_SETTLE_DEV
+        e.as_contract(&contract, || {
+            let (users, ids) = trigger_one(&e, &user, id);
+            super::execute_force_settle(&e, FEED_BTC, users, ids, &btc_price_data(&e, 125_000 * PRICE_SCALAR));
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #710)")]
+    fn test_force_settle_all_empty_pages_cannot_walk_price() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let (user, caller) = (Address::generate(&e), Address::generate(&e));
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        let id = create_pending_long(&e, &contract, &user, 1_000 * SCALAR_7, 10_000 * SCALAR_7, BTC_PRICE);
+        e.as_contract(&contract, || {
+            let (users, ids) = trigger_one(&e, &user, id);
+            super::execute_trigger(&e, &caller, FEED_BTC, users, ids, &btc_price_data(&e, BTC_PRICE));
+        });
+        update_market(&e, &contract, FEED_BTC, |m| m.enabled = false);
+
+        e.as_contract(&contract, || {
+            // 18% up is accepted but leaves the reference at the last stored price
+            super::execute_force_settle(&e, FEED_BTC, vec![&e], vec![&e], &btc_price_data(&e, 118_000 * PRICE_SCALAR));
+            assert_eq!(storage::get_last_price(&e, FEED_BTC), Some(BTC_PRICE));
+            // Within 20% of the previous page's price, but not of the reference
+            super::execute_force_settle(&e, FEED_BTC, vec![&e], vec![&e], &btc_price_data(&e, 135_000 * PRICE_SCALAR));
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #710)")]
+    fn test_force_settle_all_without_stored_price_panics() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        // A pending order never stores a price for the market
+        let id = create_pending_long(&e, &contract, &user, 1_000 * SCALAR_7, 10_000 * SCALAR_7, BTC_PRICE);
+        update_market(&e, &contract, FEED_BTC, |m| m.enabled = false);
+
+        e.as_contract(&contract, || {
+            let (users, ids) = trigger_one(&e, &user, id);
+            super::execute_force_settle(&e, FEED_BTC, users, ids, &btc_price_data(&e, BTC_PRICE));
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #704)")]
+    fn test_force_settle_all_enabled_market_panics() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        let id = create_pending_long(&e, &contract, &user, 1_000 * SCALAR_7, 10_000 * SCALAR_7, BTC_PRICE);
+
+        let pd = btc_price_data(&e, BTC_PRICE);
+        e.as_contract(&contract, || {
+            let (users, ids) = trigger_one(&e, &user, id);
+            super::execute_force_settle(&e, FEED_BTC, users, ids, &pd);
+        });
+    }
+
+    #[test]
+    fn test_frozen_position_skips_liquidation_until_unfrozen() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let (user, caller) = (Address::generate(&e), Address::generate(&e));
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        let id = e.as_contract(&contract, || {
+            crate::trading::execute_create_market(
+                &e, &user, FEED_BTC, 1_000 * SCALAR_7, 10_000 * SCALAR_7, true, 0, 0, &btc_price_data(&e, BTC_PRICE),
+            )
+        });
+        e.as_contract(&contract, || crate::trading::execute_set_frozen(&e, &user, id, true));
+
+        // -9.5% on 10x: liquidatable, but held by the freeze
+        let pd = btc_price_data(&e, 90_500 * PRICE_SCALAR);
+        e.as_contract(&contract, || {
+            let (users, ids) = trigger_one(&e, &user, id);
+            let tags = super::execute_simulate_trigger(&e, FEED_BTC, users.clone(), ids.clone(), &pd);
+            assert_eq!(tags, vec![&e, soroban_sdk::Symbol::new(&e, "frozen")]);
+            let codes = super::execute_trigger_each(&e, &caller, FEED_BTC, users, ids, &pd);
+            assert_eq!(codes, vec![&e, 722]);
+            assert!(storage::try_get_position(&e, &user, id).is_some());
+
+            crate::trading::execute_set_frozen(&e, &user, id, false);
+            let (users, ids) = trigger_one(&e, &user, id);
+            super::execute_trigger(&e, &caller, FEED_BTC, users, ids, &pd);
+            assert!(storage::try_get_position(&e, &user, id).is_none());
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #722)")]
+    fn test_liquidate_frozen_position_fails() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let (user, caller) = (Address::generate(&e), Address::generate(&e));
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        let id = e.as_contract(&contract, || {
+            crate::trading::execute_create_market(
+                &e, &user, FEED_BTC, 1_000 * SCALAR_7, 10_000 * SCALAR_7, true, 0, 0, &btc_price_data(&e, BTC_PRICE),
+            )
+        });
+        let pd = btc_price_data(&e, 90_500 * PRICE_SCALAR);
+        e.as_contract(&contract, || {
+            crate::trading::execute_set_frozen(&e, &user, id, true);
+            let (users, ids) = trigger_one(&e, &user, id);
+            super::execute_trigger(&e, &caller, FEED_BTC, users, ids, &pd);
+        });
+    }
+
+    #[test]
+    fn test_batch_actions_share_indices_accrued_once() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let (a, b, caller) = (Address::generate(&e), Address::generate(&e), Address::generate(&e));
+        token_client.mint(&a, &(100_000 * SCALAR_7));
+        token_client.mint(&b, &(100_000 * SCALAR_7));
+
+        // Open interest so the indices have something to accrue on
+        e.as_contract(&contract, || {
+            crate::trading::execute_create_market(
+                &e, &a, FEED_BTC, 1_000 * SCALAR_7, 10_000 * SCALAR_7, true, 0, 0, &btc_price_data(&e, BTC_PRICE),
+            )
+        });
+        let first = create_pending_long(&e, &contract, &a, 1_000 * SCALAR_7, 10_000 * SCALAR_7, BTC_PRICE);
+        let second = create_pending_long(&e, &contract, &b, 1_000 * SCALAR_7, 10_000 * SCALAR_7, BTC_PRICE);
+        let before = e.as_contract(&contract, || storage::get_market_data(&e, FEED_BTC));
+
+        jump(&e, 1000 + 3600);
+        let pd = btc_price_data(&e, BTC_PRICE);
+        e.as_contract(&contract, || {
+            let users = vec![&e, a.clone(), b.clone()];
+            super::execute_trigger(&e, &caller, FEED_BTC, users, vec![&e, first, second], &pd);
+
+            let data = storage::get_market_data(&e, FEED_BTC);
+            assert!(data.l_borr_idx > before.l_borr_idx);
+            let (p1, p2) = (storage::get_position(&e, &a, first), storage::get_position(&e, &b, second));
+            assert_eq!((p1.borr_idx, p1.fund_idx), (data.l_borr_idx, data.l_fund_idx));
+            assert_eq!((p2.borr_idx, p2.fund_idx), (data.l_borr_idx, data.l_fund_idx));
+
+            // A later load in the same transaction accrues nothing more
+            let ctx = crate::trading::context::Context::load(&e, FEED_BTC, &pd);
+            assert_eq!((ctx.data.l_borr_idx, ctx.data.l_fund_idx), (data.l_borr_idx, data.l_fund_idx));
+        });
+    }
+
+    /// Opens a 1_000 / 10_000 BTC long, sets `keeper_min_shares` to 500 and gives
+    /// `caller` `shares` vault shares.
+    fn staked_liquidation_setup(e: &soroban_sdk::Env, contract: &Address, user: &Address, caller: &Address, shares: i128) -> u32 {
+        let id = e.as_contract(contract, || {
+            crate::trading::execute_create_market(
+                e, user, FEED_BTC, 1_000 * SCALAR_7, 10_000 * SCALAR_7, true, 0, 0, &btc_price_data(e, BTC_PRICE),
+            )
+        });
+        update_config(e, contract, |c| c.keeper_min_shares = 500 * SCALAR_7);
+        let vault = e.as_contract(contract, || storage::get_vault(e));
+        crate::testutils::MockVaultClient::new(e, &vault).set_shares(caller, &shares);
+        id
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #763)")]
+    fn test_liquidation_by_unstaked_keeper_fails() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let (user, caller) = (Address::generate(&e), Address::generate(&e));
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+        let id = staked_liquidation_setup(&e, &contract, &user, &caller, 500 * SCALAR_7 - 1);
+
+        let pd = btc_price_data(&e, 90_500 * PRICE_SCALAR);
+        e.as_contract(&contract, || {
+            let (users, ids) = trigger_one(&e, &user, id);
+            super::execute_trigger(&e, &caller, FEED_BTC, users, ids, &pd);
+        });
+    }
+
+    #[test]
+    fn test_staked_caller_must_sign_liquidation() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let (user, caller) = (Address::generate(&e), Address::generate(&e));
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+        let id = staked_liquidation_setup(&e, &contract, &user, &caller, 500 * SCALAR_7);
+        let pv = e.as_contract(&contract, || storage::get_price_verifier(&e));
+        crate::testutils::MockPriceVerifierClient::new(&e, &pv).set_price(&FEED_BTC, &(90_500 * PRICE_SCALAR));
+
+        // An unstaked submitter naming the staked caller has no signature for it
+        let client = crate::TradingClient::new(&e, &contract);
+        let (users, ids) = trigger_one(&e, &user, id);
+        e.set_auths(&[]);
+        assert!(client.try_execute(&caller, &FEED_BTC, &users, &ids, &dummy_price(&e)).is_err());
+        assert!(e.as_contract(&contract, || storage::try_get_position(&e, &user, id)).is_some());
+
+        e.mock_all_auths();
+        client.execute(&caller, &FEED_BTC, &users, &ids, &dummy_price(&e));
+        assert_eq!(e.auths()[0].0, caller);
+        assert!(e.as_contract(&contract, || storage::try_get_position(&e, &user, id)).is_none());
+    }
+
+    #[test]
+    fn test_liquidation_by_staked_keeper() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let (user, caller, unstaked) = (Address::generate(&e), Address::generate(&e), Address::generate(&e));
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+        let id = staked_liquidation_setup(&e, &contract, &user, &caller, 500 * SCALAR_7);
+
+        let pd = btc_price_data(&e, 90_500 * PRICE_SCALAR);
+        e.as_contract(&contract, || {
+            // execute_each skips the entry for an unstaked keeper
+            let (users, ids) = trigger_one(&e, &user, id);
+            let codes = super::execute_trigger_each(&e, &unstaked, FEED_BTC, users, ids, &pd);
+            assert_eq!(codes, vec![&e, 763]);
+
+            let (users, ids) = trigger_one(&e, &user, id);
+            super::execute_trigger(&e, &caller, FEED_BTC, users, ids, &pd);
+            assert!(storage::try_get_position(&e, &user, id).is_none());
+        });
+        assert!(token_client.balance(&caller) > 0);
+    }
 }
@@ -0,0 +1,218 @@
+use crate::dependencies::scalar_from_exponent;
+use crate::storage;
+use crate::types::Position;
+use soroban_fixed_point_math::SorobanFixedPoint;
+use soroban_sdk::token::TokenClient;
+use soroban_sdk::{Address, Env, Map, Vec};
+
+/// Recompute a market's side notionals and entry weights from its open
+/// positions and compare them with the stored `MarketData` aggregates. Read-only.
+///
+/// Positions are not indexed per market on-chain, so the caller supplies the
+/// full set of open positions for the market (e.g. rebuilt from events) as
+/// parallel `users` / `ids` vectors.
+///
+/// Each position's notional is ADL-adjusted to the current side index before
+/// summing. ADL floors the aggregate and each position independently, so up
+/// to one unit of dust per position on a side is tolerated on notional, and
+/// two on entry weight, which is floored once more when derived.
+///
+/// Collateral has no stored aggregate; the listed positions' collateral plus
+/// unclaimed referral rewards and escrowed bounties must fit in the contract's
+/// token balance. Other markets' collateral shares that balance, so this only
+/// catches a shortfall, not every mismatch.
+///
+/// # Returns
+/// `false` if any listed position is missing, pending, on another market or
+/// listed twice, if either side's notional or entry weight does not match the
+/// stored aggregate, or if the collateral check fails.
+pub fn execute_check_invariants(e: &Env, market_id: u32, users: &Vec<Address>, ids: &Vec<u32>) -> bool {
+    if users.len() != ids.len() || !storage::has_market(e, market_id) {
+        return false;
+    }
+    let data = storage::get_market_data(e, market_id);
+    // No price pinned yet: nothing has been filled on the market
+    let price_scalar = storage::get_price_exponent(e, market_id).map(scalar_from_exponent);
+
+    let mut seen: Map<(Address, u32), bool> = Map::new(e);
+    let (mut l_notional, mut s_notional) = (0i128, 0i128);
+    let (mut l_entry_wt, mut s_entry_wt) = (0i128, 0i128);
+    let (mut l_count, mut s_count) = (0i128, 0i128);
+    let mut col = storage::get_referral_owed(e) + storage::get_bounty_escrow(e);
+    for (user, id) in users.iter().zip(ids.iter()) {
+        if seen.contains_key((user.clone(), id)) {
+            return false;
+        }
+        seen.set((user.clone(), id), true);
+        let position: Position = match storage::try_get_position(e, &user, id) {
+            Some(p) => p,
+            None => return false,
+        };
+        let Some(price_scalar) = price_scalar else { return false };
+        if !position.filled || position.market_id != market_id {
+            return false;
+        }
+
+        let (_, _, adl_idx) = data.indices(position.long);
+        let notional = position.notional.fixed_mul_floor(e, &adl_idx, &position.adl_idx);
+        let entry_wt = notional.fixed_div_floor(e, &position.entry_price, &price_scalar);
+        col += position.col;
+        if position.long {
+            l_notional += notional;
+            l_entry_wt += entry_wt;
+            l_count += 1;
+        } else {
+            s_notional += notional;
+            s_entry_wt += entry_wt;
+            s_count += 1;
+        }
+    }
+
+    (data.l_notional - l_notional).abs() <= l_count
+        && (data.s_notional - s_notional).abs() <= s_count
+        && (data.l_entry_wt - l_entry_wt).abs() <= 2 * l_count
+        && (data.s_entry_wt - s_entry_wt).abs() <= 2 * s_count
+        && col <= TokenClient::new(e, &storage::get_token(e)).balance(&e.current_contract_address())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::execute_check_invariants;
+    use crate::constants::SCALAR_7;
+    use crate::dependencies::PriceData;
+    use crate::storage;
+    use crate::testutils::{setup_contract, setup_env, BTC_PRICE, FEED_BTC};
+    use crate::trading::execute_create_market;
+    use soroban_sdk::testutils::Address as _;
+    use soroban_sdk::{vec, Address, Env};
+
+    fn open(e: &Env, contract: &Address, user: &Address, long: bool) -> u32 {
+        let pd = PriceData {
+            feed_id: FEED_BTC,
+            price: BTC_PRICE,
+            exponent: -8,
+            publish_time: e.ledger().timestamp(),
+        };
+        e.as_contract(contract, || {
+            execute_create_market(e, user, FEED_BTC, 1_000 * SCALAR_7, 10_000 * SCALAR_7, long, 0, 0, &pd)
+        })
+    }
+
+    #[test]
+    fn test_check_invariants_healthy_market() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        let long_id = open(&e, &contract, &user, true);
+        let short_id = open(&e, &contract, &user, false);
+
+        let ok = e.as_contract(&contract, || {
+            execute_check_invariants(&e, FEED_BTC, &vec![&e, user.clone(), user.clone()], &vec![&e, long_id, short_id])
+        });
+        assert!(ok);
+    }
+
+    #[test]
+    fn test_check_invariants_detects_orphaned_aggregate() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        let first = open(&e, &contract, &user, true);
+        let second = open(&e, &contract, &user, true);
+
+        // Reproduce a close path that removes the position without updating stats
+        e.as_contract(&contract, || storage::remove_position(&e, &user, second));
+
+        let ok = e.as_contract(&contract, || {
+            execute_check_invariants(&e, FEED_BTC, &vec![&e, user.clone()], &vec![&e, first])
+        });
+        assert!(!ok);
+    }
+
+    #[test]
+    fn test_check_invariants_rejects_missing_position() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        let id = open(&e, &contract, &user, true);
+
+        let ok = e.as_contract(&contract, || {
+            execute_check_invariants(&e, FEED_BTC, &vec![&e, user.clone(), user.clone()], &vec![&e, id, id + 1])
+        });
+        assert!(!ok);
+    }
+
+    #[test]
+    fn test_check_invariants_rejects_duplicate_entry() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        let long_id = open(&e, &contract, &user, true);
+        let short_id = open(&e, &contract, &user, false);
+        // Drift the long aggregate by exactly one position, then list that position twice
+        e.as_contract(&contract, || {
+            let mut data = storage::get_market_data(&e, FEED_BTC);
+            data.l_notional *= 2;
+            data.l_entry_wt *= 2;
+            storage::set_market_data(&e, FEED_BTC, &data);
+        });
+
+        let ok = e.as_contract(&contract, || {
+            execute_check_invariants(
+                &e,
+                FEED_BTC,
+                &vec![&e, user.clone(), user.clone(), user.clone()],
+                &vec![&e, long_id, long_id, short_id],
+            )
+        });
+        assert!(!ok);
+    }
+
+    #[test]
+    fn test_check_invariants_detects_entry_weight_drift() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        let id = open(&e, &contract, &user, true);
+        e.as_contract(&contract, || {
+            let mut data = storage::get_market_data(&e, FEED_BTC);
+            data.l_entry_wt += 10;
+            storage::set_market_data(&e, FEED_BTC, &data);
+        });
+
+        let ok = e.as_contract(&contract, || {
+            execute_check_invariants(&e, FEED_BTC, &vec![&e, user.clone()], &vec![&e, id])
+        });
+        assert!(!ok);
+    }
+
+    #[test]
+    fn test_check_invariants_detects_collateral_shortfall() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        let id = open(&e, &contract, &user, true);
+        e.as_contract(&contract, || {
+            let mut position = storage::get_position(&e, &user, id);
+            position.col = token_client.balance(&contract) + 1;
+            storage::set_position(&e, &user, id, &position);
+        });
+
+        let ok = e.as_contract(&contract, || {
+            execute_check_invariants(&e, FEED_BTC, &vec![&e, user.clone()], &vec![&e, id])
+        });
+        assert!(!ok);
+    }
+}
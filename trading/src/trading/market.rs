@@ -1,8 +1,10 @@
 use crate::constants::{ONE_HOUR_SECONDS, SCALAR_7, SCALAR_18};
-use crate::types::MarketData;
+use crate::errors::TradingError;
+use crate::events::IndexUpdate;
+use crate::types::{MarketConfig, MarketData, TradingConfig};
 use crate::trading::rates;
 use soroban_fixed_point_math::SorobanFixedPoint;
-use soroban_sdk::Env;
+use soroban_sdk::{panic_with_error, Env};
 
 impl Default for MarketData {
     fn default() -> Self {
@@ -23,6 +25,61 @@ impl Default for MarketData {
     }
 }
 
+impl MarketConfig {
+    /// Initial margin requirement for a position of `notional`: the margin of the
+    /// highest tier the notional reaches, never below the flat `margin`. Tiers taper
+    /// max leverage (1/margin) as positions grow.
+    pub fn margin_for(&self, notional: i128) -> i128 {
+        let mut margin = self.margin;
+        for tier in self.tiers.iter() {
+            if notional < tier.notional {
+                break;
+            }
+            margin = margin.max(tier.margin);
+        }
+        margin
+    }
+
+    /// Whether the market accepts new positions on the given side.
+    pub fn allows(&self, is_long: bool) -> bool {
+        if is_long { self.allow_long } else { self.allow_short }
+    }
+
+    /// Panics unless the market accepts new positions on the given side.
+    pub fn require_direction(&self, e: &Env, is_long: bool) {
+        if !self.allows(is_long) {
+            panic_with_error!(e, TradingError::DirectionDisabled);
+        }
+    }
+
+    /// Panics if the market has new opens paused. Fills of orders placed before
+    /// the pause are not opens and are not checked.
+    pub fn require_opens(&self, e: &Env) {
+        if self.open_paused {
+            panic_with_error!(e, TradingError::OpensPaused);
+        }
+    }
+
+    /// Minimum notional per position on this market: the stricter of the market's
+    /// `min_notional` and the global `global_min`.
+    pub fn notional_floor(&self, global_min: i128) -> i128 {
+        self.min_notional.max(global_min)
+    }
+
+    /// Base fee discount for a user with cumulative `volume` traded: the discount
+    /// of the highest tier the volume reaches, 0 below the first tier.
+    pub fn fee_discount_for(&self, volume: i128) -> i128 {
+        let mut discount = 0;
+        for tier in self.fee_tiers.iter() {
+            if volume < tier.volume {
+                break;
+            }
+            discount = tier.discount;
+        }
+        discount
+    }
+}
+
 /// Compute utilization = notional / (vault_balance × max_util / SCALAR_7), clamped to [0, SCALAR_7].
 fn calc_util(e: &Env, notional: i128, vault_balance: i128, max_util: i128) -> i128 {
     if vault_balance <= 0 || notional <= 0 || max_util <= 0 {
@@ -34,6 +91,23 @@ fn calc_util(e: &Env, notional: i128, vault_balance: i128, max_util: i128) -> i1
 }
 
 impl MarketData {
+    /// Normalized (vault, market) utilization in [0, SCALAR_7] used by `accrue`
+    /// to price borrowing.
+    pub fn borrow_utilization(
+        &self,
+        e: &Env,
+        vault_balance: i128,
+        total_notional: i128,
+        max_util: i128,
+        max_util_market: i128,
+    ) -> (i128, i128) {
+        let market_notional = self.l_notional + self.s_notional;
+        (
+            calc_util(e, total_notional, vault_balance, max_util),
+            calc_util(e, market_notional, vault_balance, max_util_market),
+        )
+    }
+
     /// Returns (funding_index, borrowing_index, adl_index) for the given side.
     pub fn indices(&self, is_long: bool) -> (i128, i128, i128) {
         if is_long {
@@ -43,6 +117,31 @@ impl MarketData {
         }
     }
 
+    /// Market utilization: open notional (both sides) / vault balance (SCALAR_7),
+    /// the quantity capped by `MarketConfig.max_util`. 0 if the vault is empty.
+    pub fn utilization(&self, e: &Env, vault_balance: i128) -> i128 {
+        if vault_balance <= 0 {
+            return 0;
+        }
+        (self.l_notional + self.s_notional).fixed_div_floor(e, &vault_balance, &SCALAR_7)
+    }
+
+    /// PnL-equivalent entry price of a side, in the market's price units:
+    /// `notional / entry_wt`, the single entry price at which the side's aggregate
+    /// PnL (`price × entry_wt - notional`) would be the same. That is the harmonic,
+    /// not arithmetic, notional-weighted mean of entries. 0 for an empty side.
+    pub fn avg_entry_price(&self, e: &Env, is_long: bool, price_scalar: i128) -> i128 {
+        let (notional, entry_wt) = if is_long {
+            (self.l_notional, self.l_entry_wt)
+        } else {
+            (self.s_notional, self.s_entry_wt)
+        };
+        if entry_wt <= 0 {
+            return 0;
+        }
+        notional.fixed_div_floor(e, &entry_wt, &price_scalar)
+    }
+
     /// Returns true if the given side is dominant (has more notional).
     /// `extra` is additional notional being added/removed.
     pub fn is_dominant(&self, is_long: bool, extra: i128) -> bool {
@@ -57,6 +156,21 @@ impl MarketData {
     ///
     /// Computes vault and market utilization internally from the raw inputs,
     /// then delegates to `calc_borrowing_rate` with the normalized values.
+    ///
+    /// Accrual is keyed to the ledger timestamp, which is fixed for a transaction:
+    /// the first load of a market accrues up to now and every later load in the same
+    /// transaction sees zero elapsed time and returns early. Every action in a batch,
+    /// and every call within one invocation, therefore settles against the same
+    /// indices, and interest is charged once per market per transaction.
+    ///
+    /// Indices grow additively (rate × elapsed hours), never by compounding, and
+    /// positions only use the difference from their snapshot, so no rebase is
+    /// needed. At the config bounds the horizon before an i128 index overflows:
+    /// - borrowing: at most 3 × 0.01%/hr (3e14 in SCALAR_18), ~5e23 hours
+    /// - funding, paying side: at most `MAX_RATE_HOURLY`, ~1.7e24 hours
+    /// - funding, receiving side: the payment scaled by payer / receiver notional.
+    ///   A 1e16 : 1 ratio still gives ~1.7e8 hours (~19k years); only a dust side
+    ///   against a ratio past ~1e19 gets within decades, which `min_side` rules out.
     #[allow(clippy::too_many_arguments)]
     pub fn accrue(
         &mut self,
@@ -68,6 +182,7 @@ impl MarketData {
         total_notional: i128,
         max_util: i128,
         max_util_market: i128,
+        min_side: i128,
     ) {
         // No positions, no fees to charge
         if self.l_notional == 0 && self.s_notional == 0 {
@@ -84,10 +199,7 @@ impl MarketData {
 
         let hour = ONE_HOUR_SECONDS as i128;
 
-        // Compute normalized utilizations [0, SCALAR_7]
-        let market_notional = self.l_notional + self.s_notional;
-        let util_vault = calc_util(e, total_notional, vault_balance, max_util);
-        let util_market = calc_util(e, market_notional, vault_balance, max_util_market);
+        let (util_vault, util_market) = self.borrow_utilization(e, vault_balance, total_notional, max_util, max_util_market);
 
         let borr_rate = rates::calc_borrowing_rate(e, r_base, r_var, r_var_market, util_vault, util_market);
 
@@ -105,7 +217,10 @@ impl MarketData {
 
         // Funding is peer-to-peer: if either side is empty there is no counterparty
         // to receive payment, so no accrual occurs even if fund_rate is non-zero.
-        if self.fund_rate == 0 || self.l_notional == 0 || self.s_notional == 0 {
+        // A side below `min_side` counts as empty, so a dust position can't absorb
+        // the whole payment at an unbounded per-unit rate.
+        let (l_side, s_side) = self.funding_sides(min_side);
+        if self.fund_rate == 0 || l_side == 0 || s_side == 0 {
             return;
         }
 
@@ -134,41 +249,102 @@ impl MarketData {
         }
     }
 
-    pub fn update_funding_rate(&mut self, e: &Env, base_funding_rate: i128) {
-        self.fund_rate = rates::calc_funding_rate(
+    /// [`accrue`](Self::accrue) market `market_id` under its config and the
+    /// global one, emitting `IndexUpdate` if any index moved. Every path that
+    /// accrues a market goes through here, so indexers see each index change.
+    pub fn accrue_market(
+        &mut self,
+        e: &Env,
+        market_id: u32,
+        trading_config: &TradingConfig,
+        config: &MarketConfig,
+        vault_balance: i128,
+        total_notional: i128,
+    ) {
+        let before = (self.l_fund_idx, self.s_fund_idx, self.l_borr_idx, self.s_borr_idx);
+        self.accrue(
             e,
-            self.l_notional,
-            self.s_notional,
-            base_funding_rate,
+            trading_config.r_base,
+            trading_config.r_var,
+            config.r_var_market,
+            vault_balance,
+            total_notional,
+            trading_config.max_util,
+            config.max_util,
+            config.min_side,
         );
+        if before == (self.l_fund_idx, self.s_fund_idx, self.l_borr_idx, self.s_borr_idx) {
+            return;
+        }
+        // Accrual does not move notionals, so this is the utilization it priced
+        let (util_vault, util_market) =
+            self.borrow_utilization(e, vault_balance, total_notional, trading_config.max_util, config.max_util);
+        IndexUpdate {
+            market_id,
+            l_fund_idx: self.l_fund_idx,
+            s_fund_idx: self.s_fund_idx,
+            l_borr_idx: self.l_borr_idx,
+            s_borr_idx: self.s_borr_idx,
+            util_vault,
+            util_market,
+            timestamp: self.last_update,
+        }
+        .publish(e);
+    }
+
+    pub fn update_funding_rate(&mut self, e: &Env, base_funding_rate: i128, min_side: i128) {
+        let (l_side, s_side) = self.funding_sides(min_side);
+        self.fund_rate = rates::calc_funding_rate(e, l_side, s_side, base_funding_rate);
+    }
+
+    /// Side notionals as seen by funding: a side below `min_side` reads as 0.
+    fn funding_sides(&self, min_side: i128) -> (i128, i128) {
+        let side = |notional: i128| if notional < min_side { 0 } else { notional };
+        (side(self.l_notional), side(self.s_notional))
     }
 
     /// Updates open interest and entry-weighted aggregate stats.
     /// notional_size: positive for open, negative for close/reduce.
     /// ew_delta: pre-computed |notional| / entry_price in price_scalar precision.
     ///
-    /// Note: after ADL, sequential floor operations (bulk index reduction on the
-    /// aggregate vs per-position floor at settlement) can leave small rounding
-    /// dust in the market data.
-    pub fn update_stats(&mut self, is_long: bool, notional_size: i128, ew_delta: i128) {
-        if is_long {
-            self.l_notional += notional_size;
-            if notional_size > 0 {
-                self.l_entry_wt += ew_delta;
-            } else {
-                self.l_entry_wt -= ew_delta;
-            }
+    /// A removal never reverts, so a drifted aggregate can't block closes or
+    /// liquidations: one that would go below zero floors at zero. After ADL,
+    /// sequential floor operations (bulk index reduction on the aggregate vs
+    /// per-position floor at settlement) leave such dust by design; on a side
+    /// that has never been ADL'd the shortfall is returned as
+    /// `(notional, entry_wt)` for the caller to report, `(0, 0)` otherwise.
+    ///
+    /// # Panics
+    /// - `TradingError::InvalidMarketStats` (753) if an open overflows an aggregate
+    pub fn update_stats(&mut self, e: &Env, is_long: bool, notional_size: i128, ew_delta: i128) -> (i128, i128) {
+        let ew_delta = if notional_size > 0 { ew_delta } else { -ew_delta };
+        let (notional, entry_wt, adl_idx) = if is_long {
+            (&mut self.l_notional, &mut self.l_entry_wt, self.l_adl_idx)
         } else {
-            self.s_notional += notional_size;
-            if notional_size > 0 {
-                self.s_entry_wt += ew_delta;
-            } else {
-                self.s_entry_wt -= ew_delta;
-            }
-        }
+            (&mut self.s_notional, &mut self.s_entry_wt, self.s_adl_idx)
+        };
+        let (next_notional, notional_short) = apply_stat_delta(e, *notional, notional_size);
+        let (next_entry_wt, entry_wt_short) = apply_stat_delta(e, *entry_wt, ew_delta);
+        *notional = next_notional;
+        *entry_wt = next_entry_wt;
+        if adl_idx < SCALAR_18 { (0, 0) } else { (notional_short, entry_wt_short) }
     }
 }
 
+/// Add `delta` to a non-negative aggregate: an addition that overflows panics,
+/// a removal past zero floors at zero. Returns the new value and the shortfall
+/// floored away.
+fn apply_stat_delta(e: &Env, current: i128, delta: i128) -> (i128, i128) {
+    if delta < 0 {
+        let next = current.saturating_add(delta);
+        return (next.max(0), (-next).max(0));
+    }
+    let next = current
+        .checked_add(delta)
+        .unwrap_or_else(|| panic_with_error!(e, TradingError::InvalidMarketStats));
+    (next, 0)
+}
+
 #[cfg(test)]
 mod tests {
     use crate::constants::{SCALAR_7, SCALAR_18};
@@ -180,6 +356,43 @@ mod tests {
     const MAX_UTIL: i128 = 10 * SCALAR_7;
     const MAX_UTIL_MKT: i128 = 5 * SCALAR_7;
 
+    #[test]
+    fn test_margin_for_tiers() {
+        use crate::testutils::default_market;
+        use crate::types::MarginTier;
+        let e = Env::default();
+        let mut config = default_market(&e);
+        assert_eq!(config.margin_for(i128::MAX), config.margin);
+
+        config.tiers = soroban_sdk::vec![
+            &e,
+            MarginTier { notional: 100_000 * SCALAR_7, margin: 1_000_000 },   // 10x
+            MarginTier { notional: 1_000_000 * SCALAR_7, margin: 3_333_334 }, // ~3x
+        ];
+        assert_eq!(config.margin_for(99_999 * SCALAR_7), config.margin);
+        assert_eq!(config.margin_for(100_000 * SCALAR_7), 1_000_000);
+        assert_eq!(config.margin_for(999_999 * SCALAR_7), 1_000_000);
+        assert_eq!(config.margin_for(5_000_000 * SCALAR_7), 3_333_334);
+    }
+
+    #[test]
+    fn test_fee_discount_for_volume() {
+        use crate::testutils::default_market;
+        use crate::types::FeeTier;
+        let e = Env::default();
+        let mut config = default_market(&e);
+        assert_eq!(config.fee_discount_for(i128::MAX), 0);
+
+        config.fee_tiers = soroban_sdk::vec![
+            &e,
+            FeeTier { volume: 1_000_000 * SCALAR_7, discount: 1_000_000 },  // 10%
+            FeeTier { volume: 10_000_000 * SCALAR_7, discount: 2_500_000 }, // 25%
+        ];
+        assert_eq!(config.fee_discount_for(0), 0);
+        assert_eq!(config.fee_discount_for(1_000_000 * SCALAR_7), 1_000_000);
+        assert_eq!(config.fee_discount_for(20_000_000 * SCALAR_7), 2_500_000);
+    }
+
     #[test]
     fn test_market_data_update_stats() {
         let e = Env::default();
@@ -198,23 +411,93 @@ mod tests {
             let notional_short = 5_000 * scalar_7;
 
             let ew = notional_long.fixed_div_floor(&e, &entry_price, &price_scalar);
-            data.update_stats(true, notional_long, ew);
+            data.update_stats(&e, true, notional_long, ew);
             assert_eq!(data.l_notional, notional_long);
             assert_eq!(data.s_notional, 0);
             assert!(data.l_entry_wt > 0);
 
             let ew = notional_short.fixed_div_floor(&e, &entry_price, &price_scalar);
-            data.update_stats(false, notional_short, ew);
+            data.update_stats(&e, false, notional_short, ew);
             assert_eq!(data.l_notional, notional_long);
             assert_eq!(data.s_notional, notional_short);
             assert!(data.s_entry_wt > 0);
 
             let ew = notional_short.fixed_div_floor(&e, &entry_price, &price_scalar);
-            data.update_stats(true, -notional_short, ew);
+            data.update_stats(&e, true, -notional_short, ew);
             assert_eq!(data.l_notional, notional_long - notional_short);
         });
     }
 
+    #[test]
+    fn test_avg_entry_price() {
+        let e = Env::default();
+        let (address, _) = create_trading(&e);
+
+        e.as_contract(&address, || {
+            use soroban_fixed_point_math::SorobanFixedPoint;
+
+            let price_scalar: i128 = 10_000_000;
+            let mut data = default_market_data();
+            assert_eq!(data.avg_entry_price(&e, true, price_scalar), 0);
+
+            // 10k at $100k and 30k at $50k: 40k / (0.1 + 0.6) ~= $57,142.85
+            for (notional, entry) in [(10_000, 100_000), (30_000, 50_000)] {
+                let (notional, entry) = (notional * price_scalar, entry * price_scalar);
+                data.update_stats(&e, true, notional, notional.fixed_div_floor(&e, &entry, &price_scalar));
+            }
+            assert_eq!(data.avg_entry_price(&e, true, price_scalar), 571_428_571_428);
+            assert_eq!(data.avg_entry_price(&e, false, price_scalar), 0);
+
+            // Closing the $50k leg leaves the $100k one
+            let (notional, entry) = (30_000 * price_scalar, 50_000 * price_scalar);
+            data.update_stats(&e, true, -notional, notional.fixed_div_floor(&e, &entry, &price_scalar));
+            assert_eq!(data.avg_entry_price(&e, true, price_scalar), 100_000 * price_scalar);
+        });
+    }
+
+    #[test]
+    fn test_update_stats_underflow_floors_and_reports() {
+        let e = Env::default();
+        let (address, _) = create_trading(&e);
+
+        e.as_contract(&address, || {
+            let mut data = default_market_data();
+            assert_eq!(data.update_stats(&e, true, 1_000 * SCALAR_7, 100), (0, 0));
+            // Closing more than is open on a side that was never ADL'd
+            let short = data.update_stats(&e, true, -2_000 * SCALAR_7, 200);
+            assert_eq!(short, (1_000 * SCALAR_7, 100));
+            assert_eq!((data.l_notional, data.l_entry_wt), (0, 0));
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #753)")]
+    fn test_update_stats_overflow_panics() {
+        let e = Env::default();
+        let (address, _) = create_trading(&e);
+
+        e.as_contract(&address, || {
+            let mut data = default_market_data();
+            data.update_stats(&e, false, i128::MAX, 1);
+            data.update_stats(&e, false, 1, 1);
+        });
+    }
+
+    #[test]
+    fn test_update_stats_floors_adl_dust() {
+        let e = Env::default();
+        let (address, _) = create_trading(&e);
+
+        e.as_contract(&address, || {
+            let mut data = default_market_data();
+            data.update_stats(&e, true, 1_000, 10);
+            data.l_adl_idx = SCALAR_18 / 2;
+            assert_eq!(data.update_stats(&e, true, -1_001, 11), (0, 0));
+            assert_eq!(data.l_notional, 0);
+            assert_eq!(data.l_entry_wt, 0);
+        });
+    }
+
     #[test]
     fn test_accrue_funding_longs_pay() {
         let e = Env::default();
@@ -229,7 +512,7 @@ mod tests {
             data.last_update = 0;
 
             jump(&e, 3600);
-            data.accrue(&e, 0, 0, 0, 0, 0, MAX_UTIL, MAX_UTIL_MKT);
+            data.accrue(&e, 0, 0, 0, 0, 0, MAX_UTIL, MAX_UTIL_MKT, 0);
 
             // pay_delta = fund_rate × 3600/3600 = 10_000_000_000_000
             // ratio = floor(L/S) = floor(2000/1000 × S18) = 2 × S18
@@ -241,6 +524,60 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_accrue_max_rates_over_a_century() {
+        use crate::constants::{MAX_R_VAR, MAX_R_VAR_MARKET, MAX_RATE_HOURLY};
+        let e = Env::default();
+        jump(&e, 0);
+        let (address, _) = create_trading(&e);
+
+        e.as_contract(&address, || {
+            // Fully utilized vault, and a 1e16 : 1 funding imbalance with no min_side
+            let mut data = default_market_data();
+            data.l_notional = 10_000_000_000_000_000;
+            data.s_notional = 1;
+            data.fund_rate = MAX_RATE_HOURLY;
+            data.last_update = 0;
+
+            let hours: i128 = 100 * 365 * 24;
+            jump(&e, hours as u64 * 3600);
+            data.accrue(
+                &e, MAX_RATE_HOURLY, MAX_R_VAR, MAX_R_VAR_MARKET, 1, data.l_notional, MAX_UTIL, MAX_UTIL_MKT, 0,
+            );
+
+            assert_eq!(data.l_borr_idx, 3 * MAX_RATE_HOURLY * hours);
+            assert_eq!(data.l_fund_idx, MAX_RATE_HOURLY * hours);
+            assert_eq!(data.s_fund_idx, -MAX_RATE_HOURLY * hours * 10_000_000_000_000_000);
+        });
+    }
+
+    #[test]
+    fn test_accrue_funding_dust_side_counts_as_empty() {
+        let e = Env::default();
+        jump(&e, 0);
+        let (address, _) = create_trading(&e);
+
+        e.as_contract(&address, || {
+            let mut data = default_market_data();
+            data.l_notional = 1_000_000 * SCALAR_7;
+            data.s_notional = 1;
+            data.fund_rate = 10_000_000_000_000;
+            data.last_update = 0;
+
+            jump(&e, 3600);
+            data.accrue(&e, 0, 0, 0, 0, 0, MAX_UTIL, MAX_UTIL_MKT, SCALAR_7);
+
+            // A 1-stroop short would otherwise receive 1e13× the per-unit payment
+            assert_eq!(data.l_fund_idx, 0);
+            assert_eq!(data.s_fund_idx, 0);
+            assert_eq!(data.last_update, 3600);
+
+            // The rate is bounded by the base rate, as if only longs were open
+            data.update_funding_rate(&e, BASE_RATE, SCALAR_7);
+            assert_eq!(data.fund_rate, BASE_RATE);
+        });
+    }
+
     #[test]
     fn test_accrue_borrowing_longs_dominant() {
         let e = Env::default();
@@ -255,7 +592,7 @@ mod tests {
 
             jump(&e, 3600);
             let total = data.l_notional + data.s_notional;
-            data.accrue(&e, BASE_RATE, 0, 0, VAULT, total, MAX_UTIL, MAX_UTIL_MKT);
+            data.accrue(&e, BASE_RATE, 0, 0, VAULT, total, MAX_UTIL, MAX_UTIL_MKT, 0);
 
             // r_var=0, r_var_market=0 → borr_rate = r_base = BASE_RATE
             // borrow_delta = BASE_RATE × 3600/3600 = 10_000_000_000_000
@@ -278,7 +615,7 @@ mod tests {
 
             jump(&e, 3600);
             let total = data.l_notional + data.s_notional;
-            data.accrue(&e, BASE_RATE, 0, 0, VAULT, total, MAX_UTIL, MAX_UTIL_MKT);
+            data.accrue(&e, BASE_RATE, 0, 0, VAULT, total, MAX_UTIL, MAX_UTIL_MKT, 0);
 
             assert_eq!(data.l_borr_idx, 0, "non-dominant longs should NOT accrue");
             assert_eq!(data.s_borr_idx, 10_000_000_000_000, "dominant shorts should accrue");
@@ -299,7 +636,7 @@ mod tests {
 
             jump(&e, 3600);
             let total = data.l_notional + data.s_notional;
-            data.accrue(&e, BASE_RATE, 0, 0, VAULT, total, MAX_UTIL, MAX_UTIL_MKT);
+            data.accrue(&e, BASE_RATE, 0, 0, VAULT, total, MAX_UTIL, MAX_UTIL_MKT, 0);
 
             // Balanced: both sides pay identical borrowing
             assert_eq!(data.l_borr_idx, 10_000_000_000_000);
@@ -2,16 +2,32 @@ mod actions;
 mod adl;
 mod config;
 mod execute;
+mod invariants;
 pub(crate) mod rates;
 mod context;
 mod market;
 mod position;
+mod price;
+mod referral;
 
 pub use actions::{
-    execute_apply_funding, execute_cancel_position, execute_close_position,
-    execute_create_limit, execute_create_market, execute_modify_collateral,
-    execute_set_triggers,
+    execute_apply_funding, execute_avg_entry_prices, execute_bump_position, execute_cancel_expired, execute_cancel_position,
+    execute_close_position, execute_close_position_to, execute_create_limit, execute_create_market,
+    execute_create_market_position, execute_health_factor, execute_market_utilization,
+    execute_quote_close,
+    execute_modify_collateral, execute_set_auto_trigger, execute_set_triggers, execute_set_triggers_each, execute_user_positions,
+    execute_get_positions,
 };
-pub use adl::execute_update_status;
-pub use config::{execute_del_market, execute_set_config, execute_set_market, execute_set_status};
-pub use execute::execute_trigger;
+pub use adl::{execute_solvency, execute_update_status};
+pub use config::{
+    execute_compound_reward, execute_del_market, execute_migrate, execute_set_config, execute_set_frozen,
+    execute_set_market, execute_set_reward_router, execute_set_status, execute_sweep, execute_sweep_dust,
+    execute_update_market,
+};
+pub use execute::{
+    execute_force_settle, execute_liquidatable_sorted, execute_mark_fillable, execute_simulate_transfers,
+    execute_simulate_trigger, execute_trigger, execute_trigger_each,
+};
+pub use invariants::execute_check_invariants;
+pub use referral::{execute_claim_referral, execute_set_referrer};
+pub use price::{feed_map, load_price, to_market_units, verify_market_price};
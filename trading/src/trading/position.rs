@@ -33,6 +33,19 @@ impl Settlement {
         (self.pnl - self.total_fee()).max(-col)
     }
 
+    /// Shortfall when losses plus fees exceed collateral, 0 for solvent positions.
+    /// The vault absorbs this amount; the trader receives nothing.
+    pub fn bad_debt(&self, col: i128) -> i128 {
+        (-self.equity(col)).max(0)
+    }
+
+    /// Cap a fee amount to what the position can actually pay.
+    /// Bad-debt positions can only pay fees out of collateral, so fee-based
+    /// cuts (treasury, caller) never exceed `col` and are never funded by the vault.
+    pub fn collectible(&self, col: i128, fee: i128) -> i128 {
+        if self.bad_debt(col) > 0 { fee.min(col) } else { fee }
+    }
+
     /// Trading fees only (base + impact).
     pub fn trading_fee(&self) -> i128 {
         self.base_fee + self.impact_fee
@@ -73,6 +86,17 @@ impl Position {
             borr_idx: 0,
             created_at: e.ledger().timestamp(),
             adl_idx: SCALAR_18,
+            breach_at: 0,
+            max_dev: 0,
+            at_trigger: false,
+            expiry: 0,
+            last_modified_at: 0,
+            touched_at: e.ledger().timestamp(),
+            fillable_since: 0,
+            bounty: 0,
+            frozen: false,
+            auto_trigger: true,
+            event_seq: 0,
         };
         let id = storage::next_position_id(e, user);
         (id, position)
@@ -91,21 +115,36 @@ impl Position {
     /// - `TradingError::NotionalBelowMinimum` (724) / `NotionalAboveMaximum` (725)
     /// - `TradingError::LeverageAboveMaximum` (726) if `notional * margin > col`
     pub fn validate(&self, e: &Env, enabled: bool, min_notional: i128, max_notional: i128, margin: i128) {
+        if let Some(err) = self.validation_error(e, enabled, min_notional, max_notional, margin) {
+            panic_with_error!(e, err);
+        }
+    }
+
+    /// Non-panicking [`Self::validate`]: the error it would panic with, if any.
+    pub fn validation_error(
+        &self,
+        e: &Env,
+        enabled: bool,
+        min_notional: i128,
+        max_notional: i128,
+        margin: i128,
+    ) -> Option<TradingError> {
         if self.notional <= 0 || self.entry_price <= 0 || self.col <= 0 || self.tp < 0 || self.sl < 0 {
-            panic_with_error!(e, TradingError::NegativeValueNotAllowed);
+            return Some(TradingError::NegativeValueNotAllowed);
         }
         if !enabled {
-            panic_with_error!(e, TradingError::MarketDisabled);
+            return Some(TradingError::MarketDisabled);
         }
         if self.notional < min_notional {
-            panic_with_error!(e, TradingError::NotionalBelowMinimum);
+            return Some(TradingError::NotionalBelowMinimum);
         }
         if self.notional > max_notional {
-            panic_with_error!(e, TradingError::NotionalAboveMaximum);
+            return Some(TradingError::NotionalAboveMaximum);
         }
         if self.notional.fixed_mul_ceil(e, &margin, &SCALAR_7) > self.col {
-            panic_with_error!(e, TradingError::LeverageAboveMaximum);
+            return Some(TradingError::LeverageAboveMaximum);
         }
+        None
     }
 
     /// Guard for user-initiated close: position must be filled and at least MIN_OPEN_TIME old.
@@ -127,10 +166,31 @@ impl Position {
         }
     }
 
+    /// Guard for every action on a position: the owner may freeze one (see
+    /// `freeze_position`) to hold it as-is while a dispute or bug is investigated.
+    ///
+    /// # Panics
+    /// - `TradingError::PositionFrozen` (722) if the position is frozen
+    pub fn require_unfrozen(&self, e: &Env) {
+        if self.frozen {
+            panic_with_error!(e, TradingError::PositionFrozen);
+        }
+    }
+
+    /// Equity below which the position is liquidatable (token_decimals).
+    /// ceil: a position exactly on the boundary is liquidated (vault-favoring).
+    pub fn liq_threshold(&self, e: &Env, liq_fee: i128) -> i128 {
+        self.notional.fixed_mul_ceil(e, &liq_fee, &SCALAR_7)
+    }
+
     /// Guard for liquidation path: position must be filled, and price must be
     /// at least as recent as the position open time. This prevents liquidation
     /// using prices before open, without blocking timely liquidations with MIN_OPEN_TIME.
-    pub fn require_liquidatable(&self, e: &Env, price_publish_time: u64) {
+    ///
+    /// `max_age` (the market's `liq_price_age`, 0 = unbounded) additionally caps how
+    /// old the price may be: fresh enough to quote is not always fresh enough to
+    /// seize collateral.
+    pub fn require_liquidatable(&self, e: &Env, price_publish_time: u64, max_age: u64) {
         if !self.filled {
             panic_with_error!(e, TradingError::ActionNotAllowedForStatus);
         }
@@ -138,6 +198,14 @@ impl Position {
         if price_publish_time < self.created_at {
             panic_with_error!(e, TradingError::StalePrice);
         }
+        if max_age > 0 && e.ledger().timestamp() > price_publish_time.saturating_add(max_age) {
+            panic_with_error!(e, TradingError::StalePrice);
+        }
+    }
+
+    /// True if this is a pending order that has reached its `expiry`.
+    pub fn is_expired(&self, e: &Env) -> bool {
+        !self.filled && self.expiry != 0 && e.ledger().timestamp() >= self.expiry
     }
 
     /// Transition pending → filled. Snapshots funding/borrowing/ADL indices.
@@ -181,28 +249,24 @@ impl Position {
             self.adl_idx = adl_index;
         }
 
-        // PnL: floor rounding conservative for the trader (vault keeps rounding dust).
+        // PnL: notional × price_diff / entry_price, floored once. Floor rounds toward
+        // negative infinity, so for either side profits round down and losses round
+        // up: the vault keeps under 1 unit of dust and the trader never gains any.
+        // (Flooring an intermediate return ratio would cost the trader up to
+        // notional / price_scalar units instead.)
         let price_diff = if self.long {
             market.price - self.entry_price
         } else {
             self.entry_price - market.price
         };
-        let pnl = if price_diff == 0 {
-            0
-        } else {
-            let ratio = price_diff.fixed_div_floor(e, &self.entry_price, &market.price_scalar);
-            self.notional.fixed_mul_floor(e, &ratio, &market.price_scalar)
-        };
+        let pnl = self.notional.fixed_mul_floor(e, &price_diff, &self.entry_price);
 
         // Closing from the dominant side rebalances the market (reduces imbalance),
         // so it gets the lower non-dom fee. Closing from non-dominant side worsens
         // imbalance, so it pays the higher dom fee. This mirrors open-side fee logic.
-        let base_fee = if market.data.is_dominant(self.long, -self.notional) {
-            self.notional.fixed_mul_ceil(e, &market.trading_config.fee_non_dom, &SCALAR_7)
-        } else {
-            self.notional.fixed_mul_ceil(e, &market.trading_config.fee_dom, &SCALAR_7)
-        };
-        let impact_fee = self.notional.fixed_div_floor(e, &market.config.impact, &SCALAR_7);
+        // Rounding as in `Context::trading_fees`.
+        let dominant = !market.data.is_dominant(self.long, -self.notional);
+        let (base_fee, impact_fee) = market.trading_fees(e, self.notional, dominant);
 
         // Funding: ceil when paying (positive delta), floor when receiving (negative delta).
         // This ensures payers never under-pay and receivers never over-receive.
@@ -224,6 +288,64 @@ impl Position {
         }
     }
 
+    /// True if `price` has gapped past the order's `max_dev` bound from `entry_price`.
+    pub fn gapped(&self, e: &Env, price: i128) -> bool {
+        let gap = (price - self.entry_price).abs();
+        self.max_dev > 0 && gap > self.entry_price.fixed_mul_floor(e, &self.max_dev, &SCALAR_7)
+    }
+
+    /// True if a pending limit order may fill at `price`.
+    ///
+    /// Long limit: fills when market price falls to or below the entry (buy at or better).
+    /// Short limit: fills when market price rises to or above the entry (sell at or better).
+    /// A gap past `max_dev` only fills with `at_trigger`, and a price its own bracket
+    /// has already crossed never fills, as it would be stopped out at once.
+    pub fn can_fill_at(&self, e: &Env, price: i128) -> bool {
+        let crossed = if self.long { price <= self.entry_price } else { price >= self.entry_price };
+        crossed
+            && (self.at_trigger || !self.gapped(e, price))
+            && !self.check_stop_loss(price)
+            && !self.check_take_profit(price)
+    }
+
+    /// Equity / liquidation threshold (SCALAR_7) at the context's price, or
+    /// `i128::MAX` when the threshold rounds to zero. Below `SCALAR_7` the
+    /// position is under its liquidation threshold.
+    pub fn health_factor(&self, e: &Env, ctx: &Context) -> i128 {
+        let threshold = self.liq_threshold(e, ctx.config.liq_fee);
+        if threshold <= 0 {
+            return i128::MAX;
+        }
+        let equity = self.clone().settle(e, ctx).equity(self.col);
+        equity.fixed_div_floor(e, &threshold, &SCALAR_7)
+    }
+
+    /// Advance the position's event sequence and return the new value, for the
+    /// event about to be published. Store the position afterwards if it lives on.
+    pub fn next_seq(&mut self) -> u32 {
+        self.event_seq += 1;
+        self.event_seq
+    }
+
+    /// Guard for brackets on a pending order: TP and SL must sit on the profit and
+    /// loss side of `entry_price`, so neither fires the moment the order fills.
+    ///
+    /// # Panics
+    /// - `TradingError::InvalidInput` (734) if a set trigger would fire at `entry_price`
+    pub fn require_valid_brackets(&self, e: &Env) {
+        let (tp, sl) = self.bracket_rejections();
+        if let Some(err) = tp.or(sl) {
+            panic_with_error!(e, err);
+        }
+    }
+
+    /// Non-panicking [`Self::require_valid_brackets`]: the error for the take-profit
+    /// and the stop-loss respectively, `None` where that trigger is valid.
+    pub fn bracket_rejections(&self) -> (Option<TradingError>, Option<TradingError>) {
+        let invalid = |fires: bool| fires.then_some(TradingError::InvalidInput);
+        (invalid(self.check_take_profit(self.entry_price)), invalid(self.check_stop_loss(self.entry_price)))
+    }
+
     // Check if current price triggers take profit. If TP is not set (0), always returns false.
     pub fn check_take_profit(&self, current_price: i128) -> bool {
         if self.tp == 0 {
@@ -256,7 +378,7 @@ mod tests {
     use super::*;
     use crate::constants::{SCALAR_7, SCALAR_18};
     use crate::trading::context::Context;
-    use crate::testutils::{create_trading, default_config, default_market, default_market_data, FEED_BTC};
+    use crate::testutils::{create_trading, default_config, default_market, default_market_data, jump, FEED_BTC};
     use soroban_sdk::{testutils::Address as _, Address, Env};
 
     fn create_test_position(_e: &Env) -> Position {
@@ -273,6 +395,17 @@ mod tests {
             borr_idx: 0,
             created_at: 0,
             adl_idx: SCALAR_18,
+            breach_at: 0,
+            max_dev: 0,
+            at_trigger: false,
+            expiry: 0,
+            last_modified_at: 0,
+            touched_at: 0,
+            fillable_since: 0,
+            bounty: 0,
+            frozen: false,
+            auto_trigger: true,
+            event_seq: 0,
         }
     }
 
@@ -283,6 +416,7 @@ mod tests {
             feed_id: FEED_BTC,
             price: 100_000 * SCALAR_7,
             price_scalar: SCALAR_7,
+            exponent: -7,
             config: default_market(&e),
             data,
             trading_config: default_config(),
@@ -291,6 +425,9 @@ mod tests {
             token: Address::generate(&e),
             treasury: Address::generate(&e),
             total_notional: 0,
+            total_fees: 0,
+            fee_free: false,
+            caller_signed: false,
             publish_time: 0,
         }
     }
@@ -302,6 +439,7 @@ mod tests {
             feed_id: FEED_BTC,
             price,
             price_scalar: SCALAR_7,
+            exponent: -7,
             config: default_market(&e),
             data,
             trading_config: default_config(),
@@ -310,12 +448,58 @@ mod tests {
             token: Address::generate(&e),
             treasury: Address::generate(&e),
             total_notional: 0,
+            total_fees: 0,
+            fee_free: false,
+            caller_signed: false,
             publish_time: 0,
         }
     }
 
     // Settlement tests (PnL + fees)
 
+    #[test]
+    fn test_settle_funding_rebate_exceeds_fees() {
+        let e = Env::default();
+        let (address, _) = create_trading(&e);
+        let mut position = create_test_position(&e);
+        let mut data = default_market_data();
+        data.l_fund_idx = -SCALAR_18 / 100; // longs earned 1%
+        let m = test_market(data);
+
+        e.as_contract(&address, || {
+            let s = position.settle(&e, &m);
+            assert_eq!(s.funding, -100 * SCALAR_7);
+            assert!(s.total_fee() < 0);
+            assert_eq!(s.equity(position.col), position.col - s.total_fee());
+            assert_eq!(s.net_pnl(position.col), -s.total_fee());
+        });
+    }
+
+    #[test]
+    fn test_settle_fee_free_wind_down() {
+        let e = Env::default();
+        let (address, _) = create_trading(&e);
+        let mut data = default_market_data();
+        data.l_borr_idx = SCALAR_18 / 1_000; // 0.1% accrued
+        let mut m = test_market_at(110_000 * SCALAR_7, data);
+
+        let user = Address::generate(&e);
+        m.fee_free = true;
+
+        e.as_contract(&address, || {
+            // Keeper and owner settlements still pay the trading fees
+            let paid = m.settle(&e, &mut create_test_position(&e), &user, false);
+            assert!(paid.base_fee > 0 && paid.impact_fee > 0);
+
+            let free = m.settle(&e, &mut create_test_position(&e), &user, true);
+            assert_eq!(free.base_fee, 0);
+            assert_eq!(free.impact_fee, 0);
+            assert_eq!(free.borrowing_fee, paid.borrowing_fee);
+            assert_eq!(free.borrowing_fee, 10 * SCALAR_7);
+            assert_eq!(free.pnl, 1_000 * SCALAR_7);
+        });
+    }
+
     #[test]
     fn test_settle_long_profit() {
         let e = Env::default();
@@ -371,6 +555,42 @@ mod tests {
         });
     }
 
+    /// Across long/short × profit/loss, PnL is the exact `notional × Δ / entry`
+    /// floored once: never above it (trader never gains dust), less than 1 unit below.
+    #[test]
+    fn test_settle_pnl_rounding_favors_vault_by_under_one_unit() {
+        let e = Env::default();
+        let (address, _) = create_trading(&e);
+        // Deterministic LCG: awkward, non-round entries, moves and sizes
+        let mut seed: u64 = 0x5eed;
+        let mut next = |lo: i128, hi: i128| {
+            seed = seed.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1_442_695_040_888_963_407);
+            lo + ((seed >> 33) as i128) % (hi - lo)
+        };
+
+        e.as_contract(&address, || {
+            for _ in 0..64 {
+                let entry = next(SCALAR_7 / 3, 200_000 * SCALAR_7);
+                let notional = next(10 * SCALAR_7, 1_000_000 * SCALAR_7);
+                let mv = next(1, entry / 2);
+                for (long, up) in [(true, true), (true, false), (false, true), (false, false)] {
+                    let price = if up { entry + mv } else { entry - mv };
+                    let mut position = create_test_position(&e);
+                    position.long = long;
+                    position.entry_price = entry;
+                    position.notional = notional;
+                    let pnl = position.settle(&e, &test_market_at(price, default_market_data())).pnl;
+
+                    let diff = if long { price - entry } else { entry - price };
+                    assert_eq!(pnl > 0, long == up);
+                    // pnl <= notional × diff / entry < pnl + 1
+                    assert!(pnl * entry <= notional * diff, "trader favored: long={long} up={up}");
+                    assert!(notional * diff < (pnl + 1) * entry, "vault over 1 unit: long={long} up={up}");
+                }
+            }
+        });
+    }
+
     #[test]
     fn test_settle_no_pnl() {
         let e = Env::default();
@@ -699,7 +919,7 @@ mod tests {
         position.created_at = 1000;
         position.filled = true;
         // price publish_time before position open -> StalePrice
-        position.require_liquidatable(&e, 999);
+        position.require_liquidatable(&e, 999, 0);
     }
 
     #[test]
@@ -709,9 +929,9 @@ mod tests {
         position.created_at = 1000;
         position.filled = true;
         // Exact match: price at same time as position open
-        position.require_liquidatable(&e, 1000);
+        position.require_liquidatable(&e, 1000, 0);
         // Newer price: should also succeed
-        position.require_liquidatable(&e, 1001);
+        position.require_liquidatable(&e, 1001, 0);
     }
 
     #[test]
@@ -721,7 +941,29 @@ mod tests {
         let mut position = create_test_position(&e);
         position.filled = false;
         // Even with a valid publish_time, unfilled position should fail
-        position.require_liquidatable(&e, 2000);
+        position.require_liquidatable(&e, 2000, 0);
+    }
+
+    #[test]
+    fn test_require_liquidatable_within_max_age() {
+        let e = Env::default();
+        jump(&e, 1010);
+        let mut position = create_test_position(&e);
+        position.created_at = 1000;
+        position.filled = true;
+        position.require_liquidatable(&e, 1000, 10);
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #711)")]
+    fn test_require_liquidatable_older_than_max_age_fails() {
+        let e = Env::default();
+        jump(&e, 1011);
+        let mut position = create_test_position(&e);
+        position.created_at = 1000;
+        position.filled = true;
+        // Fresh enough for the verifier, too old for this market's liquidations
+        position.require_liquidatable(&e, 1000, 10);
     }
 
 }
@@ -0,0 +1,236 @@
+use crate::constants::SCALAR_7;
+use crate::dependencies::{PriceData, PriceVerifierClient};
+use crate::errors::TradingError;
+use crate::storage;
+use crate::types::MarketConfig;
+use soroban_fixed_point_math::SorobanFixedPoint;
+use soroban_sdk::{panic_with_error, Bytes, Env, Map, Vec};
+
+/// Verify a price payload and resolve the price for `market_id` via [`load_price`],
+/// in the market's pinned exponent (see [`to_market_units`]). Read-only.
+///
+/// The payload may carry any number of feeds; basket markets need one per constituent.
+pub fn verify_market_price(e: &Env, market_id: u32, price: &Bytes) -> PriceData {
+    let pv = PriceVerifierClient::new(e, &storage::get_price_verifier(e));
+    let feeds = feed_map(e, &pv.verify_prices(price));
+    to_market_units(e, market_id, load_price(e, &storage::get_market_config(e, market_id), &feeds))
+}
+
+/// Express `price` in the exponent pinned for `market_id`. A market with no
+/// exponent pinned yet has never filled a position and takes `price` as is.
+/// Read-only.
+///
+/// Entry, trigger and liquidation prices and the entry-weight aggregates are all
+/// stored in the units of the prices they were computed from, so a market must
+/// see one exponent for its lifetime even if the oracle changes the precision it
+/// reports. Extra precision is floored away.
+///
+/// # Panics
+/// - `TradingError::InvalidPrice` (710) if the rescaled price overflows
+pub fn to_market_units(e: &Env, market_id: u32, price: PriceData) -> PriceData {
+    match storage::get_price_exponent(e, market_id) {
+        None => price,
+        Some(exponent) => rescale(e, price, exponent),
+    }
+}
+
+/// Pin `exponent` for `market_id` if it has none yet. Called when a position
+/// fills, the first write that stores prices in the market's units.
+pub fn pin_exponent(e: &Env, market_id: u32, exponent: i32) {
+    if storage::get_price_exponent(e, market_id).is_none() {
+        storage::set_price_exponent(e, market_id, exponent);
+    }
+}
+
+/// Restate `price` with `exponent`, flooring any precision lost.
+fn rescale(e: &Env, price: PriceData, exponent: i32) -> PriceData {
+    let value = if price.exponent >= exponent {
+        10i128
+            .checked_pow(price.exponent.abs_diff(exponent))
+            .and_then(|scalar| price.price.checked_mul(scalar))
+            .unwrap_or_else(|| panic_with_error!(e, TradingError::InvalidPrice))
+    } else {
+        // A divisor past i128 floors every price to 0
+        10i128.checked_pow(price.exponent.abs_diff(exponent)).map_or(0, |scalar| price.price / scalar)
+    };
+    PriceData { price: value, exponent, ..price }
+}
+
+/// Build a feed_id -> PriceData lookup from verified feeds.
+pub fn feed_map(e: &Env, feeds: &Vec<PriceData>) -> Map<u32, PriceData> {
+    let mut map: Map<u32, PriceData> = Map::new(e);
+    for f in feeds.iter() {
+        map.set(f.feed_id, f);
+    }
+    map
+}
+
+/// Resolve the price of a market from a set of verified feeds.
+///
+/// - **Single asset** (empty `basket`): returns the feed for `config.feed_id`.
+/// - **Basket**: returns `Σ price_i × weight_i / SCALAR_7`, with every constituent
+///   rescaled to the most precise exponent among them. The result carries
+///   `config.feed_id` (the index identifier) and the oldest constituent
+///   `publish_time`, so staleness checks are as strict as the stalest input.
+///
+/// # Panics
+/// - `TradingError::InvalidPrice` (710) if a required feed is missing, or a
+///   constituent rescaled to the basket's exponent (or their sum) overflows
+pub fn load_price(e: &Env, config: &MarketConfig, feeds: &Map<u32, PriceData>) -> PriceData {
+    if config.basket.is_empty() {
+        return feeds
+            .get(config.feed_id)
+            .unwrap_or_else(|| panic_with_error!(e, TradingError::InvalidPrice));
+    }
+
+    let mut parts: Vec<(PriceData, i128)> = Vec::new(e);
+    let mut exponent = i32::MAX;
+    let mut publish_time = u64::MAX;
+    for w in config.basket.iter() {
+        let f = feeds
+            .get(w.feed_id)
+            .unwrap_or_else(|| panic_with_error!(e, TradingError::InvalidPrice));
+        exponent = exponent.min(f.exponent);
+        publish_time = publish_time.min(f.publish_time);
+        parts.push_back((f, w.weight));
+    }
+
+    let mut price: i128 = 0;
+    for (f, weight) in parts.iter() {
+        let rescaled = rescale(e, f, exponent).price;
+        price = price
+            .checked_add(rescaled.fixed_mul_floor(e, &weight, &SCALAR_7))
+            .unwrap_or_else(|| panic_with_error!(e, TradingError::InvalidPrice));
+    }
+
+    PriceData {
+        feed_id: config.feed_id,
+        price,
+        exponent,
+        publish_time,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{feed_map, load_price, pin_exponent, to_market_units};
+    use crate::dependencies::PriceData;
+    use crate::storage;
+    use crate::testutils::{create_trading, default_market, BTC_PRICE, FEED_BTC, FEED_ETH};
+    use crate::types::FeedWeight;
+    use soroban_sdk::{vec, Env};
+
+    const FEED_INDEX: u32 = 100;
+
+    fn feed(feed_id: u32, price: i128, exponent: i32, publish_time: u64) -> PriceData {
+        PriceData { feed_id, price, exponent, publish_time }
+    }
+
+    #[test]
+    fn test_single_asset_market_uses_own_feed() {
+        let e = Env::default();
+        let config = default_market(&e);
+        let feeds = vec![&e, feed(FEED_ETH, 2_000, -8, 5), feed(FEED_BTC, BTC_PRICE, -8, 5)];
+
+        let p = load_price(&e, &config, &feed_map(&e, &feeds));
+        assert_eq!(p, feed(FEED_BTC, BTC_PRICE, -8, 5));
+    }
+
+    #[test]
+    fn test_two_asset_weighted_basket() {
+        let e = Env::default();
+        let mut config = default_market(&e);
+        config.feed_id = FEED_INDEX;
+        config.basket = vec![
+            &e,
+            FeedWeight { feed_id: FEED_BTC, weight: 6_000_000 }, // 60%
+            FeedWeight { feed_id: FEED_ETH, weight: 4_000_000 }, // 40%
+        ];
+        // BTC 100_000.00 at 1e-2, ETH 2_000.00000000 at 1e-8
+        let feeds = vec![
+            &e,
+            feed(FEED_BTC, 10_000_000, -2, 50),
+            feed(FEED_ETH, 200_000_000_000, -8, 40),
+        ];
+
+        let p = load_price(&e, &config, &feed_map(&e, &feeds));
+        assert_eq!(p.feed_id, FEED_INDEX);
+        assert_eq!(p.exponent, -8);
+        assert_eq!(p.publish_time, 40);
+        // 0.6 * 100_000 + 0.4 * 2_000 = 60_800
+        assert_eq!(p.price, 60_800 * 100_000_000);
+    }
+
+    #[test]
+    fn test_pin_exponent_rescales_later_prices() {
+        let e = Env::default();
+        let (address, _) = create_trading(&e);
+
+        e.as_contract(&address, || {
+            // Unpinned: prices pass through and nothing is written
+            let first = to_market_units(&e, FEED_BTC, feed(FEED_BTC, BTC_PRICE, -8, 5));
+            assert_eq!(first, feed(FEED_BTC, BTC_PRICE, -8, 5));
+            assert_eq!(storage::get_price_exponent(&e, FEED_BTC), None);
+
+            // The first fill pins 1e-8; a later pin does not move it
+            pin_exponent(&e, FEED_BTC, -8);
+            pin_exponent(&e, FEED_BTC, -2);
+            assert_eq!(storage::get_price_exponent(&e, FEED_BTC), Some(-8));
+
+            // The oracle drops to 1e-2 and later rises to 1e-10: both land in 1e-8
+            let coarse = to_market_units(&e, FEED_BTC, feed(FEED_BTC, 10_000_000, -2, 6));
+            assert_eq!(coarse, feed(FEED_BTC, 100_000 * 100_000_000, -8, 6));
+            let fine = to_market_units(&e, FEED_BTC, feed(FEED_BTC, 10_000_000_000_000_099, -10, 7));
+            assert_eq!(fine, feed(FEED_BTC, 100_000_000_000_000, -8, 7));
+
+            // Markets pin independently
+            let eth = to_market_units(&e, FEED_ETH, feed(FEED_ETH, 2_000, 0, 8));
+            assert_eq!(eth.exponent, 0);
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #710)")]
+    fn test_rescale_overflow_panics() {
+        let e = Env::default();
+        let (address, _) = create_trading(&e);
+
+        e.as_contract(&address, || {
+            pin_exponent(&e, FEED_BTC, -30);
+            to_market_units(&e, FEED_BTC, feed(FEED_BTC, BTC_PRICE, 0, 5));
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #710)")]
+    fn test_basket_rescale_overflow_panics() {
+        let e = Env::default();
+        let mut config = default_market(&e);
+        config.feed_id = FEED_INDEX;
+        config.basket = vec![
+            &e,
+            FeedWeight { feed_id: FEED_BTC, weight: 5_000_000 },
+            FeedWeight { feed_id: FEED_ETH, weight: 5_000_000 },
+        ];
+        // A 1e-30 constituent lifts BTC at 1e0 past i128
+        let feeds = vec![&e, feed(FEED_BTC, BTC_PRICE, 0, 5), feed(FEED_ETH, 2_000, -30, 5)];
+
+        load_price(&e, &config, &feed_map(&e, &feeds));
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #710)")]
+    fn test_basket_missing_constituent_panics() {
+        let e = Env::default();
+        let mut config = default_market(&e);
+        config.feed_id = FEED_INDEX;
+        config.basket = vec![
+            &e,
+            FeedWeight { feed_id: FEED_BTC, weight: 5_000_000 },
+            FeedWeight { feed_id: FEED_ETH, weight: 5_000_000 },
+        ];
+        let feeds = vec![&e, feed(FEED_BTC, BTC_PRICE, -8, 5)];
+
+        load_price(&e, &config, &feed_map(&e, &feeds));
+    }
+}
@@ -0,0 +1,151 @@
+use crate::errors::TradingError;
+use crate::events::{ClaimReferral, SetReferrer};
+use crate::storage;
+use crate::validation::require_can_manage;
+use soroban_sdk::token::TokenClient;
+use soroban_sdk::{panic_with_error, Address, Env};
+
+/// Bind `referrer` to `user`. Write-once, and only before the user's first
+/// position, so existing traders can't be attached to a referrer after the fact.
+///
+/// From then on the referrer accrues `referral_share` of the user's trading fees,
+/// taken from the vault's side (see [`credit_referral`]).
+///
+/// # Panics
+/// - `TradingError::ReferrerLocked` (762) if the user already has a referrer, has
+///   opened or placed a position, or refers themselves
+pub fn execute_set_referrer(e: &Env, user: &Address, referrer: &Address) {
+    require_can_manage(e);
+    user.require_auth();
+    if user == referrer
+        || storage::get_referrer(e, user).is_some()
+        || storage::get_user_counter(e, user) > 0
+    {
+        panic_with_error!(e, TradingError::ReferrerLocked);
+    }
+    storage::set_referrer(e, user, referrer);
+
+    SetReferrer {
+        user: user.clone(),
+        referrer: referrer.clone(),
+    }
+    .publish(e);
+}
+
+/// Pay out `referrer`'s accrued rewards. Returns the amount paid (0 if none).
+pub fn execute_claim_referral(e: &Env, referrer: &Address) -> i128 {
+    require_can_manage(e);
+    referrer.require_auth();
+    let amount = storage::get_referral_rewards(e, referrer);
+    if amount > 0 {
+        storage::add_referral_rewards(e, referrer, -amount);
+        TokenClient::new(e, &storage::get_token(e)).transfer(&e.current_contract_address(), referrer, &amount);
+        ClaimReferral {
+            referrer: referrer.clone(),
+            amount,
+        }
+        .publish(e);
+    }
+    amount
+}
+
+/// Accrue `amount` of `user`'s fees to their referrer, if they have one.
+///
+/// The tokens stay in this contract until claimed; fee splits hold them back from
+/// the vault via `Context::referral_fee`.
+pub(crate) fn credit_referral(e: &Env, user: &Address, amount: i128) {
+    if amount <= 0 {
+        return;
+    }
+    if let Some(referrer) = storage::get_referrer(e, user) {
+        storage::add_referral_rewards(e, &referrer, amount);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{execute_claim_referral, execute_set_referrer};
+    use crate::constants::SCALAR_7;
+    use crate::dependencies::PriceData;
+    use crate::storage;
+    use crate::testutils::{
+        dummy_price, jump, setup_contract, setup_env, update_config, BTC_PRICE, FEED_BTC,
+    };
+    use crate::trading::{execute_close_position, execute_create_market, execute_quote_close};
+    use soroban_sdk::testutils::Address as _;
+    use soroban_sdk::token::TokenClient;
+    use soroban_sdk::{Address, Env};
+
+    fn open(e: &Env, contract: &Address, user: &Address) -> u32 {
+        let pd = PriceData {
+            feed_id: FEED_BTC,
+            price: BTC_PRICE,
+            exponent: -8,
+            publish_time: e.ledger().timestamp(),
+        };
+        e.as_contract(contract, || {
+            execute_create_market(e, user, FEED_BTC, 1_000 * SCALAR_7, 10_000 * SCALAR_7, true, 0, 0, &pd)
+        })
+    }
+
+    #[test]
+    fn test_referrer_accrues_share_of_fees_and_claims() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let token = TokenClient::new(&e, &token_client.address);
+        let (user, referrer) = (Address::generate(&e), Address::generate(&e));
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+        update_config(&e, &contract, |c| c.referral_share = 2_000_000); // 20%
+        e.as_contract(&contract, || {
+            execute_set_referrer(&e, &user, &referrer);
+        });
+
+        let id = open(&e, &contract, &user);
+        let open_fee = 1_000 * SCALAR_7 - e.as_contract(&contract, || storage::get_position(&e, &user, id).col);
+        let rewards = || e.as_contract(&contract, || storage::get_referral_rewards(&e, &referrer));
+        assert_eq!(rewards(), open_fee / 5);
+
+        jump(&e, 1000 + 31);
+        let quote = e.as_contract(&contract, || execute_quote_close(&e, &user, id, dummy_price(&e)));
+        assert_eq!(quote.referral_fee, quote.trading_fee / 5);
+        e.as_contract(&contract, || execute_close_position(&e, &user, id, dummy_price(&e)));
+        assert_eq!(rewards(), open_fee / 5 + quote.referral_fee);
+
+        // Rewards are held back from the vault, and sweep_dust leaves them behind
+        let owed = rewards();
+        token.transfer(&contract, &user, &(token.balance(&contract) - owed - 3));
+        let swept = e.as_contract(&contract, || crate::trading::execute_sweep_dust(&e));
+        assert_eq!(swept, 3);
+        assert_eq!(token.balance(&contract), owed);
+
+        let paid = e.as_contract(&contract, || execute_claim_referral(&e, &referrer));
+        assert_eq!(paid, owed);
+        assert_eq!(token.balance(&referrer), owed);
+        assert_eq!(rewards(), 0);
+        assert_eq!(e.as_contract(&contract, || storage::get_referral_owed(&e)), 0);
+    }
+
+    #[test]
+    fn test_unreferred_user_pays_no_referral() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+        update_config(&e, &contract, |c| c.referral_share = 2_000_000);
+
+        open(&e, &contract, &user);
+        assert_eq!(e.as_contract(&contract, || storage::get_referral_owed(&e)), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Error(Contract, #762)")]
+    fn test_set_referrer_after_first_position_fails() {
+        let e = setup_env();
+        let (contract, token_client) = setup_contract(&e);
+        let user = Address::generate(&e);
+        token_client.mint(&user, &(100_000 * SCALAR_7));
+
+        open(&e, &contract, &user);
+        e.as_contract(&contract, || execute_set_referrer(&e, &user, &Address::generate(&e)));
+    }
+}
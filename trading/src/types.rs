@@ -1,5 +1,5 @@
 use crate::errors::TradingError;
-use soroban_sdk::{contracttype, panic_with_error, Env};
+use soroban_sdk::{contracttype, panic_with_error, Address, Env, Vec};
 
 #[contracttype]
 #[derive(Clone, Debug)]
@@ -13,18 +13,62 @@ pub struct TradingConfig {
     pub r_funding:    i128, // base hourly funding rate (SCALAR_18)
     pub r_base:       i128, // base hourly borrowing rate (SCALAR_18)
     pub r_var:        i128, // vault-level variable borrowing rate at full vault utilization (SCALAR_18)
+    pub fee_free_wind_down: bool, // waive base/impact fees on user closes while OnIce (fills are blocked)
+    pub max_pending:  u32,  // pending limit orders per user per market, 0 = unlimited
+    pub fill_bonus:   i128, // extra keeper share of fill fees per hour an order has been marked fillable, 0 = off (SCALAR_7)
+    pub liq_caller_cap: i128, // cap on a liquidation's keeper fee as a share of the position's collateral, 0 = uncapped (SCALAR_7)
+    pub referral_share: i128, // referrer's share of a referred user's trading fees, taken from the vault's side (SCALAR_7)
+    pub max_total_positions: u32, // pending + filled positions across all users, 0 = unlimited
+    pub fill_bounty:  i128, // fixed amount a limit order escrows for the keeper that fills it, 0 = none (token_decimals)
+    pub keeper_min_shares: i128, // vault shares a keeper must hold to liquidate, 0 = permissionless (share decimals)
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FeedWeight {
+    pub feed_id: u32,  // constituent price feed identifier
+    pub weight:  i128, // share of the index price (SCALAR_7)
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MarginTier {
+    pub notional: i128, // tier applies to positions at or above this notional (token_decimals)
+    pub margin:   i128, // initial margin requirement within the tier (SCALAR_7)
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FeeTier {
+    pub volume:   i128, // tier applies to users whose cumulative notional traded is at or above this (token_decimals)
+    pub discount: i128, // fraction of the base fee waived within the tier (SCALAR_7)
 }
 
 #[contracttype]
 #[derive(Clone, Debug)]
 pub struct MarketConfig {
-    pub feed_id:  u32,   // price feed identifier (immutable after market creation)
+    pub feed_id:  u32,   // price feed identifier, or index identifier for baskets (immutable after market creation)
+    pub basket:   Vec<FeedWeight>, // index constituents, empty = priced by feed_id alone (immutable after market creation)
     pub enabled:  bool,  // true = active, false = disabled (positions refunded)
+    pub allow_long:  bool, // false = no new longs; existing longs can still close and be liquidated
+    pub allow_short: bool, // false = no new shorts; existing shorts can still close and be liquidated
+    pub open_paused: bool, // true = no new orders or market opens; pending fills, closes and liquidations continue
     pub max_util: i128, // per-market utilization cap (SCALAR_7)
     pub r_var_market: i128, // per-market variable borrowing rate at full market utilization (SCALAR_18)
     pub margin:   i128, // initial margin requirement, max leverage = 1/margin (SCALAR_7)
     pub liq_fee:  i128, // liquidation fee/threshold, must be < margin (SCALAR_7)
     pub impact:   i128, // price-impact fee divisor, fee = notional / impact (SCALAR_7)
+    pub min_notional: i128, // per-market floor on position notional, on top of the global one, 0 = global only (token_decimals)
+    pub max_user_notional: i128, // cap on one user's open notional across their positions on the market, 0 = no cap (token_decimals)
+    pub liq_grace: u64, // seconds a position may stay below liq threshold before liquidation, 0 = immediate
+    pub liq_price_age: u64, // max seconds between price publish and liquidation, 0 = verifier's bound only
+    pub max_duration: u64, // seconds after fill a keeper may settle the position at market, 0 = no expiry
+    pub tiers: Vec<MarginTier>, // size-based margin schedule, ascending notional, empty = flat `margin`
+    pub min_modify_interval: u64, // seconds required between collateral modifications on a position, 0 = no limit
+    pub fee_tiers: Vec<FeeTier>, // volume-based base fee discounts, ascending volume, empty = no discount
+    pub fee_recipient: Option<Address>, // partner receiving `fee_share` of trading fees, None = all to the vault
+    pub fee_share: i128, // partner's share of trading fees, taken from the vault's side (SCALAR_7)
+    pub min_side: i128, // a side with less open interest counts as empty for funding, 0 = any nonzero side (token_decimals)
 }
 
 #[contracttype]
@@ -45,7 +89,7 @@ pub struct MarketData {
 }
 
 #[contracttype]
-#[derive(Clone)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Position {
     pub filled:      bool,    // false = pending limit, true = filled
     pub market_id:   u32,     // market identifier (maps to MarketConfig with feed_id)
@@ -59,6 +103,32 @@ pub struct Position {
     pub borr_idx:    i128,    // borrowing index snapshot at fill (SCALAR_18)
     pub adl_idx:     i128,    // ADL index snapshot at fill (SCALAR_18)
     pub created_at:  u64,     // timestamp of creation or fill (seconds)
+    pub breach_at:   u64,     // first time seen below liq threshold, 0 = not in breach (seconds)
+    pub max_dev:     i128,    // max limit fill distance past entry_price, 0 = unbounded (SCALAR_7)
+    pub at_trigger:  bool,    // fills past max_dev: true = fill at entry_price, false = revert
+    pub expiry:      u64,     // pending order stops filling and anyone may cancel it from this time, 0 = never (seconds)
+    pub last_modified_at: u64, // timestamp of last modify_collateral, 0 = never modified (seconds)
+    pub touched_at:  u64,     // timestamp of the last write, stamped by storage, gates bump_position (seconds)
+    pub fillable_since: u64, // start of the pending order's current fillable episode, 0 = not marked (seconds)
+    pub bounty:      i128,    // escrowed for the keeper that fills the order, refunded on cancel, 0 once filled (token_decimals)
+    pub frozen:      bool,    // owner hold (dispute, investigation): every action reverts until unfrozen
+    pub auto_trigger: bool,   // keepers may execute TP/SL; false = triggers only fire when the user submits them
+    pub event_seq:   u32,     // sequence number of the position's latest event, orders events within a ledger
+}
+
+/// Preview of a user close at a given price, as returned by `quote_close`.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CloseQuote {
+    pub pnl:            i128, // net PnL after fees, funding and borrowing (token_decimals)
+    pub trading_fee:    i128, // base + impact fee on close, after volume discount (token_decimals)
+    pub funding:        i128, // funding settled, positive = paid by the position (token_decimals)
+    pub borrowing_fee:  i128, // accrued borrowing interest (token_decimals)
+    pub user_payout:    i128, // tokens paid to the user (token_decimals)
+    pub vault_transfer: i128, // tokens to the vault, negative = paid from the vault (token_decimals)
+    pub treasury_fee:   i128, // treasury's cut of protocol fees (token_decimals)
+    pub partner_fee:    i128, // market partner's cut of trading fees (token_decimals)
+    pub referral_fee:   i128, // user's referrer's cut of trading fees, accrued until claimed (token_decimals)
 }
 
 /// Contract operational state.
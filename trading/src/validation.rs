@@ -1,11 +1,70 @@
 use crate::constants::{
-    MAX_CALLER_RATE, MAX_FEE_RATE, MAX_LIQ_FEE, MAX_MARGIN, MAX_R_VAR_MARKET,
-    MAX_R_VAR, MAX_RATE_HOURLY, MAX_UTIL, MIN_IMPACT,
+    STORAGE_VERSION, MAX_BASKET, MAX_TIERS, MAX_CALLER_RATE, MAX_FEE_SHARE, MAX_FEE_RATE, MAX_REFERRAL_SHARE, MAX_LIQ_FEE, MAX_LIQ_GRACE, MAX_MARGIN, MAX_MODIFY_INTERVAL, MAX_DURATION,
+    MAX_R_VAR_MARKET, MAX_R_VAR, MAX_RATE_HOURLY, MAX_TREASURY_RATE, MAX_UTIL, MIN_IMPACT, SCALAR_7,
 };
 use crate::errors::TradingError;
 use crate::storage;
-use crate::types::{ContractStatus, MarketConfig, TradingConfig};
-use soroban_sdk::{panic_with_error, Env};
+use crate::types::{ContractStatus, FeedWeight, MarketConfig, TradingConfig};
+use crate::dependencies::VaultClient;
+use soroban_fixed_point_math::SorobanFixedPoint;
+use soroban_sdk::{panic_with_error, Address, Env, Vec};
+
+/// Keeper, referrer and partner shares are cut from the same fees as the
+/// treasury's rate, which the treasury may raise up to `MAX_TREASURY_RATE`.
+/// Together they must leave the vault a non-negative remainder, or fills and
+/// opens would pay the other legs out of the vault or escrowed collateral.
+///
+/// # Panics
+/// - `TradingError::InvalidConfig` (700) if `caller_rate + referral_share + fee_share`
+///   exceeds `SCALAR_7 - MAX_TREASURY_RATE`
+pub fn require_fee_split(e: &Env, config: &TradingConfig, fee_share: i128) {
+    if config.caller_rate + config.referral_share + fee_share > SCALAR_7 - MAX_TREASURY_RATE {
+        panic_with_error!(e, TradingError::InvalidConfig);
+    }
+}
+
+/// A market's notional floor must leave room under the global `max_notional`
+/// and its per-user cap, or no position could be opened on it. The floor is
+/// the stricter of the market's and the global `min_notional`.
+///
+/// # Panics
+/// - `TradingError::InvalidConfig` (700) if the market's `min_notional` exceeds
+///   the global `max_notional`, or a set `max_user_notional` is below the floor
+pub fn require_notional_range(e: &Env, config: &TradingConfig, market: &MarketConfig) {
+    if market.min_notional > config.max_notional
+        || (market.max_user_notional > 0 && market.max_user_notional < market.notional_floor(config.min_notional))
+    {
+        panic_with_error!(e, TradingError::InvalidConfig);
+    }
+}
+
+/// Guard: stored entries must match the running code's layout.
+///
+/// After an upgrade that bumps `STORAGE_VERSION`, every position and funding
+/// operation is refused until the owner runs `migrate`.
+///
+/// # Panics
+/// - `TradingError::MigrationPending` (743)
+pub fn require_migrated(e: &Env) {
+    if storage::get_storage_version(e) != STORAGE_VERSION {
+        panic_with_error!(e, TradingError::MigrationPending);
+    }
+}
+
+/// Guard: the vault's underlying asset must be the collateral `token`.
+///
+/// PnL and fees are paid in `token` from the vault's balance; a vault over a
+/// different asset would silently settle in the wrong unit. Run wherever the
+/// vault is set (only the constructor today): a vault's asset is fixed, so
+/// loads don't recheck it.
+///
+/// # Panics
+/// - `TradingError::VaultAssetMismatch` (705)
+pub fn require_vault_asset(e: &Env, vault: &Address, token: &Address) {
+    if VaultClient::new(e, vault).query_asset() != *token {
+        panic_with_error!(e, TradingError::VaultAssetMismatch);
+    }
+}
 
 /// Guard: contract must be `Active` to open new positions.
 ///
@@ -13,8 +72,10 @@ use soroban_sdk::{panic_with_error, Env};
 /// can still be managed (closed, liquidated) under OnIce/AdminOnIce.
 ///
 /// # Panics
+/// - `TradingError::MigrationPending` (743) if `migrate` has not run
 /// - `TradingError::ContractOnIce` (741)
 pub fn require_active(e: &Env) {
+    require_migrated(e);
     let status = ContractStatus::from_u32(e, storage::get_status(e));
     match status {
         ContractStatus::Active => {}
@@ -28,8 +89,10 @@ pub fn require_active(e: &Env) {
 /// permit existing position operations so users can always exit.
 ///
 /// # Panics
+/// - `TradingError::MigrationPending` (743) if `migrate` has not run
 /// - `TradingError::ContractFrozen` (742)
 pub fn require_can_manage(e: &Env) {
+    require_migrated(e);
     let status = ContractStatus::from_u32(e, storage::get_status(e));
     match status {
         ContractStatus::Active | ContractStatus::OnIce | ContractStatus::AdminOnIce => {}
@@ -37,12 +100,31 @@ pub fn require_can_manage(e: &Env) {
     }
 }
 
+/// Guard: the protocol-wide position cap must have room for one more position.
+///
+/// Counts pending and filled positions across all users, so many accounts
+/// can't together grow storage and keeper workload without bound.
+///
+/// # Panics
+/// - `TradingError::ProtocolPositionCapReached` (730)
+pub fn require_position_slot(e: &Env, config: &TradingConfig) {
+    if !has_position_slot(e, config) {
+        panic_with_error!(e, TradingError::ProtocolPositionCapReached);
+    }
+}
+
+/// Non-panicking [`require_position_slot`].
+pub fn has_position_slot(e: &Env, config: &TradingConfig) -> bool {
+    config.max_total_positions == 0 || storage::get_live_positions(e) < config.max_total_positions
+}
+
 /// Validate global trading configuration parameters against safety bounds.
 ///
 /// # Panics
 /// - `TradingError::NegativeValueNotAllowed` (723) if any rate/fee is negative
-/// - `TradingError::InvalidConfig` (700) if any value exceeds its upper bound or
-///   if min_notional/max_notional/max_util are logically invalid
+/// - `TradingError::InvalidConfig` (700) if any value exceeds its upper bound,
+///   if min_notional/max_notional/max_util are logically invalid, or if the fee
+///   shares overrun the split (see [`require_fee_split`])
 pub fn require_valid_config(e: &Env, config: &TradingConfig) {
     // Lower bounds: rates and fees must be non-negative
     if config.caller_rate < 0
@@ -51,26 +133,41 @@ pub fn require_valid_config(e: &Env, config: &TradingConfig) {
         || config.r_base < 0
         || config.r_var < 0
         || config.r_funding < 0
+        || config.fill_bonus < 0
+        || config.liq_caller_cap < 0
+        || config.referral_share < 0
+        || config.fill_bounty < 0
+        || config.keeper_min_shares < 0
     {
         panic_with_error!(e, TradingError::NegativeValueNotAllowed);
     }
 
     // Upper bounds: each parameter capped to prevent misconfiguration
     if config.caller_rate > MAX_CALLER_RATE
+        || config.fill_bonus > MAX_CALLER_RATE
         || config.fee_dom > MAX_FEE_RATE
         || config.fee_non_dom > MAX_FEE_RATE
         || config.r_base > MAX_RATE_HOURLY
         || config.r_var > MAX_R_VAR
         || config.r_funding > MAX_RATE_HOURLY
         || config.max_util > MAX_UTIL
+        || config.liq_caller_cap > SCALAR_7
+        || config.referral_share > MAX_REFERRAL_SHARE
     {
         panic_with_error!(e, TradingError::InvalidConfig);
     }
+    require_fee_split(e, config, 0);
 
     if config.min_notional <= 0 || config.max_notional <= config.min_notional {
         panic_with_error!(e, TradingError::InvalidConfig);
     }
 
+    // The bounty is a keeper fee on top of the order: at most the highest trading
+    // fee (MAX_FEE_RATE) a minimum-size order can pay.
+    if config.fill_bounty > config.min_notional.fixed_mul_floor(e, &MAX_FEE_RATE, &SCALAR_7) {
+        panic_with_error!(e, TradingError::InvalidConfig);
+    }
+
     if config.max_util <= 0 {
         panic_with_error!(e, TradingError::InvalidConfig);
     }
@@ -81,11 +178,43 @@ pub fn require_valid_config(e: &Env, config: &TradingConfig) {
     }
 }
 
+/// Validate a market's index basket. Empty prices the market by its own feed;
+/// otherwise the basket holds 2..=`MAX_BASKET` distinct non-zero feeds whose
+/// positive weights sum to exactly `SCALAR_7`, so the index stays in the units
+/// of its constituents.
+///
+/// # Panics
+/// - `TradingError::InvalidConfig` (700) on a single constituent, too many
+///   constituents, a zero or repeated feed, a non-positive weight, or weights
+///   not summing to `SCALAR_7`
+pub fn require_valid_basket(e: &Env, basket: &Vec<FeedWeight>) {
+    if basket.is_empty() {
+        return;
+    }
+    if basket.len() < 2 || basket.len() > MAX_BASKET {
+        panic_with_error!(e, TradingError::InvalidConfig);
+    }
+    let mut sum: i128 = 0;
+    for (i, w) in basket.iter().enumerate() {
+        if w.feed_id == 0 || w.weight <= 0 {
+            panic_with_error!(e, TradingError::InvalidConfig);
+        }
+        if basket.iter().skip(i + 1).any(|o| o.feed_id == w.feed_id) {
+            panic_with_error!(e, TradingError::InvalidConfig);
+        }
+        sum += w.weight;
+    }
+    if sum != SCALAR_7 {
+        panic_with_error!(e, TradingError::InvalidConfig);
+    }
+}
+
 /// Validate per-market configuration parameters against safety bounds.
 ///
 /// # Panics
 /// - `TradingError::NegativeValueNotAllowed` (723) if margin or liq_fee <= 0
-/// - `TradingError::InvalidConfig` (700) if bounds exceeded or margin <= liq_fee
+/// - `TradingError::InvalidConfig` (700) if bounds exceeded (incl. `max_duration` over a year),
+///   margin <= liq_fee, max_user_notional below min_notional, or invalid tiers
 pub fn require_valid_market_config(e: &Env, config: &MarketConfig) {
     // feed_id must be a valid Pyth feed identifier (non-zero)
     if config.feed_id == 0 {
@@ -97,6 +226,9 @@ pub fn require_valid_market_config(e: &Env, config: &MarketConfig) {
     if config.margin <= 0
         || config.liq_fee <= 0
         || config.r_var_market < 0
+        || config.min_side < 0
+        || config.min_notional < 0
+        || config.max_user_notional < 0
     {
         panic_with_error!(e, TradingError::NegativeValueNotAllowed);
     }
@@ -106,6 +238,9 @@ pub fn require_valid_market_config(e: &Env, config: &MarketConfig) {
         || config.r_var_market > MAX_R_VAR_MARKET
         || config.impact < MIN_IMPACT
         || config.max_util > MAX_UTIL
+        || config.liq_grace > MAX_LIQ_GRACE
+        || config.min_modify_interval > MAX_MODIFY_INTERVAL
+        || config.max_duration > MAX_DURATION
     {
         panic_with_error!(e, TradingError::InvalidConfig);
     }
@@ -120,4 +255,44 @@ pub fn require_valid_market_config(e: &Env, config: &MarketConfig) {
     if config.max_util <= 0 {
         panic_with_error!(e, TradingError::InvalidConfig);
     }
+
+    // A per-user cap below the market's own notional floor would admit no position.
+    if config.max_user_notional > 0 && config.max_user_notional < config.min_notional {
+        panic_with_error!(e, TradingError::InvalidConfig);
+    }
+
+    // Margin tiers: bounded, strictly ascending notional, margin never below the
+    // previous tier (or the flat margin) and within MAX_MARGIN.
+    if config.tiers.len() > MAX_TIERS {
+        panic_with_error!(e, TradingError::InvalidConfig);
+    }
+    let (mut prev_notional, mut prev_margin) = (0, config.margin);
+    for tier in config.tiers.iter() {
+        if tier.notional <= prev_notional || tier.margin < prev_margin || tier.margin > MAX_MARGIN {
+            panic_with_error!(e, TradingError::InvalidConfig);
+        }
+        prev_notional = tier.notional;
+        prev_margin = tier.margin;
+    }
+
+    // Partner fee split: a share needs a recipient, and stays within MAX_FEE_SHARE.
+    if config.fee_share < 0
+        || config.fee_share > MAX_FEE_SHARE
+        || (config.fee_share > 0 && config.fee_recipient.is_none())
+    {
+        panic_with_error!(e, TradingError::InvalidConfig);
+    }
+
+    // Fee tiers: bounded, strictly ascending volume, discount non-decreasing and at most 100%.
+    if config.fee_tiers.len() > MAX_TIERS {
+        panic_with_error!(e, TradingError::InvalidConfig);
+    }
+    let (mut prev_volume, mut prev_discount) = (0, 0);
+    for tier in config.fee_tiers.iter() {
+        if tier.volume <= prev_volume || tier.discount < prev_discount || tier.discount > SCALAR_7 {
+            panic_with_error!(e, TradingError::InvalidConfig);
+        }
+        prev_volume = tier.volume;
+        prev_discount = tier.discount;
+    }
 }